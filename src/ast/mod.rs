@@ -16,49 +16,22 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use std::fmt::Display;
+use crate::source::FileId;
+use num_bigint::BigInt;
 use std::hash::{Hash, Hasher};
 
-#[derive(Debug, Clone)]
+/// A range of bytes in a source file. Its text, line and column are found
+/// through the `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Span {
+    pub file: FileId,
     pub start: usize,
     pub end: usize,
-    pub input: String,
 }
 
 impl Span {
-    pub fn new(start: usize, end: usize, input: String) -> Self {
-        Span { start, end, input }
-    }
-
-    pub fn get_line_number(&self, source: &str) -> usize {
-        let mut line = 1;
-        for c in source.chars().skip(self.start).take(self.end - self.start) {
-            if c == '\n' {
-                line += 1;
-            }
-        }
-        line
-    }
-}
-
-impl Hash for Span {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.input.hash(state);
-    }
-}
-
-impl PartialEq for Span {
-    fn eq(&self, other: &Self) -> bool {
-        self.input == other.input
-    }
-}
-
-impl Eq for Span {}
-
-impl Display for Span {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.input)
+    pub fn new(file: FileId, start: usize, end: usize) -> Self {
+        Span { file, start, end }
     }
 }
 
@@ -78,6 +51,11 @@ pub enum Statement<T> {
     Bind(Bind<T>),
     TypeDecl(TypeDecl),
     TypeAssign(TypeAssign),
+    Fixity(FixityDecl),
+    Class(ClassDecl<T>),
+    Instance(InstanceDecl<T>),
+    Module(ModuleDecl),
+    Import(ImportDecl),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -112,6 +90,11 @@ pub enum AnnExpr<Annot = ()> {
         rhs: Box<AnnExpr<Annot>>,
         ann: Annot,
     },
+    UnOp {
+        op: UnOp,
+        expr: Box<AnnExpr<Annot>>,
+        ann: Annot,
+    },
     Lambda {
         args: Vec<Pattern>,
         ret: Box<AnnExpr<Annot>>,
@@ -129,6 +112,31 @@ pub enum AnnExpr<Annot = ()> {
         list: Vec<AnnExpr<Annot>>,
         ann: Annot,
     },
+    /// `C { field = expr, ... }`, the record `C` built from named fields.
+    Record {
+        id: Identifier,
+        fields: Vec<(Identifier, AnnExpr<Annot>)>,
+        ann: Annot,
+    },
+    /// `expr { field = expr, ... }`, a copy of the record `expr` with some
+    /// fields replaced.
+    Update {
+        expr: Box<AnnExpr<Annot>>,
+        fields: Vec<(Identifier, AnnExpr<Annot>)>,
+        ann: Annot,
+    },
+    /// The guarded body of a clause or a match arm, `| cond = expr ...`. The
+    /// first guard that holds gives the value; when none does, the next
+    /// clause or arm is tried.
+    Guards {
+        guards: Vec<(AnnExpr<Annot>, AnnExpr<Annot>)>,
+        ann: Annot,
+    },
+    /// Stands for an expression that failed to parse, which has already been
+    /// reported.
+    Error {
+        ann: Annot,
+    },
 }
 
 pub type ParsedExpr = AnnExpr<Span>;
@@ -144,24 +152,35 @@ impl ParsedExpr {
             AnnExpr::Match { ann, .. } => ann,
             AnnExpr::Literal(lit) => &lit.span,
             AnnExpr::BinOp { ann, .. } => ann,
+            AnnExpr::UnOp { ann, .. } => ann,
             AnnExpr::Lambda { ann, .. } => ann,
             AnnExpr::Ann { ann, .. } => &ann.0,
             AnnExpr::List { ann, .. } => ann,
             AnnExpr::Tuple { ann, .. } => ann,
+            AnnExpr::Record { ann, .. } => ann,
+            AnnExpr::Update { ann, .. } => ann,
+            AnnExpr::Guards { ann, .. } => ann,
+            AnnExpr::Error { ann } => ann,
         }
     }
 }
 
+/// `head` applied to `args`. The head is any expression: a function or
+/// constructor name, but also a lambda or the result of another application.
 #[derive(Debug, PartialEq, Clone)]
 pub struct App<T> {
-    pub ident: Identifier,
+    pub head: Box<AnnExpr<T>>,
     pub args: Vec<AnnExpr<T>>,
     pub span: Span,
 }
 
 impl<T> App<T> {
-    pub fn new(ident: Identifier, args: Vec<AnnExpr<T>>, span: Span) -> Self {
-        App { ident, args, span }
+    pub fn new(head: AnnExpr<T>, args: Vec<AnnExpr<T>>, span: Span) -> Self {
+        App {
+            head: Box::new(head),
+            args,
+            span,
+        }
     }
 }
 
@@ -178,6 +197,116 @@ impl TypeAssign {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Assoc {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Fixity {
+    /// From 0 to 9, higher binds tighter. Application binds tighter than any
+    /// operator.
+    pub precedence: u8,
+    pub assoc: Assoc,
+}
+
+/// ``infixl 6 <>, `plus` ``: gives a fixity to user-defined operators.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FixityDecl {
+    pub fixity: Fixity,
+    pub operators: Vec<Identifier>,
+    pub span: Span,
+}
+
+impl FixityDecl {
+    pub fn new(fixity: Fixity, operators: Vec<Identifier>, span: Span) -> Self {
+        FixityDecl {
+            fixity,
+            operators,
+            span,
+        }
+    }
+}
+
+/// `module Geometry.Shapes (area, Shape)`: the name of the module a file
+/// defines, and the names it exports. Without a list, everything it declares
+/// is exported.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ModuleDecl {
+    pub name: Identifier,
+    pub exports: Option<Vec<Identifier>>,
+    pub span: Span,
+}
+
+/// `import qualified Geometry.Shapes as S (area, Shape)`: brings the names
+/// exported by `module` in scope, only those of `items` if there is a list.
+/// They are written `S.area`, with the module name when there is no alias,
+/// and also `area` unless the import is qualified.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportDecl {
+    pub module: Identifier,
+    pub qualified: bool,
+    pub alias: Option<Identifier>,
+    pub items: Option<Vec<Identifier>>,
+    pub span: Span,
+}
+
+/// `name` without the modules qualifying it: `area` for `Geometry.area`,
+/// `<+>` for `Vector.<+>`.
+pub fn unqualified(name: &str) -> &str {
+    let mut rest = name;
+    while let Some((module, name)) = rest.split_once('.') {
+        let is_module = module.starts_with(char::is_uppercase)
+            && module.chars().all(|c| c.is_alphanumeric() || c == '\'');
+        if !is_module || name.is_empty() {
+            break;
+        }
+        rest = name;
+    }
+    rest
+}
+
+/// `C a`, requiring the type variable `a` to be an instance of the class `C`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Constraint {
+    pub class: Identifier,
+    pub var: Identifier,
+    pub span: Span,
+}
+
+impl Constraint {
+    pub fn new(class: Identifier, var: Identifier, span: Span) -> Self {
+        Constraint { class, var, span }
+    }
+}
+
+/// `class Eq a => Ord a where ...`: the class `name` of the types `var`,
+/// whose instances are also instances of the classes of `context`. Each
+/// method has a signature, and possibly a default definition used by the
+/// instances that do not give one.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClassDecl<T> {
+    pub context: Vec<Constraint>,
+    pub name: Identifier,
+    pub var: Identifier,
+    pub signatures: Vec<TypeAssign>,
+    pub defaults: Vec<Bind<T>>,
+    pub span: Span,
+}
+
+/// `instance Eq a => Eq (Maybe a) where ...`: makes `ty` an instance of
+/// `class`, provided the type variables of `ty` satisfy `context`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InstanceDecl<T> {
+    pub context: Vec<Constraint>,
+    pub class: Identifier,
+    pub ty: Type,
+    pub binds: Vec<Bind<T>>,
+    pub span: Span,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Bind<T> {
     pub name: Identifier,
@@ -240,16 +369,63 @@ pub enum BinOp {
     ListCons,
 }
 
+impl BinOp {
+    /// How the operator is written.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Div => "/",
+            BinOp::Mul => "*",
+            BinOp::Mod => "%",
+            BinOp::Exp => "^",
+            BinOp::LessThan => "<",
+            BinOp::GreaterThan => ">",
+            BinOp::LessThanOrEq => "<=",
+            BinOp::GreaterThanOrEq => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ineq => "!=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::ListCons => ":",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Variant {
     pub id: Identifier,
     pub types: Vec<Type>,
+    /// The names of the fields of a record, in the order of `types`. Empty
+    /// for a positional variant.
+    pub fields: Vec<Identifier>,
     pub span: Span,
 }
 
 impl Variant {
     pub fn new(id: Identifier, types: Vec<Type>, span: Span) -> Self {
-        Variant { id, types, span }
+        Variant {
+            id,
+            types,
+            fields: Vec::new(),
+            span,
+        }
+    }
+
+    pub fn record(id: Identifier, fields: Vec<(Identifier, Type)>, span: Span) -> Self {
+        let (fields, types) = fields.into_iter().unzip();
+        Variant {
+            id,
+            types,
+            fields,
+            span,
+        }
     }
 }
 
@@ -284,6 +460,11 @@ pub enum Type {
     App(Identifier, Vec<Type>, Span),
     Tuple(Vec<Type>, Span),
     Func(Box<Type>, Vec<Type>, Span),
+    /// `[T]`, the type of the lists of `T`.
+    List(Box<Type>, Span),
+    /// `Eq a => T`, the type `T` for every `a` that is an instance of `Eq`.
+    /// Only found at the top of a signature.
+    Qualified(Vec<Constraint>, Box<Type>, Span),
 }
 
 impl Type {
@@ -294,6 +475,8 @@ impl Type {
             Type::App(_, _, span) => span,
             Type::Tuple(_, span) => span,
             Type::Func(_, _, span) => span,
+            Type::List(_, span) => span,
+            Type::Qualified(_, _, span) => span,
         }
     }
 }
@@ -306,6 +489,14 @@ pub enum Pattern {
     Id(Identifier),
     App(Identifier, Vec<Pattern>, Span),
     Literal(Literal),
+    Tuple(Vec<Pattern>, Span),
+    List(Vec<Pattern>, Span),
+    /// `name@pattern`, binding `name` to the whole value matched by
+    /// `pattern`.
+    As(Identifier, Box<Pattern>, Span),
+    /// `C { field = pattern, ... }`, matching some fields of the record `C`
+    /// by name.
+    Record(Identifier, Vec<(Identifier, Pattern)>, Span),
 }
 
 impl Pattern {
@@ -317,13 +508,37 @@ impl Pattern {
             Pattern::Id(id) => &id.span,
             Pattern::App(_, _, span) => span,
             Pattern::Literal(lit) => &lit.span,
+            Pattern::Tuple(_, span) => span,
+            Pattern::List(_, span) => span,
+            Pattern::As(_, _, span) => span,
+            Pattern::Record(_, _, span) => span,
+        }
+    }
+
+    /// Collects the names the pattern binds.
+    pub fn variables<'p>(&'p self, out: &mut Vec<&'p str>) {
+        match self {
+            Pattern::Variable(id) => out.push(&id.name),
+            Pattern::As(id, pattern, _) => {
+                out.push(&id.name);
+                pattern.variables(out);
+            }
+            Pattern::ListCons(head, tail, _) => {
+                head.variables(out);
+                tail.variables(out);
+            }
+            Pattern::App(_, patterns, _) | Pattern::Tuple(patterns, _) | Pattern::List(patterns, _) => {
+                patterns.iter().for_each(|pattern| pattern.variables(out))
+            }
+            Pattern::Record(_, fields, _) => fields.iter().for_each(|(_, pattern)| pattern.variables(out)),
+            Pattern::Wildcard(_) | Pattern::Id(_) | Pattern::Literal(_) => {}
         }
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum LiteralKind {
-    Integer(i64),
+    Integer(BigInt),
     Float(f64),
     String(String),
     Char(char),
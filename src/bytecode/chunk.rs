@@ -2,6 +2,10 @@ use crate::ast::Span;
 use crate::bytecode::constant::Constant;
 use crate::bytecode::opcode::OpCode;
 
+/// The number of bytes of the index of a constant following an
+/// instruction.
+pub const INDEX_SIZE: usize = 4;
+
 #[derive(Debug, Clone)]
 pub struct Chunk {
     data: Vec<u8>,
@@ -34,11 +38,43 @@ impl Chunk {
         self.count += 1;
     }
 
+    /// Appends the index of a constant, after an instruction that takes one.
+    pub fn addIndex(&mut self, index: usize, span: Span) {
+        let index = u32::try_from(index).expect("more constants than an index can address");
+        for byte in index.to_be_bytes() {
+            self.addByte(byte, span);
+        }
+    }
+
+    /// The index of a constant starting at `offset`.
+    pub fn getIndex(&self, offset: usize) -> usize {
+        let mut bytes = [0; INDEX_SIZE];
+        bytes.copy_from_slice(&self.data[offset..offset + INDEX_SIZE]);
+        u32::from_be_bytes(bytes) as usize
+    }
+
     pub fn addConstant(&mut self, constant: Constant) -> usize {
         self.constant.push(constant);
         self.constant.len() - 1
     }
 
+    /// Appends the code of `other`, its constants following those of this
+    /// chunk.
+    pub fn link(&mut self, other: Chunk) {
+        let base = self.constant.len();
+        let mut offset = 0;
+        while offset < other.count {
+            let op = other.getOpCode(offset);
+            self.addOpCode(op, other.spans[offset]);
+            if op.has_constant() {
+                self.addIndex(other.getIndex(offset + 1) + base, other.spans[offset + 1]);
+                offset += INDEX_SIZE;
+            }
+            offset += 1;
+        }
+        self.constant.extend(other.constant);
+    }
+
     pub fn getOpCode(&self, index: usize) -> OpCode {
         OpCode::from(self.data[index])
     }
@@ -64,69 +100,111 @@ impl Chunk {
 
     pub fn dissassembleInstruction(&mut self, offset: usize) -> usize {
         let instruction = self.getOpCode(offset);
-        match instruction {
-            OpCode::PushConst => {
-                print!("{:04} ", offset);
-                self.constantInstruction("PUSH_CONST", offset)
-            }
-            OpCode::Vec => {
-                print!("{:04} ", offset);
-                self.constantInstruction("VEC", offset)
-            }
-            OpCode::PushLocal => {
-                print!("{:04} ", offset);
-                self.simpleInstruction("PUSH_LOCAL", offset)
-            }
-            OpCode::PushGlobal => {
-                print!("{:04} ", offset);
-                self.constantInstruction("PUSH_GLOBAL", offset)
-            }
-            OpCode::Bind => {
-                print!("{:04} ", offset);
-                self.constantInstruction("BIND", offset)
-            }
-            OpCode::ExprApp => {
-                print!("{:04} ", offset);
-                self.simpleInstruction("EXPR_APP", offset)
-            }
-            OpCode::ExprList => {
-                print!("{:04} ", offset);
-                self.simpleInstruction("EXPR_LIST", offset)
-            }
+        let name = mnemonic(instruction);
+        print!("{:04} ", offset);
+        if instruction.has_constant() {
+            self.constantInstruction(&name, offset)
+        } else {
+            self.simpleInstruction(&name, offset)
+        }
+    }
 
-            OpCode::TypeGeneric => {
-                print!("{:04} ", offset);
-                self.constantInstruction("TYPE_GENERIC", offset)
-            }
+    pub fn constantInstruction(&mut self, name: &str, offset: usize) -> usize {
+        let constant = self.getConstant(self.getIndex(offset + 1));
+        let span = self.spans[offset];
+        println!("{:<16} '{}'   {}..{}", name, constant, span.start, span.end);
+        offset + 1 + INDEX_SIZE
+    }
 
-            OpCode::Variant => {
-                print!("{:04} ", offset);
-                self.simpleInstruction("VARIANT", offset)
-            }
+    pub fn simpleInstruction(&self, name: &str, offset: usize) -> usize {
+        let span = self.spans[offset];
+        println!("{:<16}        {}..{}", name, span.start, span.end);
+        offset + 1
+    }
+}
 
-            OpCode::TypeDecl => {
-                print!("{:04} ", offset);
-                self.simpleInstruction("TYPE_DECL", offset)
-            }
-            OpCode::ExprCondition => {
-                print!("{:04} ", offset);
-                self.simpleInstruction("EXPR_CONDITION", offset)
-            }
+/// The name of `op` in the listing, `PUSH_CONST` for `OpCode::PushConst`.
+fn mnemonic(op: OpCode) -> String {
+    let mut name = String::new();
+    for (i, c) in format!("{:?}", op).chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.push(c.to_ascii_uppercase());
+    }
+    name
+}
 
-            _ => {
-                panic!("Unknown opcode {:?}", instruction)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{compile_modules, compile_program};
+    use crate::semantics::loader::Loader;
+    use crate::source::{FileId, SourceMap};
+
+    fn compile(content: &str) -> Chunk {
+        let mut parser = crate::parser::Parser::new(FileId::default(), content);
+        let (program, errors) = parser.parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        compile_program(program.statements)
+    }
+
+    /// The instructions from `offset` on, with the constant they take.
+    fn instructions(chunk: &Chunk, mut offset: usize) -> Vec<(OpCode, Option<Constant>)> {
+        let mut instructions = Vec::new();
+        while offset < chunk.getOpCodeCount() {
+            let op = chunk.getOpCode(offset);
+            if op.has_constant() {
+                instructions.push((op, Some(chunk.getConstant(chunk.getIndex(offset + 1)))));
+                offset += 1 + INDEX_SIZE;
+            } else {
+                instructions.push((op, None));
+                offset += 1;
             }
         }
+        instructions
     }
 
-    pub fn constantInstruction(&mut self, name: &str, offset: usize) -> usize {
-        let constant = self.getConstant(self.data[offset + 1] as usize);
-        println!("{:<16} '{}'   \"{}\"", name, constant, self.spans[offset]);
-        offset + 2
+    #[test]
+    fn test_operators() {
+        let mut chunk = compile("f x y = (x : y, x != x && True || False)");
+        let ops: Vec<OpCode> = instructions(&chunk, 0).into_iter().map(|(op, _)| op).collect();
+        for op in [OpCode::ListCons, OpCode::Ineq, OpCode::And, OpCode::Or] {
+            assert!(ops.contains(&op), "{:?} in {:?}", op, ops);
+        }
+        chunk.dissassemble("test");
     }
 
-    pub fn simpleInstruction(&self, name: &str, offset: usize) -> usize {
-        println!("{:<16}        \"{}\"", name, self.spans[offset]);
-        offset + 1
+    #[test]
+    fn test_link() {
+        // More constants than a byte can index come first.
+        let first: String = (0..300).map(|i| format!("a{} = {}\n", i, i)).collect();
+        let mut linked = compile(&first);
+        let second = compile("b = 'x'\nc = [1.5, 2.5]");
+        let start = linked.getOpCodeCount();
+        let expected = instructions(&second, 0);
+        linked.link(second);
+        assert!(linked.getConstantCount() > 600);
+        assert_eq!(instructions(&linked, start), expected);
+    }
+
+    #[test]
+    fn test_compile_modules() {
+        let mut sources = SourceMap::new();
+        let mut loader = Loader::new(std::env::temp_dir(), &mut sources);
+        loader.load_main(
+            "main.rk",
+            "main = let y = 2 in f y\n  where f x = x + 1".to_string(),
+        );
+        assert!(loader.syntax_errors.is_empty(), "{:?}", loader.syntax_errors);
+        assert!(loader.errors.is_empty(), "{:?}", loader.errors);
+        // The Prelude comes first, the program is in the last chunk.
+        let program = compile_program(loader.modules.last().unwrap().program.statements.clone());
+        let expected = instructions(&program, 0);
+        let chunk = compile_modules(&loader.modules);
+        let start = chunk.getOpCodeCount() - program.getOpCodeCount();
+        let ops: Vec<OpCode> = expected.iter().map(|(op, _)| *op).collect();
+        assert_eq!(ops.iter().filter(|op| **op == OpCode::ExprLet).count(), 2, "{:?}", ops);
+        assert_eq!(instructions(&chunk, start), expected);
     }
 }
@@ -1,9 +1,11 @@
+use num_bigint::BigInt;
 use std::fmt;
 
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Constant {
     Int(i64),
+    BigInt(BigInt), // integers that do not fit in an `Int`
     Float(f64),
     Bool(bool),
     Char(char),
@@ -57,6 +59,11 @@ impl Constant {
                 let mut bytes = Vec::from(s.as_bytes());
                 bytes.push(4);
                 bytes
+            },
+            Constant::BigInt(i) => {
+                let mut bytes = i.to_signed_bytes_be();
+                bytes.push(5);
+                bytes
             }
         }
     }
@@ -88,6 +95,7 @@ impl Constant {
                 let s = String::from_utf8(bytes[..bytes.len() - 1].to_vec()).unwrap();
                 Constant::String(s)
             },
+            5 => Constant::BigInt(BigInt::from_signed_bytes_be(&bytes[..bytes.len() - 1])),
             _ => panic!("Invalid tag"),
         }
     }
@@ -97,7 +105,8 @@ impl fmt::Display for Constant {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Constant::Int(i) => write!(f, "{}", i),
-            Constant::Float(fl) => write!(f, "{}", fl),
+            Constant::BigInt(i) => write!(f, "{}", i),
+            Constant::Float(fl) => write!(f, "{}", crate::vm::value::format_float(*fl)),
             Constant::Bool(b) => write!(f, "{}", b),
             Constant::Char(c) => write!(f, "{}", c),
             Constant::String(_) => {
@@ -135,6 +144,11 @@ mod tests {
         let c2 = Constant::from_bytecode(&bytes);
         assert_eq!(c, c2);
 
+        let c = Constant::BigInt("123456789012345678901234567890".parse().unwrap());
+        let bytes = c.into_bytecode();
+        let c2 = Constant::from_bytecode(&bytes);
+        assert_eq!(c, c2);
+
         let c = Constant::new_string("Hello, world!");
         let bytes = c.into_bytecode();
         let c2 = Constant::from_bytecode(&bytes);
@@ -23,7 +23,9 @@ pub enum OpCode {
     Ineq,
     And,
     Or,
+    ListCons,
     Not,
+    Neg,
     ExprApp,
     ExprCondition,
     ExprLet,
@@ -32,6 +34,9 @@ pub enum OpCode {
     ExprAnn,
     ExprList,
     ExprTuple,
+    ExprGuards,
+    ExprRecord,
+    ExprUpdate,
 
     Jump,
     JumpIfFalse,
@@ -43,6 +48,7 @@ pub enum OpCode {
     TypeApp,
     TypeTuple,
     TypeFunc,
+    TypeList,
 
     PatternWildCard,
     PatternListCons,
@@ -50,6 +56,10 @@ pub enum OpCode {
     PatternId,
     PatternApp,
     PatternLiteral,
+    PatternTuple,
+    PatternList,
+    PatternAs,
+    PatternRecord,
 
     Variant,
 
@@ -61,6 +71,21 @@ pub enum OpCode {
 }
 
 
+impl OpCode {
+    /// Whether the instruction is followed by the index of a constant.
+    pub fn has_constant(&self) -> bool {
+        matches!(
+            self,
+            OpCode::PushConst
+                | OpCode::PushGlobal
+                | OpCode::Bind
+                | OpCode::TypeId
+                | OpCode::TypeGeneric
+                | OpCode::Vec
+        )
+    }
+}
+
 impl From<u8> for OpCode {
     fn from(value: u8) -> Self {
         match value {
@@ -83,35 +108,45 @@ impl From<u8> for OpCode {
             16 => OpCode::Ineq,
             17 => OpCode::And,
             18 => OpCode::Or,
-            19 => OpCode::Not,
-            20 => OpCode::ExprApp,
-            21 => OpCode::ExprCondition,
-            22 => OpCode::ExprLet,
-            23 => OpCode::ExprMatch,
-            24 => OpCode::ExprLambda,
-            25 => OpCode::ExprAnn,
-            26 => OpCode::ExprList,
-            27 => OpCode::ExprTuple,
-            28 => OpCode::Jump,
-            29 => OpCode::JumpIfFalse,
-            30 => OpCode::Call,
-            31 => OpCode::Return,
-            32 => OpCode::TypeId,
-            33 => OpCode::TypeGeneric,
-            34 => OpCode::TypeApp,
-            35 => OpCode::TypeTuple,
-            36 => OpCode::TypeFunc,
-            37 => OpCode::PatternWildCard,
-            38 => OpCode::PatternListCons,
-            39 => OpCode::PatternVar,
-            40 => OpCode::PatternId,
-            41 => OpCode::PatternApp,
-            42 => OpCode::PatternLiteral,
-            43 => OpCode::Variant,
-            44 => OpCode::Bind,
-            45 => OpCode::TypeAssign,
-            46 => OpCode::TypeDecl,
-            47 => OpCode::Vec,
+            19 => OpCode::ListCons,
+            20 => OpCode::Not,
+            21 => OpCode::Neg,
+            22 => OpCode::ExprApp,
+            23 => OpCode::ExprCondition,
+            24 => OpCode::ExprLet,
+            25 => OpCode::ExprMatch,
+            26 => OpCode::ExprLambda,
+            27 => OpCode::ExprAnn,
+            28 => OpCode::ExprList,
+            29 => OpCode::ExprTuple,
+            30 => OpCode::ExprGuards,
+            31 => OpCode::ExprRecord,
+            32 => OpCode::ExprUpdate,
+            33 => OpCode::Jump,
+            34 => OpCode::JumpIfFalse,
+            35 => OpCode::Call,
+            36 => OpCode::Return,
+            37 => OpCode::TypeId,
+            38 => OpCode::TypeGeneric,
+            39 => OpCode::TypeApp,
+            40 => OpCode::TypeTuple,
+            41 => OpCode::TypeFunc,
+            42 => OpCode::TypeList,
+            43 => OpCode::PatternWildCard,
+            44 => OpCode::PatternListCons,
+            45 => OpCode::PatternVar,
+            46 => OpCode::PatternId,
+            47 => OpCode::PatternApp,
+            48 => OpCode::PatternLiteral,
+            49 => OpCode::PatternTuple,
+            50 => OpCode::PatternList,
+            51 => OpCode::PatternAs,
+            52 => OpCode::PatternRecord,
+            53 => OpCode::Variant,
+            54 => OpCode::Bind,
+            55 => OpCode::TypeAssign,
+            56 => OpCode::TypeDecl,
+            57 => OpCode::Vec,
             _ => panic!("Invalid OpCode"),
         }
 
@@ -0,0 +1,740 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Dictionary passing: turns a type-checked program with classes into one
+//! without, that the VM runs.
+//!
+//! A class `C` becomes the constructor `$C`, whose fields are the
+//! dictionaries of its superclasses then its methods, read by `$C.S` and
+//! `$C.m`. The instance of `C` for `T` becomes the global `$C T`, a function
+//! of the dictionaries of its context, and its methods the functions
+//! `$C T.m`. Overloaded functions take their dictionaries `$d0`, `$d1`...
+//! first. None of these names can be written in Risk.
+//!
+//! Builtin operators, `show` and `negate` keep using the VM directly when
+//! the instance is the primitive one or only compares or prints what the
+//! value holds.
+
+use crate::ast::*;
+use crate::semantics::tc::class::{ClassInfo, Dict, Evidence, InstanceKind, Unit, UnitEvidence};
+use crate::vm::native::PRIMITIVES;
+use std::collections::{HashMap, HashSet};
+
+pub fn elaborate(program: Program<Span>, evidence: &Evidence) -> Program<Span> {
+    let mut elaborator = Elaborator {
+        evidence,
+        classes: HashMap::new(),
+        instances: HashMap::new(),
+        types: HashMap::new(),
+        requested: Vec::new(),
+        emitted: HashSet::new(),
+        structural: HashMap::new(),
+        out: Vec::new(),
+    };
+    elaborator.run(&program);
+    Program::new(elaborator.out)
+}
+
+struct Elaborator<'e> {
+    evidence: &'e Evidence,
+    classes: HashMap<String, ClassDecl<Span>>,
+    instances: HashMap<(String, String), InstanceDecl<Span>>,
+    types: HashMap<String, TypeDecl>,
+    /// The instances whose dictionary is used, and the ones already emitted.
+    requested: Vec<(String, String)>,
+    emitted: HashSet<(String, String)>,
+    structural: HashMap<(String, String, Vec<bool>), bool>,
+    out: Vec<Statement<Span>>,
+}
+
+impl<'e> Elaborator<'e> {
+    fn run(&mut self, program: &Program<Span>) {
+        for class in &self.evidence.classes {
+            self.dictionary_type(class);
+        }
+
+        for statement in &program.statements {
+            match statement {
+                Statement::Class(decl) => {
+                    self.classes.insert(decl.name.name.clone(), decl.clone());
+                }
+                Statement::Instance(decl) => {
+                    if let Some((head, _)) = crate::semantics::tc::class::instance_head(&decl.ty) {
+                        self.instances.insert((decl.class.name.clone(), head), decl.clone());
+                    }
+                }
+                Statement::TypeDecl(decl) => {
+                    self.types.insert(decl.name.name.clone(), decl.clone());
+                }
+                _ => {}
+            }
+        }
+
+        for statement in &program.statements {
+            match statement {
+                Statement::Bind(bind) => {
+                    let unit = Unit::Bind(bind.name.name.clone());
+                    let bind = self.bind(bind, &unit, bind.name.name.clone());
+                    self.out.push(Statement::Bind(bind));
+                }
+                Statement::Class(_) | Statement::Instance(_) => {}
+                statement => self.out.push(statement.clone()),
+            }
+        }
+
+        while let Some(key) = self.requested.pop() {
+            if self.emitted.insert(key.clone()) {
+                self.instance(&key.0, &key.1);
+            }
+        }
+    }
+
+    fn class(&self, name: &str) -> &'e ClassInfo {
+        let evidence = self.evidence;
+        evidence
+            .classes
+            .iter()
+            .find(|class| class.name == name)
+            .expect("classes are known to the type checker")
+    }
+
+    /// The constructor of the dictionaries of a class and the functions
+    /// reading them.
+    fn dictionary_type(&mut self, class: &ClassInfo) {
+        let fields: Vec<String> = class
+            .superclasses
+            .iter()
+            .chain(class.methods.iter().map(|method| &method.name))
+            .cloned()
+            .collect();
+        let name = format!("${}", class.name);
+        let types = fields.iter().map(|_| Type::Id(id("a"))).collect();
+        let variant = Variant::new(id(&name), types, Span::default());
+        self.out.push(Statement::TypeDecl(TypeDecl::new(
+            id(&name),
+            Vec::new(),
+            vec![variant],
+            Span::default(),
+        )));
+
+        for (i, field) in fields.iter().enumerate() {
+            let patterns = (0..fields.len())
+                .map(|j| if i == j { var_pattern("x") } else { Pattern::Wildcard(Span::default()) })
+                .collect();
+            let pattern = Pattern::App(id(&name), patterns, Span::default());
+            self.out.push(bind(&format!("{}.{}", name, field), vec![pattern], var("x")));
+        }
+    }
+
+    /// Gives a bind the dictionaries of its unit as first arguments, and
+    /// its uses of overloaded names theirs.
+    fn bind(&mut self, bind: &Bind<Span>, unit: &Unit, name: String) -> Bind<Span> {
+        let evidence = self.evidence.units.get(unit).cloned().unwrap_or_default();
+        let mut args: Vec<Pattern> = (0..evidence.params).map(|i| var_pattern(&param(i))).collect();
+        args.extend(bind.args.iter().cloned());
+        let expr = self.expr(&bind.expr, &evidence);
+        Bind::new(Identifier::new(name, bind.name.span), args, expr, bind.span)
+    }
+
+    fn expr(&mut self, expr: &ParsedExpr, unit: &UnitEvidence) -> ParsedExpr {
+        match expr {
+            AnnExpr::Identifier { id } => match unit.sites.get(&id.span) {
+                Some(dicts) => self.overloaded(id, dicts),
+                None => expr.clone(),
+            },
+            AnnExpr::BinOp { op, lhs, rhs, ann } => {
+                let lhs = self.expr(lhs, unit);
+                let rhs = self.expr(rhs, unit);
+                match unit.sites.get(ann) {
+                    Some(dicts) if !self.is_structural(&dicts[0]) => self.operator(*op, &dicts[0], lhs, rhs, *ann),
+                    _ => AnnExpr::BinOp {
+                        op: *op,
+                        lhs: Box::new(lhs),
+                        rhs: Box::new(rhs),
+                        ann: *ann,
+                    },
+                }
+            }
+            AnnExpr::UnOp { op, expr, ann } => {
+                let expr = self.expr(expr, unit);
+                match unit.sites.get(ann) {
+                    Some(dicts) if !self.is_structural(&dicts[0]) => {
+                        let dict = self.dict(&dicts[0]);
+                        AnnExpr::App(App::new(var("$Num.negate"), vec![dict, expr], *ann))
+                    }
+                    _ => AnnExpr::UnOp {
+                        op: *op,
+                        expr: Box::new(expr),
+                        ann: *ann,
+                    },
+                }
+            }
+            AnnExpr::PCIdentifier { .. } | AnnExpr::Literal(_) | AnnExpr::Error { .. } => expr.clone(),
+            AnnExpr::App(App { head, args, span }) => AnnExpr::App(App::new(
+                self.expr(head, unit),
+                args.iter().map(|arg| self.expr(arg, unit)).collect(),
+                *span,
+            )),
+            AnnExpr::Condition {
+                cond,
+                then,
+                els,
+                ann,
+            } => AnnExpr::Condition {
+                cond: Box::new(self.expr(cond, unit)),
+                then: Box::new(self.expr(then, unit)),
+                els: Box::new(self.expr(els, unit)),
+                ann: *ann,
+            },
+            AnnExpr::Let { binds, ret, ann } => AnnExpr::Let {
+                binds: binds
+                    .iter()
+                    .map(|bind| Bind::new(bind.name.clone(), bind.args.clone(), self.expr(&bind.expr, unit), bind.span))
+                    .collect(),
+                ret: Box::new(self.expr(ret, unit)),
+                ann: *ann,
+            },
+            AnnExpr::Match {
+                referral,
+                cases,
+                ann,
+            } => AnnExpr::Match {
+                referral: Box::new(self.expr(referral, unit)),
+                cases: cases
+                    .iter()
+                    .map(|(pattern, body)| (pattern.clone(), Box::new(self.expr(body, unit))))
+                    .collect(),
+                ann: *ann,
+            },
+            AnnExpr::Lambda { args, ret, ann } => AnnExpr::Lambda {
+                args: args.clone(),
+                ret: Box::new(self.expr(ret, unit)),
+                ann: *ann,
+            },
+            AnnExpr::Ann { expr, ann } => AnnExpr::Ann {
+                expr: Box::new(self.expr(expr, unit)),
+                ann: ann.clone(),
+            },
+            AnnExpr::List { list, ann } => AnnExpr::List {
+                list: list.iter().map(|item| self.expr(item, unit)).collect(),
+                ann: *ann,
+            },
+            AnnExpr::Tuple { list, ann } => AnnExpr::Tuple {
+                list: list.iter().map(|item| self.expr(item, unit)).collect(),
+                ann: *ann,
+            },
+            AnnExpr::Record { id, fields, ann } => AnnExpr::Record {
+                id: id.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.expr(value, unit)))
+                    .collect(),
+                ann: *ann,
+            },
+            AnnExpr::Update { expr, fields, ann } => AnnExpr::Update {
+                expr: Box::new(self.expr(expr, unit)),
+                fields: fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.expr(value, unit)))
+                    .collect(),
+                ann: *ann,
+            },
+            AnnExpr::Guards { guards, ann } => AnnExpr::Guards {
+                guards: guards
+                    .iter()
+                    .map(|(cond, body)| (self.expr(cond, unit), self.expr(body, unit)))
+                    .collect(),
+                ann: *ann,
+            },
+        }
+    }
+
+    /// An overloaded name given its dictionaries. A method reads itself
+    /// from the first one.
+    fn overloaded(&mut self, name: &Identifier, dicts: &[Dict]) -> ParsedExpr {
+        let evidence = self.evidence;
+        let class = evidence
+            .classes
+            .iter()
+            .find(|class| class.methods.iter().any(|method| method.name == name.name));
+        let dicts: Vec<ParsedExpr> = match class {
+            Some(class) if self.is_structural(&dicts[0]) => {
+                let head = match &dicts[0] {
+                    Dict::Instance { head, .. } => head.as_str(),
+                    _ => "",
+                };
+                return var(&primitive(&class.name, head, &name.name));
+            }
+            Some(class) => {
+                let accessor = format!("${}.{}", class.name, name.name);
+                let dicts = dicts.iter().map(|dict| self.dict(dict)).collect();
+                return AnnExpr::App(App::new(var(&accessor), dicts, name.span));
+            }
+            None => dicts.iter().map(|dict| self.dict(dict)).collect(),
+        };
+        if dicts.is_empty() {
+            return AnnExpr::Identifier { id: name.clone() };
+        }
+        AnnExpr::App(App::new(AnnExpr::Identifier { id: name.clone() }, dicts, name.span))
+    }
+
+    /// A builtin operator whose operands' instance is not structural.
+    fn operator(&mut self, op: BinOp, dict: &Dict, lhs: ParsedExpr, rhs: ParsedExpr, span: Span) -> ParsedExpr {
+        let dict = self.dict(dict);
+        let call = |method: &str, args: Vec<ParsedExpr>| {
+            let mut all = vec![dict.clone()];
+            all.extend(args);
+            AnnExpr::App(App::new(var(method), all, span))
+        };
+        match op {
+            BinOp::Eq => call("$Eq.==", vec![lhs, rhs]),
+            BinOp::Ineq => not(call("$Eq.==", vec![lhs, rhs])),
+            BinOp::LessThan => call("$Ord.<", vec![lhs, rhs]),
+            BinOp::GreaterThan => call("$Ord.<", vec![rhs, lhs]),
+            BinOp::LessThanOrEq => not(call("$Ord.<", vec![rhs, lhs])),
+            BinOp::GreaterThanOrEq => not(call("$Ord.<", vec![lhs, rhs])),
+            _ => call(&format!("$Num.{}", op.symbol()), vec![lhs, rhs]),
+        }
+    }
+
+    /// The expression giving a dictionary, requesting the instances it is
+    /// built from.
+    fn dict(&mut self, dict: &Dict) -> ParsedExpr {
+        match dict {
+            Dict::Param(i) => var(&param(*i)),
+            Dict::Super {
+                class,
+                superclass,
+                dict,
+            } => {
+                let dict = self.dict(dict);
+                app(var(&format!("${}.{}", class, superclass)), vec![dict])
+            }
+            Dict::Instance { class, head, args } => {
+                let key = (class.clone(), head.clone());
+                if !self.emitted.contains(&key) {
+                    self.requested.push(key);
+                }
+                let args = args.iter().map(|arg| self.dict(arg)).collect();
+                app(var(&instance_name(class, head)), args)
+            }
+        }
+    }
+
+    /// Whether the builtin operation on values does what the dictionary
+    /// does: for primitive types, and for `Eq` and `Show` on values that
+    /// hold only such types.
+    fn is_structural(&mut self, dict: &Dict) -> bool {
+        self.structural_in(dict, &[])
+    }
+
+    /// `params` tells which dictionaries of the enclosing instance are
+    /// structural.
+    fn structural_in(&mut self, dict: &Dict, params: &[bool]) -> bool {
+        let Dict::Instance { class, head, args } = dict else {
+            return matches!(dict, Dict::Param(i) if params.get(*i) == Some(&true));
+        };
+        let args: Vec<bool> = args.iter().map(|arg| self.structural_in(arg, params)).collect();
+        let key = (class.clone(), head.clone(), args.clone());
+        if let Some(known) = self.structural.get(&key) {
+            return *known;
+        }
+        let evidence = self.evidence;
+        let Some(instance) = evidence.instances.get(&(class.clone(), head.clone())) else {
+            return false;
+        };
+        let derived = matches!(class.as_str(), "Eq" | "Show");
+        let result = match &instance.kind {
+            InstanceKind::Primitive => true,
+            InstanceKind::Structure => derived && args.iter().all(|arg| *arg),
+            InstanceKind::Derived(fields) if derived => {
+                // Recursive types refer to the instance being decided.
+                self.structural.insert(key.clone(), true);
+                fields.iter().flatten().all(|field| self.structural_in(field, &args))
+            }
+            _ => false,
+        };
+        self.structural.insert(key, result);
+        result
+    }
+
+    /// Emits the dictionary of an instance and its methods.
+    fn instance(&mut self, class: &str, head: &str) {
+        let evidence = self.evidence;
+        let info = self.class(class);
+        let Some(instance) = evidence.instances.get(&(class.to_string(), head.to_string())) else {
+            return;
+        };
+        let params: Vec<ParsedExpr> = (0..instance.context.len()).map(|i| var(&param(i))).collect();
+        let name = instance_name(class, head);
+
+        let mut fields: Vec<ParsedExpr> = instance.superclasses.iter().map(|dict| self.dict(dict)).collect();
+        for method in &info.methods {
+            let field = match instance.kind {
+                InstanceKind::Primitive => var(&primitive(class, head, &method.name)),
+                _ => app(var(&format!("{}.{}", name, method.name)), params.clone()),
+            };
+            fields.push(field);
+        }
+        let args = (0..params.len()).map(|i| var_pattern(&param(i))).collect();
+        let constructor = AnnExpr::PCIdentifier {
+            id: id(&format!("${}", class)),
+        };
+        self.out.push(bind(&name, args, app(constructor, fields)));
+
+        match &instance.kind {
+            InstanceKind::Primitive => {}
+            InstanceKind::Structure if head == "[]" => self.list_methods(class, &name),
+            InstanceKind::Structure => self.tuple_methods(class, &name, head.len() - 1),
+            InstanceKind::Derived(fields) => self.derived_methods(class, head, &name, fields),
+            InstanceKind::Declared => self.declared_methods(info, head, &name, instance.context.len()),
+        }
+    }
+
+    fn declared_methods(&mut self, class: &ClassInfo, head: &str, name: &str, params: usize) {
+        let Some(decl) = self.instances.get(&(class.name.clone(), head.to_string())).cloned() else {
+            return;
+        };
+        let defaults = self
+            .classes
+            .get(&class.name)
+            .map(|class| class.defaults.clone())
+            .unwrap_or_default();
+
+        for method in &class.methods {
+            let unit = Unit::Method {
+                class: class.name.clone(),
+                head: head.to_string(),
+                method: method.name.clone(),
+            };
+            let method_name = format!("{}.{}", name, method.name);
+            let mut binds: Vec<_> = decl.binds.iter().filter(|bind| bind.name.name == method.name).collect();
+            if binds.is_empty() {
+                binds = defaults.iter().filter(|bind| bind.name.name == method.name).collect();
+            }
+            if binds.is_empty() {
+                // Calling it stops the program, the semantic analysis warned.
+                let names = vec![string(&method.name)];
+                let call = AnnExpr::App(App::new(var("$missing"), names, decl.span));
+                let args = (0..params + method.dicts + method.arity)
+                    .map(|_| Pattern::Wildcard(decl.span))
+                    .collect();
+                self.out.push(Statement::Bind(Bind::new(id(&method_name), args, call, decl.span)));
+                continue;
+            }
+            for bind in binds {
+                let bind = self.bind(bind, &unit, method_name.clone());
+                self.out.push(Statement::Bind(bind));
+            }
+        }
+    }
+
+    /// `Eq`, `Ord` and `Show` for lists, from the dictionary of the items.
+    fn list_methods(&mut self, class: &str, name: &str) {
+        let d = || var_pattern(&param(0));
+        let nil = || Pattern::List(Vec::new(), Span::default());
+        let cons = |x: &str, xs: &str| {
+            Pattern::ListCons(Box::new(var_pattern(x)), Box::new(var_pattern(xs)), Span::default())
+        };
+        let wild = || Pattern::Wildcard(Span::default());
+        let item = |method: &str, args: Vec<ParsedExpr>| {
+            let mut all = vec![var(&param(0))];
+            all.extend(args);
+            app(var(&format!("${}.{}", class, method)), all)
+        };
+        let rest = |method: &str, args: Vec<ParsedExpr>| {
+            let mut all = vec![var(&param(0))];
+            all.extend(args);
+            app(var(&format!("{}.{}", name, method)), all)
+        };
+
+        let clauses = match class {
+            "Eq" => vec![
+                ("==", vec![d(), nil(), nil()], boolean(true)),
+                (
+                    "==",
+                    vec![d(), cons("x", "xs"), cons("y", "ys")],
+                    and(item("==", vec![var("x"), var("y")]), rest("==", vec![var("xs"), var("ys")])),
+                ),
+                ("==", vec![d(), wild(), wild()], boolean(false)),
+            ],
+            "Ord" => vec![
+                ("<", vec![d(), wild(), nil()], boolean(false)),
+                ("<", vec![d(), nil(), wild()], boolean(true)),
+                (
+                    "<",
+                    vec![d(), cons("x", "xs"), cons("y", "ys")],
+                    or(
+                        item("<", vec![var("x"), var("y")]),
+                        and(not(item("<", vec![var("y"), var("x")])), rest("<", vec![var("xs"), var("ys")])),
+                    ),
+                ),
+            ],
+            _ => vec![
+                ("show", vec![d(), nil()], string("[]")),
+                (
+                    "show",
+                    vec![d(), cons("x", "xs")],
+                    concat(vec![string("["), item("show", vec![var("x")]), rest("items", vec![var("xs")])]),
+                ),
+                ("items", vec![d(), nil()], string("]")),
+                (
+                    "items",
+                    vec![d(), cons("x", "xs")],
+                    concat(vec![string(", "), item("show", vec![var("x")]), rest("items", vec![var("xs")])]),
+                ),
+            ],
+        };
+        for (method, args, body) in clauses {
+            self.out.push(bind(&format!("{}.{}", name, method), args, body));
+        }
+    }
+
+    /// `Eq`, `Ord` and `Show` for tuples of `size` items, from the
+    /// dictionary of each.
+    fn tuple_methods(&mut self, class: &str, name: &str, size: usize) {
+        let mut args: Vec<Pattern> = (0..size).map(|i| var_pattern(&param(i))).collect();
+        let tuple = |prefix: &str| {
+            Pattern::Tuple((0..size).map(|i| var_pattern(&format!("{}{}", prefix, i))).collect(), Span::default())
+        };
+        let item = |method: &str, i: usize, args: Vec<ParsedExpr>| {
+            let mut all = vec![var(&param(i))];
+            all.extend(args);
+            app(var(&format!("${}.{}", class, method)), all)
+        };
+        let (x, y) = (|i: usize| var(&format!("x{}", i)), |i: usize| var(&format!("y{}", i)));
+
+        let (method, body) = match class {
+            "Eq" => {
+                args.extend([tuple("x"), tuple("y")]);
+                let equal = (0..size).map(|i| item("==", i, vec![x(i), y(i)]));
+                ("==", equal.reduce(and).unwrap_or(boolean(true)))
+            }
+            "Ord" => {
+                args.extend([tuple("x"), tuple("y")]);
+                let less = (0..size).rev().fold(None, |acc, i| {
+                    let lt = item("<", i, vec![x(i), y(i)]);
+                    Some(match acc {
+                        None => lt,
+                        Some(acc) => or(lt, and(not(item("<", i, vec![y(i), x(i)])), acc)),
+                    })
+                });
+                ("<", less.unwrap_or(boolean(false)))
+            }
+            _ => {
+                args.push(tuple("x"));
+                let mut parts = vec![string("(")];
+                for i in 0..size {
+                    if i > 0 {
+                        parts.push(string(", "));
+                    }
+                    parts.push(item("show", i, vec![x(i)]));
+                }
+                parts.push(string(")"));
+                ("show", concat(parts))
+            }
+        };
+        self.out.push(bind(&format!("{}.{}", name, method), args, body));
+    }
+
+    /// `Eq`, `Ord` and `Show` for a type that declares none, from the
+    /// dictionaries of its fields.
+    fn derived_methods(&mut self, class: &str, head: &str, name: &str, fields: &[Vec<Dict>]) {
+        let Some(decl) = self.types.get(head).cloned() else {
+            return;
+        };
+        let params = || -> Vec<Pattern> { (0..decl.typevars.len()).map(|i| var_pattern(&param(i))).collect() };
+        let constructor = |variant: &Variant, prefix: &str| {
+            if variant.types.is_empty() {
+                return Pattern::Id(variant.id.clone());
+            }
+            let fields = (0..variant.types.len())
+                .map(|i| var_pattern(&format!("{}{}", prefix, i)))
+                .collect();
+            Pattern::App(variant.id.clone(), fields, Span::default())
+        };
+        let wildcards = |variant: &Variant| {
+            if variant.types.is_empty() {
+                return Pattern::Id(variant.id.clone());
+            }
+            let fields = variant.types.iter().map(|_| Pattern::Wildcard(Span::default())).collect();
+            Pattern::App(variant.id.clone(), fields, Span::default())
+        };
+        let (x, y) = (|i: usize| var(&format!("x{}", i)), |i: usize| var(&format!("y{}", i)));
+        let method = |m: &str| format!("{}.{}", name, m);
+
+        let mut clauses = Vec::new();
+        for (variant, dicts) in decl.variants.iter().zip(fields) {
+            let dicts: Vec<ParsedExpr> = dicts.iter().map(|dict| self.dict(dict)).collect();
+            let item = |m: &str, i: usize, args: Vec<ParsedExpr>| {
+                let mut all = vec![dicts[i].clone()];
+                all.extend(args);
+                app(var(&format!("${}.{}", class, m)), all)
+            };
+            let mut args = params();
+            match class {
+                "Eq" => {
+                    args.extend([constructor(variant, "x"), constructor(variant, "y")]);
+                    let equal = (0..dicts.len()).map(|i| item("==", i, vec![x(i), y(i)]));
+                    clauses.push((method("=="), args, equal.reduce(and).unwrap_or(boolean(true))));
+                }
+                "Ord" => {
+                    args.extend([constructor(variant, "x"), constructor(variant, "y")]);
+                    let less = (0..dicts.len()).rev().fold(None, |acc, i| {
+                        let lt = item("<", i, vec![x(i), y(i)]);
+                        Some(match acc {
+                            None => lt,
+                            Some(acc) => or(lt, and(not(item("<", i, vec![y(i), x(i)])), acc)),
+                        })
+                    });
+                    clauses.push((method("<"), args, less.unwrap_or(boolean(false))));
+                }
+                _ => {
+                    args.push(constructor(variant, "x"));
+                    let mut parts = vec![string(unqualified(&variant.id.name))];
+                    if variant.fields.is_empty() {
+                        for i in 0..dicts.len() {
+                            let shown = item("show", i, vec![x(i)]);
+                            parts.push(string(" "));
+                            parts.push(app(var("$parens"), vec![x(i), shown]));
+                        }
+                    } else {
+                        for (i, field) in variant.fields.iter().enumerate() {
+                            let separator = if i == 0 { " { " } else { ", " };
+                            parts.push(string(&format!("{}{} = ", separator, unqualified(&field.name))));
+                            parts.push(item("show", i, vec![x(i)]));
+                        }
+                        parts.push(string(" }"));
+                    }
+                    clauses.push((method("show"), args, concat(parts)));
+                }
+            }
+        }
+
+        // Values built by different constructors are ordered like them.
+        let wild = || Pattern::Wildcard(Span::default());
+        match class {
+            "Eq" if decl.variants.len() > 1 => {
+                let mut args = params();
+                args.extend([wild(), wild()]);
+                clauses.push((method("=="), args, boolean(false)));
+            }
+            "Ord" => {
+                for variant in decl.variants.iter().take(decl.variants.len().saturating_sub(1)) {
+                    let mut args = params();
+                    args.extend([wild(), wildcards(variant)]);
+                    clauses.push((method("<"), args, boolean(false)));
+                    let mut args = params();
+                    args.extend([wildcards(variant), wild()]);
+                    clauses.push((method("<"), args, boolean(true)));
+                }
+            }
+            _ => {}
+        }
+
+        for (name, args, body) in clauses {
+            self.out.push(bind(&name, args, body));
+        }
+    }
+}
+
+fn instance_name(class: &str, head: &str) -> String {
+    format!("${} {}", class, head)
+}
+
+/// The operation of the VM implementing `method` for a primitive type: the
+/// one of its instance if there is one, as for `fromInteger` at `Float`,
+/// the one shared by all primitive types otherwise.
+fn primitive(class: &str, head: &str, method: &str) -> String {
+    let own = format!("{}.{}", instance_name(class, head), method);
+    if PRIMITIVES.iter().any(|native| native.name == own) {
+        own
+    } else {
+        format!("${}", method)
+    }
+}
+
+fn param(i: usize) -> String {
+    format!("$d{}", i)
+}
+
+fn id(name: &str) -> Identifier {
+    Identifier::new(name.to_string(), Span::default())
+}
+
+fn var(name: &str) -> ParsedExpr {
+    AnnExpr::Identifier { id: id(name) }
+}
+
+fn var_pattern(name: &str) -> Pattern {
+    Pattern::Variable(id(name))
+}
+
+fn app(head: ParsedExpr, args: Vec<ParsedExpr>) -> ParsedExpr {
+    if args.is_empty() {
+        return head;
+    }
+    AnnExpr::App(App::new(head, args, Span::default()))
+}
+
+fn string(s: &str) -> ParsedExpr {
+    AnnExpr::Literal(Literal::new(LiteralKind::String(s.to_string()), Span::default()))
+}
+
+fn boolean(b: bool) -> ParsedExpr {
+    let b = if b { Bool::True } else { Bool::False };
+    AnnExpr::Literal(Literal::new(LiteralKind::Bool(b), Span::default()))
+}
+
+fn binop(op: BinOp, lhs: ParsedExpr, rhs: ParsedExpr) -> ParsedExpr {
+    AnnExpr::BinOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+        ann: Span::default(),
+    }
+}
+
+fn and(lhs: ParsedExpr, rhs: ParsedExpr) -> ParsedExpr {
+    binop(BinOp::And, lhs, rhs)
+}
+
+fn or(lhs: ParsedExpr, rhs: ParsedExpr) -> ParsedExpr {
+    binop(BinOp::Or, lhs, rhs)
+}
+
+fn not(expr: ParsedExpr) -> ParsedExpr {
+    AnnExpr::UnOp {
+        op: UnOp::Not,
+        expr: Box::new(expr),
+        ann: Span::default(),
+    }
+}
+
+/// The strings `parts` put end to end.
+fn concat(parts: Vec<ParsedExpr>) -> ParsedExpr {
+    parts
+        .into_iter()
+        .rev()
+        .reduce(|acc, part| app(var("$++"), vec![part, acc]))
+        .unwrap_or(string(""))
+}
+
+fn bind(name: &str, args: Vec<Pattern>, expr: ParsedExpr) -> Statement<Span> {
+    Statement::Bind(Bind::new(id(name), args, expr, Span::default()))
+}
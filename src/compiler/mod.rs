@@ -1,4 +1,7 @@
-use crate::{ast::{AnnExpr, BinOp, Bind, Identifier, Literal, LiteralKind, Pattern, Span, Statement, Type, TypeAssign, TypeDecl, Variant}, bytecode::{chunk::Chunk, constant::Constant, opcode::OpCode}};
+pub mod elaborate;
+
+use crate::{ast::{AnnExpr, BinOp, Bind, Identifier, Literal, LiteralKind, Pattern, Span, Statement, Type, TypeAssign, TypeDecl, UnOp, Variant}, bytecode::{chunk::Chunk, constant::Constant, opcode::OpCode}};
+use crate::semantics::loader::Module;
 
 
 struct Compiler {
@@ -38,22 +41,28 @@ impl Compiler {
             Statement::TypeAssign(ref type_assign) => {
                 self.compile_type_assign(type_assign);
             },
+            // Fixities are resolved by the parser, and imports when the
+            // modules are loaded.
+            Statement::Fixity(_) | Statement::Module(_) | Statement::Import(_) => {},
+            // Classes and instances are turned into dictionaries by
+            // `elaborate` beforehand.
+            Statement::Class(_) | Statement::Instance(_) => {},
         }
         self.current += 1;
     }
 
     fn compile_bind(&mut self, bind: &Bind<Span>) {
-        self.spans.push(bind.span.clone());
+        self.spans.push(bind.span);
         let index = self.current_chunk.addConstant(Constant::new_string(&bind.name.name));
         self.compile_vec(bind.args.clone(), Self::compile_pattern);
         self.compile_expr(&bind.expr);
         self.addOpCode(OpCode::Bind);
-        self.addByte(index as u8);
+        self.addIndex(index);
         self.spans.pop();
     }
 
     fn compile_type_decl(&mut self, type_decl: &TypeDecl) {
-        self.spans.push(type_decl.span.clone());
+        self.spans.push(type_decl.span);
         self.compile_identifer(&type_decl.name);
         self.compile_vec(type_decl.typevars.clone(), Self::compile_identifer);
         self.compile_vec(type_decl.variants.clone(), Self::compile_variant);
@@ -62,7 +71,7 @@ impl Compiler {
     }
 
     fn compile_variant(&mut self, variant: &Variant) {
-        self.spans.push(variant.span.clone());
+        self.spans.push(variant.span);
         self.compile_identifer(&variant.id);
         self.compile_vec(variant.types.clone(), Self::compile_type);
         self.addOpCode(OpCode::Variant);
@@ -70,7 +79,7 @@ impl Compiler {
     }
 
     fn compile_type_assign(&mut self, type_assign: &TypeAssign) {
-        self.spans.push(type_assign.span.clone());
+        self.spans.push(type_assign.span);
         self.compile_identifer(&type_assign.id);
         self.compile_type(&type_assign.ty);
         self.addOpCode(OpCode::TypeAssign);
@@ -84,7 +93,7 @@ impl Compiler {
         }
 
         self.addOpCode(OpCode::Vec);
-        self.addByte(constant as u8);
+        self.addIndex(constant);
 
 
 
@@ -93,56 +102,82 @@ impl Compiler {
     fn compile_pattern(&mut self, pattern: &Pattern) {
         match pattern {
             Pattern::ListCons(p1, p2,  s) => {
-                self.spans.push(s.clone());
+                self.spans.push(*s);
                 self.compile_pattern(p1);
                 self.compile_pattern(p2);
                 self.addOpCode(OpCode::PatternListCons);
                 self.spans.pop();
            },
            Pattern::App(id, pats,  span) => {
-               self.spans.push(span.clone());
+               self.spans.push(*span);
                self.compile_identifer(id);
                self.compile_vec(pats.clone(), Self::compile_pattern);
                self.addOpCode(OpCode::PatternApp);
                self.spans.pop();
            },
            Pattern::Wildcard(span) => {
-               self.spans.push(span.clone());
+               self.spans.push(*span);
                self.addOpCode(OpCode::PatternWildCard);
                self.spans.pop();
            },
            Pattern::Id(id) => {
-               self.spans.push(id.span.clone());
+               self.spans.push(id.span);
                self.compile_identifer(id);
                self.addOpCode(OpCode::PatternId);
                self.spans.pop();
            },
            Pattern::Literal(lit) => {
-               self.spans.push(lit.span.clone());
+               self.spans.push(lit.span);
                self.compile_literal(lit);
                self.addOpCode(OpCode::PatternLiteral);
                self.spans.pop();
            },
            Pattern::Variable(var) => {
-               self.spans.push(var.span.clone());
+               self.spans.push(var.span);
                self.compile_identifer(var);
                self.addOpCode(OpCode::PatternVar);
                self.spans.pop();
            },
+           Pattern::Tuple(pats, span) => {
+               self.spans.push(*span);
+               self.compile_vec(pats.clone(), Self::compile_pattern);
+               self.addOpCode(OpCode::PatternTuple);
+               self.spans.pop();
+           },
+           Pattern::List(pats, span) => {
+               self.spans.push(*span);
+               self.compile_vec(pats.clone(), Self::compile_pattern);
+               self.addOpCode(OpCode::PatternList);
+               self.spans.pop();
+           },
+           Pattern::Record(id, fields, span) => {
+               self.spans.push(*span);
+               self.compile_identifer(id);
+               self.compile_vec(fields.clone(), Self::compile_field_pattern);
+               self.addOpCode(OpCode::PatternRecord);
+               self.spans.pop();
+           },
+           Pattern::As(id, pat, span) => {
+               self.spans.push(*span);
+               self.compile_identifer(id);
+               self.compile_pattern(pat);
+               self.addOpCode(OpCode::PatternAs);
+               self.spans.pop();
+           },
        }
     }
 
     fn compile_expr(&mut self, expr: &AnnExpr<Span>) {
         match expr {
             AnnExpr::App(a) => {
-                self.spans.push(a.span.clone());
-                self.compile_identifer(&a.ident);
+                self.spans.push(a.span);
+                self.compile_expr(&a.head);
                 self.compile_vec(a.args.clone(), Self::compile_expr);
                 self.addOpCode(OpCode::ExprApp);
                 self.spans.pop();
             },
             AnnExpr::Condition { cond, then, els, ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_expr(then);
                 self.compile_expr(els);
                 self.compile_expr(cond);
@@ -153,28 +188,28 @@ impl Compiler {
                 self.compile_identifer(id);
             },
             AnnExpr::Lambda { args, ret, ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_vec(args.clone(), Self::compile_pattern);
                 self.compile_expr(ret);
                 self.addOpCode(OpCode::ExprLambda);
                 self.spans.pop();
             },
             AnnExpr::Let { binds, ret, ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_vec(binds.clone(), Self::compile_bind);
-                self.compile_expr(expr);
+                self.compile_expr(ret);
                 self.addOpCode(OpCode::ExprLet);
                 self.spans.pop();
             },
             AnnExpr::Match { referral, cases,  ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_expr(referral);
                 self.compile_vec(cases.clone(), Self::compile_arm);
                 self.addOpCode(OpCode::ExprMatch);
                 self.spans.pop();
             },
             AnnExpr::BinOp { op, lhs, rhs, ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_expr(lhs);
                 self.compile_expr(rhs);
                 self.addOpCode(match op {
@@ -189,18 +224,30 @@ impl Compiler {
                     BinOp::GreaterThan => OpCode::GreaterThan,
                     BinOp::LessThanOrEq => OpCode::LessThanOrEq,
                     BinOp::GreaterThanOrEq => OpCode::GreaterThanOrEq,
-                    _ => panic!("Invalid binary operator")
+                    BinOp::Ineq => OpCode::Ineq,
+                    BinOp::And => OpCode::And,
+                    BinOp::Or => OpCode::Or,
+                    BinOp::ListCons => OpCode::ListCons,
+                });
+                self.spans.pop();
+            },
+            AnnExpr::UnOp { op, expr, ann } => {
+                self.spans.push(*ann);
+                self.compile_expr(expr);
+                self.addOpCode(match op {
+                    UnOp::Neg => OpCode::Neg,
+                    UnOp::Not => OpCode::Not,
                 });
                 self.spans.pop();
             },
             AnnExpr::List { list, ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_vec(list.clone(), Self::compile_expr);
                 self.addOpCode(OpCode::ExprList);
                 self.spans.pop();
             },
             AnnExpr::PCIdentifier { id } => {
-                self.spans.push(id.span.clone());
+                self.spans.push(id.span);
                 self.compile_identifer(id);
                 self.addOpCode(OpCode::PushPcIdent);
                 self.spans.pop();
@@ -210,109 +257,160 @@ impl Compiler {
                 self.compile_literal(lit);
             },
             AnnExpr::Tuple { list, ann } => {
-                self.spans.push(ann.clone());
+                self.spans.push(*ann);
                 self.compile_vec(list.clone(), Self::compile_expr);
                 self.addOpCode(OpCode::ExprTuple);
                 self.spans.pop();
             },
+            AnnExpr::Record { id, fields, ann } => {
+                self.spans.push(*ann);
+                self.compile_identifer(id);
+                self.compile_vec(fields.clone(), Self::compile_field);
+                self.addOpCode(OpCode::ExprRecord);
+                self.spans.pop();
+            },
+            AnnExpr::Update { expr, fields, ann } => {
+                self.spans.push(*ann);
+                self.compile_expr(expr);
+                self.compile_vec(fields.clone(), Self::compile_field);
+                self.addOpCode(OpCode::ExprUpdate);
+                self.spans.pop();
+            },
+            AnnExpr::Guards { guards, ann } => {
+                self.spans.push(*ann);
+                self.compile_vec(guards.clone(), Self::compile_guard);
+                self.addOpCode(OpCode::ExprGuards);
+                self.spans.pop();
+            },
             AnnExpr::Ann { expr, ann } => {
-                self.spans.push(ann.0.clone());
+                self.spans.push(ann.0);
                 self.compile_expr(expr);
                 self.compile_type(&ann.1);
                 self.addOpCode(OpCode::ExprAnn);
                 self.spans.pop();
             },
+            AnnExpr::Error { .. } => unreachable!("programs with syntax errors are not compiled"),
         }
     }
 
+    fn compile_field(&mut self, field: &(Identifier, AnnExpr<Span>)) {
+        self.compile_identifer(&field.0);
+        self.compile_expr(&field.1);
+    }
+
+    fn compile_field_pattern(&mut self, field: &(Identifier, Pattern)) {
+        self.compile_identifer(&field.0);
+        self.compile_pattern(&field.1);
+    }
+
+    fn compile_guard(&mut self, guard: &(AnnExpr<Span>, AnnExpr<Span>)) {
+        self.compile_expr(&guard.0);
+        self.compile_expr(&guard.1);
+    }
+
     fn compile_arm(&mut self, arms: &(Pattern, Box<AnnExpr<Span>>)) {
         self.compile_pattern(&arms.0);
         self.compile_expr(&arms.1);
     }
 
     fn compile_identifer(&mut self, id: &Identifier) {
-        self.spans.push(id.span.clone());
+        self.spans.push(id.span);
         let index = self.current_chunk.addConstant(Constant::new_string(&id.name));
         self.addOpCode(OpCode::PushGlobal);
-        self.addByte(index as u8);
+        self.addIndex(index);
         self.spans.pop();
     }
 
     fn compile_type(&mut self, ty: &Type) {
         match ty {
             Type::Generic(gen) => {
-                self.spans.push(gen.span.clone());
+                self.spans.push(gen.span);
                 let index = self.current_chunk.addConstant(Constant::new_string(&gen.name));
                 self.addOpCode(OpCode::TypeGeneric);
-                self.addByte(index as u8);
+                self.addIndex(index);
                 self.spans.pop();
             },
             Type::Tuple(tys, span) => {
-                self.spans.push(span.clone());
+                self.spans.push(*span);
                 self.compile_vec(tys.clone(), Self::compile_type);
                 self.addOpCode(OpCode::TypeTuple);
                 self.spans.pop();
             },
             Type::Id(id) => {
-                self.spans.push(id.span.clone());
+                self.spans.push(id.span);
                 let index = self.current_chunk.addConstant(Constant::new_string(&id.name));
-                self.addByte(index as u8);
                 self.addOpCode(OpCode::TypeId);
+                self.addIndex(index);
                 self.spans.pop();
             },
             Type::Func(ret, args, span) => {
-                self.spans.push(span.clone());
+                self.spans.push(*span);
                 self.compile_vec(args.clone(), Self::compile_type);
                 self.compile_type(ret);
                 self.addOpCode(OpCode::TypeFunc);
                 self.spans.pop();
             },
             Type::App(app, tys, span) => {
-                self.spans.push(span.clone());
+                self.spans.push(*span);
                 self.compile_identifer(app);
                 self.compile_vec(tys.clone(), Self::compile_type);
                 self.addOpCode(OpCode::TypeApp);
                 self.spans.pop();
             },
+            Type::List(ty, span) => {
+                self.spans.push(*span);
+                self.compile_type(ty);
+                self.addOpCode(OpCode::TypeList);
+                self.spans.pop();
+            },
+            // The dictionaries the constraints stand for are explicit
+            // arguments once elaborated.
+            Type::Qualified(_, ty, _) => {
+                self.compile_type(ty);
+            },
         }
     }
 
     fn compile_literal(&mut self, lit: &Literal) {
-        self.spans.push(lit.span.clone());
+        self.spans.push(lit.span);
         self.addOpCode(OpCode::PushConst);
         match lit.lit.clone() {
             LiteralKind::Integer(i) => {
-                let index = self.current_chunk.addConstant(Constant::Int(i));
-                self.addByte(index as u8);
+                let constant = match i64::try_from(&i) {
+                    Ok(i) => Constant::Int(i),
+                    Err(_) => Constant::BigInt(i),
+                };
+                let index = self.current_chunk.addConstant(constant);
+                self.addIndex(index);
             },
             LiteralKind::Float(f) => {
                 let index = self.current_chunk.addConstant(Constant::Float(f));
-                self.addByte(index as u8);
+                self.addIndex(index);
             },
             LiteralKind::String(s) => {
                 let index = self.current_chunk.addConstant(Constant::new_string(&s));
-                self.addByte(index as u8);
+                self.addIndex(index);
             },
             LiteralKind::Char(c) => {
                 let index = self.current_chunk.addConstant(Constant::Char(c));
-                self.addByte(index as u8);
+                self.addIndex(index);
             },
             LiteralKind::Bool(b) => {
                 let index = self.current_chunk.addConstant(Constant::Bool(b.into()));
-                self.addByte(index as u8);
+                self.addIndex(index);
             },
         }
         self.spans.pop();
     }
 
-    fn addByte(&mut self, byte: u8) {
+    fn addIndex(&mut self, index: usize) {
         let span = self.spans.last().unwrap();
-        self.current_chunk.addByte(byte, span.clone());
+        self.current_chunk.addIndex(index, *span);
     }
 
     fn addOpCode(&mut self, op: OpCode) {
         let span = self.spans.last().unwrap();
-        self.current_chunk.addOpCode(op, span.clone());
+        self.current_chunk.addOpCode(op, *span);
     }
 }
 
@@ -322,3 +420,15 @@ pub fn compile_program(program: Vec<Statement<Span>>) -> Chunk {
     compiler.compile(program);
     compiler.current_chunk
 }
+
+/// Compiles each module to a chunk, and links them in order.
+pub fn compile_modules(modules: &[Module]) -> Chunk {
+    let mut chunks = modules
+        .iter()
+        .map(|module| compile_program(module.program.statements.clone()));
+    let mut linked = chunks.next().unwrap_or_else(|| Compiler::new().current_chunk);
+    for chunk in chunks {
+        linked.link(chunk);
+    }
+    linked
+}
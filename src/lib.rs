@@ -0,0 +1,160 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! The Risk compiler and evaluator. `run_source` takes a program from its
+//! text to its value, through the passes `run_with_limits` expects it to
+//! have gone through.
+
+#![cfg_attr(test, feature(test))]
+#[cfg(test)]
+extern crate test;
+
+pub mod ast;
+pub mod source;
+pub mod parser;
+pub mod semantics;
+pub mod bytecode;
+pub mod compiler;
+pub mod vm;
+
+pub use vm::{run_with_limits, Limits, Run};
+
+use semantics::error::SemanticError;
+use semantics::loader::{Loader, Module};
+use semantics::tc::error::TypeCheckerError;
+use semantics::tc::TypeChecker;
+use semantics::warning::SemanticWarning;
+use semantics::AnalysisOutput;
+use source::SourceMap;
+use std::path::PathBuf;
+use vm::heap::HeapConfig;
+
+/// What the passes found wrong with a program, with the sources they point
+/// into.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub sources: SourceMap,
+    pub syntax_errors: Vec<parser::error::Error>,
+    pub errors: Vec<SemanticError>,
+    pub warnings: Vec<SemanticWarning>,
+    pub type_errors: Vec<TypeCheckerError>,
+    /// Why the thread of the evaluator could not be created.
+    pub stack_error: Option<std::io::Error>,
+}
+
+impl Diagnostics {
+    /// Whether the program cannot be run.
+    pub fn failed(&self) -> bool {
+        !self.syntax_errors.is_empty()
+            || !self.errors.is_empty()
+            || !self.type_errors.is_empty()
+            || self.stack_error.is_some()
+    }
+
+    /// Reports the errors and warnings, in the order of the passes.
+    pub fn report(&self) {
+        for e in &self.syntax_errors {
+            e.report(&self.sources);
+        }
+        for e in &self.errors {
+            e.report(&self.sources);
+        }
+        for w in &self.warnings {
+            w.report(&self.sources);
+        }
+        for e in &self.type_errors {
+            e.report(&self.sources);
+        }
+        if let Some(e) = &self.stack_error {
+            eprintln!("Cannot reserve the stack of the evaluator: {}. Try a smaller --max-depth.", e);
+        }
+    }
+}
+
+/// Loads the module `name` of text `text`, the modules it imports from
+/// `root` and the Prelude, then resolves the names they import. The modules
+/// come each after the modules it imports.
+pub fn load_source(name: &str, text: String, root: PathBuf) -> (Vec<Module>, Diagnostics) {
+    let (modules, ao, mut diagnostics) = load(name, text, root);
+    diagnostics.errors = ao.errors;
+    (modules, diagnostics)
+}
+
+/// `load_source`, keeping what the resolution of the imports learned for
+/// the analysis.
+fn load(name: &str, text: String, root: PathBuf) -> (Vec<Module>, AnalysisOutput, Diagnostics) {
+    let mut diagnostics = Diagnostics::default();
+    let mut loader = Loader::new(root, &mut diagnostics.sources);
+    loader.load_main(name, text);
+    let Loader {
+        mut modules,
+        syntax_errors,
+        errors,
+        ..
+    } = loader;
+    diagnostics.syntax_errors = syntax_errors;
+
+    let mut ao = AnalysisOutput::new();
+    ao.errors.extend(errors);
+    semantics::module::resolve(&mut ao, &mut modules);
+    (modules, ao, diagnostics)
+}
+
+/// Runs the program of `text`, as the module `name`, within `limits`: the
+/// program is loaded, analyzed, type checked and elaborated first. Gives
+/// the warnings along with the run, and everything found wrong when the
+/// program cannot be run.
+pub fn run_source(
+    name: &str,
+    text: String,
+    root: PathBuf,
+    heap: HeapConfig,
+    limits: Limits,
+) -> Result<(Run, Diagnostics), Box<Diagnostics>> {
+    let (modules, mut ao, mut diagnostics) = load(name, text, root);
+    let program = ast::Program::new(
+        modules
+            .into_iter()
+            .flat_map(|module| module.program.statements)
+            .collect(),
+    );
+
+    // The later passes still run on what could be parsed, so that a single
+    // run reports as many errors as possible.
+    semantics::analyze(&mut ao, program.clone());
+    diagnostics.errors = ao.errors;
+    diagnostics.warnings = ao.warnings;
+    if !diagnostics.errors.is_empty() {
+        return Err(Box::new(diagnostics));
+    }
+
+    let mut tc = TypeChecker::new();
+    tc.check_program(&program);
+    diagnostics.type_errors = tc.errors;
+    if diagnostics.failed() {
+        return Err(Box::new(diagnostics));
+    }
+
+    let program = compiler::elaborate::elaborate(program, &tc.evidence);
+    match run_with_limits(&program, heap, limits) {
+        Ok(run) => Ok((run, diagnostics)),
+        Err(e) => {
+            diagnostics.stack_error = Some(e);
+            Err(Box::new(diagnostics))
+        }
+    }
+}
@@ -15,54 +15,121 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-#![feature(str_from_raw_parts)]
-#![feature(strict_provenance)]
-use semantics::analyze;
-use semantics::AnalysisOutput;
+use risk::{compiler, vm};
 use std::env;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-mod ast;
-mod parser;
-mod semantics;
-mod bytecode;
-mod compiler;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        panic!("You must provide filecode.");
-    }
-
-    let content = fs::read_to_string(args[1].clone()).expect("Cannot read file for some reasons");
-    let mut parser = parser::Parser::new(&content);
-
-    let ast = parser.parse();
+struct Options {
+    filename: String,
+    /// The directory modules are looked up in.
+    root: Option<PathBuf>,
+    gc_stats: bool,
+    bytecode: bool,
+    heap: vm::heap::HeapConfig,
+    limits: vm::Limits,
+}
 
-    if let Err(e) = ast {
-        e.report(&args[1]);
-    } else {
-        let mut ao = AnalysisOutput::new();
+/// The value following the option `name`.
+fn value<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>, name: &str, expected: &str) -> Result<T, String> {
+    args.next()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} expects {}.", name, expected))
+}
 
-        let ast = ast.unwrap();
+fn parse_options() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut filename = None;
+    let mut options = Options {
+        filename: String::new(),
+        root: None,
+        gc_stats: false,
+        bytecode: false,
+        heap: vm::heap::HeapConfig::default(),
+        limits: vm::Limits::default(),
+    };
 
-        // Print the AST
-        // println!("╭─━━━━━━━━━ AST ━━━━━━━\n{:#?}\n╰─━━━━━━━━━━━━━━━━━━━━━\n", ast.clone());
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gc-stats" => options.gc_stats = true,
+            "--bytecode" => options.bytecode = true,
+            "--root" => options.root = Some(value::<String>(&mut args, "--root", "a directory")?.into()),
+            "--heap-size" => options.heap.heap_size = value(&mut args, "--heap-size", "a number of bytes")?,
+            "--fuel" => options.limits.fuel = Some(value(&mut args, "--fuel", "a number of evaluation steps")?),
+            "--max-depth" => {
+                let depth = value(&mut args, "--max-depth", "a number of nested calls")?;
+                if depth > vm::MAX_CALL_DEPTH {
+                    return Err(format!("--max-depth cannot exceed {}.", vm::MAX_CALL_DEPTH));
+                }
+                options.limits.max_call_depth = depth;
+            }
+            "--max-heap" => options.limits.max_heap = Some(value(&mut args, "--max-heap", "a number of bytes")?),
+            _ => filename = Some(arg),
+        }
+    }
 
-        analyze(&mut ao, ast.clone());
+    options.filename = filename.ok_or("You must provide a file to run.")?;
+    Ok(options)
+}
 
-        for se in ao.errors {
-            se.report(&args[1])
+/// The exit status is 1 when the program has errors or stops with a runtime
+/// error, and 2 when it cannot be run at all.
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
         }
+    };
 
-        for sw in ao.warnings {
-            sw.report(&args[1])
+    let content = match fs::read_to_string(&options.filename) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", options.filename, e);
+            return ExitCode::from(2);
         }
+    };
+    let root = options.root.clone().unwrap_or_else(|| {
+        Path::new(&options.filename)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    });
+    if options.bytecode {
+        let (modules, diagnostics) = risk::load_source(&options.filename, content.clone(), root.clone());
+        if diagnostics.syntax_errors.is_empty() {
+            let mut chunk = compiler::compile_modules(&modules);
 
-        let mut chunk = compiler::compile_program(ast.statements);
+            println!("\n╭─━━━━━━━━━ Bytecode ━━━━━━━━━─╮");
+            chunk.dissassemble("test.txt");
+            println!("╰─━━━━━━━━━━━━━━━━━━━━━━━━━━━━━─╯");
+        }
+    }
 
-        println!("\n╭─━━━━━━━━━ Bytecode ━━━━━━━━━─╮");
-        chunk.dissassemble("test.txt");
-        println!("╰─━━━━━━━━━━━━━━━━━━━━━━━━━━━━━─╯");
+    let (run, diagnostics) = match risk::run_source(&options.filename, content, root, options.heap, options.limits) {
+        Ok(run) => run,
+        Err(diagnostics) => {
+            diagnostics.report();
+            return if diagnostics.stack_error.is_some() {
+                ExitCode::from(2)
+            } else {
+                ExitCode::FAILURE
+            };
+        }
+    };
+    diagnostics.report();
+    if options.gc_stats {
+        eprintln!("{}", run.stats);
+    }
+    match run.result {
+        Some(Ok(value)) => println!("{}", value),
+        Some(Err(e)) => {
+            e.report(&diagnostics.sources);
+            return ExitCode::FAILURE;
+        }
+        None => {}
     }
+    ExitCode::SUCCESS
 }
@@ -17,7 +17,8 @@
 */
 
 use crate::ast::Span;
-use ariadne::{Label, Report, ReportKind, Source};
+use crate::source::SourceMap;
+use ariadne::{Label, Report, ReportKind};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum ErrorKind {
@@ -27,6 +28,15 @@ pub enum ErrorKind {
     UnexpectedEndOfInput,
     UnexpectedTokenInPattern { found: Span },
     TooMuchExpr { found: Span },
+    NonAssociative { first: Span, second: Span },
+    MixedAssociativity { first: Span, second: Span },
+    InvalidPrecedence { found: Span },
+    MisplacedHeader { found: Span },
+    InvalidEscape { found: Span },
+    InvalidChar { found: Span },
+    InvalidCharacter { found: Span },
+    UnterminatedString { found: Span },
+    InvalidNumber { found: Span },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -40,25 +50,20 @@ impl Error {
         Error { kind, span }
     }
 
-    pub fn report(&self, filename: &str) {
-        let source = &std::fs::read_to_string(filename).unwrap();
-        let mut report = Report::build(
-            ReportKind::Error,
-            filename,
-            self.span.get_line_number(source),
-        );
+    pub fn report(&self, sources: &SourceMap) {
+        let mut report = Report::build(ReportKind::Error, self.span.file, sources.label(self.span).1.start);
         match &self.kind {
             ErrorKind::UnexpectedToken { expected, found } => {
                 report = report
                     .with_code("unexpected-token")
                     .with_message(format!(
                         "Unexpected token found at line {}, expected {}",
-                        found.get_line_number(source),
+                        sources.line(*found),
                         expected
                     ))
                     .with_label(
-                        Label::new((filename, found.start..found.end))
-                            .with_message(format!("found {:?}", found.input)),
+                        Label::new(sources.label(*found))
+                            .with_message(format!("found {:?}", sources.text(*found))),
                     );
             }
             ErrorKind::UnexpectedEOF { expected } => {
@@ -66,7 +71,7 @@ impl Error {
                     .with_code("unexpected-eof")
                     .with_message("Unexpected end of input")
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message(format!("Expected {}", expected)),
                     );
             }
@@ -75,7 +80,7 @@ impl Error {
                     .with_code("unexpected-end-of-input")
                     .with_message("Unexpected end of input")
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message("Expected more input"),
                     );
             }
@@ -84,10 +89,10 @@ impl Error {
                     .with_code("unexpected-token-in-pattern")
                     .with_message(format!(
                         "Unexpected token found in pattern at line {}",
-                        found.get_line_number(source)
+                        sources.line(*found)
                     ))
                     .with_label(
-                        Label::new((filename, found.start..found.end))
+                        Label::new(sources.label(*found))
                             .with_message("Unexpected token in pattern"),
                     );
             }
@@ -96,22 +101,122 @@ impl Error {
                     .with_code("too-much-expr")
                     .with_message(format!(
                         "Too much expr found at line {}",
-                        found.get_line_number(source)
+                        sources.line(*found)
                     ))
                     .with_label(
-                        Label::new((filename, found.start..found.end))
+                        Label::new(sources.label(*found))
                             .with_message("Too much expr"),
                     );
             }
+            ErrorKind::NonAssociative { first, second } => {
+                report = report
+                    .with_code("non-associative")
+                    .with_message(format!(
+                        "Cannot chain `{}` and `{}`, a non-associative operator cannot be chained with an operator of the same precedence",
+                        sources.text(*first), sources.text(*second)
+                    ))
+                    .with_label(
+                        Label::new(sources.label(*first))
+                            .with_message("First operator"),
+                    )
+                    .with_label(
+                        Label::new(sources.label(*second))
+                            .with_message("Second operator"),
+                    )
+                    .with_note("Add parentheses to make the grouping explicit.");
+            }
+            ErrorKind::MixedAssociativity { first, second } => {
+                report = report
+                    .with_code("mixed-associativity")
+                    .with_message(format!(
+                        "Cannot chain `{}` and `{}`, they have the same precedence but do not associate the same way",
+                        sources.text(*first), sources.text(*second)
+                    ))
+                    .with_label(
+                        Label::new(sources.label(*first))
+                            .with_message("First operator"),
+                    )
+                    .with_label(
+                        Label::new(sources.label(*second))
+                            .with_message("Second operator"),
+                    )
+                    .with_note("Add parentheses to make the grouping explicit.");
+            }
+            ErrorKind::InvalidPrecedence { found } => {
+                report = report
+                    .with_code("invalid-precedence")
+                    .with_message(format!("Invalid precedence `{}`", sources.text(*found)))
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Expected an integer from 0 to 9"),
+                    );
+            }
+            ErrorKind::MisplacedHeader { found } => {
+                report = report
+                    .with_code("misplaced-header")
+                    .with_message("Module and import declarations must come first")
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Move this to the top of the file"),
+                    )
+                    .with_note("The module declaration goes first, then the imports.");
+            }
+            ErrorKind::InvalidEscape { found } => {
+                report = report
+                    .with_code("invalid-escape")
+                    .with_message(format!("Invalid escape sequence `{}`", sources.text(*found)))
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Unknown or malformed escape"),
+                    )
+                    .with_note("The escapes are \\n, \\t, \\\\, \\\", \\' and \\u{...} with one to six hexadecimal digits.");
+            }
+            ErrorKind::InvalidChar { found } => {
+                report = report
+                    .with_code("invalid-char")
+                    .with_message("Invalid character literal")
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Expected exactly one character"),
+                    );
+            }
+            ErrorKind::InvalidCharacter { found } => {
+                report = report
+                    .with_code("invalid-character")
+                    .with_message(format!("Invalid character `{}`", sources.text(*found)))
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Not part of any token"),
+                    );
+            }
+            ErrorKind::UnterminatedString { found } => {
+                report = report
+                    .with_code("unterminated-string")
+                    .with_message("Unterminated string")
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Missing the closing quote on this line"),
+                    );
+            }
+            ErrorKind::InvalidNumber { found } => {
+                report = report
+                    .with_code("invalid-number")
+                    .with_message(format!("Malformed number `{}`", sources.text(*found)))
+                    .with_label(
+                        Label::new(sources.label(*found))
+                            .with_message("Not a valid number"),
+                    )
+                    .with_note("Numbers are written like 42, 1_000, 0xFF, 0o17, 0b1010, 2.5 or 1.5e-3.");
+            }
             ErrorKind::ExpectedNewline { found } => {
                 report = report
                     .with_code("expected-newline")
                     .with_message(format!(
                         "Expected newline at line {}",
-                        found.get_line_number(source)
+                        sources.line(*found)
                     ))
                     .with_label(
-                        Label::new((filename, found.start..found.end))
+                        Label::new(sources.label(*found))
                             .with_message("Expected newline"),
                     );
             }
@@ -119,7 +224,7 @@ impl Error {
 
         report
             .finish()
-            .print((filename, Source::from(source)))
+            .print(sources)
             .unwrap();
     }
 }
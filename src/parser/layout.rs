@@ -0,0 +1,242 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The layout rule, which makes indentation significant. It runs between the
+//! lexer and the parser and inserts virtual tokens, so that the parser never
+//! has to look at whitespace:
+//!
+//! - A line starting at column 0 starts a new statement: `StatementEnd` is
+//!   inserted before it, after closing every open block. A more indented line
+//!   continues the current statement.
+//! - `let`, `with` and `where` open a block whose column is the one of the token
+//!   following them (`BlockStart`). In a block, a line starting at that column
+//!   starts a new item (`BlockSep`), and a line starting to the left of it
+//!   closes the block (`BlockEnd`).
+//! - A block is also closed by the token that cannot belong to it: `in` for
+//!   the innermost `let` block, `then` and `else` for the blocks opened since
+//!   the matching `if`, and `,`, `)`, `]` and `}` for the blocks opened inside
+//!   the brackets.
+//!
+//! Lines inside brackets that do not start a statement or a block item are
+//! continuations, whatever their indentation.
+
+use crate::ast::Span;
+use crate::parser::lexer::{Token, TokenKind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context<'a> {
+    /// A block opened by `keyword`, whose items start at `column`.
+    Block {
+        column: usize,
+        keyword: TokenKind<'a>,
+    },
+    /// An open parenthesis or bracket.
+    Bracket,
+    /// An `if` waiting for its `else`.
+    If,
+}
+
+struct Layout<'a> {
+    tokens: Vec<Token<'a>>,
+    contexts: Vec<Context<'a>>,
+}
+
+impl<'a> Layout<'a> {
+    /// Appends a virtual token, empty and placed at the end of the previous
+    /// token, so that it does not extend the span of what precedes it.
+    fn insert(&mut self, kind: TokenKind<'a>) {
+        let span = self.tokens.last().map_or(Span::default(), |token| {
+            Span::new(token.span.file, token.span.end, token.span.end)
+        });
+        self.tokens.push(Token::new(kind, span));
+    }
+
+    /// The index of the innermost context that is not inside brackets and
+    /// satisfies `f`.
+    fn find(&self, f: impl Fn(&Context<'a>) -> bool) -> Option<usize> {
+        for (i, context) in self.contexts.iter().enumerate().rev() {
+            if f(context) {
+                return Some(i);
+            }
+            if *context == Context::Bracket {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn close_all(&mut self) {
+        while let Some(context) = self.contexts.pop() {
+            if let Context::Block { .. } = context {
+                self.insert(TokenKind::BlockEnd);
+            }
+        }
+    }
+
+    /// Pops the contexts above `index`, closing their blocks.
+    fn close_above(&mut self, index: usize) {
+        while self.contexts.len() > index + 1 {
+            if let Some(Context::Block { .. }) = self.contexts.pop() {
+                self.insert(TokenKind::BlockEnd);
+            }
+        }
+    }
+
+    /// Pops the contexts from `index` included, closing their blocks.
+    fn close_from(&mut self, index: usize) {
+        self.close_above(index);
+        if let Some(Context::Block { .. }) = self.contexts.pop() {
+            self.insert(TokenKind::BlockEnd);
+        }
+    }
+
+    /// The innermost block outside brackets.
+    fn innermost_block(&self) -> Option<(usize, usize)> {
+        let i = self.find(|context| matches!(context, Context::Block { .. }))?;
+        match self.contexts[i] {
+            Context::Block { column, .. } => Some((i, column)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Handles the first token of a line.
+    fn new_line(&mut self, column: usize) {
+        if column == 0 {
+            self.close_all();
+            self.insert(TokenKind::StatementEnd);
+            return;
+        }
+
+        while let Some((i, block)) = self.innermost_block() {
+            if column < block {
+                self.close_from(i);
+            } else {
+                if column == block {
+                    self.close_above(i);
+                    self.insert(TokenKind::BlockSep);
+                }
+                break;
+            }
+        }
+    }
+
+    /// Closes the blocks a token cannot belong to, and opens the contexts it
+    /// starts.
+    fn keyword(&mut self, kind: TokenKind<'a>) {
+        match kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => {
+                self.contexts.push(Context::Bracket)
+            }
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                if let Some(i) = self.contexts.iter().rposition(|c| *c == Context::Bracket) {
+                    self.close_from(i);
+                }
+            }
+            TokenKind::Comma => {
+                if let Some(i) = self.contexts.iter().rposition(|c| *c == Context::Bracket) {
+                    self.close_above(i);
+                }
+            }
+            TokenKind::In => {
+                let let_block = |context: &Context| {
+                    matches!(
+                        context,
+                        Context::Block {
+                            keyword: TokenKind::Let,
+                            ..
+                        }
+                    )
+                };
+                if let Some(i) = self.find(let_block) {
+                    self.close_from(i);
+                }
+            }
+            TokenKind::If => self.contexts.push(Context::If),
+            TokenKind::Then => {
+                if let Some(i) = self.find(|context| *context == Context::If) {
+                    self.close_above(i);
+                }
+            }
+            TokenKind::Else => {
+                if let Some(i) = self.find(|context| *context == Context::If) {
+                    self.close_from(i);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The column of the byte at `offset`, counted in characters.
+fn column(source: &str, offset: usize) -> usize {
+    let line = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    source[line..offset].chars().count()
+}
+
+/// Applies the layout rule to the tokens of `source`. Whitespace tokens are
+/// dropped, as the parser builds spans from token offsets.
+pub fn layout<'a>(source: &str, tokens: Vec<Token<'a>>) -> Vec<Token<'a>> {
+    let mut layout = Layout {
+        tokens: Vec::with_capacity(tokens.len()),
+        contexts: Vec::new(),
+    };
+    // The keyword whose block starts at the next token.
+    let mut opening: Option<TokenKind> = None;
+    // End of the previous significant token.
+    let mut previous: Option<usize> = None;
+
+    for token in tokens {
+        if token.kind.is_whitespace() {
+            continue;
+        }
+        if token.kind == TokenKind::Eof {
+            layout.close_all();
+            layout.tokens.push(token);
+            break;
+        }
+
+        let start = token.span.start;
+        let first_of_line = previous.is_some_and(|end| source[end..start].contains('\n'));
+
+        if let Some(keyword) = opening.take() {
+            let column = column(source, start);
+            let enclosing = layout.innermost_block().map_or(0, |(_, column)| column);
+            layout.insert(TokenKind::BlockStart);
+            if column > enclosing {
+                layout.contexts.push(Context::Block { column, keyword });
+            } else {
+                // Nothing is indented enough to belong to the block.
+                layout.insert(TokenKind::BlockEnd);
+                if first_of_line {
+                    layout.new_line(column);
+                }
+            }
+        } else if first_of_line {
+            layout.new_line(column(source, start));
+        }
+
+        layout.keyword(token.kind);
+        if matches!(token.kind, TokenKind::Let | TokenKind::With | TokenKind::Where) {
+            opening = Some(token.kind);
+        }
+        previous = Some(token.span.end);
+        layout.tokens.push(token);
+    }
+
+    layout.tokens
+}
@@ -17,6 +17,8 @@
 */
 
 use crate::ast::Span;
+use crate::parser::error::{Error, ErrorKind};
+use crate::source::FileId;
 use logos::Logos;
 
 #[derive(Logos, Debug, PartialEq, Copy, Clone)]
@@ -45,20 +47,61 @@ pub enum TokenKind<'a> {
     #[token("with")]
     With,
 
+    #[token("where")]
+    Where,
+
     #[token("type")]
     Type,
 
-    // Primitives
-    #[regex(r"[-+]?\d+", |lex| lex.slice().parse().ok(), priority = 3)]
-    Integer(i64),
+    #[token("class")]
+    Class,
+
+    #[token("instance")]
+    Instance,
+
+    #[token("module")]
+    Module,
+
+    #[token("import")]
+    Import,
+
+    #[token("infixl")]
+    Infixl,
+
+    #[token("infixr")]
+    Infixr,
 
-    #[regex(r"[-+]?\d+(\.\d*)?", |lex| lex.slice().parse::<f64>().ok())]
+    #[token("infix")]
+    Infix,
+
+    #[token("not")]
+    Not,
+
+    // Primitives. Numbers have no sign, `-` is a prefix operator. Digits may
+    // be separated by single underscores.
+    #[regex(r"[0-9](_?[0-9])*", priority = 4)]
+    #[regex(r"0x[0-9a-fA-F](_?[0-9a-fA-F])*", priority = 4)]
+    #[regex(r"0o[0-7](_?[0-7])*", priority = 4)]
+    #[regex(r"0b[01](_?[01])*", priority = 4)]
+    Integer(&'a str),
+
+    #[regex(
+        r"[0-9](_?[0-9])*(\.[0-9](_?[0-9])*([eE][+-]?[0-9](_?[0-9])*)?|[eE][+-]?[0-9](_?[0-9])*)",
+        |lex| lex.slice().replace('_', "").parse::<f64>().ok(),
+        priority = 4
+    )]
     Float(f64),
 
-    #[regex("\"[^\"]+\"", |lex| &lex.slice()[1..lex.slice().len() - 1])]
+    // Whatever else starts with a digit is a malformed number, which `lexer`
+    // reports as a whole.
+    #[regex(r"[0-9][0-9a-zA-Z_]*(\.[0-9a-zA-Z_]+)?", |_| false, priority = 2)]
+    MalformedNumber,
+
+    // The text between the quotes, escapes are decoded by `unescape`.
+    #[regex(r#""([^"\\\n]|\\[^\n])*""#, |lex| &lex.slice()[1..lex.slice().len() - 1])]
     String(&'a str),
 
-    #[regex("'[^']'", |lex| &lex.slice()[1..lex.slice().len() - 1])]
+    #[regex(r"'([^'\\\n]|\\[^\n][^'\n]*)'", |lex| &lex.slice()[1..lex.slice().len() - 1])]
     Char(&'a str),
 
     #[token("True")]
@@ -67,10 +110,12 @@ pub enum TokenKind<'a> {
     #[token("False")]
     False,
 
-    #[regex(r"[A-Z][a-zA-Z0-9']*")]
+    // A name can be qualified by the module it comes from, as in `Data.List`,
+    // `S.Shape` or `S.area`.
+    #[regex(r"([A-Z][a-zA-Z0-9']*\.)*[A-Z][a-zA-Z0-9']*")]
     PCIdentifier(&'a str),
 
-    #[regex(r"[a-z][a-zA-Z0-9']*")]
+    #[regex(r"([A-Z][a-zA-Z0-9']*\.)*[a-z][a-zA-Z0-9']*")]
     Identifier(&'a str),
 
     // Syntactic operators
@@ -95,9 +140,18 @@ pub enum TokenKind<'a> {
     #[token("]")]
     RBracket,
 
+    #[token("{")]
+    LBrace,
+
+    #[token("}")]
+    RBrace,
+
     #[token("->")]
     Arrow,
 
+    #[token("=>")]
+    FatArrow,
+
     #[token(",")]
     Comma,
 
@@ -155,6 +209,19 @@ pub enum TokenKind<'a> {
     #[token("=")]
     Assign,
 
+    #[token("`")]
+    Backtick,
+
+    // Reserved for as-patterns, `xs@(x:_)`.
+    #[token("@")]
+    At,
+
+    // User-defined operators. The fixed tokens above take precedence when
+    // they match the same text. They can be qualified too, as in `S.<+>`.
+    #[regex(r"[!#$%&*+./<=>?^|~:\-]+", priority = 1)]
+    #[regex(r"([A-Z][a-zA-Z0-9']*\.)+[!#$%&*+./<=>?^|~:\-]+")]
+    Operator(&'a str),
+
     #[token(" ")]
     Space,
 
@@ -164,6 +231,15 @@ pub enum TokenKind<'a> {
     #[token("\n")]
     Newline,
 
+    // Text that is not a token, the reason being reported by `lexer`.
+    Error,
+
+    // Virtual tokens inserted by the layout rule, see `layout.rs`.
+    BlockStart,
+    BlockSep,
+    BlockEnd,
+    StatementEnd,
+
     Eof,
 }
 
@@ -186,6 +262,28 @@ impl<'a> TokenKind<'a> {
         }
     }
 
+    /// Whether this token can start an argument of an application.
+    pub fn starts_atom(&self) -> bool {
+        self.is_literal()
+            || self.is_identifier()
+            || matches!(self, TokenKind::LParen | TokenKind::LBracket | TokenKind::Error)
+    }
+
+    /// Whether this token can start a pattern, save for a negative number.
+    pub fn starts_pattern(&self) -> bool {
+        self.is_literal()
+            || self.is_identifier()
+            || matches!(
+                self,
+                TokenKind::Underscore | TokenKind::LParen | TokenKind::LBracket
+            )
+    }
+
+    /// Whether this token can start an argument of a type application.
+    pub fn starts_type_atom(&self) -> bool {
+        self.is_identifier() || matches!(self, TokenKind::LParen | TokenKind::LBracket)
+    }
+
     pub fn is_whitespace(&self) -> bool {
         match self {
             TokenKind::Space | TokenKind::Tab | TokenKind::Newline => true,
@@ -206,29 +304,118 @@ impl<'token> Token<'token> {
     }
 }
 
-pub fn lexer<'a>(input: &'a str) -> Vec<Token> {
+/// Splits `input` into tokens. Text that does not form a token becomes an
+/// `Error` token, and the reason is returned alongside, so that lexing never
+/// stops at the first mistake.
+pub fn lexer(file: FileId, input: &str) -> (Vec<Token>, Vec<Error>) {
     let mut lexer = TokenKind::lexer(input);
-    let mut tokens = Vec::new();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut errors: Vec<Error> = Vec::new();
+
     while let Some(token) = lexer.next() {
-        tokens.push(Token {
-            kind: token.unwrap(),
-            span: Span::new(
-                lexer.span().start,
-                lexer.span().end,
-                lexer.slice().to_string(),
-            ),
-        });
+        let kind = match token {
+            Ok(kind) => kind,
+            Err(()) => {
+                let start = lexer.span().start;
+                let (kind, end) = lexical_error(input, start, lexer.span().end, file);
+                lexer.bump(end - lexer.span().end);
+
+                // A run of invalid characters is reported once, and makes a
+                // single `Error` token.
+                match (errors.last_mut(), &kind) {
+                    (
+                        Some(Error {
+                            kind: ErrorKind::InvalidCharacter { found },
+                            span,
+                        }),
+                        ErrorKind::InvalidCharacter { .. },
+                    ) if found.end == start => {
+                        found.end = end;
+                        span.end = end;
+                        tokens.last_mut().unwrap().span.end = end;
+                        continue;
+                    }
+                    _ => errors.push(Error::new(kind, Span::new(file, start, end))),
+                }
+                TokenKind::Error
+            }
+        };
+        tokens.push(Token::new(kind, Span::new(file, lexer.span().start, lexer.span().end)));
     }
 
     tokens.push(Token::new(
         TokenKind::Eof,
-        Span::new(
-            lexer.span().start,
-            lexer.span().end,
-            lexer.slice().to_string(),
-        ),
+        Span::new(file, lexer.span().start, lexer.span().end),
     ));
-    tokens
+    (tokens, errors)
+}
+
+/// Classifies the text at `start..end` that `logos` could not turn into a
+/// token, returning the error and where the erroneous text ends. Literals are
+/// reported as a whole rather than from the character where they went wrong.
+fn lexical_error(input: &str, start: usize, end: usize, file: FileId) -> (ErrorKind, usize) {
+    let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    let found = |end| Span::new(file, start, end);
+
+    match input[start..].chars().next() {
+        Some('0'..='9') => (ErrorKind::InvalidNumber { found: found(end) }, end),
+        Some('"') => (ErrorKind::UnterminatedString { found: found(line_end) }, line_end),
+        Some('\'') => {
+            // Up to the closing quote, if the line has one.
+            let end = input[start + 1..line_end]
+                .find('\'')
+                .map_or(line_end, |i| start + 1 + i + 1);
+            (ErrorKind::InvalidChar { found: found(end) }, end)
+        }
+        _ => (ErrorKind::InvalidCharacter { found: found(end) }, end),
+    }
+}
+
+/// Decodes the escape sequences of the body of a string or char literal,
+/// `span` being the span of `body`. A malformed escape is reported with the
+/// span of the escape alone.
+pub fn unescape(body: &str, span: Span) -> Result<String, Error> {
+    let mut decoded = String::with_capacity(body.len());
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, '"')) => Some('"'),
+            Some((_, '\'')) => Some('\''),
+            Some((_, 'u')) => {
+                // `\u{...}`, with one to six hexadecimal digits.
+                let mut digits = String::new();
+                let braced = chars.next_if(|&(_, c)| c == '{').is_some();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_hexdigit()) {
+                    digits.push(c);
+                }
+                let closed = braced && chars.next_if(|&(_, c)| c == '}').is_some();
+                if closed && (1..=6).contains(&digits.len()) {
+                    u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(c) => decoded.push(c),
+            None => {
+                let end = chars.peek().map_or(body.len(), |&(i, _)| i);
+                let found = Span::new(span.file, span.start + start, span.start + end);
+                return Err(Error::new(ErrorKind::InvalidEscape { found }, found));
+            }
+        }
+    }
+
+    Ok(decoded)
 }
 
 #[macro_export]
@@ -236,260 +423,284 @@ macro_rules! token {
     (if) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::If,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (then) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Then,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (else) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Else,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (let) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Let,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (in) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::In,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (match) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Match,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (with) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::With,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (type) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Type,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (true) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::True,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (false) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::False,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (pc_identifier, $id: expr) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::PCIdentifier($id),
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (identifier, $id: expr) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Identifier($id),
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
 
     (integer, $int: expr) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Integer($int),
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (float, $float: expr) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Float($float),
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (string, $str: expr) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::String($str),
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (char, $char: expr) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Char($char),
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (lparen) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::LParen,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (rparen) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::RParen,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (lbracket) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::LBracket,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (rbracket) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::RBracket,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
+        )
+    };
+    (lbrace) => {
+        $crate::parser::lexer::Token::new(
+            $crate::parser::lexer::TokenKind::LBrace,
+            $crate::ast::Span::default(),
+        )
+    };
+    (rbrace) => {
+        $crate::parser::lexer::Token::new(
+            $crate::parser::lexer::TokenKind::RBrace,
+            $crate::ast::Span::default(),
         )
     };
     (inversed_slash) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::InversedSlash,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (_) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Underscore,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (|) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Pipe,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (->) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Arrow,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (,) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Comma,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (;) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Semicolon,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (::) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::DoubleCollon,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (<) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Lt,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (>) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Gt,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (>=) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Gte,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (<=) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Lte,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (&&) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::And,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (||) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Or,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (==) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Eq,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (!=) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Neq,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (:) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Colon,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (+) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Add,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (-) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Sub,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (*) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Mul,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (/) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Div,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (^) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Exp,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (%) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Mod,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
         )
     };
     (=) => {
         $crate::parser::lexer::Token::new(
             $crate::parser::lexer::TokenKind::Assign,
-            $crate::ast::Span::new(0, 0, "".to_string()),
+            $crate::ast::Span::default(),
+        )
+    };
+    (block_start) => {
+        $crate::parser::lexer::Token::new(
+            $crate::parser::lexer::TokenKind::BlockStart,
+            $crate::ast::Span::default(),
+        )
+    };
+    (backtick) => {
+        $crate::parser::lexer::Token::new(
+            $crate::parser::lexer::TokenKind::Backtick,
+            $crate::ast::Span::default(),
         )
     };
 }
@@ -16,56 +16,230 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-use crate::ast::{App, BinOp, Bool, Literal, LiteralKind, ParsedExpr, TypeDecl};
+use crate::ast::{App, Assoc, BinOp, Bool, Fixity, FixityDecl, Literal, LiteralKind, TypeDecl, UnOp};
 use crate::parser::lexer::{lexer, Token, TokenKind};
+use crate::source::FileId;
 use crate::{ast, token};
+use num_bigint::BigInt;
+use std::collections::HashMap;
 
 #[cfg(test)]
 mod test;
 
-mod error;
+pub mod error;
+mod layout;
 pub mod lexer;
 
 type ParserResult<T> = Result<T, error::Error>;
 
+/// Precedence and associativity of the binary operators, as in Haskell.
+#[rustfmt::skip]
+const FIXITIES: [(BinOp, Fixity); 15] = [
+    (BinOp::Exp, Fixity { precedence: 8, assoc: Assoc::Right }),
+    (BinOp::Mul, Fixity { precedence: 7, assoc: Assoc::Left }),
+    (BinOp::Div, Fixity { precedence: 7, assoc: Assoc::Left }),
+    (BinOp::Mod, Fixity { precedence: 7, assoc: Assoc::Left }),
+    (BinOp::Add, Fixity { precedence: 6, assoc: Assoc::Left }),
+    (BinOp::Sub, Fixity { precedence: 6, assoc: Assoc::Left }),
+    (BinOp::ListCons, Fixity { precedence: 5, assoc: Assoc::Right }),
+    (BinOp::Eq, Fixity { precedence: 4, assoc: Assoc::None }),
+    (BinOp::Ineq, Fixity { precedence: 4, assoc: Assoc::None }),
+    (BinOp::LessThan, Fixity { precedence: 4, assoc: Assoc::None }),
+    (BinOp::GreaterThan, Fixity { precedence: 4, assoc: Assoc::None }),
+    (BinOp::LessThanOrEq, Fixity { precedence: 4, assoc: Assoc::None }),
+    (BinOp::GreaterThanOrEq, Fixity { precedence: 4, assoc: Assoc::None }),
+    (BinOp::And, Fixity { precedence: 3, assoc: Assoc::Right }),
+    (BinOp::Or, Fixity { precedence: 2, assoc: Assoc::Right }),
+];
+
+/// Fixity of the user-defined operators that have no fixity declaration.
+const DEFAULT_FIXITY: Fixity = Fixity {
+    precedence: 9,
+    assoc: Assoc::Left,
+};
+
+fn fixity(op: BinOp) -> Fixity {
+    FIXITIES
+        .iter()
+        .find(|(o, _)| *o == op)
+        .map(|(_, fixity)| *fixity)
+        .unwrap()
+}
+
+fn binop(kind: &TokenKind) -> Option<BinOp> {
+    Some(match kind {
+        TokenKind::Exp => BinOp::Exp,
+        TokenKind::Mul => BinOp::Mul,
+        TokenKind::Div => BinOp::Div,
+        TokenKind::Mod => BinOp::Mod,
+        TokenKind::Add => BinOp::Add,
+        TokenKind::Sub => BinOp::Sub,
+        TokenKind::Colon => BinOp::ListCons,
+        TokenKind::Eq => BinOp::Eq,
+        TokenKind::Neq => BinOp::Ineq,
+        TokenKind::Lt => BinOp::LessThan,
+        TokenKind::Gt => BinOp::GreaterThan,
+        TokenKind::Lte => BinOp::LessThanOrEq,
+        TokenKind::Gte => BinOp::GreaterThanOrEq,
+        TokenKind::And => BinOp::And,
+        TokenKind::Or => BinOp::Or,
+        _ => return None,
+    })
+}
+
+/// An operator between two operands: either a builtin one, or a function
+/// applied infix (`x |> f`, ``x `div` y``).
+enum InfixOp {
+    Builtin(BinOp),
+    Named(ast::Identifier),
+}
+
+/// Collects the fixity declarations of the whole program up front, so that an
+/// operator can be used before the line declaring its fixity. Malformed
+/// declarations are skipped here and reported when they are parsed.
+fn declared_fixities(tokens: &[Token]) -> HashMap<String, Fixity> {
+    let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind).collect();
+    let mut fixities = HashMap::new();
+
+    for (i, kind) in kinds.iter().enumerate() {
+        let assoc = match kind {
+            TokenKind::Infixl => Assoc::Left,
+            TokenKind::Infixr => Assoc::Right,
+            TokenKind::Infix => Assoc::None,
+            _ => continue,
+        };
+        let Some(TokenKind::Integer(digits)) = kinds.get(i + 1) else {
+            continue;
+        };
+        let Ok(precedence @ 0..=9) = digits.parse::<u8>() else {
+            continue;
+        };
+
+        let mut rest = &kinds[i + 2..];
+        loop {
+            let name = match rest {
+                [TokenKind::Operator(name), ..] => {
+                    rest = &rest[1..];
+                    name
+                }
+                [TokenKind::Backtick, TokenKind::Identifier(name), TokenKind::Backtick, ..] => {
+                    rest = &rest[3..];
+                    name
+                }
+                _ => break,
+            };
+            fixities.insert(name.to_string(), Fixity { precedence, assoc });
+            match rest {
+                [TokenKind::Comma, ..] => rest = &rest[1..],
+                _ => break,
+            }
+        }
+    }
+
+    fixities
+}
+
+/// The modules imported by a file and the spans of their names, found up
+/// front so that they can be loaded before the file is parsed, and their
+/// fixities known.
+fn imported_modules(tokens: &[Token]) -> Vec<(String, ast::Span)> {
+    let mut imports = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Import {
+            continue;
+        }
+        let mut rest = &tokens[i + 1..];
+        if let [Token { kind: TokenKind::Identifier("qualified"), .. }, ..] = rest {
+            rest = &rest[1..];
+        }
+        if let [Token { kind: TokenKind::PCIdentifier(name), span }, ..] = rest {
+            imports.push((name.to_string(), *span));
+        }
+    }
+    imports
+}
+
+/// A type named `id` with no arguments: a variable if it is lowercase, a
+/// type constructor otherwise.
+fn type_name(id: ast::Identifier) -> ast::Type {
+    if id.name.starts_with(char::is_uppercase) {
+        ast::Type::Id(id)
+    } else {
+        ast::Type::Generic(id)
+    }
+}
+
+/// The span of `statement` if it is a module or an import declaration that
+/// does not come first: the module declaration goes before the imports, and
+/// the imports before everything else.
+fn misplaced_header(previous: &[ast::Statement<ast::Span>], statement: &ast::Statement<ast::Span>) -> Option<ast::Span> {
+    let is_header = |statement: &ast::Statement<ast::Span>| {
+        matches!(statement, ast::Statement::Module(_) | ast::Statement::Import(_))
+    };
+    match statement {
+        ast::Statement::Module(decl) if !previous.is_empty() => Some(decl.span),
+        ast::Statement::Import(decl) if !previous.iter().all(is_header) => Some(decl.span),
+        _ => None,
+    }
+}
+
+/// Integer tokens are validated by the lexer, so parsing them cannot fail.
+fn parse_integer(literal: &str) -> BigInt {
+    let literal = literal.replace('_', "");
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x") => (16, &literal[2..]),
+        Some("0o") => (8, &literal[2..]),
+        Some("0b") => (2, &literal[2..]),
+        _ => (10, &literal[..]),
+    };
+    BigInt::parse_bytes(digits.as_bytes(), radix).unwrap()
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Parser<'a> {
     pub content: &'a str,
     pub tokens: Vec<lexer::Token<'a>>,
     pub current_span: ast::Span,
     pub current: usize,
-    pub oneline: bool,
+    pub fixities: HashMap<String, Fixity>,
+    /// Errors recovered from so far.
+    pub errors: Vec<error::Error>,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(content: &'a str) -> Parser<'a> {
+    pub fn new(file: FileId, content: &'a str) -> Parser<'a> {
+        let (tokens, errors) = lexer(file, content);
+        let tokens = layout::layout(content, tokens);
         Parser {
             content,
-            tokens: lexer(content),
-            current_span: ast::Span::new(0, 0, "".to_string()),
+            fixities: declared_fixities(&tokens),
+            tokens,
+            current_span: ast::Span::default(),
             current: 0,
-            oneline: false,
+            errors,
         }
     }
 
-    fn calculate_real_size(&self, index: usize) -> usize {
-        let mut size = 0;
-        for i in 0..index {
-            size += self.tokens[i].span.input.len();
-        }
-        size
+    /// The modules the file imports, with the spans of their names.
+    pub fn imports(&self) -> Vec<(String, ast::Span)> {
+        imported_modules(&self.tokens)
     }
 
     fn start_recording(&mut self) -> usize {
         self.current
     }
 
+    /// The span from the token at `start` to the last token consumed. Virtual
+    /// tokens are placed at the end of the token before them, so they never
+    /// extend a span.
     fn end_recording(&mut self, start: usize) -> ast::Span {
-        let end = self.calculate_real_size(self.current);
-        let start = self.calculate_real_size(start);
-        let input = self.content[start..end].to_string();
+        let first = self.tokens[start].span;
+        let end = match self.current.checked_sub(1) {
+            Some(last) => self.tokens[last].span.end.max(first.start),
+            None => first.start,
+        };
 
-        ast::Span::new(start, end, input)
+        ast::Span::new(first.file, first.start, end)
     }
 
     fn advance(&mut self) -> ParserResult<lexer::Token<'a>> {
@@ -75,17 +249,6 @@ impl<'a> Parser<'a> {
                 self.peek().span,
             ));
         }
-        if !self.oneline
-            && self.peek().kind == lexer::TokenKind::Newline
-            && !self.is_eof_with_whitespace()
-        {
-            // return Err(error::Error::new(
-            // error::ErrorKind::ExpectedNewline {
-            //     found: self.peek().span.clone(),
-            // },
-            // self.peek().span,
-            // ));
-        }
         self.current += 1;
         if self.is_eof() {
             return Err(error::Error::new(
@@ -93,40 +256,7 @@ impl<'a> Parser<'a> {
                 self.peek().span,
             ));
         }
-        let token = self.tokens[self.current].clone();
-
-        if token.kind.is_whitespace()
-            && (self.peek().kind != lexer::TokenKind::Newline || !self.oneline)
-        {
-            return self.advance();
-        }
-        Ok(token)
-    }
-
-    fn is_eof_with_whitespace(&self) -> bool {
-        let mut offset = 1;
-        while self.tokens.len() > self.current + offset
-            && self.tokens[self.current + offset].kind.is_whitespace()
-        {
-            offset += 1;
-        }
-
-        self.current + offset + 1 >= self.tokens.len()
-    }
-
-    fn expect(&mut self, token: Token<'a>) -> ParserResult<Token<'a>> {
-        let tok = self.advance()?;
-        if self.peek().kind == token.kind {
-            Ok(tok)
-        } else {
-            Err(error::Error::new(
-                error::ErrorKind::UnexpectedToken {
-                    expected: format!("`{}`", token.span.input),
-                    found: self.peek().span,
-                },
-                self.peek().span,
-            ))
-        }
+        Ok(self.tokens[self.current].clone())
     }
 
     fn expect_current(&mut self, token: Token<'a>) -> ParserResult<Token<'a>> {
@@ -135,7 +265,7 @@ impl<'a> Parser<'a> {
         } else {
             Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
-                    expected: format!("`{}`", token.span.input),
+                    expected: format!("{:?}", token.kind),
                     found: self.peek().span,
                 },
                 self.peek().span,
@@ -152,14 +282,6 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn past(&self) -> lexer::Token<'a> {
-        let mut n = 1;
-        while self.tokens[self.current - n].kind.is_whitespace() {
-            n += 1;
-        }
-        self.tokens[self.current - n].clone()
-    }
-
     fn expect_identifier(&mut self) -> ParserResult<ast::Identifier> {
         let peek = self.peek();
         match peek.kind {
@@ -171,7 +293,7 @@ impl<'a> Parser<'a> {
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "identifier".to_string(),
-                    found: peek.span.clone(),
+                    found: peek.span,
                 },
                 peek.span,
             )),
@@ -189,7 +311,7 @@ impl<'a> Parser<'a> {
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "pascal case identifier".to_string(),
-                    found: peek.span.clone(),
+                    found: peek.span,
                 },
                 peek.span,
             )),
@@ -212,7 +334,24 @@ impl<'a> Parser<'a> {
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "identifier".to_string(),
-                    found: peek.span.clone(),
+                    found: peek.span,
+                },
+                peek.span,
+            )),
+        }
+    }
+
+    fn expect_operator(&mut self) -> ParserResult<ast::Identifier> {
+        let peek = self.peek();
+        match peek.kind {
+            lexer::TokenKind::Operator(op) => {
+                self.advance()?;
+                Ok(ast::Identifier::new(op.to_string(), peek.span))
+            }
+            _ => Err(error::Error::new(
+                error::ErrorKind::UnexpectedToken {
+                    expected: "operator".to_string(),
+                    found: peek.span,
                 },
                 peek.span,
             )),
@@ -223,65 +362,325 @@ impl<'a> Parser<'a> {
         self.tokens[self.current].clone()
     }
 
-    fn back_up(&mut self) -> Option<Token> {
-        self.current -= 1;
-        if self.is_eof() {
-            return None;
-        }
-        let token = self.tokens[self.current].clone();
+    /// The significant token following the current one.
+    fn lookahead(&self) -> lexer::TokenKind<'a> {
+        self.tokens
+            .get(self.current + 1)
+            .map_or(lexer::TokenKind::Eof, |token| token.kind)
+    }
 
-        if token.kind.is_whitespace() {
-            return self.back_up();
-        }
-        Some(token)
+    /// Whether the current token is a minus sign negating a number literal.
+    fn at_negative_number(&self) -> bool {
+        self.peek().kind == lexer::TokenKind::Sub
+            && matches!(
+                self.lookahead(),
+                lexer::TokenKind::Integer(_) | lexer::TokenKind::Float(_)
+            )
     }
 
     fn is_eof(&self) -> bool {
         self.current >= self.tokens.len()
     }
 
-    pub fn parse(&mut self) -> ParserResult<ast::Program<ast::Span>> {
+    /// Parses as much of the program as possible. A statement that fails to
+    /// parse is skipped up to the next line starting at column 0, and a
+    /// malformed group or bind body is replaced with an error node, so the
+    /// program can still be analyzed. Every syntax error is returned.
+    pub fn parse(&mut self) -> (ast::Program<ast::Span>, Vec<error::Error>) {
         let mut statements = Vec::new();
-        while !self.is_eof() && self.peek().kind != lexer::TokenKind::Eof {
-            statements.push(self.parse_statement()?);
+        loop {
+            while !self.is_eof() && self.peek().kind == lexer::TokenKind::StatementEnd {
+                self.current += 1;
+            }
+            if self.is_eof() || self.peek().kind == lexer::TokenKind::Eof {
+                break;
+            }
+            let (start, reported) = (self.current, self.errors.len());
+            match self.parse_statement() {
+                Ok(statement) => {
+                    if let Some(span) = misplaced_header(&statements, &statement) {
+                        self.errors.push(error::Error::new(
+                            error::ErrorKind::MisplacedHeader { found: span },
+                            span,
+                        ));
+                    }
+                    statements.push(statement)
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                }
+            }
+            // A statement containing text the lexer rejected fails because
+            // of it, which has already been reported.
+            if self.tokens[start..self.current]
+                .iter()
+                .any(|token| token.kind == lexer::TokenKind::Error)
+            {
+                self.errors.truncate(reported);
+            }
         }
-        Ok(ast::Program::new(statements))
+        // Lexical errors were found first, report everything in source order.
+        self.errors.sort_by_key(|e| e.span.start);
+        (
+            ast::Program::new(statements),
+            std::mem::take(&mut self.errors),
+        )
+    }
+
+    /// Skips to the end of the current statement.
+    fn synchronize(&mut self) {
+        while !self.at_statement_end(self.current) {
+            self.current += 1;
+        }
+    }
+
+    fn at_statement_end(&self, index: usize) -> bool {
+        match self.tokens.get(index).map(|token| token.kind) {
+            None | Some(lexer::TokenKind::Eof | lexer::TokenKind::StatementEnd) => true,
+            Some(_) => false,
+        }
+    }
+
+    /// The index of the token closing the parenthesis or bracket at `index`,
+    /// if it is in the same statement.
+    fn closing_token(&self, index: usize) -> Option<usize> {
+        let mut depth = 0;
+        for i in index..self.tokens.len() {
+            match self.tokens[i].kind {
+                lexer::TokenKind::LParen | lexer::TokenKind::LBracket | lexer::TokenKind::LBrace => {
+                    depth += 1
+                }
+                lexer::TokenKind::RParen | lexer::TokenKind::RBracket | lexer::TokenKind::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ if self.at_statement_end(i) => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Recovers from an error inside the group opened at token `index` by
+    /// skipping past its closing token, the whole group becoming an error
+    /// node. Errors going past the end of the statement are left to the
+    /// caller.
+    fn recover_group(
+        &mut self,
+        result: ParserResult<ast::ParsedExpr>,
+        index: usize,
+    ) -> ParserResult<ast::ParsedExpr> {
+        let error = match result {
+            Ok(expr) => return Ok(expr),
+            Err(error) => error,
+        };
+        let Some(close) = self.closing_token(index) else {
+            return Err(error);
+        };
+        self.errors.push(error);
+        self.current = close;
+        self.advance()?;
+        Ok(ast::ParsedExpr::Error {
+            ann: self.end_recording(index),
+        })
     }
 
+    /// Parses a statement, which has to end with its line: the next line
+    /// starting at column 0 begins another statement.
     fn parse_statement(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
-        self.oneline = false;
         let res = match self.peek().kind {
-            lexer::TokenKind::Identifier(_) => self.parse_stmt_identifier(),
+            lexer::TokenKind::Identifier(_) | lexer::TokenKind::LParen => {
+                self.parse_stmt_identifier()
+            }
             lexer::TokenKind::Type => self.parse_type_decl(),
+            lexer::TokenKind::Class => self.parse_class_decl(),
+            lexer::TokenKind::Instance => self.parse_instance_decl(),
+            lexer::TokenKind::Module => self.parse_module_decl(),
+            lexer::TokenKind::Import => self.parse_import_decl(),
+            lexer::TokenKind::Infixl | lexer::TokenKind::Infixr | lexer::TokenKind::Infix => {
+                self.parse_fixity_decl()
+            }
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "statement".to_string(),
-                    found: self.peek().span.clone(),
+                    found: self.peek().span,
                 },
-                self.peek().span.clone(),
+                self.peek().span,
             )),
         }?;
-        self.match_token(lexer::TokenKind::Newline)?;
 
+        if !self.at_statement_end(self.current) {
+            let found = self.peek().span;
+            self.errors.push(error::Error::new(
+                error::ErrorKind::ExpectedNewline {
+                    found,
+                },
+                found,
+            ));
+            self.synchronize();
+        }
         Ok(res)
     }
 
     fn parse_stmt_identifier(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
-        self.oneline = true;
         let index = self.start_recording();
-        let id = self.expect_identifier()?;
+        let id = self.parse_bind_name()?;
         if self.peek().kind == lexer::TokenKind::DoubleCollon {
             self.advance()?;
-            let ty = self.parse_type()?;
+            let ty = self.parse_signature_type()?;
             Ok(ast::Statement::TypeAssign(ast::TypeAssign::new(
                 id,
                 ty,
                 self.end_recording(index),
             )))
         } else {
-            self.back_up();
-            Ok(ast::Statement::Bind(self.parse_bind()?))
+            let (name, args) = self.parse_bind_head(id)?;
+            let expr = self.parse_body();
+            Ok(ast::Statement::Bind(ast::Bind::new(
+                name,
+                args,
+                expr,
+                self.end_recording(index),
+            )))
+        }
+    }
+
+    /// Parses the body of a top-level bind. A malformed body becomes an error
+    /// node, so that the name stays defined for the later passes.
+    fn parse_body(&mut self) -> ast::ParsedExpr {
+        let index = self.start_recording();
+        self.parse_clause_body().unwrap_or_else(|e| {
+            self.errors.push(e);
+            self.synchronize();
+            ast::ParsedExpr::Error {
+                ann: self.end_recording(index),
+            }
+        })
+    }
+
+    fn parse_fixity_decl(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
+        let index = self.start_recording();
+        let assoc = match self.peek().kind {
+            lexer::TokenKind::Infixl => Assoc::Left,
+            lexer::TokenKind::Infixr => Assoc::Right,
+            _ => Assoc::None,
+        };
+        self.advance()?;
+
+        let peek = self.peek();
+        let precedence = match peek.kind {
+            lexer::TokenKind::Integer(digits) => match digits.parse::<u8>() {
+                Ok(precedence @ 0..=9) => precedence,
+                _ => {
+                    return Err(error::Error::new(
+                        error::ErrorKind::InvalidPrecedence {
+                            found: peek.span,
+                        },
+                        peek.span,
+                    ))
+                }
+            },
+            _ => {
+                return Err(error::Error::new(
+                    error::ErrorKind::UnexpectedToken {
+                        expected: "precedence".to_string(),
+                        found: peek.span,
+                    },
+                    peek.span,
+                ))
+            }
+        };
+        self.advance()?;
+
+        let mut operators = Vec::new();
+        loop {
+            match self.parse_infix_name()? {
+                Some(op) => operators.push(op),
+                None => {
+                    return Err(error::Error::new(
+                        error::ErrorKind::UnexpectedToken {
+                            expected: "operator".to_string(),
+                            found: self.peek().span,
+                        },
+                        self.peek().span,
+                    ))
+                }
+            }
+            if !self.match_token(lexer::TokenKind::Comma)? {
+                break;
+            }
+        }
+
+        Ok(ast::Statement::Fixity(FixityDecl::new(
+            Fixity { precedence, assoc },
+            operators,
+            self.end_recording(index),
+        )))
+    }
+
+    /// `module Name (items)`, the list being optional.
+    fn parse_module_decl(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
+        let index = self.start_recording();
+        self.advance()?;
+        let name = self.expect_pc_identifier()?;
+        let exports = self.parse_items()?;
+        Ok(ast::Statement::Module(ast::ModuleDecl {
+            name,
+            exports,
+            span: self.end_recording(index),
+        }))
+    }
+
+    /// `import [qualified] Name [as Alias] [(items)]`. `qualified` and `as`
+    /// are only keywords here.
+    fn parse_import_decl(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
+        let index = self.start_recording();
+        self.advance()?;
+        let qualified = self.match_token(lexer::TokenKind::Identifier("qualified"))?;
+        let module = self.expect_pc_identifier()?;
+        let alias = if self.match_token(lexer::TokenKind::Identifier("as"))? {
+            Some(self.expect_pc_identifier()?)
+        } else {
+            None
+        };
+        let items = self.parse_items()?;
+        Ok(ast::Statement::Import(ast::ImportDecl {
+            module,
+            qualified,
+            alias,
+            items,
+            span: self.end_recording(index),
+        }))
+    }
+
+    /// An optional list of exported or imported names between parentheses:
+    /// functions, operators between parentheses, types and classes.
+    fn parse_items(&mut self) -> ParserResult<Option<Vec<ast::Identifier>>> {
+        if !self.match_token(lexer::TokenKind::LParen)? {
+            return Ok(None);
+        }
+        let mut items = Vec::new();
+        if self.match_token(lexer::TokenKind::RParen)? {
+            return Ok(Some(items));
+        }
+        loop {
+            let item = if self.match_token(lexer::TokenKind::LParen)? {
+                let op = self.expect_operator()?;
+                self.expect_current(token![rparen])?;
+                op
+            } else {
+                self.expect_any_identifier()?
+            };
+            items.push(item);
+            if !self.match_token(lexer::TokenKind::Comma)? {
+                break;
+            }
         }
+        self.expect_current(token![rparen])?;
+        Ok(Some(items))
     }
 
     fn parse_type_decl(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
@@ -315,186 +714,518 @@ impl<'a> Parser<'a> {
     fn parse_variant(&mut self) -> ParserResult<ast::Variant> {
         let index = self.start_recording();
         let id = self.expect_pc_identifier()?;
-        let mut ty = Vec::new();
-        let mut current_ty;
-        loop {
-            current_ty = self.parse_type();
-            if current_ty.is_ok() {
-                ty.push(current_ty.unwrap());
-            } else {
-                break;
+        if self.match_token(lexer::TokenKind::LBrace)? {
+            let mut fields = Vec::new();
+            while !self.match_token(lexer::TokenKind::RBrace)? {
+                if !fields.is_empty() {
+                    self.expect_current(token![,])?;
+                }
+                let name = self.expect_identifier()?;
+                self.expect_current(token![::])?;
+                fields.push((name, self.parse_type()?));
             }
+            return Ok(ast::Variant::record(id, fields, self.end_recording(index)));
+        }
+        let mut ty = Vec::new();
+        while self.peek().kind.starts_type_atom() {
+            ty.push(self.parse_type_atom()?);
         }
         Ok(ast::Variant::new(id, ty, self.end_recording(index)))
     }
 
-    fn parse_bind(&mut self) -> ParserResult<ast::Bind<ast::Span>> {
+    /// `class Eq a => Ord a where ...`, followed by a block of signatures and
+    /// default definitions.
+    fn parse_class_decl(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
         let index = self.start_recording();
-        let id = self.expect_identifier()?;
-        let mut args = Vec::new();
-        while !self.match_token(lexer::TokenKind::Assign)? {
-            let arg = self.parse_pattern()?;
-            args.push(arg);
+        self.advance()?;
+        let (context, head) = self.parse_context()?;
+        let head = self.constraint(head)?;
+        let mut signatures = Vec::new();
+        let mut defaults = Vec::new();
+        if self.match_token(lexer::TokenKind::Where)? {
+            for member in self.parse_block(Self::parse_class_member)? {
+                match member {
+                    ast::Statement::TypeAssign(signature) => signatures.push(signature),
+                    ast::Statement::Bind(bind) => defaults.push(bind),
+                    _ => unreachable!(),
+                }
+            }
         }
-        let expr = self.parse_expr()?;
-        Ok(ast::Bind::new(id, args, expr, self.end_recording(index)))
+
+        Ok(ast::Statement::Class(ast::ClassDecl {
+            context,
+            name: head.class,
+            var: head.var,
+            signatures,
+            defaults,
+            span: self.end_recording(index),
+        }))
     }
 
-    fn parse_expr(&mut self) -> ParserResult<ast::ParsedExpr> {
+    /// `instance Eq a => Eq (Maybe a) where ...`, followed by a block of
+    /// method definitions.
+    fn parse_instance_decl(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
         let index = self.start_recording();
-        let mut lhs = self.parse_factor()?;
-        while self.match_token(lexer::TokenKind::Add)? || self.match_token(lexer::TokenKind::Sub)? {
-            let op = match self.past().kind {
-                lexer::TokenKind::Add => ast::BinOp::Add,
-                lexer::TokenKind::Sub => ast::BinOp::Sub,
-                e => panic!("Unexpected token: {:?}", e),
-            };
-            let rhs = self.parse_factor()?;
-            lhs = ast::ParsedExpr::BinOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-                ann: self.end_recording(index),
-            };
+        self.advance()?;
+        let (context, head) = self.parse_context()?;
+        let (class, ty) = match head {
+            ast::Type::App(class, mut args, _)
+                if args.len() == 1 && class.name.starts_with(char::is_uppercase) =>
+            {
+                (class, args.pop().unwrap())
+            }
+            head => {
+                return Err(error::Error::new(
+                    error::ErrorKind::UnexpectedToken {
+                        expected: "class and type".to_string(),
+                        found: *head.get_span(),
+                    },
+                    *head.get_span(),
+                ))
+            }
+        };
+        let binds = if self.match_token(lexer::TokenKind::Where)? {
+            self.parse_block(Self::parse_method)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(ast::Statement::Instance(ast::InstanceDecl {
+            context,
+            class,
+            ty,
+            binds,
+            span: self.end_recording(index),
+        }))
+    }
+
+    /// A type preceded by an optional context, `C a =>` or `(C a, D b) =>`.
+    fn parse_context(&mut self) -> ParserResult<(Vec<ast::Constraint>, ast::Type)> {
+        let ty = self.parse_type()?;
+        if !self.match_token(lexer::TokenKind::FatArrow)? {
+            return Ok((Vec::new(), ty));
         }
+        let context = match ty {
+            ast::Type::Tuple(items, _) => items
+                .into_iter()
+                .map(|item| self.constraint(item))
+                .collect::<ParserResult<_>>()?,
+            ty => vec![self.constraint(ty)?],
+        };
+        Ok((context, self.parse_type()?))
+    }
 
-        Ok(lhs)
+    /// Reads `C a`, parsed as a type, as a constraint.
+    fn constraint(&self, ty: ast::Type) -> ParserResult<ast::Constraint> {
+        match ty {
+            ast::Type::App(class, mut args, span)
+                if args.len() == 1 && class.name.starts_with(char::is_uppercase) =>
+            {
+                match args.pop() {
+                    Some(ast::Type::Generic(var)) => Ok(ast::Constraint::new(class, var, span)),
+                    Some(arg) => Err(error::Error::new(
+                        error::ErrorKind::UnexpectedToken {
+                            expected: "type variable".to_string(),
+                            found: *arg.get_span(),
+                        },
+                        *arg.get_span(),
+                    )),
+                    None => unreachable!(),
+                }
+            }
+            ty => Err(error::Error::new(
+                error::ErrorKind::UnexpectedToken {
+                    expected: "constraint".to_string(),
+                    found: *ty.get_span(),
+                },
+                *ty.get_span(),
+            )),
+        }
     }
 
-    fn parse_factor(&mut self) -> ParserResult<ast::ParsedExpr> {
+    /// The type of a signature, which can be qualified by a context.
+    fn parse_signature_type(&mut self) -> ParserResult<ast::Type> {
         let index = self.start_recording();
-        let mut lhs = self.parse_or_and()?;
-
-        while self.match_token(lexer::TokenKind::Mul)?
-            || self.match_token(lexer::TokenKind::Div)?
-            || self.match_token(lexer::TokenKind::Mod)?
-        {
-            let op = match self.past().kind {
-                lexer::TokenKind::Mul => BinOp::Mul,
-                lexer::TokenKind::Div => BinOp::Div,
-                lexer::TokenKind::Mod => BinOp::Mod,
-                _ => unreachable!(),
-            };
-            let rhs = self.parse_or_and()?;
-            lhs = ast::ParsedExpr::BinOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-                ann: self.end_recording(index),
-            };
+        let (context, ty) = self.parse_context()?;
+        if context.is_empty() {
+            return Ok(ty);
         }
+        Ok(ast::Type::Qualified(context, Box::new(ty), self.end_recording(index)))
+    }
 
-        Ok(lhs)
+    /// A member of a class: the signature of a method, or its default
+    /// definition.
+    fn parse_class_member(&mut self) -> ParserResult<ast::Statement<ast::Span>> {
+        let index = self.start_recording();
+        let id = self.parse_method_name()?;
+        if self.match_token(lexer::TokenKind::DoubleCollon)? {
+            let ty = self.parse_signature_type()?;
+            return Ok(ast::Statement::TypeAssign(ast::TypeAssign::new(
+                id,
+                ty,
+                self.end_recording(index),
+            )));
+        }
+        self.parse_method_clause(index, id).map(ast::Statement::Bind)
     }
 
-    fn parse_or_and(&mut self) -> ParserResult<ast::ParsedExpr> {
+    fn parse_method(&mut self) -> ParserResult<ast::Bind<ast::Span>> {
         let index = self.start_recording();
-        let mut lhs = self.parse_cmp()?;
+        let id = self.parse_method_name()?;
+        self.parse_method_clause(index, id)
+    }
 
-        while self.match_token(lexer::TokenKind::Or)? || self.match_token(lexer::TokenKind::And)? {
-            let op = match self.past().kind {
-                lexer::TokenKind::Or => ast::BinOp::Or,
-                lexer::TokenKind::And => ast::BinOp::And,
-                _ => unreachable!(),
-            };
-            let rhs = self.parse_cmp()?;
-            lhs = ast::ParsedExpr::BinOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-                ann: self.end_recording(index),
-            };
+    /// The name of a method. Unlike other binds, methods can be named after
+    /// a builtin operator, as in `(==) a b = ...`.
+    fn parse_method_name(&mut self) -> ParserResult<ast::Identifier> {
+        if self.peek().kind == lexer::TokenKind::LParen && binop(&self.lookahead()).is_some() {
+            self.advance()?;
+            let op = self.builtin_operator()?;
+            self.expect_current(token![rparen])?;
+            return Ok(op);
         }
+        self.parse_bind_name()
+    }
 
-        Ok(lhs)
+    /// A clause of a method starting at `index`, given its first name. It
+    /// can also be written infix with a builtin operator: `a == b = ...`.
+    fn parse_method_clause(
+        &mut self,
+        index: usize,
+        id: ast::Identifier,
+    ) -> ParserResult<ast::Bind<ast::Span>> {
+        let (name, args) = if binop(&self.peek().kind).is_some() {
+            let op = self.builtin_operator()?;
+            let rhs = self.parse_pattern()?;
+            (op, vec![ast::Pattern::Variable(id), rhs])
+        } else {
+            self.parse_bind_head(id)?
+        };
+        let expr = self.parse_clause_body()?;
+        Ok(ast::Bind::new(name, args, expr, self.end_recording(index)))
     }
 
-    fn parse_cmp(&mut self) -> ParserResult<ast::ParsedExpr> {
+    /// Consumes a builtin operator, as the name of a method.
+    fn builtin_operator(&mut self) -> ParserResult<ast::Identifier> {
+        let span = self.peek().span;
+        self.advance()?;
+        Ok(ast::Identifier::new(
+            self.content[span.start..span.end].to_string(),
+            span,
+        ))
+    }
+
+    fn parse_bind(&mut self) -> ParserResult<ast::Bind<ast::Span>> {
         let index = self.start_recording();
-        let mut lhs = self.parse_exp()?;
-
-        while self.match_token(lexer::TokenKind::Eq)?
-            || self.match_token(lexer::TokenKind::Neq)?
-            || self.match_token(lexer::TokenKind::Lt)?
-            || self.match_token(lexer::TokenKind::Gt)?
-            || self.match_token(lexer::TokenKind::Lte)?
-            || self.match_token(lexer::TokenKind::Gte)?
-        {
-            let op = match self.past().kind {
-                lexer::TokenKind::Eq => BinOp::Eq,
-                lexer::TokenKind::Neq => BinOp::Ineq,
-                lexer::TokenKind::Lt => BinOp::LessThan,
-                lexer::TokenKind::Gt => BinOp::GreaterThan,
-                lexer::TokenKind::Lte => BinOp::LessThanOrEq,
-                lexer::TokenKind::Gte => BinOp::GreaterThanOrEq,
-                _ => unreachable!(),
-            };
-            let rhs = self.parse_exp()?;
-            lhs = ast::ParsedExpr::BinOp {
-                op,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-                ann: self.end_recording(index),
-            };
-        }
+        let id = self.parse_bind_name()?;
+        let (name, args) = self.parse_bind_head(id)?;
+        let expr = self.parse_clause_body()?;
+        Ok(ast::Bind::new(name, args, expr, self.end_recording(index)))
+    }
 
-        Ok(lhs)
+    /// The name of a bind or signature: an identifier, or an operator between
+    /// parentheses (`(|>) x f = f x`).
+    fn parse_bind_name(&mut self) -> ParserResult<ast::Identifier> {
+        if self.match_token(lexer::TokenKind::LParen)? {
+            let op = self.expect_operator()?;
+            self.expect_current(token![rparen])?;
+            Ok(op)
+        } else {
+            self.expect_identifier()
+        }
     }
 
-    fn parse_exp(&mut self) -> ParserResult<ast::ParsedExpr> {
-        let index = self.start_recording();
-        let mut lhs = self.parse_list_cons()?;
-
-        while self.match_token(lexer::TokenKind::Exp)? {
-            let rhs = self.parse_list_cons()?;
-            lhs = ast::ParsedExpr::BinOp {
-                op: BinOp::Exp,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-                ann: self.end_recording(index),
-            };
+    /// Parses the arguments of a bind up to `=`, given the identifier it
+    /// starts with. Returns the name of the bind and its arguments.
+    fn parse_bind_head(
+        &mut self,
+        id: ast::Identifier,
+    ) -> ParserResult<(ast::Identifier, Vec<ast::Pattern>)> {
+        // `x |> f = f x` defines `|>`.
+        if let Some(op) = self.parse_infix_name()? {
+            let lhs = ast::Pattern::Variable(id);
+            let rhs = self.parse_pattern()?;
+            return Ok((op, vec![lhs, rhs]));
         }
 
-        Ok(lhs)
+        let mut args = Vec::new();
+        while !matches!(
+            self.peek().kind,
+            lexer::TokenKind::Assign | lexer::TokenKind::Pipe
+        ) {
+            let arg = self.parse_pattern_atom()?;
+            args.push(arg);
+        }
+        Ok((id, args))
     }
 
-    fn parse_list_cons(&mut self) -> ParserResult<ast::ParsedExpr> {
+    /// The body of a bind, followed by an optional `where` block. The binds
+    /// of the block scope over the guards and the body, which become the
+    /// body of a `let`.
+    fn parse_clause_body(&mut self) -> ParserResult<ast::ParsedExpr> {
         let index = self.start_recording();
-        let mut lhs = self.parse_annotation()?;
-        while self.match_token(lexer::TokenKind::DoubleCollon)? {
-            let rhs = self.parse_annotation()?;
-            lhs = ast::ParsedExpr::BinOp {
-                op: BinOp::ListCons,
-                lhs: Box::new(lhs),
-                rhs: Box::new(rhs),
-                ann: self.end_recording(index),
-            };
+        let expr = self.parse_guarded(token![=])?;
+        if !self.match_token(lexer::TokenKind::Where)? {
+            return Ok(expr);
         }
+        let binds = self.parse_block(Self::parse_bind)?;
+        Ok(ast::ParsedExpr::Let {
+            binds,
+            ret: Box::new(expr),
+            ann: self.end_recording(index),
+        })
+    }
 
-        Ok(lhs)
+    /// The body of a clause or an arm, following its patterns: `sep` and an
+    /// expression, or guards `| cond sep expr`, `sep` being `=` or `->`.
+    /// Guards go on until there is no more `|`, so an arm with guards ends
+    /// the arms written on its line.
+    fn parse_guarded(&mut self, sep: lexer::Token<'a>) -> ParserResult<ast::ParsedExpr> {
+        if self.peek().kind != lexer::TokenKind::Pipe {
+            self.expect_current(sep)?;
+            return self.parse_expr();
+        }
+        let index = self.start_recording();
+        let mut guards = Vec::new();
+        while self.match_token(lexer::TokenKind::Pipe)? {
+            let cond = self.parse_expr()?;
+            self.expect_current(sep.clone())?;
+            guards.push((cond, self.parse_expr()?));
+        }
+        Ok(ast::ParsedExpr::Guards {
+            guards,
+            ann: self.end_recording(index),
+        })
     }
 
-    fn parse_annotation(&mut self) -> ParserResult<ast::ParsedExpr> {
+    fn parse_expr(&mut self) -> ParserResult<ast::ParsedExpr> {
         let index = self.start_recording();
-        let mut lhs = self.parse_primary()?;
+        let expr = self.parse_binary(0, None)?;
         if self.match_token(lexer::TokenKind::DoubleCollon)? {
             let ty = self.parse_type()?;
-            lhs = ast::ParsedExpr::Ann {
-                expr: Box::new(lhs),
+            return Ok(ast::ParsedExpr::Ann {
+                expr: Box::new(expr),
                 ann: (self.end_recording(index), ty),
+            });
+        }
+
+        Ok(expr)
+    }
+
+    /// Precedence climbing over the builtin operators of `FIXITIES` and the
+    /// user-defined ones. Only operators binding at least as tightly as
+    /// `min_precedence` are consumed; `previous` is the operator whose right
+    /// operand is being parsed, if any.
+    fn parse_binary(
+        &mut self,
+        min_precedence: u8,
+        mut previous: Option<(Fixity, ast::Span)>,
+    ) -> ParserResult<ast::ParsedExpr> {
+        let index = self.start_recording();
+        let mut lhs = match self.peek().kind {
+            lexer::TokenKind::Sub | lexer::TokenKind::Not => self.parse_prefix()?,
+            _ => self.parse_primary()?,
+        };
+
+        while let Some((op, fixity)) = self.peek_operator() {
+            if fixity.precedence < min_precedence {
+                break;
+            }
+            let span = match &op {
+                InfixOp::Builtin(_) => self.peek().span,
+                InfixOp::Named(id) => id.span,
             };
+            if let Some((prev, prev_span)) = &previous {
+                if prev.precedence == fixity.precedence
+                    && (fixity.assoc == Assoc::None || fixity.assoc != prev.assoc)
+                {
+                    let (first, second) = (*prev_span, span);
+                    let kind = if fixity.assoc == Assoc::None || prev.assoc == Assoc::None {
+                        error::ErrorKind::NonAssociative { first, second }
+                    } else {
+                        error::ErrorKind::MixedAssociativity { first, second }
+                    };
+                    return Err(error::Error::new(kind, span));
+                }
+            }
+
+            match op {
+                InfixOp::Builtin(_) => {
+                    self.advance()?;
+                }
+                InfixOp::Named(_) => {
+                    self.parse_infix_name()?;
+                }
+            }
+            let next = match fixity.assoc {
+                Assoc::Right => fixity.precedence,
+                Assoc::Left | Assoc::None => fixity.precedence + 1,
+            };
+            let rhs = self.parse_binary(next, Some((fixity, span)))?;
+            lhs = match op {
+                InfixOp::Builtin(op) => ast::ParsedExpr::BinOp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                    ann: self.end_recording(index),
+                },
+                InfixOp::Named(id) => ast::ParsedExpr::App(App::new(
+                    ast::ParsedExpr::Identifier { id },
+                    vec![lhs, rhs],
+                    self.end_recording(index),
+                )),
+            };
+            previous = Some((fixity, span));
         }
 
         Ok(lhs)
     }
 
+    /// `-x` and `not x`. The operand takes the operators binding tighter than
+    /// the prefix operator: `-` has the precedence of binary `-`, so `-x ^ 2`
+    /// is `-(x ^ 2)`, and `not` sits between comparisons and `&&`, so
+    /// `not x == y && p` is `(not (x == y)) && p`. A negated number literal
+    /// is folded into the literal.
+    fn parse_prefix(&mut self) -> ParserResult<ast::ParsedExpr> {
+        let index = self.start_recording();
+        let (op, precedence) = match self.peek().kind {
+            lexer::TokenKind::Sub => (UnOp::Neg, fixity(BinOp::Sub).precedence),
+            _ => (UnOp::Not, fixity(BinOp::And).precedence),
+        };
+        self.advance()?;
+        let expr = self.parse_binary(precedence + 1, None)?;
+        let ann = self.end_recording(index);
+        Ok(match (op, expr) {
+            (UnOp::Neg, ast::ParsedExpr::Literal(Literal { lit: LiteralKind::Integer(i), .. })) => {
+                ast::ParsedExpr::Literal(Literal::new(LiteralKind::Integer(-i), ann))
+            }
+            (UnOp::Neg, ast::ParsedExpr::Literal(Literal { lit: LiteralKind::Float(f), .. })) => {
+                ast::ParsedExpr::Literal(Literal::new(LiteralKind::Float(-f), ann))
+            }
+            (op, expr) => ast::ParsedExpr::UnOp {
+                op,
+                expr: Box::new(expr),
+                ann,
+            },
+        })
+    }
+
+    /// The operator at the current token, without consuming it.
+    fn peek_operator(&self) -> Option<(InfixOp, Fixity)> {
+        let peek = self.peek();
+        if let Some(op) = binop(&peek.kind) {
+            return Some((InfixOp::Builtin(op), fixity(op)));
+        }
+
+        let id = match peek.kind {
+            lexer::TokenKind::Operator(op) => ast::Identifier::new(op.to_string(), peek.span),
+            lexer::TokenKind::Backtick => match &self.tokens[self.current + 1..] {
+                [Token {
+                    kind: lexer::TokenKind::Identifier(name),
+                    span,
+                }, Token {
+                    kind: lexer::TokenKind::Backtick,
+                    ..
+                }, ..] => ast::Identifier::new(name.to_string(), *span),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let fixity = self
+            .fixities
+            .get(ast::unqualified(&id.name))
+            .copied()
+            .unwrap_or(DEFAULT_FIXITY);
+        Some((InfixOp::Named(id), fixity))
+    }
+
+    /// Consumes a user-defined operator or a function name between backticks.
+    fn parse_infix_name(&mut self) -> ParserResult<Option<ast::Identifier>> {
+        match self.peek().kind {
+            lexer::TokenKind::Operator(_) => Ok(Some(self.expect_operator()?)),
+            lexer::TokenKind::Backtick => {
+                self.advance()?;
+                let id = self.expect_identifier()?;
+                self.expect_current(token![backtick])?;
+                Ok(Some(id))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// `(op)`: an operator as a function of its two operands, desugared to
+    /// `\_lhs _rhs -> _lhs op _rhs`. The parameter names cannot be written in
+    /// Risk, so they never shadow a user variable.
+    fn parse_section(&mut self, index: usize) -> ParserResult<Option<ast::ParsedExpr>> {
+        let peek = self.peek();
+        let is_operator =
+            binop(&peek.kind).is_some() || matches!(peek.kind, lexer::TokenKind::Operator(_));
+        if !is_operator || self.lookahead() != lexer::TokenKind::RParen {
+            return Ok(None);
+        }
+        self.advance()?;
+        self.advance()?;
+
+        let ann = self.end_recording(index);
+        // The parameters must not share the span of the operator, by which
+        // the type checker records its instance.
+        let param = |name: &str, at: usize| {
+            ast::Identifier::new(
+                name.to_string(),
+                ast::Span {
+                    start: at,
+                    end: at,
+                    ..peek.span
+                },
+            )
+        };
+        let (lhs, rhs) = (param("_lhs", peek.span.start), param("_rhs", peek.span.end));
+        let operand = |id: &ast::Identifier| ast::ParsedExpr::Identifier { id: id.clone() };
+        let ret = match (binop(&peek.kind), peek.kind) {
+            (Some(op), _) => ast::ParsedExpr::BinOp {
+                op,
+                lhs: Box::new(operand(&lhs)),
+                rhs: Box::new(operand(&rhs)),
+                ann,
+            },
+            (None, lexer::TokenKind::Operator(op)) => ast::ParsedExpr::App(App::new(
+                ast::ParsedExpr::Identifier {
+                    id: ast::Identifier::new(op.to_string(), peek.span),
+                },
+                vec![operand(&lhs), operand(&rhs)],
+                ann,
+            )),
+            _ => unreachable!(),
+        };
+
+        Ok(Some(ast::ParsedExpr::Lambda {
+            args: vec![ast::Pattern::Variable(lhs), ast::Pattern::Variable(rhs)],
+            ret: Box::new(ret),
+            ann,
+        }))
+    }
+
+    /// Decodes the body of a string or char literal spanning `span`. A
+    /// malformed escape is recorded rather than returned, so that parsing
+    /// goes on.
+    fn unescape(&mut self, body: &str, span: ast::Span) -> Option<String> {
+        let body_span = ast::Span::new(span.file, span.start + 1, span.end - 1);
+        match lexer::unescape(body, body_span) {
+            Ok(decoded) => Some(decoded),
+            Err(e) => {
+                self.errors.push(e);
+                None
+            }
+        }
+    }
+
     fn parse_literal(&mut self) -> ParserResult<Literal> {
         let index = self.start_recording();
         let peek = self.peek();
         match peek.kind {
             lexer::TokenKind::Integer(i) => {
                 self.advance()?;
-                Ok(Literal::new(LiteralKind::Integer(i), peek.span))
+                Ok(Literal::new(LiteralKind::Integer(parse_integer(i)), peek.span))
             }
             lexer::TokenKind::Float(f) => {
                 self.advance()?;
@@ -505,7 +1236,35 @@ impl<'a> Parser<'a> {
             }
             lexer::TokenKind::String(s) => {
                 self.advance()?;
-                Ok(Literal::new(LiteralKind::String(s.to_string()), peek.span))
+                let s = self.unescape(s, peek.span).unwrap_or_else(|| s.to_string());
+                Ok(Literal::new(LiteralKind::String(s), peek.span))
+            }
+            lexer::TokenKind::Char(c) => {
+                self.advance()?;
+                let decoded = self.unescape(c, peek.span).unwrap_or_default();
+                let mut chars = decoded.chars();
+                let c = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    // The malformed escape has already been reported.
+                    (None, _) => char::REPLACEMENT_CHARACTER,
+                    _ => {
+                        self.errors.push(error::Error::new(
+                            error::ErrorKind::InvalidChar { found: peek.span },
+                            peek.span,
+                        ));
+                        char::REPLACEMENT_CHARACTER
+                    }
+                };
+                Ok(Literal::new(LiteralKind::Char(c), peek.span))
+            }
+            lexer::TokenKind::Sub if self.at_negative_number() => {
+                self.advance()?;
+                let lit = match self.parse_literal()?.lit {
+                    LiteralKind::Integer(i) => LiteralKind::Integer(-i),
+                    LiteralKind::Float(f) => LiteralKind::Float(-f),
+                    _ => unreachable!(),
+                };
+                Ok(Literal::new(lit, self.end_recording(index)))
             }
             lexer::TokenKind::True => {
                 self.advance()?;
@@ -518,114 +1277,20 @@ impl<'a> Parser<'a> {
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "literal".to_string(),
-                    found: self.peek().span.clone(),
+                    found: self.peek().span,
                 },
-                self.peek().span.clone(),
+                self.peek().span,
             )),
         }
     }
 
     fn parse_primary(&mut self) -> ParserResult<ast::ParsedExpr> {
         let index = self.start_recording();
-        let peek = self.peek();
-        match peek.kind {
-            lexer::TokenKind::Integer(i) => {
-                self.advance()?;
-                Ok(ast::ParsedExpr::Literal(Literal::new(
-                    LiteralKind::Integer(i),
-                    peek.span,
-                )))
-            }
-            lexer::TokenKind::Float(f) => {
-                self.advance()?;
-                Ok(ast::ParsedExpr::Literal(Literal::new(
-                    LiteralKind::Float(f),
-                    peek.span,
-                )))
-            }
-            lexer::TokenKind::String(s) => {
-                self.advance()?;
-                let span = peek.span;
-                Ok(ast::ParsedExpr::Literal(Literal::new(
-                    LiteralKind::String(s.to_string()),
-                    span,
-                )))
-            }
-            lexer::TokenKind::True => {
-                self.advance()?;
-                Ok(ast::ParsedExpr::Literal(Literal::new(
-                    LiteralKind::Bool(Bool::True),
-                    peek.span,
-                )))
-            }
-            lexer::TokenKind::False => {
-                self.advance()?;
-                Ok(ast::ParsedExpr::Literal(Literal::new(
-                    LiteralKind::Bool(Bool::False),
-                    peek.span,
-                )))
-            }
-            lexer::TokenKind::Char(c) => {
-                self.advance()?;
-                Ok(ast::ParsedExpr::Literal(Literal::new(
-                    LiteralKind::Char(c.chars().nth(0).unwrap()),
-                    peek.span,
-                )))
-            }
-            n @ (lexer::TokenKind::Identifier(_) | lexer::TokenKind::PCIdentifier(_)) => {
-                let id = self.expect_any_identifier()?;
-                let cloned = self.clone();
-                let mut expr = self.parse_expr();
-                if expr.is_ok() {
-                    let mut exprs = vec![];
-                    while expr.is_ok() {
-                        exprs.push(expr.unwrap());
-
-                        expr = self.parse_expr();
-                    }
-
-                    return Ok(ast::ParsedExpr::App(App::new(
-                        id,
-                        exprs,
-                        self.end_recording(index),
-                    )));
-                } else {
-                    *self = cloned;
-                }
-
-                match n {
-                    lexer::TokenKind::Identifier(_) => Ok(ast::ParsedExpr::Identifier { id }),
-                    lexer::TokenKind::PCIdentifier(_) => Ok(ast::ParsedExpr::PCIdentifier { id }),
-                    _ => unreachable!(),
-                }
-            }
-            lexer::TokenKind::LParen => {
-                self.advance()?;
-                let expr = self.parse_expr()?;
-                if self.peek().kind == lexer::TokenKind::Comma {
-                    let mut exprs = vec![expr];
-                    while !self.match_token(TokenKind::RParen)? {
-                        self.expect_current(token![,])?;
-                        let expr = self.parse_expr()?;
-                        exprs.push(expr);
-                    }
-                    return Ok(ast::ParsedExpr::Tuple {
-                        list: exprs,
-                        ann: self.end_recording(index),
-                    });
-                }
-                self.expect_current(token![rparen])?;
-                Ok(expr)
-            }
+        match self.peek().kind {
             lexer::TokenKind::Let => {
                 self.advance()?;
-                let mut binds = Vec::new();
-                while !self.match_token(lexer::TokenKind::In)? {
-                    let bind = self.parse_bind()?;
-                    self.expect_current(token![;])?;
-                    binds.push(bind);
-                }
-
+                let binds = self.parse_block(Self::parse_bind)?;
+                self.expect_current(token![in])?;
                 let expr = self.parse_expr()?;
                 Ok(ast::ParsedExpr::Let {
                     binds,
@@ -648,25 +1313,10 @@ impl<'a> Parser<'a> {
                 })
             }
             lexer::TokenKind::Match => {
-                self.oneline = false;
                 self.advance()?;
                 let expr = self.parse_expr()?;
                 self.expect_current(token![with])?;
-                let mut arms = Vec::new();
-                self.expect_current(token![lparen])?;
-                let arm_pat = self.parse_pattern()?;
-                self.expect_current(token![->])?;
-                let arm_expr = Box::new(self.parse_expr()?);
-                self.expect_current(token![rparen])?;
-                arms.push((arm_pat, arm_expr));
-                while self.match_token(lexer::TokenKind::LParen)? {
-                    let pat = Box::new(self.parse_pattern()?);
-                    self.expect_current(token![->])?;
-                    let expr = Box::new(self.parse_expr()?);
-                    arms.push((*pat, expr));
-                    self.expect_current(token![rparen])?;
-                }
-                self.oneline = true;
+                let arms = self.parse_block(Self::parse_arm)?;
                 Ok(ast::ParsedExpr::Match {
                     referral: Box::new(expr),
                     cases: arms,
@@ -679,7 +1329,7 @@ impl<'a> Parser<'a> {
                 let mut pats = Vec::new();
 
                 while !self.match_token(lexer::TokenKind::Arrow)? {
-                    let pat = self.parse_pattern()?;
+                    let pat = self.parse_pattern_atom()?;
                     pats.push(pat);
                 }
 
@@ -691,95 +1341,270 @@ impl<'a> Parser<'a> {
                 })
             }
 
-            lexer::TokenKind::LBracket => {
+            // A literal is never applied, so `1 2` is two expressions.
+            n if n.is_literal() => self.parse_atom(),
+            _ => {
+                let head = self.parse_atom()?;
+                // Application binds tighter than any operator: the head and
+                // the arguments are atoms, anything else has to be
+                // parenthesized, as in `(\x -> x + 1) 2`.
+                let mut args = vec![];
+                while self.peek().kind.starts_atom() {
+                    args.push(self.parse_atom()?);
+                }
+                if args.is_empty() {
+                    return Ok(head);
+                }
+                Ok(ast::ParsedExpr::App(App::new(
+                    head,
+                    args,
+                    self.end_recording(index),
+                )))
+            }
+        }
+    }
+
+    /// Parses an expression that can be an argument of an application
+    /// without parentheses.
+    fn parse_atom(&mut self) -> ParserResult<ast::ParsedExpr> {
+        let index = self.start_recording();
+        let mut atom = self.parse_atom_primary()?;
+        // A record update binds tighter than application: `f r { x = 1 }`
+        // is `f (r { x = 1 })`.
+        while self.peek().kind == lexer::TokenKind::LBrace {
+            let fields = self.parse_fields(Self::parse_expr)?;
+            atom = ast::ParsedExpr::Update {
+                expr: Box::new(atom),
+                fields,
+                ann: self.end_recording(index),
+            };
+        }
+        Ok(atom)
+    }
+
+    fn parse_atom_primary(&mut self) -> ParserResult<ast::ParsedExpr> {
+        let index = self.start_recording();
+        let peek = self.peek();
+        match peek.kind {
+            n if n.is_literal() => Ok(ast::ParsedExpr::Literal(self.parse_literal()?)),
+            lexer::TokenKind::Error => {
                 self.advance()?;
-                let mut exprs = Vec::new();
-                if !self.match_token(lexer::TokenKind::RBracket)? {
-                    let first_expr = self.parse_expr();
-                    exprs.push(first_expr?);
-                    while !self.match_token(lexer::TokenKind::RBracket)? {
-                        self.expect_current(token![,])?;
-                        let expr = self.parse_expr();
-                        exprs.push(expr?);
-                    }
+                Ok(ast::ParsedExpr::Error { ann: peek.span })
+            }
+            lexer::TokenKind::Identifier(_) => Ok(ast::ParsedExpr::Identifier {
+                id: self.expect_identifier()?,
+            }),
+            lexer::TokenKind::PCIdentifier(_) => {
+                let id = self.expect_pc_identifier()?;
+                if self.peek().kind != lexer::TokenKind::LBrace {
+                    return Ok(ast::ParsedExpr::PCIdentifier { id });
                 }
-                Ok(ast::ParsedExpr::List {
-                    list: exprs,
+                let fields = self.parse_fields(Self::parse_expr)?;
+                Ok(ast::ParsedExpr::Record {
+                    id,
+                    fields,
                     ann: self.end_recording(index),
                 })
             }
+            lexer::TokenKind::LParen => {
+                let group = self.parse_parenthesized(index);
+                self.recover_group(group, index)
+            }
+            lexer::TokenKind::LBracket => {
+                let group = self.parse_list(index);
+                self.recover_group(group, index)
+            }
 
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "primary expression".to_string(),
-                    found: self.peek().span.clone(),
+                    found: self.peek().span,
                 },
-                self.peek().span.clone(),
+                self.peek().span,
             )),
         }
     }
 
-    fn parse_pattern(&mut self) -> ParserResult<ast::Pattern> {
-        let index = self.start_recording();
-        let pat = self.parse_pattern_primary()?;
-        if self.match_token(lexer::TokenKind::Colon)? {
-            let pat2 = match self.peek().kind {
-                TokenKind::PCIdentifier(_) => {
-                    let id = self.expect_pc_identifier()?;
-                    ast::Pattern::Id(id)
-                }
-                TokenKind::Identifier(_) => {
-                    let id = self.expect_identifier()?;
-                    ast::Pattern::Variable(id)
+    /// `{ field = item, ... }`, the fields of a record built, updated or
+    /// matched, `item` parsing what is given for each field.
+    fn parse_fields<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> ParserResult<T>,
+    ) -> ParserResult<Vec<(ast::Identifier, T)>> {
+        self.expect_current(token![lbrace])?;
+        let mut fields = Vec::new();
+        if self.match_token(lexer::TokenKind::RBrace)? {
+            return Ok(fields);
+        }
+        loop {
+            let name = self.expect_identifier()?;
+            self.expect_current(token![=])?;
+            fields.push((name, item(self)?));
+            if self.match_token(lexer::TokenKind::RBrace)? {
+                return Ok(fields);
+            }
+            self.expect_current(token![,])?;
+        }
+    }
+
+    /// Parses the items of a block opened by the layout rule. Items are
+    /// separated by new lines at the column of the block, or by `;`.
+    fn parse_block<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> ParserResult<T>,
+    ) -> ParserResult<Vec<T>> {
+        self.expect_current(token![block_start])?;
+        let mut items = Vec::new();
+        loop {
+            match self.peek().kind {
+                lexer::TokenKind::BlockEnd => {
+                    self.advance()?;
+                    return Ok(items);
                 }
-                _ => {
-                    return Err(error::Error::new(
-                        error::ErrorKind::UnexpectedToken {
-                            expected: "identifier".to_string(),
-                            found: self.peek().span.clone(),
-                        },
-                        self.peek().span.clone(),
-                    ))
+                lexer::TokenKind::BlockSep | lexer::TokenKind::Semicolon => {
+                    self.advance()?;
                 }
-            };
-            Ok(ast::Pattern::ListCons(
-                Box::new(pat),
-                Box::new(pat2),
-                self.end_recording(index),
-            ))
+                _ => items.push(item(self)?),
+            }
+        }
+    }
+
+    /// A match arm, `p -> e`. It may be preceded by `|`, or parenthesized as
+    /// in `(p -> e)` so that several arms fit on a line.
+    fn parse_arm(&mut self) -> ParserResult<(ast::Pattern, Box<ast::ParsedExpr>)> {
+        let index = self.start_recording();
+        let pat = if self.match_token(lexer::TokenKind::LParen)? {
+            // Either a whole arm between parentheses, `(p -> e)`, or a
+            // pattern starting with one, `(x, y) -> e`, which `->` tells
+            // apart once the first pattern is parsed.
+            let first = self.parse_pattern()?;
+            if matches!(
+                self.peek().kind,
+                lexer::TokenKind::Arrow | lexer::TokenKind::Pipe
+            ) {
+                let expr = self.parse_guarded(token![->])?;
+                self.expect_current(token![rparen])?;
+                return Ok((first, Box::new(expr)));
+            }
+            let group = self.parse_pattern_group(index, first)?;
+            self.parse_cons_tail(index, group)?
         } else {
-            Ok(pat)
+            self.match_token(lexer::TokenKind::Pipe)?;
+            self.parse_pattern()?
+        };
+        let expr = self.parse_guarded(token![->])?;
+        Ok((pat, Box::new(expr)))
+    }
+
+    /// A parenthesized expression, a tuple or an operator section.
+    fn parse_parenthesized(&mut self, index: usize) -> ParserResult<ast::ParsedExpr> {
+        self.advance()?;
+        if let Some(section) = self.parse_section(index)? {
+            return Ok(section);
+        }
+        let expr = self.parse_expr()?;
+        if self.peek().kind == lexer::TokenKind::Comma {
+            let mut exprs = vec![expr];
+            while !self.match_token(TokenKind::RParen)? {
+                self.expect_current(token![,])?;
+                let expr = self.parse_expr()?;
+                exprs.push(expr);
+            }
+            return Ok(ast::ParsedExpr::Tuple {
+                list: exprs,
+                ann: self.end_recording(index),
+            });
+        }
+        self.expect_current(token![rparen])?;
+        Ok(expr)
+    }
+
+    fn parse_list(&mut self, index: usize) -> ParserResult<ast::ParsedExpr> {
+        self.advance()?;
+        let mut exprs = Vec::new();
+        if !self.match_token(lexer::TokenKind::RBracket)? {
+            let first_expr = self.parse_expr();
+            exprs.push(first_expr?);
+            while !self.match_token(lexer::TokenKind::RBracket)? {
+                self.expect_current(token![,])?;
+                let expr = self.parse_expr();
+                exprs.push(expr?);
+            }
+        }
+        Ok(ast::ParsedExpr::List {
+            list: exprs,
+            ann: self.end_recording(index),
+        })
+    }
+
+    /// A full pattern: a constructor applied to its fields, or a pattern
+    /// atom, possibly consed onto another pattern with `:`.
+    fn parse_pattern(&mut self) -> ParserResult<ast::Pattern> {
+        let index = self.start_recording();
+        let pat = self.parse_pattern_app()?;
+        self.parse_cons_tail(index, pat)
+    }
+
+    /// `:` and the pattern following it, if any, after `head`.
+    fn parse_cons_tail(&mut self, index: usize, head: ast::Pattern) -> ParserResult<ast::Pattern> {
+        if !self.match_token(lexer::TokenKind::Colon)? {
+            return Ok(head);
         }
+        let tail = self.parse_pattern()?;
+        Ok(ast::Pattern::ListCons(
+            Box::new(head),
+            Box::new(tail),
+            self.end_recording(index),
+        ))
     }
 
-    fn parse_pattern_primary(&mut self) -> ParserResult<ast::Pattern> {
+    fn parse_pattern_app(&mut self) -> ParserResult<ast::Pattern> {
+        let index = self.start_recording();
+        if !matches!(self.peek().kind, lexer::TokenKind::PCIdentifier(_)) {
+            return self.parse_pattern_atom();
+        }
+        let id = self.expect_pc_identifier()?;
+        if self.peek().kind == lexer::TokenKind::LBrace {
+            let fields = self.parse_fields(Self::parse_pattern)?;
+            return Ok(ast::Pattern::Record(id, fields, self.end_recording(index)));
+        }
+        // The fields of a constructor go up to the first token that cannot
+        // start a pattern, such as `->` or `=`.
+        let mut fields = vec![];
+        while self.peek().kind.starts_pattern() || self.at_negative_number() {
+            fields.push(self.parse_pattern_atom()?);
+        }
+        if fields.is_empty() {
+            return Ok(ast::Pattern::Id(id));
+        }
+        Ok(ast::Pattern::App(id, fields, self.end_recording(index)))
+    }
+
+    /// Parses a pattern that can be a field of a constructor or an argument
+    /// of a function without parentheses.
+    fn parse_pattern_atom(&mut self) -> ParserResult<ast::Pattern> {
         let index = self.start_recording();
         match self.peek().kind {
-            n if n.is_literal() => {
+            n if n.is_literal() || self.at_negative_number() => {
                 let lit = self.parse_literal()?;
                 Ok(ast::Pattern::Literal(lit))
             }
-            n if n.is_identifier() => {
-                let id = self.expect_any_identifier()?;
-                let cloned = self.clone();
-                let mut ty = self.parse_pattern();
-                if ty.is_ok() && id.name.chars().nth(0).unwrap().is_uppercase() {
-                    let mut types = vec![];
-                    while ty.is_ok() {
-                        types.push(ty.unwrap());
-                        ty = self.parse_pattern();
-                    }
-
-                    return Ok(ast::Pattern::App(id, types, self.end_recording(index)));
-                } else {
-                    *self = cloned;
+            lexer::TokenKind::Identifier(_) => {
+                let id = self.expect_identifier()?;
+                if self.peek().kind != lexer::TokenKind::At {
+                    return Ok(ast::Pattern::Variable(id));
                 }
-
-                match n {
-                    lexer::TokenKind::Identifier(_) => Ok(ast::Pattern::Variable(id)),
-                    lexer::TokenKind::PCIdentifier(_) => Ok(ast::Pattern::Id(id)),
-                    _ => unreachable!(),
+                self.advance()?;
+                let pat = self.parse_pattern_atom()?;
+                Ok(ast::Pattern::As(id, Box::new(pat), self.end_recording(index)))
+            }
+            lexer::TokenKind::PCIdentifier(_) => {
+                let id = self.expect_pc_identifier()?;
+                if self.peek().kind != lexer::TokenKind::LBrace {
+                    return Ok(ast::Pattern::Id(id));
                 }
+                let fields = self.parse_fields(Self::parse_pattern)?;
+                Ok(ast::Pattern::Record(id, fields, self.end_recording(index)))
             }
 
             lexer::TokenKind::Underscore => {
@@ -787,16 +1612,50 @@ impl<'a> Parser<'a> {
                 Ok(ast::Pattern::Wildcard(self.end_recording(index)))
             }
 
+            lexer::TokenKind::LParen => {
+                self.advance()?;
+                let first = self.parse_pattern()?;
+                self.parse_pattern_group(index, first)
+            }
+
+            lexer::TokenKind::LBracket => {
+                self.advance()?;
+                let mut pats = Vec::new();
+                if !self.match_token(lexer::TokenKind::RBracket)? {
+                    pats.push(self.parse_pattern()?);
+                    while !self.match_token(lexer::TokenKind::RBracket)? {
+                        self.expect_current(token![,])?;
+                        pats.push(self.parse_pattern()?);
+                    }
+                }
+                Ok(ast::Pattern::List(pats, self.end_recording(index)))
+            }
+
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "pattern".to_string(),
-                    found: self.peek().span.clone(),
+                    found: self.peek().span,
                 },
-                self.peek().span.clone(),
+                self.peek().span,
             )),
         }
     }
 
+    /// The rest of a pattern between parentheses, `first` being the pattern
+    /// following the opening parenthesis: a tuple, or a single pattern that
+    /// is only grouped.
+    fn parse_pattern_group(&mut self, index: usize, first: ast::Pattern) -> ParserResult<ast::Pattern> {
+        if self.match_token(lexer::TokenKind::RParen)? {
+            return Ok(first);
+        }
+        let mut pats = vec![first];
+        while !self.match_token(lexer::TokenKind::RParen)? {
+            self.expect_current(token![,])?;
+            pats.push(self.parse_pattern()?);
+        }
+        Ok(ast::Pattern::Tuple(pats, self.end_recording(index)))
+    }
+
     fn parse_type(&mut self) -> ParserResult<ast::Type> {
         let index = self.start_recording();
         let mut lhs = self.parse_type_primary()?;
@@ -809,39 +1668,36 @@ impl<'a> Parser<'a> {
         Ok(lhs)
     }
 
+    /// A type constructor or variable applied to arguments, or a type atom.
     fn parse_type_primary(&mut self) -> ParserResult<ast::Type> {
         let index = self.start_recording();
-        match self.peek().kind {
-            n if n.is_identifier() => {
-                let id = self.expect_any_identifier()?;
-                let mut cloned = self.clone();
-                let mut ty = self.parse_type();
-                if ty.is_ok() {
-                    let mut types = vec![];
-                    while ty.is_ok() {
-                        types.push(ty.unwrap());
-                        ty = self.parse_type();
-                        cloned = self.clone();
-                    }
-                    *self = cloned;
-                    return Ok(ast::Type::App(id, types, self.end_recording(index)));
-                } else {
-                    *self = cloned;
-                }
+        if !self.peek().kind.is_identifier() {
+            return self.parse_type_atom();
+        }
+        let id = self.expect_any_identifier()?;
+        let mut args = vec![];
+        while self.peek().kind.starts_type_atom() {
+            args.push(self.parse_type_atom()?);
+        }
+        if !args.is_empty() {
+            return Ok(ast::Type::App(id, args, self.end_recording(index)));
+        }
+        Ok(type_name(id))
+    }
 
-                match n {
-                    lexer::TokenKind::Identifier(_) => Ok(ast::Type::Generic(id)),
-                    lexer::TokenKind::PCIdentifier(_) => Ok(ast::Type::Id(id)),
-                    _ => unreachable!(),
-                }
-            }
+    /// A type that can be an argument of a type application without
+    /// parentheses.
+    fn parse_type_atom(&mut self) -> ParserResult<ast::Type> {
+        let index = self.start_recording();
+        match self.peek().kind {
+            n if n.is_identifier() => Ok(type_name(self.expect_any_identifier()?)),
 
             lexer::TokenKind::LBracket => {
                 self.advance()?;
                 let ty = self.parse_type()?;
                 self.expect_current(token![rbracket])?;
 
-                Ok(ty)
+                Ok(ast::Type::List(Box::new(ty), self.end_recording(index)))
             }
 
             lexer::TokenKind::LParen => {
@@ -855,15 +1711,19 @@ impl<'a> Parser<'a> {
                     tys.push(ty);
                 }
 
+                // A single type between parentheses is only grouped.
+                if tys.len() == 1 {
+                    return Ok(tys.pop().unwrap());
+                }
                 Ok(ast::Type::Tuple(tys, self.end_recording(index)))
             }
 
             _ => Err(error::Error::new(
                 error::ErrorKind::UnexpectedToken {
                     expected: "type".to_string(),
-                    found: self.peek().span.clone(),
+                    found: self.peek().span,
                 },
-                self.peek().span.clone(),
+                self.peek().span,
             )),
         }
     }
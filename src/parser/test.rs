@@ -1,21 +1,26 @@
 use crate::ast::*;
+use crate::parser::error::ErrorKind;
+use crate::source::FileId;
 
 
 
 #[cfg(test)]
 fn check_ast(content: &str, expected: Program<Span>) {
-    let mut parser = crate::parser::Parser::new(content);
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
     let ast = parser.parse();
-    assert_eq!(ast, Ok(expected));
+    assert_eq!(ast, (expected, vec![]));
+}
+
+#[cfg(test)]
+fn errors(content: &str) -> Vec<ErrorKind> {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (_, errors) = parser.parse();
+    errors.into_iter().map(|e| e.kind).collect()
 }
 
 macro_rules! span {
-    ($s:expr, $e:expr, $i:expr) => {
-        Span {
-            start: $s,
-            end: $e,
-            input: $i.to_string(),
-        }
+    ($s:expr, $e:expr) => {
+        Span::new(FileId::default(), $s, $e)
     };
 }
 
@@ -34,13 +39,13 @@ fn test_bind() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("x".to_string(), span!(0, 1, "x")), 
+                    Identifier::new("x".to_string(), span!(0, 1)), 
                     vec![], 
                     ParsedExpr::Literal(Literal::new(
-                        LiteralKind::Integer(5),
-                        span!(4, 5, "5")
+                        LiteralKind::Integer(5.into()),
+                        span!(4, 5)
                     )), 
-                    span!(0, 5, "x = 5")
+                    span!(0, 5)
                 )
             )],
         },
@@ -54,23 +59,23 @@ fn test_bind_with_params() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("f".to_string(), span!(0, 1, "f")), 
+                    Identifier::new("f".to_string(), span!(0, 1)), 
                     vec![
                         Pattern::Variable(
-                            Identifier::new("x".to_string(), span!(2, 3, "x"))
+                            Identifier::new("x".to_string(), span!(2, 3))
                         ),
                         Pattern::Variable(
-                            Identifier::new("y".to_string(), span!(4, 5, "y"))
+                            Identifier::new("y".to_string(), span!(4, 5))
                         ),
                     ], 
                     ParsedExpr::Literal(
                         Literal::new(
-                            LiteralKind::Integer(3),
-                            span!(8, 9, "3")
+                            LiteralKind::Integer(3.into()),
+                            span!(8, 9)
                         )
                     
                     ), 
-                    span!(0, 9, "f x y = 3")
+                    span!(0, 9)
                 )
             )],
         },
@@ -84,15 +89,15 @@ fn test_literal() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                    Identifier::new("a".to_string(), span!(0, 1)), 
                     vec![], 
                     ParsedExpr::Literal(
                         Literal::new(
-                            LiteralKind::Integer(3),
-                            span!(4, 5, "3")
+                            LiteralKind::Integer(3.into()),
+                            span!(4, 5)
                         )
                     ), 
-                    span!(0, 5, "a = 3")
+                    span!(0, 5)
                 )
             )],
         },
@@ -101,15 +106,15 @@ fn test_literal() {
     check_ast("a = \"hello\"", Program {
         statements: vec![Statement::Bind(
             Bind::new(
-                Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                Identifier::new("a".to_string(), span!(0, 1)), 
                 vec![], 
                 ParsedExpr::Literal(
                     Literal::new(
                         LiteralKind::String("hello".to_string()),
-                        span!(4, 11, "\"hello\"")
+                        span!(4, 11)
                     )
                 ), 
-                span!(0, 11, "a = \"hello\"")
+                span!(0, 11)
             )
         )],
     });
@@ -117,15 +122,15 @@ fn test_literal() {
     check_ast("a = True", Program {
         statements: vec![Statement::Bind(
             Bind::new(
-                Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                Identifier::new("a".to_string(), span!(0, 1)), 
                 vec![], 
                 ParsedExpr::Literal(
                     Literal::new(
                         LiteralKind::Bool(Bool::True),
-                        span!(4, 8, "True")
+                        span!(4, 8)
                     )
                 ), 
-                span!(0, 8, "a = True")
+                span!(0, 8)
             )
         )],
     });
@@ -133,15 +138,15 @@ fn test_literal() {
     check_ast("a = False", Program {
         statements: vec![Statement::Bind(
             Bind::new(
-                Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                Identifier::new("a".to_string(), span!(0, 1)), 
                 vec![], 
                 ParsedExpr::Literal(
                     Literal::new(
                         LiteralKind::Bool(Bool::False),
-                        span!(4, 9, "False")
+                        span!(4, 9)
                     )
                 ), 
-                span!(0, 9, "a = False")
+                span!(0, 9)
             )
         )],
     });
@@ -149,15 +154,15 @@ fn test_literal() {
     check_ast("a = 3.14", Program {
         statements: vec![Statement::Bind(
             Bind::new(
-                Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                Identifier::new("a".to_string(), span!(0, 1)), 
                 vec![], 
                 ParsedExpr::Literal(
                     Literal::new(
                         LiteralKind::Float(3.14),
-                        span!(4, 8, "3.14")
+                        span!(4, 8)
                     )
                 ), 
-                span!(0, 8, "a = 3.14")
+                span!(0, 8)
             )
         )],
     });
@@ -171,23 +176,25 @@ fn test_app() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                    Identifier::new("a".to_string(), span!(0, 1)), 
                     vec![], 
                     ParsedExpr::App(
                         App::new(
-                            Identifier::new("f".to_string(), span!(4, 5, "f")), 
+                            ParsedExpr::Identifier {
+                                id: Identifier::new("f".to_string(), span!(4, 5)),
+                            },
                             vec![
                                 ParsedExpr::Literal(
                                     Literal::new(
-                                        LiteralKind::Integer(3),
-                                        span!(6, 7, "3")
+                                        LiteralKind::Integer(3.into()),
+                                        span!(6, 7)
                                     )
                                 )
                             ], 
-                            span!(4, 7, "f 3")
+                            span!(4, 7)
                         )
                     ), 
-                    span!(0, 7, "a = f 3")
+                    span!(0, 7)
                 )
             )],
         },
@@ -202,32 +209,32 @@ fn test_list() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                    Identifier::new("a".to_string(), span!(0, 1)), 
                     vec![], 
                     AnnExpr::List { list: 
                             vec![
                                 AnnExpr::Literal(
                                     Literal::new(
-                                        LiteralKind::Integer(1),
-                                        span!(5, 6, "1")
+                                        LiteralKind::Integer(1.into()),
+                                        span!(5, 6)
                                     )
                                 ),
                                 AnnExpr::Literal(
                                     Literal::new(
-                                        LiteralKind::Integer(2),
-                                        span!(8, 9, "2")
+                                        LiteralKind::Integer(2.into()),
+                                        span!(8, 9)
                                     )
                                 ),
                                 AnnExpr::Literal(
                                     Literal::new(
-                                        LiteralKind::Integer(3),
-                                        span!(11, 12, "3")
+                                        LiteralKind::Integer(3.into()),
+                                        span!(11, 12)
                                     )
                                 ),
                             ], 
-                    ann: span!(4, 13, "[1, 2, 3]")
+                    ann: span!(4, 13)
                 }, 
-                    span!(0, 13, "a = [1, 2, 3]")
+                    span!(0, 13)
                 )
             )],
         },
@@ -242,26 +249,26 @@ fn test_let() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                    Identifier::new("a".to_string(), span!(0, 1)), 
                     vec![], 
                     AnnExpr::Let {
                         binds: vec![Bind::new(
-                            Identifier::new("x".to_string(), span!(8, 9, "x")), 
+                            Identifier::new("x".to_string(), span!(8, 9)), 
                             vec![], 
                             ParsedExpr::Literal(
                                 Literal::new(
-                                    LiteralKind::Integer(3),
-                                    span!(12, 13, "3")
+                                    LiteralKind::Integer(3.into()),
+                                    span!(12, 13)
                                 )
                             ), 
-                            span!(8, 13, "x = 3")
+                            span!(8, 13)
                         )], 
                         ret: Box::new(AnnExpr::Identifier {
-                            id: Identifier::new("x".to_string(), span!(18, 19, "x"))
+                            id: Identifier::new("x".to_string(), span!(18, 19))
                         }), 
-                        ann: span!(4, 19, "let x = 3; in x")
+                        ann: span!(4, 19)
                     }, 
-                    span!(0, 19, "a = let x = 3; in x")
+                    span!(0, 19)
                 )
             )],
         },
@@ -275,29 +282,29 @@ fn test_match() {
         Program {
             statements: vec![Statement::Bind(
                 Bind::new(
-                    Identifier::new("a".to_string(), span!(0, 1, "a")), 
+                    Identifier::new("a".to_string(), span!(0, 1)), 
                     vec![], 
                     ParsedExpr::Match {
                         referral: Box::new(AnnExpr::Literal(
                             Literal::new(
-                                LiteralKind::Integer(3),
-                                span!(10, 11, "3")
+                                LiteralKind::Integer(3.into()),
+                                span!(10, 11)
                             )
                         )), 
                         cases: vec![
                             (   
                                 Pattern::Literal(
                                     Literal::new(
-                                        LiteralKind::Integer(3),
-                                        span!(19, 20, "3")
+                                        LiteralKind::Integer(3.into()),
+                                        span!(19, 20)
                                     )
                                 )
                                 ,
                                 Box::new(
                                     ParsedExpr::Literal(
                                         Literal::new(
-                                            LiteralKind::Integer(4),
-                                            span!(24, 25, "4")
+                                            LiteralKind::Integer(4.into()),
+                                            span!(24, 25)
                                         )
                                     )
                                 )
@@ -305,27 +312,797 @@ fn test_match() {
                             (
                                 Pattern::Literal(
                                     Literal::new(
-                                        LiteralKind::Integer(4),
-                                        span!(28, 29, "4")
+                                        LiteralKind::Integer(4.into()),
+                                        span!(28, 29)
                                     )
                                 )
                                 ,
                                 Box::new(
                                     ParsedExpr::Literal(
                                         Literal::new(
-                                            LiteralKind::Integer(5),
-                                            span!(33, 34, "5")
+                                            LiteralKind::Integer(5.into()),
+                                            span!(33, 34)
                                         )
                                     )
                                 )
                             ),
 
                         ], 
-                        ann: span!(4, 34, "match 3 with | 3 -> 4 | 4 -> 5")
+                        ann: span!(4, 34)
                     }, 
-                    span!(0, 34, "a = match 3 with | 3 -> 4 | 4 -> 5")
+                    span!(0, 34)
+                )
+            )],
+        },
+    );
+}
+#[test]
+fn test_big_integer() {
+    check_ast(
+        "a = 123456789012345678901234567890",
+        Program {
+            statements: vec![Statement::Bind(
+                Bind::new(
+                    Identifier::new("a".to_string(), span!(0, 1)),
+                    vec![],
+                    ParsedExpr::Literal(
+                        Literal::new(
+                            LiteralKind::Integer("123456789012345678901234567890".parse().unwrap()),
+                            span!(4, 34)
+                        )
+                    ),
+                    span!(0, 34)
                 )
             )],
         },
     );
-}
\ No newline at end of file
+}
+
+/// Renders an expression with every application and operator parenthesized.
+#[cfg(test)]
+fn render(expr: &ParsedExpr) -> String {
+    match expr {
+        AnnExpr::Literal(lit) => match &lit.lit {
+            LiteralKind::Integer(i) => i.to_string(),
+            LiteralKind::Float(f) => f.to_string(),
+            LiteralKind::String(s) => format!("{:?}", s),
+            LiteralKind::Char(c) => format!("{:?}", c),
+            LiteralKind::Bool(Bool::True) => "True".to_string(),
+            LiteralKind::Bool(Bool::False) => "False".to_string(),
+        },
+        AnnExpr::Identifier { id } | AnnExpr::PCIdentifier { id } => id.name.clone(),
+        AnnExpr::App(app) => {
+            let args: Vec<String> = app.args.iter().map(render).collect();
+            format!("({} {})", render(&app.head), args.join(" "))
+        }
+        AnnExpr::BinOp { op, lhs, rhs, .. } => {
+            format!("({} {} {})", render(lhs), op.symbol(), render(rhs))
+        }
+        AnnExpr::UnOp { op: UnOp::Neg, expr, .. } => format!("(-{})", render(expr)),
+        AnnExpr::UnOp { op: UnOp::Not, expr, .. } => format!("(not {})", render(expr)),
+        AnnExpr::Ann { expr, .. } => format!("({} :: _)", render(expr)),
+        AnnExpr::Condition { cond, then, els, .. } => {
+            format!("(if {} then {} else {})", render(cond), render(then), render(els))
+        }
+        AnnExpr::Tuple { list, .. } => {
+            let items: Vec<String> = list.iter().map(render).collect();
+            format!("({})", items.join(", "))
+        }
+        AnnExpr::List { list, .. } => {
+            let items: Vec<String> = list.iter().map(render).collect();
+            format!("[{}]", items.join(", "))
+        }
+        AnnExpr::Lambda { args, ret, .. } => {
+            let args: Vec<String> = args.iter().map(render_pattern).collect();
+            format!("(\\{} -> {})", args.join(" "), render(ret))
+        }
+        AnnExpr::Let { binds, ret, .. } => {
+            let binds: Vec<String> = binds
+                .iter()
+                .map(|bind| {
+                    let mut lhs = vec![bind.name.name.clone()];
+                    lhs.extend(bind.args.iter().map(render_pattern));
+                    format!("{} = {}", lhs.join(" "), render(&bind.expr))
+                })
+                .collect();
+            format!("(let {} in {})", binds.join("; "), render(ret))
+        }
+        AnnExpr::Match {
+            referral, cases, ..
+        } => {
+            let arms: Vec<String> = cases
+                .iter()
+                .map(|(pat, expr)| format!("{} -> {}", render_pattern(pat), render(expr)))
+                .collect();
+            format!("(match {} with {})", render(referral), arms.join("; "))
+        }
+        AnnExpr::Record { id, fields, .. } => format!("{} {}", id.name, render_fields(fields, render)),
+        AnnExpr::Update { expr, fields, .. } => format!("({} {})", render(expr), render_fields(fields, render)),
+        AnnExpr::Guards { guards, .. } => {
+            let guards: Vec<String> = guards
+                .iter()
+                .map(|(cond, body)| format!("| {} => {}", render(cond), render(body)))
+                .collect();
+            format!("({})", guards.join(" "))
+        }
+        AnnExpr::Error { .. } => "<error>".to_string(),
+    }
+}
+
+#[cfg(test)]
+fn render_pattern(pat: &Pattern) -> String {
+    match pat {
+        Pattern::Variable(id) | Pattern::Id(id) => id.name.clone(),
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Literal(lit) => render(&AnnExpr::Literal(lit.clone())),
+        Pattern::ListCons(head, tail, _) => {
+            format!("({}:{})", render_pattern(head), render_pattern(tail))
+        }
+        Pattern::App(id, args, _) => {
+            let args: Vec<String> = args.iter().map(render_pattern).collect();
+            format!("({} {})", id.name, args.join(" "))
+        }
+        Pattern::Tuple(items, _) => {
+            let items: Vec<String> = items.iter().map(render_pattern).collect();
+            format!("({})", items.join(", "))
+        }
+        Pattern::List(items, _) => {
+            let items: Vec<String> = items.iter().map(render_pattern).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Pattern::As(id, pat, _) => format!("{}@{}", id.name, render_pattern(pat)),
+        Pattern::Record(id, fields, _) => format!("{} {}", id.name, render_fields(fields, render_pattern)),
+    }
+}
+
+#[cfg(test)]
+fn render_fields<T>(fields: &[(Identifier, T)], f: fn(&T) -> String) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("{} = {}", name.name, f(value)))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+#[cfg(test)]
+fn check_expr(content: &str, expected: &str) {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (ast, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    // Fixity declarations may surround the bind.
+    let binds: Vec<&Bind<Span>> = ast
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Bind(bind) => Some(bind),
+            _ => None,
+        })
+        .collect();
+    match binds[..] {
+        [bind] => assert_eq!(render(&bind.expr), expected, "{}", content),
+        _ => panic!("expected a single bind in {:?}", content),
+    }
+}
+
+#[test]
+fn test_precedence() {
+    check_expr("a = 1 + 2 * 3", "(1 + (2 * 3))");
+    check_expr("a = 1 * 2 + 3", "((1 * 2) + 3)");
+    check_expr("a = x + 1 > y", "((x + 1) > y)");
+    check_expr("a = p && b == True", "(p && (b == True))");
+    check_expr("a = p || q && r", "(p || (q && r))");
+    check_expr("a = x < y || y < z", "((x < y) || (y < z))");
+    check_expr("a = 2 * 3 ^ 2", "(2 * (3 ^ 2))");
+    check_expr("a = x + 1 : xs", "((x + 1) : xs)");
+    check_expr("a = (1 + 2) * 3", "((1 + 2) * 3)");
+}
+
+#[test]
+fn test_associativity() {
+    check_expr("a = 1 - 2 - 3", "((1 - 2) - 3)");
+    check_expr("a = 8 / 4 / 2", "((8 / 4) / 2)");
+    check_expr("a = 2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))");
+    check_expr("a = 1 : 2 : xs", "(1 : (2 : xs))");
+    check_expr("a = p && q && r", "(p && (q && r))");
+}
+
+#[test]
+fn test_application_precedence() {
+    check_expr("a = f x + g y", "((f x) + (g y))");
+    check_expr("a = f x y * 2", "((f x y) * 2)");
+    check_expr("a = f (x + 1) [y]", "(f (x + 1) [y])");
+    check_expr("a = Just x : xs", "((Just x) : xs)");
+}
+
+#[test]
+fn test_expression_head() {
+    check_expr("a = (\\x -> x + 1) 2", "((\\x -> (x + 1)) 2)");
+    check_expr("a = (compose f g) x", "((compose f g) x)");
+    check_expr("a = (if c then f else g) x y", "((if c then f else g) x y)");
+    check_expr("a = (match b with\n    _ -> f) 1", "((match b with _ -> f) 1)");
+    check_expr("a = (f x) y + 1", "(((f x) y) + 1)");
+    check_expr("a = Just x y", "(Just x y)");
+}
+
+#[test]
+fn test_annotation_scope() {
+    check_expr("a = x + 1 :: Integer", "((x + 1) :: _)");
+    check_expr("a = (x :: Integer) + 1", "((x :: _) + 1)");
+}
+
+#[test]
+fn test_non_associative() {
+    assert!(matches!(
+        errors("a = x == y == z")[..],
+        [ErrorKind::NonAssociative { .. }]
+    ));
+    check_expr("a = (x == y) == z", "((x == y) == z)");
+}
+
+#[test]
+fn test_user_operators() {
+    check_expr("a = x |> f |> g", "(|> (|> x f) g)");
+    check_expr("a = x + y <> z", "(x + (<> y z))");
+    check_expr("infixl 1 |>\na = x + 1 |> f", "(|> (x + 1) f)");
+    check_expr("infixr 5 ++\na = x ++ y ++ z", "(++ x (++ y z))");
+    // Fixity declarations apply to the whole program.
+    check_expr("a = x ++ y ++ z\ninfixr 5 ++", "(++ x (++ y z))");
+    check_expr("a = x `div` y + 1", "((div x y) + 1)");
+    check_expr("infixl 6 `plus`\na = x `plus` y * z", "(plus x (y * z))");
+}
+
+#[test]
+fn test_operator_sections() {
+    check_expr("a = (+)", "(\\_lhs _rhs -> (_lhs + _rhs))");
+    check_expr("a = (:)", "(\\_lhs _rhs -> (_lhs : _rhs))");
+    check_expr("a = foldr (<>) z xs", "(foldr (\\_lhs _rhs -> (<> _lhs _rhs)) z xs)");
+}
+
+#[test]
+fn test_operator_definitions() {
+    let mut parser = crate::parser::Parser::new(
+        FileId::default(),
+        "infixl 1 |>, `apply`\n(|>) :: a -> (a -> b) -> b\nx |> f = f x\n(<>) x y = x\nx `apply` f = f x",
+    );
+    let (ast, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let names: Vec<(&str, usize)> = ast
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Bind(bind) => Some((bind.name.name.as_str(), bind.args.len())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, vec![("|>", 2), ("<>", 2), ("apply", 2)]);
+
+    match &ast.statements[..2] {
+        [Statement::Fixity(decl), Statement::TypeAssign(assign)] => {
+            assert_eq!(
+                decl.fixity,
+                Fixity {
+                    precedence: 1,
+                    assoc: Assoc::Left
+                }
+            );
+            let operators: Vec<&str> = decl.operators.iter().map(|op| op.name.as_str()).collect();
+            assert_eq!(operators, vec!["|>", "apply"]);
+            assert_eq!(assign.id.name, "|>");
+        }
+        statements => panic!("unexpected statements {:?}", statements),
+    }
+}
+
+#[test]
+fn test_reserved_at() {
+    // `@` belongs to as-patterns, this does not define an operator `@`.
+    let mut parser = crate::parser::Parser::new(FileId::default(), "xs@(x:_) = [1]\nys = [1]");
+    let (ast, _) = parser.parse();
+    let names: Vec<&str> = ast
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Bind(bind) => Some(bind.name.name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, vec!["ys"]);
+    assert!(matches!(errors("xs@(x:_) = [1]")[..], [ErrorKind::UnexpectedToken { .. }]));
+    assert!(!errors("a <@> b = a").is_empty());
+}
+
+#[test]
+fn test_fixity_errors() {
+    assert!(matches!(
+        errors("infixl 6 <+\ninfixr 6 +>\na = x <+ y +> z")[..],
+        [ErrorKind::MixedAssociativity { .. }]
+    ));
+    assert!(matches!(
+        errors("infixl 6 <+\ninfixr 6 +>\na = x +> y <+ z")[..],
+        [ErrorKind::MixedAssociativity { .. }]
+    ));
+    assert!(matches!(
+        errors("infix 6 <+\ninfixl 6 +>\na = x +> y <+ z")[..],
+        [ErrorKind::NonAssociative { .. }]
+    ));
+    assert!(matches!(
+        errors("infixl 10 <>")[..],
+        [ErrorKind::InvalidPrecedence { .. }]
+    ));
+}
+
+#[test]
+fn test_recovery() {
+    let mut parser = crate::parser::Parser::new(FileId::default(), "a = 1 +\nb = [1, , 2]\nc = (1 +) * 2\n)\nd = 3");
+    let (ast, errors) = parser.parse();
+    assert_eq!(errors.len(), 4, "{:?}", errors);
+
+    let binds: Vec<(&str, String)> = ast
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Bind(bind) => (bind.name.name.as_str(), render(&bind.expr)),
+            _ => panic!("unexpected statement {:?}", statement),
+        })
+        .collect();
+    assert_eq!(
+        binds,
+        vec![
+            ("a", "<error>".to_string()),
+            ("b", "<error>".to_string()),
+            ("c", "(<error> * 2)".to_string()),
+            ("d", "3".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_blank_lines() {
+    check_expr("\n\na = 1\n\n  \n", "1");
+    assert!(errors("a = 1\n\nb = 2\n").is_empty());
+}
+
+#[test]
+fn test_layout_let() {
+    check_expr("a = let x = 1; y = 2; in x + y", "(let x = 1; y = 2 in (x + y))");
+    check_expr("a = let x = 1 in x", "(let x = 1 in x)");
+    check_expr(
+        "a = let x = 1\n        y = 2\n    in x + y",
+        "(let x = 1; y = 2 in (x + y))",
+    );
+    check_expr(
+        "a =\n  let\n    f x = x\n    y = f\n          2\n  in y",
+        "(let f x = x; y = (f 2) in y)",
+    );
+    // The inner block is closed by `in`, the outer one by the dedent.
+    check_expr(
+        "a = let x = let y = 1 in y\n        z = let w = 2\n                in w\n    in x",
+        "(let x = (let y = 1 in y); z = (let w = 2 in w) in x)",
+    );
+}
+
+#[test]
+fn test_layout_match() {
+    check_expr(
+        "a = match x with\n  0 -> 1\n  _ -> 2",
+        "(match x with 0 -> 1; _ -> 2)",
+    );
+    check_expr(
+        "a = match 2 * 4 with\n            (0 -> 'a')\n            (8 -> 'd') (_ -> 'z')",
+        "(match (2 * 4) with 0 -> 'a'; 8 -> 'd'; _ -> 'z')",
+    );
+    check_expr(
+        "a = match x with\n  0 -> match y with\n         0 -> 1\n         _ -> 2\n  _ -> 3",
+        "(match x with 0 -> (match y with 0 -> 1; _ -> 2); _ -> 3)",
+    );
+    check_expr(
+        "a = match x with\n  0 -> let y = 1\n       in y\n  n -> f\n         n",
+        "(match x with 0 -> (let y = 1 in y); n -> (f n))",
+    );
+}
+
+#[test]
+fn test_layout_closing_tokens() {
+    check_expr("a = (match x with 1 -> 2) + 1", "((match x with 1 -> 2) + 1)");
+    check_expr("a = (match x with 1 -> 2, 3)", "((match x with 1 -> 2), 3)");
+    check_expr("a = let y = match x with 1 -> 2 in y", "(let y = (match x with 1 -> 2) in y)");
+    check_expr(
+        "a = if c then match x with 1 -> 2 else 3",
+        "(if c then (match x with 1 -> 2) else 3)",
+    );
+}
+
+#[test]
+fn test_statement_end() {
+    check_expr("a = f\n  x\n\n  y", "(f x y)");
+    assert!(errors("a = f\nb = 2").is_empty());
+    assert!(matches!(errors("a = 1 2")[..], [ErrorKind::ExpectedNewline { .. }]));
+    assert!(matches!(
+        errors("a = 1 )\nb = 2")[..],
+        [ErrorKind::ExpectedNewline { .. }]
+    ));
+}
+
+#[test]
+fn test_spans() {
+    let mut parser = crate::parser::Parser::new(FileId::default(), "f x =\n    let y = x\n    in  y   \n\nmain = f 1");
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let spans: Vec<(usize, usize)> = program
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Bind(bind) => (bind.span.start, bind.span.end),
+            _ => unreachable!(),
+        })
+        .collect();
+    // Trailing whitespace and blank lines are not part of a statement.
+    assert_eq!(spans, vec![(0, 29), (34, 44)]);
+}
+
+/// A synthetic program of a few thousand lines, to make sure parsing stays
+/// linear in the size of the source.
+#[bench]
+fn bench_large_program(b: &mut ::test::Bencher) {
+    let mut content = String::new();
+    for i in 0..5000 {
+        content.push_str(&format!(
+            "f{i} x y =\n    let z = x * {i} + y\n    in match z with\n        0 -> [x, y]\n        _ -> [z]\n\n"
+        ));
+    }
+    b.iter(|| {
+        let (program, errors) = crate::parser::Parser::new(FileId::default(), &content).parse();
+        assert!(errors.is_empty());
+        assert_eq!(program.statements.len(), 5000);
+    });
+}
+
+#[test]
+fn test_escapes() {
+    check_expr(r#"x = "a\tb\n\\\"\'\u{e9}""#, r#""a\tb\n\\\"'é""#);
+    check_expr(r#"x = """#, r#""""#);
+    check_expr(r#"x = ['\'', '\n', '\\', '\u{1F600}', '"']"#, r#"['\'', '\n', '\\', '😀', '"']"#);
+
+    let errors = |content| {
+        let mut parser = crate::parser::Parser::new(FileId::default(), content);
+        parser.parse().1.into_iter().map(|e| e.kind).collect::<Vec<_>>()
+    };
+    assert_eq!(errors(r#"x = "a\qb""#), [ErrorKind::InvalidEscape { found: span!(6, 8) }]);
+    assert_eq!(errors(r#"x = "\u{110000}""#), [ErrorKind::InvalidEscape { found: span!(5, 15) }]);
+    assert_eq!(errors(r#"x = "\u{}""#), [ErrorKind::InvalidEscape { found: span!(5, 9) }]);
+    assert_eq!(errors(r#"x = '\ab'"#), [ErrorKind::InvalidEscape { found: span!(5, 7) }]);
+    assert_eq!(errors(r#"x = '\nn'"#), [ErrorKind::InvalidChar { found: span!(4, 9) }]);
+}
+
+#[test]
+fn test_lexical_errors() {
+    assert_eq!(
+        errors("x = 1 § 2\ny = \"abc\nz = 'ab'\nw = ''"),
+        [
+            ErrorKind::InvalidCharacter { found: span!(6, 8) },
+            ErrorKind::UnterminatedString { found: span!(15, 19) },
+            ErrorKind::InvalidChar { found: span!(24, 28) },
+            ErrorKind::InvalidChar { found: span!(33, 35) },
+        ]
+    );
+    // A run of invalid characters is a single error, and the statements
+    // around it are still parsed.
+    let mut parser = crate::parser::Parser::new(FileId::default(), "a = 1\nb = f €€ 2\nc = 3");
+    let (program, errors) = parser.parse();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(program.statements.len(), 3);
+}
+
+#[test]
+fn test_numbers() {
+    check_expr("a = [0xFF, 0o17, 0b1010, 1_000_000, 0xdead_BEEF]", "[255, 15, 10, 1000000, 3735928559]");
+    check_expr("a = [2.5, 1.5e-3, 1e3, 2E+2, 1_0.2_5]", "[2.5, 0.0015, 1000, 200, 10.25]");
+    // Signs are not part of number literals.
+    check_expr("a = n-1", "(n - 1)");
+    check_expr("a = n -1", "(n - 1)");
+    check_expr("a = f -1", "(f - 1)");
+    check_expr("a = -1 + x", "(-1 + x)");
+    check_expr("a = x * -2.5", "(x * -2.5)");
+    check_expr("a = match x with\n    -1 -> 0\n    _ -> 1", "(match x with -1 -> 0; _ -> 1)");
+    check_ast("a = -0x10", Program {
+        statements: vec![Statement::Bind(
+            Bind::new(
+                Identifier::new("a".to_string(), span!(0, 1)),
+                vec![],
+                ParsedExpr::Literal(
+                    Literal::new(
+                        LiteralKind::Integer((-16).into()),
+                        span!(4, 9)
+                    )
+                ),
+                span!(0, 9)
+            )
+        )],
+    });
+
+    assert_eq!(errors("a = 0b102"), [ErrorKind::InvalidNumber { found: span!(4, 9) }]);
+    assert_eq!(errors("a = 1_"), [ErrorKind::InvalidNumber { found: span!(4, 6) }]);
+    assert_eq!(errors("a = 1__2"), [ErrorKind::InvalidNumber { found: span!(4, 8) }]);
+    assert_eq!(errors("a = 1.5e"), [ErrorKind::InvalidNumber { found: span!(4, 8) }]);
+    assert_eq!(errors("a = 0x"), [ErrorKind::InvalidNumber { found: span!(4, 6) }]);
+    assert_eq!(errors("a = 0x_f"), [ErrorKind::InvalidNumber { found: span!(4, 8) }]);
+    assert_eq!(errors("a = 12ab"), [ErrorKind::InvalidNumber { found: span!(4, 8) }]);
+}
+
+#[test]
+fn test_prefix_operators() {
+    check_expr("a = -x", "(-x)");
+    check_expr("a = -x + y", "((-x) + y)");
+    check_expr("a = -x * y", "(-(x * y))");
+    check_expr("a = -2 ^ 2", "(-(2 ^ 2))");
+    check_expr("a = -f x", "(-(f x))");
+    check_expr("a = x * -y", "(x * (-y))");
+    check_expr("a = - -x", "(-(-x))");
+    check_expr("a = f (-x)", "(f (-x))");
+    check_expr("a = not p", "(not p)");
+    check_expr("a = not x == y", "(not (x == y))");
+    check_expr("a = not p && q", "((not p) && q)");
+    check_expr("a = p || not q", "(p || (not q))");
+    check_expr("a = not not p", "(not (not p))");
+    // `-` followed by a number is still a literal.
+    check_expr("a = -1 + x", "(-1 + x)");
+}
+
+#[cfg(test)]
+fn render_type(ty: &Type) -> String {
+    match ty {
+        Type::Generic(id) | Type::Id(id) => id.name.clone(),
+        Type::App(id, args, _) => {
+            let args: Vec<String> = args.iter().map(render_type).collect();
+            format!("({} {})", id.name, args.join(" "))
+        }
+        Type::Tuple(types, _) => {
+            let types: Vec<String> = types.iter().map(render_type).collect();
+            format!("({})", types.join(", "))
+        }
+        Type::Func(arg, ret, _) => {
+            let ret: Vec<String> = ret.iter().map(render_type).collect();
+            format!("({} -> {})", render_type(arg), ret.join(" -> "))
+        }
+        Type::List(ty, _) => format!("[{}]", render_type(ty)),
+        Type::Qualified(context, ty, _) => {
+            let context: Vec<String> = context
+                .iter()
+                .map(|c| format!("{} {}", c.class.name, c.var.name))
+                .collect();
+            format!("({} => {})", context.join(", "), render_type(ty))
+        }
+    }
+}
+
+#[cfg(test)]
+fn check_type(content: &str, expected: &str) {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (ast, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    match &ast.statements[..] {
+        [Statement::TypeAssign(assign)] => assert_eq!(render_type(&assign.ty), expected, "{}", content),
+        [Statement::TypeDecl(decl)] => {
+            let variants: Vec<String> = decl
+                .variants
+                .iter()
+                .map(|variant| {
+                    let mut fields: Vec<String> = variant.types.iter().map(render_type).collect();
+                    for (field, name) in fields.iter_mut().zip(&variant.fields) {
+                        *field = format!("{} :: {}", name.name, field);
+                    }
+                    format!("{} [{}]", variant.id.name, fields.join(", "))
+                })
+                .collect();
+            assert_eq!(variants.join(" | "), expected, "{}", content);
+        }
+        _ => panic!("expected a single signature or type declaration in {:?}", content),
+    }
+}
+
+#[test]
+fn test_types() {
+    check_type("f :: Box Integer -> Integer", "((Box Integer) -> Integer)");
+    check_type("f :: a -> b -> a", "(a -> (b -> a))");
+    check_type("f :: (a -> b) -> Maybe (Box a) -> (a, b)", "((a -> b) -> ((Maybe (Box a)) -> (a, b)))");
+    check_type("f :: Pair (a, b) c", "(Pair (a, b) c)");
+    check_type("f :: [Integer] -> [[a]] -> Integer", "([Integer] -> ([[a]] -> Integer))");
+    check_type("f :: Maybe [a -> b]", "(Maybe [(a -> b)])");
+    check_type(
+        "type P a = (P a (Box a) Integer) (Q (Integer -> a)) (R)",
+        "P [a, (Box a), Integer] | Q [(Integer -> a)] | R []",
+    );
+}
+
+#[test]
+fn test_constructor_patterns() {
+    check_expr(
+        "a = match x with\n    Pair y 1 -> y\n    Box _ -> 0\n    None -> -1",
+        "(match x with (Pair y 1) -> y; (Box _) -> 0; None -> -1)",
+    );
+    check_expr("a = \\(Box y) -> y", "(\\(Box y) -> y)");
+    // The error is reported where parsing fails, not where a speculative
+    // parse started.
+    assert_eq!(
+        errors("f :: Maybe Integer -> ,"),
+        [ErrorKind::UnexpectedToken { expected: "type".to_string(), found: span!(22, 23) }]
+    );
+    assert_eq!(
+        errors("a = match x with\n    Just 1 + -> 2"),
+        [ErrorKind::UnexpectedToken { expected: "Arrow".to_string(), found: span!(28, 29) }]
+    );
+}
+
+#[test]
+fn test_nested_patterns() {
+    check_expr("a = \\(a, b) -> a", "(\\(a, b) -> a)");
+    check_expr("a = \\[x, y] [] -> x", "(\\[x, y] [] -> x)");
+    check_expr("a = \\(x:y:rest) -> x", "(\\(x:(y:rest)) -> x)");
+    check_expr("a = \\all@(x:_) -> all", "(\\all@(x:_) -> all)");
+    check_expr("a = \\(Just (x:xs)) -> x", "(\\(Just (x:xs)) -> x)");
+    check_expr("a = \\'a' -> 1", "(\\'a' -> 1)");
+    check_expr(
+        "a = match p with\n    (x, [Just y]) -> y\n    ((a, _), []) -> a\n    ps@_ -> 0",
+        "(match p with (x, [(Just y)]) -> y; ((a, _), []) -> a; ps@_ -> 0)",
+    );
+    check_expr(
+        "a = match xs with\n    (x:_) -> x\n    ([] -> 0)",
+        "(match xs with (x:_) -> x; [] -> 0)",
+    );
+    assert_eq!(
+        errors("f (a, b = a"),
+        [ErrorKind::UnexpectedToken { expected: "Comma".to_string(), found: span!(8, 9) }]
+    );
+}
+
+#[test]
+fn test_guards() {
+    check_expr(
+        "f x | x < 0 = -1 | otherwise = 1",
+        "(| (x < 0) => -1 | otherwise => 1)",
+    );
+    check_expr(
+        "f x y\n  | x == y = 0\n  | x > y = 1",
+        "(| (x == y) => 0 | (x > y) => 1)",
+    );
+    check_expr(
+        "a = match xs with\n    (x:_) | x > 0 -> x\n          | x < 0 -> -x\n    _ -> 0",
+        "(match xs with (x:_) -> (| (x > 0) => x | (x < 0) => (-x)); _ -> 0)",
+    );
+    check_expr(
+        "a = match n with (m | m > 0 -> 1) (_ -> 0)",
+        "(match n with m -> (| (m > 0) => 1); _ -> 0)",
+    );
+    check_expr(
+        "a = let f x | x > 0 = x\n        g = 1\n    in f g",
+        "(let f x = (| (x > 0) => x); g = 1 in (f g))",
+    );
+    // Guards go on until there is no more `|`.
+    check_expr(
+        "a = match n with | m | m > 0 -> 1 | True -> 0",
+        "(match n with m -> (| (m > 0) => 1 | True => 0))",
+    );
+}
+
+#[test]
+fn test_where() {
+    check_expr("f x = y where y = x", "(let y = x in y)");
+    check_expr(
+        "f x = y + z\n  where y = x\n        z = 2",
+        "(let y = x; z = 2 in (y + z))",
+    );
+    // The binds scope over every guard.
+    check_expr(
+        "f x\n  | x < n = 0\n  | otherwise = n\n  where n = 10",
+        "(let n = 10 in (| (x < n) => 0 | otherwise => n))",
+    );
+    check_expr(
+        "a = let f x = y\n          where y = x\n        g = 1\n    in f g",
+        "(let f x = (let y = x in y); g = 1 in (f g))",
+    );
+    check_expr(
+        "f x = g x\n  where g y = h y\n          where h z = z",
+        "(let g y = (let h z = z in (h y)) in (g x))",
+    );
+}
+
+#[test]
+fn test_records() {
+    check_type(
+        "type P a = (P { name :: String, tags :: [a] }) (Q Integer)",
+        "P [name :: String, tags :: [a]] | Q [Integer]",
+    );
+    check_type("type E = (E {})", "E []");
+    check_expr("a = P { name = \"x\", age = 1 + 2 }", "P {name = \"x\", age = (1 + 2)}");
+    check_expr("a = p { age = 3 }", "(p {age = 3})");
+    // An update binds tighter than application.
+    check_expr("a = f p { age = 3 } q", "(f (p {age = 3}) q)");
+    check_expr("a = P {} { x = 1 } { y = 2 }", "((P {} {x = 1}) {y = 2})");
+    check_expr(
+        "a = P {\n      name = n,\n      age = 1\n    }",
+        "P {name = n, age = 1}",
+    );
+    check_expr(
+        "a = match p with\n    P { name = n, tags = (t:_) } -> t\n    Q _ -> 0",
+        "(match p with P {name = n, tags = (t:_)} -> t; (Q _) -> 0)",
+    );
+    check_expr("a = \\P { age = a } -> a", "(\\P {age = a} -> a)");
+    assert_eq!(
+        errors("a = P { name }"),
+        [ErrorKind::UnexpectedToken { expected: "Assign".to_string(), found: span!(13, 14) }]
+    );
+}
+
+#[test]
+fn test_classes() {
+    check_type("f :: Eq a => a -> Bool", "(Eq a => (a -> Bool))");
+    check_type("f :: (Ord a, Show b) => a -> b -> String", "(Ord a, Show b => (a -> (b -> String)))");
+
+    let mut parser = crate::parser::Parser::new(
+        FileId::default(),
+        "class Eq a => Container a where\n  empty :: a\n  (<+>) :: a -> a -> a\n  same x y = x == y\ninstance Show a => Show (Box a) where\n  show (Box x) = show x\n  x == y = True",
+    );
+    let (ast, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    match &ast.statements[..] {
+        [Statement::Class(class), Statement::Instance(instance)] => {
+            assert_eq!(class.name.name, "Container");
+            assert_eq!(class.var.name, "a");
+            assert_eq!(class.context.iter().map(|c| c.class.name.as_str()).collect::<Vec<_>>(), ["Eq"]);
+            let signatures: Vec<_> = class.signatures.iter().map(|s| s.id.name.as_str()).collect();
+            assert_eq!(signatures, ["empty", "<+>"]);
+            assert_eq!(class.defaults.len(), 1);
+            assert_eq!(render(&class.defaults[0].expr), "(x == y)");
+
+            assert_eq!(instance.class.name, "Show");
+            assert_eq!(render_type(&instance.ty), "(Box a)");
+            assert_eq!(instance.context.iter().map(|c| c.var.name.as_str()).collect::<Vec<_>>(), ["a"]);
+            let binds: Vec<_> = instance.binds.iter().map(|b| b.name.name.as_str()).collect();
+            assert_eq!(binds, ["show", "=="]);
+        }
+        statements => panic!("expected a class and an instance, got {:?}", statements),
+    }
+}
+
+#[test]
+fn test_modules() {
+    let mut parser = crate::parser::Parser::new(
+        FileId::default(),
+        "module Geometry.Shapes (area, Shape, (<+>))\nimport qualified Data.List as L (map)\nimport Util\nmain = L.map S.area [S.Square 1]",
+    );
+    assert_eq!(
+        parser.imports().iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(),
+        ["Data.List", "Util"]
+    );
+    let (ast, parse_errors) = parser.parse();
+    assert!(parse_errors.is_empty(), "{:?}", parse_errors);
+    match &ast.statements[..] {
+        [Statement::Module(module), Statement::Import(list), Statement::Import(util), Statement::Bind(bind)] => {
+            assert_eq!(module.name.name, "Geometry.Shapes");
+            let exports: Vec<_> = module.exports.iter().flatten().map(|item| item.name.as_str()).collect();
+            assert_eq!(exports, ["area", "Shape", "<+>"]);
+
+            assert_eq!(list.module.name, "Data.List");
+            assert!(list.qualified);
+            assert_eq!(list.alias.as_ref().map(|alias| alias.name.as_str()), Some("L"));
+            assert_eq!(list.items.as_ref().map(Vec::len), Some(1));
+            assert!(!util.qualified && util.alias.is_none() && util.items.is_none());
+
+            assert_eq!(render(&bind.expr), "(L.map S.area [(S.Square 1)])");
+        }
+        statements => panic!("expected a module header and a bind, got {:?}", statements),
+    }
+
+    // `qualified` and `as` are only keywords in an import.
+    check_expr("a = as qualified", "(as qualified)");
+    // A qualified operator has the fixity declared for its name.
+    check_expr("infixl 1 |>\na = x + 1 P.|> f", "(P.|> (x + 1) f)");
+    assert_eq!(
+        errors("x = 1\nimport Util"),
+        [ErrorKind::MisplacedHeader { found: span!(6, 17) }]
+    );
+    assert_eq!(
+        errors("import Util\nmodule Main"),
+        [ErrorKind::MisplacedHeader { found: span!(12, 23) }]
+    );
+}
@@ -17,6 +17,7 @@
 */
 
 use crate::semantics::Span;
+use crate::source::SourceMap;
 use ariadne::*;
 
 #[derive(Debug)]
@@ -49,13 +50,96 @@ pub enum SemanticErrorKind {
         span: Span,
     },
     NotACallee {
-        name: String,
         span: Span,
     },
     UndefinedConstructor {
         constructor_name: String,
         span: Span,
     },
+    /// A field declared, given or matched twice, or declared by two types.
+    DuplicateField {
+        name: String,
+        span: Span,
+    },
+    /// A record built without giving all of its fields.
+    MissingFields {
+        constructor: String,
+        fields: Vec<String>,
+        span: Span,
+    },
+    /// A field that the constructor does not have, or that no record has
+    /// when `constructor` is `None`.
+    UndefinedField {
+        name: String,
+        constructor: Option<String>,
+        span: Span,
+    },
+    ClassAlreadyDefined {
+        class_name: String,
+    },
+    UndefinedClass {
+        class_name: String,
+        span: Span,
+    },
+    /// A definition in an instance or a class of a method that the class
+    /// does not declare.
+    UndefinedMethod {
+        class_name: String,
+        method: String,
+        span: Span,
+    },
+    /// A type variable constrained by a context that does not appear in the
+    /// type, the instance or the class it qualifies.
+    UndefinedTypeVariable {
+        name: String,
+        span: Span,
+    },
+    /// An instance for something else than a type constructor applied to
+    /// distinct type variables.
+    InvalidInstance {
+        span: Span,
+    },
+    DuplicateInstance {
+        class_name: String,
+        type_name: String,
+    },
+    /// An import of a module whose file is not found under the source root.
+    UndefinedModule {
+        module_name: String,
+        span: Span,
+    },
+    /// An import of a module that imports, maybe indirectly, the module
+    /// importing it.
+    CyclicImport {
+        module_name: String,
+        span: Span,
+    },
+    /// A module declaration that does not match the path of its file.
+    WrongModuleName {
+        expected: String,
+        span: Span,
+    },
+    NotExported {
+        module_name: String,
+        name: String,
+        span: Span,
+    },
+    /// A name in an export list that is not in scope.
+    UndefinedExport {
+        name: String,
+        span: Span,
+    },
+    /// A name imported from several modules, used without qualification.
+    AmbiguousName {
+        name: String,
+        candidates: Vec<String>,
+        span: Span,
+    },
+    /// A declaration whose name is qualified by a module.
+    QualifiedDeclaration {
+        name: String,
+        span: Span,
+    },
 }
 
 #[derive(Debug)]
@@ -65,13 +149,8 @@ pub struct SemanticError {
 }
 
 impl SemanticError {
-    pub fn report(&self, filename: &str) {
-        let source = &std::fs::read_to_string(filename).unwrap();
-        let mut report = Report::build(
-            ReportKind::Error,
-            filename,
-            self.span.get_line_number(source),
-        );
+    pub fn report(&self, sources: &SourceMap) {
+        let mut report = Report::build(ReportKind::Error, self.span.file, sources.label(self.span).1.start);
 
         match &self.kind {
             SemanticErrorKind::MultipleDeclarations { name, span } => {
@@ -79,7 +158,7 @@ impl SemanticError {
                     .with_code("multiple-declarations")
                     .with_message(format!("Multiple declarations of '{}'", name))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message("Already defined previously")
                             .with_color(Color::Cyan)
                     )
@@ -90,7 +169,7 @@ impl SemanticError {
                     .with_code("type-already-defined")
                     .with_message(format!("Type '{}' is already defined", type_name))
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message("Type already defined")
                             .with_color(Color::Cyan),
                     );
@@ -100,7 +179,7 @@ impl SemanticError {
                     .with_code("reserved-name")
                     .with_message(format!("'{}' is a reserved name", name))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message("Reserved name")
                             .with_color(Color::Cyan),
                     )
@@ -115,7 +194,7 @@ impl SemanticError {
                     .with_code("wrong-arity")
                     .with_message(format!("Expected {} arguments, found {}", expected, found))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message(format!("Expected {} arguments", expected))
                             .with_color(Color::Cyan),
                     );
@@ -125,7 +204,7 @@ impl SemanticError {
                     .with_code("already-typed-symbol")
                     .with_message(format!("'{}' is already typed", symbol_name))
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message("Symbol already typed")
                             .with_color(Color::Cyan),
                     );
@@ -135,7 +214,7 @@ impl SemanticError {
                     .with_code("undefined-symbol")
                     .with_message(format!("Undefined symbol '{}'", symbol_name))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message("Symbol not found")
                             .with_color(Color::Cyan),
                     );
@@ -145,21 +224,21 @@ impl SemanticError {
                     .with_code("undefined-type")
                     .with_message(format!("Undefined type '{}'", type_name))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message("Type not found")
                             .with_color(Color::Cyan),
                     );
             }
-            SemanticErrorKind::NotACallee { name, span } => {
+            SemanticErrorKind::NotACallee { span } => {
                 report = report
                     .with_code("not-a-callee")
-                    .with_message(format!("'{}' is not a callee", name))
+                    .with_message(format!("'{}' is not a callee", sources.text(*span)))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message("Not a callee")
                             .with_color(Color::Cyan)
                     )
-                    .with_note("Callee are symbols that take arguments, such as constructors or function names.");
+                    .with_note("Callee are expressions that take arguments, such as constructors, functions or lambdas.");
             }
             SemanticErrorKind::UndefinedConstructor {
                 constructor_name,
@@ -169,16 +248,209 @@ impl SemanticError {
                     .with_code("undefined-constructor")
                     .with_message(format!("Undefined constructor '{}'", constructor_name))
                     .with_label(
-                        Label::new((filename, span.start..span.end))
+                        Label::new(sources.label(*span))
                             .with_message("Constructor not found")
                             .with_color(Color::Cyan),
                     );
             }
+            SemanticErrorKind::DuplicateField { name, span } => {
+                report = report
+                    .with_code("duplicate-field")
+                    .with_message(format!("Field '{}' appears more than once", name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Duplicate field")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("A field belongs to a single type, and is given at most once in a record.");
+            }
+            SemanticErrorKind::MissingFields {
+                constructor,
+                fields,
+                span,
+            } => {
+                report = report
+                    .with_code("missing-fields")
+                    .with_message(format!("Missing fields in '{}': {}", constructor, fields.join(", ")))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Fields missing")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::UndefinedField {
+                name,
+                constructor,
+                span,
+            } => {
+                let message = match constructor {
+                    Some(constructor) => format!("Constructor '{}' has no field '{}'", constructor, name),
+                    None => format!("Undefined field '{}'", name),
+                };
+                report = report
+                    .with_code("undefined-field")
+                    .with_message(message)
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Field not found")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::ClassAlreadyDefined { class_name } => {
+                report = report
+                    .with_code("class-already-defined")
+                    .with_message(format!("Class '{}' is already defined", class_name))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Class already defined")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Eq, Ord, Num and Show are builtin classes.");
+            }
+            SemanticErrorKind::UndefinedClass { class_name, span } => {
+                report = report
+                    .with_code("undefined-class")
+                    .with_message(format!("Undefined class '{}'", class_name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Class not found")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::UndefinedMethod {
+                class_name,
+                method,
+                span,
+            } => {
+                report = report
+                    .with_code("undefined-method")
+                    .with_message(format!("Class '{}' has no method '{}'", class_name, method))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Method not found")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::UndefinedTypeVariable { name, span } => {
+                report = report
+                    .with_code("undefined-type-variable")
+                    .with_message(format!("Type variable '{}' is constrained but never used", name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Not in the type")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::InvalidInstance { span } => {
+                report = report
+                    .with_code("invalid-instance")
+                    .with_message(format!("Cannot declare an instance for '{}'", sources.text(*span)))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Invalid instance type")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Instances are declared for a type applied to distinct type variables, as in `Maybe a`, `[a]` or `(a, b)`.");
+            }
+            SemanticErrorKind::DuplicateInstance {
+                class_name,
+                type_name,
+            } => {
+                report = report
+                    .with_code("duplicate-instance")
+                    .with_message(format!("'{}' is already an instance of '{}'", type_name, class_name))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Instance already declared")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Primitive types, lists and tuples are builtin instances of Eq, Ord and Show, and Integer and Float of Num.");
+            }
+            SemanticErrorKind::UndefinedModule { module_name, span } => {
+                report = report
+                    .with_code("undefined-module")
+                    .with_message(format!("Module '{}' not found", module_name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Module not found")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Module `A.B` is the file `A/B.rk` of the source root.");
+            }
+            SemanticErrorKind::CyclicImport { module_name, span } => {
+                report = report
+                    .with_code("cyclic-import")
+                    .with_message(format!("Module '{}' imports itself", module_name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("This import leads back here")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::WrongModuleName { expected, span } => {
+                report = report
+                    .with_code("wrong-module-name")
+                    .with_message(format!("This file should declare module '{}'", expected))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Does not match the path of the file")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::NotExported {
+                module_name,
+                name,
+                span,
+            } => {
+                report = report
+                    .with_code("not-exported")
+                    .with_message(format!("Module '{}' does not export '{}'", module_name, name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Not exported")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::UndefinedExport { name, span } => {
+                report = report
+                    .with_code("undefined-export")
+                    .with_message(format!("Cannot export undefined '{}'", name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Not in scope")
+                            .with_color(Color::Cyan),
+                    );
+            }
+            SemanticErrorKind::AmbiguousName {
+                name,
+                candidates,
+                span,
+            } => {
+                report = report
+                    .with_code("ambiguous-name")
+                    .with_message(format!("'{}' could be any of {}", name, candidates.join(", ")))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Ambiguous name")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Qualify it with the module it comes from.");
+            }
+            SemanticErrorKind::QualifiedDeclaration { name, span } => {
+                report = report
+                    .with_code("qualified-declaration")
+                    .with_message(format!("Cannot declare the qualified name '{}'", name))
+                    .with_label(
+                        Label::new(sources.label(*span))
+                            .with_message("Qualified name")
+                            .with_color(Color::Cyan),
+                    );
+            }
         }
 
         report
             .finish()
-            .print((filename, Source::from(source)))
+            .print(sources)
             .unwrap();
     }
 }
@@ -0,0 +1,184 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Finds the files of the modules a program imports and parses them. Module
+//! `A.B` is the file `A/B.rk` of the source root, usually the directory of
+//! the file the program starts from. The Prelude is part of the compiler
+//! and imported by every other module, unless it imports it itself.
+
+use crate::ast::{Fixity, Identifier, ImportDecl, Program, Span, Statement};
+use crate::parser::error::Error;
+use crate::parser::lexer::TokenKind;
+use crate::parser::Parser;
+use crate::semantics::error::{SemanticError, SemanticErrorKind};
+use crate::source::{FileId, SourceMap};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// The name of the module of a file without a module declaration.
+pub const MAIN_MODULE: &str = "Main";
+
+/// The module every other one imports, whose source is part of the
+/// compiler.
+pub const PRELUDE: &str = "Prelude";
+
+const PRELUDE_SOURCE: &str = include_str!("../../lib/Prelude.rk");
+
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub program: Program<Span>,
+    /// The fixities the module declares, which the modules importing it
+    /// use too.
+    pub fixities: HashMap<String, Fixity>,
+}
+
+pub struct Loader<'s> {
+    root: PathBuf,
+    sources: &'s mut SourceMap,
+    /// The modules loaded so far, each after the modules it imports.
+    pub modules: Vec<Module>,
+    pub syntax_errors: Vec<Error>,
+    pub errors: Vec<SemanticError>,
+    /// The modules being loaded, each imported by the one before it.
+    loading: Vec<String>,
+}
+
+impl<'s> Loader<'s> {
+    pub fn new(root: PathBuf, sources: &'s mut SourceMap) -> Self {
+        Loader {
+            root,
+            sources,
+            modules: Vec::new(),
+            syntax_errors: Vec::new(),
+            errors: Vec::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    /// Loads the program starting from the file `name`, and every module it
+    /// imports. It is the last of the modules.
+    pub fn load_main(&mut self, name: &str, text: String) {
+        let file = self.sources.add(name, text);
+        self.load_file(file, None);
+    }
+
+    /// Loads the module `name` imported at `span`, unless it already is.
+    fn load(&mut self, name: &str, span: Span) {
+        if self.modules.iter().any(|module| module.name == name) {
+            return;
+        }
+        if self.loading.iter().any(|module| module == name) {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::CyclicImport {
+                    module_name: name.to_string(),
+                    span,
+                },
+                span,
+            });
+            return;
+        }
+
+        if name == PRELUDE {
+            let file = self.sources.add("Prelude.rk", PRELUDE_SOURCE.to_string());
+            self.load_file(file, Some(name));
+            return;
+        }
+
+        let path = self.root.join(name.replace('.', "/")).with_extension("rk");
+        let Ok(text) = fs::read_to_string(&path) else {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::UndefinedModule {
+                    module_name: name.to_string(),
+                    span,
+                },
+                span,
+            });
+            return;
+        };
+        let file = self.sources.add(&path.display().to_string(), text);
+        self.load_file(file, Some(name));
+    }
+
+    /// Parses `file`, after the modules it imports so that their operators
+    /// have their fixity. `expected` is the name it is imported by.
+    fn load_file(&mut self, file: FileId, expected: Option<&str>) {
+        // The parser borrows the text while other files are added.
+        let text = self.sources.file(file).text().to_string();
+        let mut parser = Parser::new(file, &text);
+        let declared = match &parser.tokens[..] {
+            [first, second, ..] if first.kind == TokenKind::Module => match second.kind {
+                TokenKind::PCIdentifier(name) => Some(name.to_string()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let name = expected
+            .map(str::to_string)
+            .or(declared)
+            .unwrap_or_else(|| MAIN_MODULE.to_string());
+
+        let fixities = parser.fixities.clone();
+        let mut imports = parser.imports();
+        let implicit = name != PRELUDE && imports.iter().all(|(import, _)| import != PRELUDE);
+        if implicit {
+            imports.insert(0, (PRELUDE.to_string(), Span::default()));
+        }
+        self.loading.push(name.clone());
+        for (import, span) in imports {
+            self.load(&import, span);
+            if let Some(module) = self.modules.iter().find(|module| module.name == import) {
+                for (op, fixity) in &module.fixities {
+                    parser.fixities.entry(op.clone()).or_insert(*fixity);
+                }
+            }
+        }
+        self.loading.pop();
+
+        let (mut program, errors) = parser.parse();
+        self.syntax_errors.extend(errors);
+        if implicit {
+            let header = matches!(program.statements.first(), Some(Statement::Module(_)));
+            let import = ImportDecl {
+                module: Identifier::new(PRELUDE.to_string(), Span::default()),
+                qualified: false,
+                alias: None,
+                items: None,
+                span: Span::default(),
+            };
+            program.statements.insert(header as usize, Statement::Import(import));
+        }
+        if let (Some(expected), Some(Statement::Module(decl))) = (expected, program.statements.first()) {
+            if decl.name.name != expected {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::WrongModuleName {
+                        expected: expected.to_string(),
+                        span: decl.name.span,
+                    },
+                    span: decl.span,
+                });
+            }
+        }
+        self.modules.push(Module {
+            name,
+            program,
+            fixities,
+        });
+    }
+}
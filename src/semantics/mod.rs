@@ -16,50 +16,75 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-mod error;
-mod tc;
-mod warning;
+pub mod error;
+pub mod loader;
+pub mod module;
+pub mod tc;
+#[cfg(test)]
+mod test;
+pub mod warning;
 
 use self::warning::SemanticWarningKind;
 use crate::ast::{self, *};
 use crate::semantics::error::*;
+use crate::semantics::tc::class::{builtin_class, instance_head, BUILTIN_CLASSES};
 use crate::semantics::warning::SemanticWarning;
+use crate::vm::native::NATIVES;
+use crate::vm::ENTRY;
 use polonius_the_crab::{polonius, polonius_return};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
+/// The types of the runtime. The others, `Maybe` and `Either` among them,
+/// are declared in Risk, most in the Prelude.
 const BUILTIN_TYPES: [&str; 7] = [
     "Integer", "Float", "Bool", "String", "Char", "True", "False",
 ];
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Symbol {
-    span: Span,
+    name: String,
     scope_id: u16,
     level: u16,
 }
 
+/// `span` is where the symbol is declared.
 #[derive(Debug, PartialEq)]
 pub struct SymbolData {
-    arity: u8,
     used: bool,
+    span: Span,
 }
 
 #[derive(Debug)]
 pub struct TypeData {
     arity: u8,
     used: bool,
+    span: Span,
 }
 
 #[derive(Debug)]
 pub struct VariantData {
     arity: u8,
     used: bool,
+    span: Span,
+    /// The names of the fields of a record, empty for a positional variant.
+    fields: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct ClassData {
+    /// The methods, in the order of their signatures.
+    methods: Vec<String>,
+    /// The methods that have a default definition.
+    defaults: Vec<String>,
 }
 
-type VariantName = Span;
-type TypeName = Span;
+type VariantName = String;
+type TypeName = String;
 type FuncName = String;
+type FieldName = String;
+type ClassName = String;
+type MethodName = String;
 
 #[derive(Debug)]
 pub struct AnalysisOutput {
@@ -68,37 +93,150 @@ pub struct AnalysisOutput {
     pub symbols: HashMap<Symbol, SymbolData>,
     pub types: HashMap<TypeName, TypeData>,
     pub variants: HashMap<VariantName, VariantData>,
+    pub fields: HashMap<FieldName, TypeName>, // the type each field belongs to
     pub signatures: Vec<FuncName>, // symbols to which a type has been assigned
+    pub classes: HashMap<ClassName, ClassData>,
+    pub methods: HashMap<MethodName, ClassName>, // the class each method belongs to
+    pub instances: Vec<(ClassName, TypeName)>, // the instances declared so far
+    /// The declarations other modules can import, never reported unused.
+    pub exported: HashSet<String>,
     pub scope_id: u16,
     pub level: u16,
+    /// The scopes enclosing the current expression, innermost last.
+    scopes: Vec<u16>,
+}
+
+impl Default for AnalysisOutput {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AnalysisOutput {
     pub fn new() -> Self {
+        // Natives and the methods of the builtin classes are global symbols
+        // that are always considered used.
+        let methods = BUILTIN_CLASSES
+            .iter()
+            .flat_map(|class| class.methods.iter().map(|(name, _)| *name));
+        let symbols = NATIVES
+            .iter()
+            .map(|native| native.name)
+            .chain(methods.clone())
+            .map(|name| {
+                (
+                    Symbol {
+                        name: name.to_string(),
+                        scope_id: 0,
+                        level: 0,
+                    },
+                    SymbolData {
+                        used: true,
+                        span: Span::default(),
+                    },
+                )
+            })
+            .collect();
+        let classes = BUILTIN_CLASSES
+            .iter()
+            .map(|class| {
+                let data = ClassData {
+                    methods: class.methods.iter().map(|(name, _)| name.to_string()).collect(),
+                    defaults: Vec::new(),
+                };
+                (class.name.to_string(), data)
+            })
+            .collect();
+        let methods = BUILTIN_CLASSES
+            .iter()
+            .flat_map(|class| {
+                class
+                    .methods
+                    .iter()
+                    .map(|(name, _)| (name.to_string(), class.name.to_string()))
+            })
+            .collect();
+
         Self {
             errors: Vec::new(),
             warnings: Vec::new(),
-            symbols: HashMap::new(),
+            symbols,
             types: HashMap::new(),
             variants: HashMap::new(),
+            fields: HashMap::new(),
             signatures: Vec::new(),
+            classes,
+            methods,
+            instances: Vec::new(),
+            exported: HashSet::new(),
             scope_id: 0,
             level: 0,
+            scopes: Vec::new(),
         }
     }
 
-    fn find_identifier(&mut self, name: Span) -> Option<&mut SymbolData> {
+    /// Opens a scope nested in the current one.
+    fn enter_scope(&mut self) {
+        self.scope_id += 1;
+        self.level += 1;
+        self.scopes.push(self.scope_id);
+    }
+
+    fn exit_scope(&mut self) {
+        self.level -= 1;
+        self.scopes.pop();
+    }
+
+    /// The symbol `name` declared in the current scope.
+    fn symbol(&self, name: &str) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            scope_id: self.scopes.last().copied().unwrap_or(0),
+            level: self.level,
+        }
+    }
+
+    /// Declares the bind `name` in the current scope. A function can be
+    /// defined by several clauses, a constant only once.
+    fn declare_bind(&mut self, name: &Identifier, constant: bool, span: Span) {
+        let sym = self.symbol(&name.name);
+        if !self.symbols.contains_key(&sym) {
+            self.symbols.insert(
+                sym,
+                SymbolData {
+                    used: false,
+                    span: name.span,
+                },
+            );
+        } else if constant {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::MultipleDeclarations {
+                    name: name.name.clone(),
+                    span: name.span,
+                },
+                span,
+            });
+        }
+    }
+
+    /// Looks `name` up from the current scope outwards, marking it used.
+    fn find_identifier(&mut self, name: &str) -> Option<&mut SymbolData> {
         let mut sym = Symbol {
-            span: name,
-            scope_id: self.scope_id + 1,
+            name: name.to_string(),
+            scope_id: 0,
             level: 0,
         };
+        let scopes: Vec<(u16, u16)> = std::iter::once(0)
+            .chain(self.scopes.iter().copied())
+            .enumerate()
+            .map(|(level, scope_id)| (level as u16, scope_id))
+            .collect();
 
         let mut symbols = &mut self.symbols;
 
-        for level in (0..=self.level).rev() {
+        for (level, scope_id) in scopes.into_iter().rev() {
             sym.level = level;
-            sym.scope_id = if sym.level == 0 { 0 } else { sym.scope_id - 1 };
+            sym.scope_id = scope_id;
 
             polonius!(|symbols| -> Option<&'polonius mut SymbolData> {
                 if let Some(data) = symbols.get_mut(&sym) {
@@ -110,79 +248,239 @@ impl AnalysisOutput {
         None
     }
 
-    pub fn analyze_statement(&mut self, statement: Statement<ast::Span>) {
-        match statement {
-            Statement::Bind(Bind {
-                name,
-                args,
-                expr,
-                span,
-            }) => {
-                let sym = Symbol {
-                    span: name.span.clone(),
-                    scope_id: self.scope_id,
-                    level: self.level,
-                };
+    /// Declares the fields of a record variant of `type_name`. Their
+    /// selectors are global functions, so a field cannot have the name of
+    /// another global.
+    fn declare_fields(&mut self, type_name: &str, fields: &[Identifier], span: Span) {
+        for (i, field) in fields.iter().enumerate() {
+            // The variants of a type can share a field, two types cannot.
+            let declared = self.fields.get(&field.name).map(String::as_str);
+            if fields[..i].iter().any(|other| other.name == field.name)
+                || declared.is_some_and(|other| other != type_name)
+            {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::DuplicateField {
+                        name: field.name.clone(),
+                        span: field.span,
+                    },
+                    span,
+                });
+                continue;
+            }
+            if declared.is_some() {
+                continue;
+            }
 
-                let some_arguments = args.len() != 0;
+            let sym = Symbol {
+                name: field.name.clone(),
+                scope_id: 0,
+                level: 0,
+            };
+            if self.symbols.contains_key(&sym) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::MultipleDeclarations {
+                        name: field.name.clone(),
+                        span: field.span,
+                    },
+                    span,
+                });
+                continue;
+            }
+            // Selectors are never reported as unused.
+            self.symbols.insert(
+                sym,
+                SymbolData {
+                    used: true,
+                    span: field.span,
+                },
+            );
+            self.fields.insert(field.name.clone(), type_name.to_string());
+        }
+    }
 
-                if !some_arguments {
-                    if !self.symbols.contains_key(&sym) {
-                        self.symbols.insert(
-                            sym,
-                            SymbolData {
-                                arity: 0,
-                                used: false,
-                            },
-                        );
-                    } else {
-                        self.errors.push(SemanticError {
-                            kind: SemanticErrorKind::MultipleDeclarations {
-                                name: name.name,
-                                span: name.span,
-                            },
-                            span: span.clone(),
-                        });
-                    }
-                } else if !self.symbols.contains_key(&sym) {
-                    self.symbols.insert(
-                        sym,
-                        SymbolData {
-                            arity: args.len() as u8,
-                            used: false,
-                        },
-                    );
-                }
+    /// Reports the fields given more than once in a record.
+    fn check_duplicate_fields(&mut self, given: &[&Identifier], span_context: Span) {
+        for (i, field) in given.iter().enumerate() {
+            if given[..i].iter().any(|other| other.name == field.name) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::DuplicateField {
+                        name: field.name.clone(),
+                        span: field.span,
+                    },
+                    span: span_context,
+                });
+            }
+        }
+    }
 
-                if some_arguments {
-                    self.level += 1;
-                    self.scope_id += 1;
+    /// Checks the fields given to the record constructor `id`, returning
+    /// the fields it declares if it exists.
+    fn check_record_fields(
+        &mut self,
+        id: &Identifier,
+        given: &[&Identifier],
+        span_context: Span,
+    ) -> Option<Vec<String>> {
+        let Some(data) = self.variants.get_mut(&id.name) else {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::UndefinedConstructor {
+                    constructor_name: id.name.clone(),
+                    span: id.span,
+                },
+                span: span_context,
+            });
+            return None;
+        };
+        data.used = true;
+        let fields = data.fields.clone();
 
-                    for arg in args {
-                        self.analyze_pattern(arg, span.clone());
-                    }
-                }
+        self.check_duplicate_fields(given, span_context);
+        for field in given {
+            if !fields.contains(&field.name) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::UndefinedField {
+                        name: field.name.clone(),
+                        constructor: Some(id.name.clone()),
+                        span: field.span,
+                    },
+                    span: span_context,
+                });
+            }
+        }
+        Some(fields)
+    }
 
-                self.analyze_expr(expr, span);
+    /// Declares a class and its methods, which are global functions.
+    fn declare_class(&mut self, decl: &ClassDecl<Span>) {
+        if self.classes.contains_key(&decl.name.name) {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::ClassAlreadyDefined {
+                    class_name: decl.name.name.clone(),
+                },
+                span: decl.span,
+            });
+            return;
+        }
 
-                if some_arguments {
-                    self.level -= 1;
-                }
+        let mut methods = Vec::new();
+        for signature in &decl.signatures {
+            let name = &signature.id;
+            let sym = Symbol {
+                name: name.name.clone(),
+                scope_id: 0,
+                level: 0,
+            };
+            if self.methods.contains_key(&name.name) || self.symbols.contains_key(&sym) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::MultipleDeclarations {
+                        name: name.name.clone(),
+                        span: name.span,
+                    },
+                    span: signature.span,
+                });
+                continue;
+            }
+            // Methods are never reported as unused.
+            self.symbols.insert(
+                sym,
+                SymbolData {
+                    used: true,
+                    span: name.span,
+                },
+            );
+            self.methods.insert(name.name.clone(), decl.name.name.clone());
+            methods.push(name.name.clone());
+        }
+
+        let mut defaults: Vec<String> = Vec::new();
+        for bind in &decl.defaults {
+            if methods.contains(&bind.name.name) && !defaults.contains(&bind.name.name) {
+                defaults.push(bind.name.name.clone());
+            }
+        }
+        self.classes
+            .insert(decl.name.name.clone(), ClassData { methods, defaults });
+    }
+
+    /// Checks that the classes of `context` exist and that it constrains
+    /// some of `vars`.
+    fn analyze_context(&mut self, context: &[Constraint], vars: &[String], span_context: Span) {
+        for constraint in context {
+            if !self.classes.contains_key(&constraint.class.name) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::UndefinedClass {
+                        class_name: constraint.class.name.clone(),
+                        span: constraint.class.span,
+                    },
+                    span: span_context,
+                });
+            }
+            if !vars.contains(&constraint.var.name) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::UndefinedTypeVariable {
+                        name: constraint.var.name.clone(),
+                        span: constraint.var.span,
+                    },
+                    span: span_context,
+                });
+            }
+        }
+    }
+
+    /// The head of an instance and its type variables, see
+    /// `tc::class::instance_head`.
+    fn instance_head(&mut self, ty: &Type, span_context: Span) -> Option<(String, Vec<String>)> {
+        let head = instance_head(ty);
+        if head.is_none() {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::InvalidInstance {
+                    span: *ty.get_span(),
+                },
+                span: span_context,
+            });
+        }
+        head
+    }
+
+    /// Analyzes a bind declared beforehand.
+    fn analyze_bind(&mut self, Bind { args, expr, span, .. }: Bind<ast::Span>) {
+        let some_arguments = args.len() != 0;
+
+        if some_arguments {
+            self.enter_scope();
+
+            for arg in args {
+                self.analyze_pattern(arg, span);
             }
+        }
+
+        self.analyze_expr(expr, span);
+
+        if some_arguments {
+            self.exit_scope();
+        }
+    }
+
+    pub fn analyze_statement(&mut self, statement: Statement<ast::Span>) {
+        match statement {
+            // Top-level binds are declared beforehand by `analyze`, so that
+            // they can be used above their definition.
+            Statement::Bind(bind) => self.analyze_bind(bind),
             Statement::TypeDecl(TypeDecl {
                 name,
                 variants,
                 typevars,
                 span,
             }) => {
-                if !self.types.contains_key(&name.span) {
+                let type_name = name.name.clone();
+                if !self.types.contains_key(&name.name) {
                     if !BUILTIN_TYPES.contains(&name.name.as_str()) {
                         self.types.insert(
-                            // name.name,
-                            span.clone(),
+                            name.name,
                             TypeData {
                                 arity: typevars.len() as u8,
                                 used: false,
+                                span: name.span,
                             },
                         );
                     } else {
@@ -191,7 +489,7 @@ impl AnalysisOutput {
                                 name: name.name,
                                 span: name.span,
                             },
-                            span: span.clone(),
+                            span,
                         });
                     }
                 } else {
@@ -199,18 +497,20 @@ impl AnalysisOutput {
                         kind: SemanticErrorKind::TypeAlreadyDefined {
                             type_name: name.name,
                         },
-                        span: span.clone(),
+                        span,
                     });
                 }
 
                 for variant in variants {
-                    if !self.variants.contains_key(&variant.id.span) {
+                    if !self.variants.contains_key(&variant.id.name) {
                         if !BUILTIN_TYPES.contains(&variant.id.name.as_str()) {
                             self.variants.insert(
-                                variant.id.span,
+                                variant.id.name,
                                 VariantData {
                                     arity: variant.types.len() as u8,
                                     used: false,
+                                    span: variant.id.span,
+                                    fields: variant.fields.iter().map(|field| field.name.clone()).collect(),
                                 },
                             );
                         } else {
@@ -219,7 +519,7 @@ impl AnalysisOutput {
                                     name: variant.id.name,
                                     span: variant.id.span,
                                 },
-                                span: span.clone(),
+                                span,
                             });
                         }
                     } else {
@@ -228,12 +528,14 @@ impl AnalysisOutput {
                                 name: variant.id.name,
                                 span: variant.id.span,
                             },
-                            span: span.clone(),
+                            span,
                         });
                     }
 
+                    self.declare_fields(&type_name, &variant.fields, span);
+
                     for ty in variant.types {
-                        self.analyze_type(ty, span.clone());
+                        self.analyze_type(ty, span);
                     }
                 }
             }
@@ -245,30 +547,132 @@ impl AnalysisOutput {
                         kind: SemanticErrorKind::AlreadyTypedSymbol {
                             symbol_name: id.name,
                         },
-                        span: span.clone(),
+                        span,
                     });
                 }
                 self.analyze_type(ty, span);
             }
+            // Fixities are resolved by the parser, and modules by
+            // `module::resolve` beforehand.
+            Statement::Fixity(_) | Statement::Module(_) | Statement::Import(_) => {}
+            // The class and its methods are declared by `analyze`.
+            Statement::Class(ClassDecl {
+                context,
+                name,
+                var,
+                signatures,
+                defaults,
+                span,
+            }) => {
+                self.analyze_context(&context, &[var.name], span);
+
+                for signature in &signatures {
+                    self.analyze_type(signature.ty.clone(), signature.span);
+                }
+
+                for bind in defaults {
+                    if !signatures.iter().any(|signature| signature.id == bind.name) {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::UndefinedMethod {
+                                class_name: name.name.clone(),
+                                method: bind.name.name.clone(),
+                                span: bind.name.span,
+                            },
+                            span,
+                        });
+                    }
+                    self.analyze_statement(Statement::Bind(bind));
+                }
+            }
+            Statement::Instance(InstanceDecl {
+                context,
+                class,
+                ty,
+                binds,
+                span,
+            }) => {
+                let head = self.instance_head(&ty, span);
+                self.analyze_type(ty, span);
+
+                let vars = head.as_ref().map(|(_, vars)| vars.clone()).unwrap_or_default();
+                self.analyze_context(&context, &vars, span);
+
+                let Some(data) = self.classes.get(&class.name) else {
+                    self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::UndefinedClass {
+                            class_name: class.name.clone(),
+                            span: class.span,
+                        },
+                        span,
+                    });
+                    for bind in binds {
+                        self.analyze_statement(Statement::Bind(bind));
+                    }
+                    return;
+                };
+
+                let missing: Vec<String> = data
+                    .methods
+                    .iter()
+                    .filter(|method| {
+                        !data.defaults.contains(method) && !binds.iter().any(|bind| bind.name.name == **method)
+                    })
+                    .cloned()
+                    .collect();
+                let methods = data.methods.clone();
+
+                if let Some((head, _)) = head {
+                    let builtin = builtin_class(&class.name).is_some_and(|builtin| {
+                        builtin.primitives.contains(&head.as_str())
+                            || (builtin.derived && (head == "[]" || head.starts_with("(,")))
+                    });
+                    let instance = (class.name.clone(), head);
+                    if builtin || self.instances.contains(&instance) {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::DuplicateInstance {
+                                class_name: instance.0,
+                                type_name: instance.1,
+                            },
+                            span,
+                        });
+                    } else {
+                        self.instances.push(instance);
+                    }
+                }
+
+                if !missing.is_empty() {
+                    self.warnings.push(SemanticWarning {
+                        kind: SemanticWarningKind::MissingMethods {
+                            class_name: class.name.clone(),
+                            methods: missing,
+                        },
+                        span: class.span,
+                    });
+                }
+
+                for bind in binds {
+                    if !methods.contains(&bind.name.name) {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::UndefinedMethod {
+                                class_name: class.name.clone(),
+                                method: bind.name.name.clone(),
+                                span: bind.name.span,
+                            },
+                            span,
+                        });
+                    }
+                    self.analyze_statement(Statement::Bind(bind));
+                }
+            }
         }
     }
 
     pub fn analyze_expr(&mut self, expr: ParsedExpr, span_context: Span) {
         match expr {
+            // Functions and constructors are values like any other, whether
+            // they are applied is left to the type checker.
             ParsedExpr::Identifier { id: Identifier { name, span } } => {
-                if let Some(data) = self.find_identifier(span.clone()) {
-                    let arity = data.arity;
-                    if arity != 0 {
-                        self.errors.push(SemanticError {
-                            kind: SemanticErrorKind::WrongArity {
-                                expected: arity as usize,
-                                found: 0,
-                                span,
-                            },
-                            span: span_context,
-                        });
-                    }
-                } else {
+                if self.find_identifier(&name).is_none() {
                     self.errors.push(SemanticError {
                         kind: SemanticErrorKind::UndefinedSymbol {
                             symbol_name: name,
@@ -279,19 +683,7 @@ impl AnalysisOutput {
                 }
             }
             ParsedExpr::PCIdentifier { id: Identifier { name, span } } => {
-                if let Some(data) = self.variants.get_mut(&span) {
-                    let arity = data.arity;
-
-                    if arity != 0 {
-                        self.errors.push(SemanticError {
-                            kind: SemanticErrorKind::WrongArity {
-                                expected: arity as usize,
-                                found: 0,
-                                span,
-                            },
-                            span: span_context,
-                        });
-                    }
+                if let Some(data) = self.variants.get_mut(&name) {
                     data.used = true;
                 } else {
                     self.errors.push(SemanticError {
@@ -303,129 +695,151 @@ impl AnalysisOutput {
                     });
                 }
             }
-            ParsedExpr::App(App { ident, args, span }) => {
-                if ident.name.chars().next().unwrap().is_lowercase() {
-                    if let Some(data) = self.find_identifier(ident.span) {
-                        let arity = data.arity;
-
-                        if arity != args.len() as u8 {
-                            self.errors.push(SemanticError {
-                                kind: SemanticErrorKind::WrongArity {
-                                    expected: arity as usize,
-                                    found: args.len(),
-                                    span,
-                                },
-                                span: span_context.clone(),
-                            });
-                        }
-                    }
-                } else if let Some(data) = self.variants.get_mut(&ident.span) {
-                    data.used = true;
-
-                    if data.arity != args.len() as u8 {
+            ParsedExpr::App(App { head, args, .. }) => {
+                match *head {
+                    ParsedExpr::Literal(_) | ParsedExpr::List { .. } | ParsedExpr::Tuple { .. } => {
                         self.errors.push(SemanticError {
-                            kind: SemanticErrorKind::WrongArity {
-                                expected: data.arity as usize,
-                                found: args.len(),
-                                span,
-                            },
-                            span: span_context.clone(),
+                            kind: SemanticErrorKind::NotACallee { span: *head.get_span() },
+                            span: span_context,
                         });
                     }
-                } else {
-                    self.errors.push(SemanticError {
-                        kind: SemanticErrorKind::NotACallee {
-                            name: ident.name,
-                            span: span,
-                        },
-                        span: span_context.clone(),
-                    });
+                    head => self.analyze_expr(head, span_context),
                 }
 
                 for arg in args {
-                    self.analyze_expr(arg, span_context.clone());
+                    self.analyze_expr(arg, span_context);
                 }
             }
             ParsedExpr::Condition { cond, then, els, ann } => {
-                self.analyze_expr(*cond, ann.clone());
-                self.analyze_expr(*then, ann.clone());
+                self.analyze_expr(*cond, ann);
+                self.analyze_expr(*then, ann);
                 self.analyze_expr(*els, ann);
             }
             ParsedExpr::Let { binds, ret, ann } => {
-                self.level += 1;
-                self.scope_id += 1;
+                self.enter_scope();
 
+                // As at the top level, the binds of a block can be used
+                // above their definition.
+                for bind in &binds {
+                    self.declare_bind(&bind.name, bind.args.is_empty(), bind.span);
+                }
                 for bind in binds {
-                    self.analyze_statement(Statement::Bind(bind));
+                    self.analyze_bind(bind);
                 }
 
                 self.analyze_expr(*ret, ann);
 
-                self.level -= 1;
+                self.exit_scope();
             }
             ParsedExpr::Match { referral, cases, .. } => {
-                self.analyze_expr(*referral, span_context.clone());
+                self.analyze_expr(*referral, span_context);
 
                 for case in cases {
-                    self.level += 1;
-                    self.scope_id += 1;
+                    self.enter_scope();
 
-                    self.analyze_pattern(case.0, span_context.clone());
-                    self.analyze_expr(*case.1, span_context.clone());
+                    self.analyze_pattern(case.0, span_context);
+                    self.analyze_expr(*case.1, span_context);
 
-                    self.level -= 1;
+                    self.exit_scope();
                 }
             }
             ParsedExpr::BinOp { lhs, rhs, .. } => {
-                self.analyze_expr(*lhs, span_context.clone());
+                self.analyze_expr(*lhs, span_context);
                 self.analyze_expr(*rhs, span_context);
             }
+            ParsedExpr::UnOp { expr, .. } => self.analyze_expr(*expr, span_context),
             ParsedExpr::Lambda { args, ret, .. } => {
                 let some_arguments = args.len() != 0;
 
                 if some_arguments {
-                    self.level += 1;
-                    self.scope_id += 1;
+                    self.enter_scope();
 
                     for arg in args {
-                        self.analyze_pattern(arg, span_context.clone());
+                        self.analyze_pattern(arg, span_context);
                     }
                 }
 
                 self.analyze_expr(*ret, span_context);
 
                 if some_arguments {
-                    self.level -= 1;
+                    self.exit_scope();
                 }
             }
             ParsedExpr::Ann { expr, ann, .. } => {
-                self.analyze_expr(*expr, span_context.clone());
+                self.analyze_expr(*expr, span_context);
                 self.analyze_type(ann.1, span_context);
             }
             ParsedExpr::List { list, ..} | ParsedExpr::Tuple { list, .. } => {
                 for item in list {
-                    self.analyze_expr(item, span_context.clone());
+                    self.analyze_expr(item, span_context);
                 }
             }
-            ParsedExpr::Literal(_) => {}
+            ParsedExpr::Record { id, fields, ann } => {
+                let given: Vec<&Identifier> = fields.iter().map(|(name, _)| name).collect();
+                if let Some(declared) = self.check_record_fields(&id, &given, span_context) {
+                    let missing: Vec<String> = declared
+                        .into_iter()
+                        .filter(|field| !given.iter().any(|name| name.name == *field))
+                        .collect();
+                    if !missing.is_empty() {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::MissingFields {
+                                constructor: id.name.clone(),
+                                fields: missing,
+                                span: ann,
+                            },
+                            span: span_context,
+                        });
+                    }
+                }
+
+                for (_, value) in fields {
+                    self.analyze_expr(value, span_context);
+                }
+            }
+            ParsedExpr::Update { expr, fields, .. } => {
+                self.analyze_expr(*expr, span_context);
+
+                let given: Vec<&Identifier> = fields.iter().map(|(name, _)| name).collect();
+                self.check_duplicate_fields(&given, span_context);
+                for field in given {
+                    if !self.fields.contains_key(&field.name) {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::UndefinedField {
+                                name: field.name.clone(),
+                                constructor: None,
+                                span: field.span,
+                            },
+                            span: span_context,
+                        });
+                    }
+                }
+
+                for (_, value) in fields {
+                    self.analyze_expr(value, span_context);
+                }
+            }
+            ParsedExpr::Guards { guards, .. } => {
+                for (cond, body) in guards {
+                    self.analyze_expr(cond, span_context);
+                    self.analyze_expr(body, span_context);
+                }
+            }
+            ParsedExpr::Literal(_) | ParsedExpr::Error { .. } => {}
         }
     }
 
     pub fn analyze_pattern(&mut self, pattern: Pattern, span_context: Span) {
         match pattern {
             Pattern::Variable(id) => {
-                let sym = Symbol {
-                    span: id.span.clone(),
-                    scope_id: self.scope_id,
-                    level: self.level,
-                };
+                let sym = self.symbol(&id.name);
 
                 if !self.symbols.contains_key(&sym) {
                     self.symbols.insert(
                         sym,
                         SymbolData {
-                            arity: 0,
                             used: false,
+                            span: id.span,
                         },
                     );
                 } else {
@@ -434,16 +848,16 @@ impl AnalysisOutput {
                             name: id.name,
                             span: id.span,
                         },
-                        span: span_context.clone(),
+                        span: span_context,
                     });
                 }
             }
             Pattern::ListCons(lhs, rhs, _) => {
-                self.analyze_pattern(*lhs, span_context.clone());
+                self.analyze_pattern(*lhs, span_context);
                 self.analyze_pattern(*rhs, span_context);
             }
             Pattern::App(id, patterns, ..) => {
-                if let Some(data) = self.variants.get_mut(&id.span) {
+                if let Some(data) = self.variants.get_mut(&id.name) {
                     data.used = true;
 
                     if data.arity != patterns.len() as u8 {
@@ -453,7 +867,7 @@ impl AnalysisOutput {
                                 found: patterns.len(),
                                 span: id.span,
                             },
-                            span: span_context.clone(),
+                            span: span_context,
                         });
                     }
                 } else {
@@ -462,16 +876,16 @@ impl AnalysisOutput {
                             constructor_name: id.name,
                             span: id.span,
                         },
-                        span: span_context.clone(),
+                        span: span_context,
                     });
                 }
 
-                // for pattern in patterns {
-                //    self.analyze_pattern(pattern);
-                // }
+                for pattern in patterns {
+                    self.analyze_pattern(pattern, span_context);
+                }
             }
             Pattern::Id(id, ..) => {
-                if let Some(data) = self.variants.get_mut(&id.span) {
+                if let Some(data) = self.variants.get_mut(&id.name) {
                     let arity = data.arity;
 
                     if arity != 0 {
@@ -481,7 +895,7 @@ impl AnalysisOutput {
                                 found: 0,
                                 span: id.span,
                             },
-                            span: span_context.clone(),
+                            span: span_context,
                         });
                     }
                 } else {
@@ -494,7 +908,24 @@ impl AnalysisOutput {
                     });
                 }
             }
-            _ => {}
+            Pattern::Tuple(patterns, _) | Pattern::List(patterns, _) => {
+                for pattern in patterns {
+                    self.analyze_pattern(pattern, span_context);
+                }
+            }
+            Pattern::As(id, pattern, span) => {
+                self.analyze_pattern(Pattern::Variable(id), span_context);
+                self.analyze_pattern(*pattern, span);
+            }
+            Pattern::Record(id, fields, _) => {
+                let given: Vec<&Identifier> = fields.iter().map(|(name, _)| name).collect();
+                self.check_record_fields(&id, &given, span_context);
+
+                for (_, pattern) in fields {
+                    self.analyze_pattern(pattern, span_context);
+                }
+            }
+            Pattern::Literal(_) | Pattern::Wildcard(_) => {}
         }
     }
 
@@ -502,7 +933,7 @@ impl AnalysisOutput {
         match r#type {
             Type::Id(Identifier { name, span }) => {
                 if BUILTIN_TYPES.contains(&name.as_str()) {
-                } else if let Some(data) = self.types.get_mut(&span) {
+                } else if let Some(data) = self.types.get_mut(&name) {
                     data.used = true;
 
                     if data.arity != 0 {
@@ -535,7 +966,7 @@ impl AnalysisOutput {
                         },
                         span: span_context,
                     })
-                } else if let Some(data) = self.types.get_mut(&id.span) {
+                } else if let Some(data) = self.types.get_mut(&id.name) {
                     data.used = true;
 
                     if types.len() as u8 != data.arity {
@@ -560,12 +991,19 @@ impl AnalysisOutput {
             }
             Type::Tuple(types, ..) => {
                 for ty in types {
-                    self.analyze_type(ty, span_context.clone());
+                    self.analyze_type(ty, span_context);
                 }
             }
+            Type::List(ty, _) => self.analyze_type(*ty, span_context),
+            Type::Qualified(context, ty, _) => {
+                let mut vars = Vec::new();
+                type_variables(&ty, &mut vars);
+                self.analyze_context(&context, &vars, span_context);
+                self.analyze_type(*ty, span_context);
+            }
             Type::Func(ret, args, ..) => {
                 for arg in args {
-                    self.analyze_type(arg, span_context.clone());
+                    self.analyze_type(arg, span_context);
                 }
 
                 self.analyze_type(*ret, span_context);
@@ -575,34 +1013,73 @@ impl AnalysisOutput {
     }
 }
 
+/// Collects the names of the type variables of `ty`.
+fn type_variables(ty: &Type, out: &mut Vec<String>) {
+    match ty {
+        Type::Generic(id) => out.push(id.name.clone()),
+        Type::Id(_) => {}
+        Type::App(_, types, _) | Type::Tuple(types, _) => {
+            types.iter().for_each(|ty| type_variables(ty, out))
+        }
+        Type::Func(first, rest, _) => {
+            type_variables(first, out);
+            rest.iter().for_each(|ty| type_variables(ty, out));
+        }
+        Type::List(ty, _) | Type::Qualified(_, ty, _) => type_variables(ty, out),
+    }
+}
+
 pub fn analyze(ao: &mut AnalysisOutput, input: Program<ast::Span>) {
+    for statement in &input.statements {
+        if let Statement::Class(decl) = statement {
+            ao.declare_class(decl);
+        }
+    }
+
+    for statement in &input.statements {
+        if let Statement::Bind(bind) = statement {
+            if ao.methods.contains_key(&bind.name.name) {
+                ao.errors.push(SemanticError {
+                    kind: SemanticErrorKind::MultipleDeclarations {
+                        name: bind.name.name.clone(),
+                        span: bind.name.span,
+                    },
+                    span: bind.span,
+                });
+            } else {
+                ao.declare_bind(&bind.name, bind.args.is_empty(), bind.span);
+            }
+        }
+    }
+
     for statement in input.statements {
         ao.analyze_statement(statement);
     }
 
-    for (sym, data) in ao.symbols.iter() {
-        if data.used == false {
+    // The entry point is used by running the program.
+    for (symbol, data) in &ao.symbols {
+        if data.used == false && !ao.exported.contains(&symbol.name) && symbol.name != ENTRY {
             ao.warnings.push(SemanticWarning {
                 kind: SemanticWarningKind::UnusedSymbol,
-                span: sym.span.clone(),
+                span: data.span,
             });
         }
     }
 
-    for (sym, data) in ao.types.iter() {
-        if data.used == false {
+    for (name, data) in &ao.types {
+        if data.used == false && !ao.exported.contains(name) {
             ao.warnings.push(SemanticWarning {
                 kind: SemanticWarningKind::UnusedType,
-                span: sym.clone(),
+                span: data.span,
             })
         }
     }
 
-    for (sym, data) in ao.variants.iter() {
-        if data.used == false {
+    for (name, data) in &ao.variants {
+        if data.used == false && !ao.exported.contains(name) {
             ao.warnings.push(SemanticWarning {
                 kind: SemanticWarningKind::UnusedVariant,
-                span: sym.clone(),
+                span: data.span,
             })
         }
     }
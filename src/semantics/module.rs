@@ -0,0 +1,564 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Name resolution across modules. Every name declared at the top level of
+//! a module is renamed after the module, `area` of `Geometry` becoming
+//! `Geometry.area`, and every use of it to that name, however it was
+//! imported. The names of the module the program starts from are kept, so
+//! that a program of a single file is left as it is. The modules can then be
+//! analyzed, checked and run as a single program.
+
+use crate::ast::*;
+use crate::semantics::error::{SemanticError, SemanticErrorKind};
+use crate::semantics::loader::Module;
+use crate::semantics::AnalysisOutput;
+use std::collections::HashMap;
+
+/// The names a module can use at the top level, each with the declarations
+/// it may stand for. Values are binds, constructors, fields and methods,
+/// types are types and classes.
+#[derive(Debug, Default)]
+struct Scope {
+    values: HashMap<String, Vec<String>>,
+    types: HashMap<String, Vec<String>>,
+}
+
+impl Scope {
+    fn add(names: &mut HashMap<String, Vec<String>>, name: String, declaration: &str) {
+        let declarations = names.entry(name).or_default();
+        if !declarations.iter().any(|other| other == declaration) {
+            declarations.push(declaration.to_string());
+        }
+    }
+
+    /// Brings the declarations of `entity` in scope, written `prefix.name`
+    /// if there is a prefix.
+    fn import(&mut self, entity: &Entity, prefix: Option<&str>) {
+        let name = |declaration: &str| match prefix {
+            Some(prefix) => format!("{}.{}", prefix, unqualified(declaration)),
+            None => unqualified(declaration).to_string(),
+        };
+        for value in &entity.values {
+            Scope::add(&mut self.values, name(value), value);
+        }
+        for ty in &entity.types {
+            Scope::add(&mut self.types, name(ty), ty);
+        }
+    }
+}
+
+/// What exporting or importing a name brings: a type comes with its
+/// constructors and fields, and a class with its methods.
+#[derive(Debug, Default, Clone)]
+struct Entity {
+    values: Vec<String>,
+    types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Namespace {
+    Value,
+    Type,
+}
+
+/// Renames the names of `modules`, given each after the modules it imports
+/// and ending with the one the program starts from. Names that cannot be
+/// resolved are left for the analysis to report.
+pub fn resolve(ao: &mut AnalysisOutput, modules: &mut [Module]) {
+    let mut resolver = Resolver {
+        exports: HashMap::new(),
+        entities: HashMap::new(),
+        errors: Vec::new(),
+    };
+    let count = modules.len();
+    for (i, module) in modules.iter_mut().enumerate() {
+        let root = i + 1 == count;
+        let exported = resolver.resolve_module(module, root);
+        // Nothing is reported unused in a module when another can use it.
+        if !root {
+            ao.exported.extend(exported);
+        }
+    }
+    ao.errors.extend(resolver.errors);
+}
+
+struct Resolver {
+    /// What each module exports, by the names it exports them as.
+    exports: HashMap<String, HashMap<String, Entity>>,
+    /// The entity of each type and class.
+    entities: HashMap<String, Entity>,
+    errors: Vec<SemanticError>,
+}
+
+impl Resolver {
+    /// Renames the names of `module`, returning the declarations it
+    /// exports.
+    fn resolve_module(&mut self, module: &mut Module, root: bool) -> Vec<String> {
+        let qualify = |name: &str| {
+            if root {
+                name.to_string()
+            } else {
+                format!("{}.{}", module.name, name)
+            }
+        };
+
+        // The declarations of the module, by the name they are declared
+        // with.
+        let mut declared: Vec<(String, Entity)> = Vec::new();
+        for statement in &module.program.statements {
+            let mut names = Vec::new();
+            match statement {
+                Statement::Bind(bind) => {
+                    names.push(&bind.name);
+                    if declared.iter().any(|(name, _)| *name == bind.name.name) {
+                        continue;
+                    }
+                    let entity = Entity {
+                        values: vec![qualify(&bind.name.name)],
+                        types: Vec::new(),
+                    };
+                    declared.push((bind.name.name.clone(), entity));
+                }
+                Statement::TypeDecl(decl) => {
+                    names.push(&decl.name);
+                    let mut entity = Entity {
+                        values: Vec::new(),
+                        types: vec![qualify(&decl.name.name)],
+                    };
+                    for variant in &decl.variants {
+                        names.push(&variant.id);
+                        entity.values.push(qualify(&variant.id.name));
+                        for field in &variant.fields {
+                            let field = qualify(&field.name);
+                            if !entity.values.contains(&field) {
+                                entity.values.push(field);
+                            }
+                        }
+                    }
+                    self.entities.insert(qualify(&decl.name.name), entity.clone());
+                    declared.push((decl.name.name.clone(), entity));
+                }
+                Statement::Class(decl) => {
+                    names.push(&decl.name);
+                    let mut entity = Entity {
+                        values: Vec::new(),
+                        types: vec![qualify(&decl.name.name)],
+                    };
+                    for signature in &decl.signatures {
+                        names.push(&signature.id);
+                        entity.values.push(qualify(&signature.id.name));
+                    }
+                    self.entities.insert(qualify(&decl.name.name), entity.clone());
+                    declared.push((decl.name.name.clone(), entity));
+                }
+                _ => {}
+            }
+            for id in names {
+                if unqualified(&id.name) != id.name {
+                    self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::QualifiedDeclaration {
+                            name: id.name.clone(),
+                            span: id.span,
+                        },
+                        span: id.span,
+                    });
+                }
+            }
+        }
+
+        // The declarations of the module shadow the imported ones. They can
+        // also be qualified by the name of the module.
+        let mut own = Scope::default();
+        for (_, entity) in &declared {
+            own.import(entity, None);
+            own.import(entity, Some(&module.name));
+        }
+        let mut imported = Scope::default();
+        for statement in &module.program.statements {
+            if let Statement::Import(import) = statement {
+                self.import(&mut imported, import);
+            }
+        }
+
+        let mut renamer = Renamer {
+            own: &own,
+            imported: &imported,
+            entities: &self.entities,
+            locals: Vec::new(),
+            errors: &mut self.errors,
+        };
+        let exports = match module.program.statements.first() {
+            Some(Statement::Module(ModuleDecl { exports: Some(items), .. })) => {
+                let mut exports = HashMap::new();
+                for item in items {
+                    if let Some(entity) = renamer.export(item) {
+                        exports.insert(unqualified(&item.name).to_string(), entity);
+                    }
+                }
+                exports
+            }
+            _ => declared.into_iter().collect(),
+        };
+        for statement in &mut module.program.statements {
+            renamer.statement(statement);
+        }
+
+        let exported = exports
+            .values()
+            .flat_map(|entity| entity.values.iter().chain(&entity.types).cloned())
+            .collect();
+        self.exports.insert(module.name.clone(), exports);
+        exported
+    }
+
+    /// Brings the names imported by `import` in `scope`.
+    fn import(&mut self, scope: &mut Scope, import: &ImportDecl) {
+        // A module that could not be loaded has been reported.
+        let Some(exports) = self.exports.get(&import.module.name) else {
+            return;
+        };
+        let prefix = import.alias.as_ref().unwrap_or(&import.module);
+        let mut entities = Vec::new();
+        match &import.items {
+            Some(items) => {
+                for item in items {
+                    match exports.get(&item.name) {
+                        Some(entity) => entities.push(entity),
+                        None => self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::NotExported {
+                                module_name: import.module.name.clone(),
+                                name: item.name.clone(),
+                                span: item.span,
+                            },
+                            span: import.span,
+                        }),
+                    }
+                }
+            }
+            None => entities.extend(exports.values()),
+        }
+        for entity in entities {
+            if !import.qualified {
+                scope.import(entity, None);
+            }
+            scope.import(entity, Some(&prefix.name));
+        }
+    }
+}
+
+/// Renames the names used by the statements of a module.
+struct Renamer<'a> {
+    own: &'a Scope,
+    imported: &'a Scope,
+    entities: &'a HashMap<String, Entity>,
+    /// The variables bound around the current expression.
+    locals: Vec<String>,
+    errors: &'a mut Vec<SemanticError>,
+}
+
+impl<'a> Renamer<'a> {
+    /// The declaration `name` stands for. When it is ambiguous, it is
+    /// reported and the first one is taken, so that it is not reported
+    /// undefined as well.
+    fn lookup(&mut self, namespace: Namespace, id: &Identifier) -> Option<String> {
+        let names = |scope: &'a Scope| match namespace {
+            Namespace::Value => &scope.values,
+            Namespace::Type => &scope.types,
+        };
+        let declarations = names(self.own)
+            .get(&id.name)
+            .or_else(|| names(self.imported).get(&id.name))?;
+        match &declarations[..] {
+            [declaration] => Some(declaration.clone()),
+            _ => {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::AmbiguousName {
+                        name: id.name.clone(),
+                        candidates: declarations.clone(),
+                        span: id.span,
+                    },
+                    span: id.span,
+                });
+                declarations.first().cloned()
+            }
+        }
+    }
+
+    fn rename(&mut self, namespace: Namespace, id: &mut Identifier) {
+        if let Some(declaration) = self.lookup(namespace, id) {
+            id.name = declaration;
+        }
+    }
+
+    /// Renames a value, unless it is a local variable.
+    fn rename_value(&mut self, id: &mut Identifier) {
+        if !self.locals.contains(&id.name) {
+            self.rename(Namespace::Value, id);
+        }
+    }
+
+    /// What exporting `item` brings.
+    fn export(&mut self, item: &Identifier) -> Option<Entity> {
+        let namespace = if unqualified(&item.name).starts_with(char::is_uppercase) {
+            Namespace::Type
+        } else {
+            Namespace::Value
+        };
+        let Some(declaration) = self.lookup(namespace, item) else {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::UndefinedExport {
+                    name: item.name.clone(),
+                    span: item.span,
+                },
+                span: item.span,
+            });
+            return None;
+        };
+        Some(match namespace {
+            Namespace::Type => self.entities.get(&declaration).cloned().unwrap_or_default(),
+            Namespace::Value => Entity {
+                values: vec![declaration],
+                types: Vec::new(),
+            },
+        })
+    }
+
+    fn statement(&mut self, statement: &mut Statement<Span>) {
+        match statement {
+            Statement::Bind(bind) => {
+                self.rename(Namespace::Value, &mut bind.name);
+                self.bind_body(bind);
+            }
+            Statement::TypeDecl(decl) => {
+                self.rename(Namespace::Type, &mut decl.name);
+                for variant in &mut decl.variants {
+                    self.rename(Namespace::Value, &mut variant.id);
+                    for field in &mut variant.fields {
+                        self.rename(Namespace::Value, field);
+                    }
+                    for ty in &mut variant.types {
+                        self.ty(ty);
+                    }
+                }
+            }
+            Statement::TypeAssign(assign) => {
+                self.rename(Namespace::Value, &mut assign.id);
+                self.ty(&mut assign.ty);
+            }
+            Statement::Fixity(decl) => {
+                for op in &mut decl.operators {
+                    self.rename(Namespace::Value, op);
+                }
+            }
+            Statement::Class(decl) => {
+                self.rename(Namespace::Type, &mut decl.name);
+                self.context(&mut decl.context);
+                for signature in &mut decl.signatures {
+                    self.rename(Namespace::Value, &mut signature.id);
+                    self.ty(&mut signature.ty);
+                }
+                for bind in &mut decl.defaults {
+                    self.rename(Namespace::Value, &mut bind.name);
+                    self.bind_body(bind);
+                }
+            }
+            Statement::Instance(decl) => {
+                self.rename(Namespace::Type, &mut decl.class);
+                self.context(&mut decl.context);
+                self.ty(&mut decl.ty);
+                // The methods are those of the class, wherever it comes
+                // from. The builtin classes keep their names.
+                let methods = self
+                    .entities
+                    .get(&decl.class.name)
+                    .map(|entity| entity.values.clone())
+                    .unwrap_or_default();
+                for bind in &mut decl.binds {
+                    if let Some(method) = methods.iter().find(|method| unqualified(method) == bind.name.name) {
+                        bind.name.name = method.clone();
+                    }
+                    self.bind_body(bind);
+                }
+            }
+            Statement::Module(_) | Statement::Import(_) => {}
+        }
+    }
+
+    /// Renames the arguments and the body of a bind, whose name is renamed
+    /// by the caller.
+    fn bind_body(&mut self, bind: &mut Bind<Span>) {
+        let depth = self.locals.len();
+        for arg in &mut bind.args {
+            self.pattern(arg);
+        }
+        self.expr(&mut bind.expr);
+        self.locals.truncate(depth);
+    }
+
+    fn context(&mut self, context: &mut [Constraint]) {
+        for constraint in context {
+            self.rename(Namespace::Type, &mut constraint.class);
+        }
+    }
+
+    fn ty(&mut self, ty: &mut Type) {
+        match ty {
+            Type::Generic(_) => {}
+            Type::Id(id) => self.rename(Namespace::Type, id),
+            Type::App(id, args, _) => {
+                self.rename(Namespace::Type, id);
+                for arg in args {
+                    self.ty(arg);
+                }
+            }
+            Type::Tuple(types, _) => {
+                for ty in types {
+                    self.ty(ty);
+                }
+            }
+            Type::Func(ret, args, _) => {
+                self.ty(ret);
+                for arg in args {
+                    self.ty(arg);
+                }
+            }
+            Type::List(ty, _) => self.ty(ty),
+            Type::Qualified(context, ty, _) => {
+                self.context(context);
+                self.ty(ty);
+            }
+        }
+    }
+
+    /// Renames the constructors and fields of `pattern`, and binds its
+    /// variables.
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::ListCons(head, tail, _) => {
+                self.pattern(head);
+                self.pattern(tail);
+            }
+            Pattern::Wildcard(_) | Pattern::Literal(_) => {}
+            Pattern::Variable(id) => self.locals.push(id.name.clone()),
+            Pattern::Id(id) => self.rename(Namespace::Value, id),
+            Pattern::App(id, args, _) => {
+                self.rename(Namespace::Value, id);
+                for arg in args {
+                    self.pattern(arg);
+                }
+            }
+            Pattern::Tuple(patterns, _) | Pattern::List(patterns, _) => {
+                for pattern in patterns {
+                    self.pattern(pattern);
+                }
+            }
+            Pattern::As(id, pattern, _) => {
+                self.locals.push(id.name.clone());
+                self.pattern(pattern);
+            }
+            Pattern::Record(id, fields, _) => {
+                self.rename(Namespace::Value, id);
+                for (field, pattern) in fields {
+                    self.rename(Namespace::Value, field);
+                    self.pattern(pattern);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut ParsedExpr) {
+        match expr {
+            AnnExpr::Identifier { id } => self.rename_value(id),
+            AnnExpr::PCIdentifier { id } => self.rename(Namespace::Value, id),
+            AnnExpr::App(app) => {
+                self.expr(&mut app.head);
+                for arg in &mut app.args {
+                    self.expr(arg);
+                }
+            }
+            AnnExpr::Condition { cond, then, els, .. } => {
+                self.expr(cond);
+                self.expr(then);
+                self.expr(els);
+            }
+            AnnExpr::Let { binds, ret, .. } => {
+                // The binds of a `let` see each other.
+                let depth = self.locals.len();
+                self.locals.extend(binds.iter().map(|bind| bind.name.name.clone()));
+                for bind in binds {
+                    self.bind_body(bind);
+                }
+                self.expr(ret);
+                self.locals.truncate(depth);
+            }
+            AnnExpr::Match { referral, cases, .. } => {
+                self.expr(referral);
+                for (pattern, expr) in cases {
+                    let depth = self.locals.len();
+                    self.pattern(pattern);
+                    self.expr(expr);
+                    self.locals.truncate(depth);
+                }
+            }
+            AnnExpr::Literal(_) | AnnExpr::Error { .. } => {}
+            AnnExpr::BinOp { lhs, rhs, .. } => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            AnnExpr::UnOp { expr, .. } => self.expr(expr),
+            AnnExpr::Lambda { args, ret, .. } => {
+                let depth = self.locals.len();
+                for arg in args {
+                    self.pattern(arg);
+                }
+                self.expr(ret);
+                self.locals.truncate(depth);
+            }
+            AnnExpr::Ann { expr, ann } => {
+                self.expr(expr);
+                self.ty(&mut ann.1);
+            }
+            AnnExpr::List { list, .. } | AnnExpr::Tuple { list, .. } => {
+                for expr in list {
+                    self.expr(expr);
+                }
+            }
+            // Fields are not variables, a local variable does not hide them.
+            AnnExpr::Record { id, fields, .. } => {
+                self.rename(Namespace::Value, id);
+                for (field, expr) in fields {
+                    self.rename(Namespace::Value, field);
+                    self.expr(expr);
+                }
+            }
+            AnnExpr::Update { expr, fields, .. } => {
+                self.expr(expr);
+                for (field, expr) in fields {
+                    self.rename(Namespace::Value, field);
+                    self.expr(expr);
+                }
+            }
+            AnnExpr::Guards { guards, .. } => {
+                for (cond, expr) in guards {
+                    self.expr(cond);
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,656 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Classes, instances and the dictionaries that the type checker finds for
+//! each use of an overloaded name. `compiler::elaborate` turns them into
+//! explicit arguments.
+
+use super::error::{TypeCheckerError, TypeCheckerErrorKind};
+use super::types::{Pred, Ty, TyVar, LIST};
+use super::TypeChecker;
+use crate::ast::{ClassDecl, InstanceDecl, Span, Type, TypeDecl};
+use std::collections::{HashMap, HashSet};
+
+/// A class every program knows. Its methods are written in Risk syntax,
+/// over the class variable `a`.
+#[derive(Debug)]
+pub struct BuiltinClass {
+    pub name: &'static str,
+    pub superclasses: &'static [&'static str],
+    pub methods: &'static [(&'static str, &'static str)],
+    /// The primitive types that are instances. Their methods are the
+    /// operations of the VM, see `vm::native::PRIMITIVES`.
+    pub primitives: &'static [&'static str],
+    /// Whether lists, tuples and the types that do not declare an instance
+    /// are instances too, by comparing or printing what they hold.
+    pub derived: bool,
+}
+
+const PRIMITIVE_TYPES: &[&str] = &["Integer", "Float", "Char", "String", "Bool"];
+
+pub const BUILTIN_CLASSES: &[BuiltinClass] = &[
+    BuiltinClass {
+        name: "Eq",
+        superclasses: &[],
+        methods: &[("==", "a -> a -> Bool")],
+        primitives: PRIMITIVE_TYPES,
+        derived: true,
+    },
+    BuiltinClass {
+        name: "Ord",
+        superclasses: &["Eq"],
+        methods: &[("<", "a -> a -> Bool")],
+        primitives: PRIMITIVE_TYPES,
+        derived: true,
+    },
+    BuiltinClass {
+        name: "Num",
+        superclasses: &[],
+        methods: &[
+            ("+", "a -> a -> a"),
+            ("-", "a -> a -> a"),
+            ("*", "a -> a -> a"),
+            ("/", "a -> a -> a"),
+            ("%", "a -> a -> a"),
+            ("^", "a -> a -> a"),
+            ("negate", "a -> a"),
+            ("fromInteger", "Integer -> a"),
+        ],
+        primitives: &["Integer", "Float"],
+        derived: false,
+    },
+    BuiltinClass {
+        name: "Show",
+        superclasses: &[],
+        methods: &[("show", "a -> String")],
+        primitives: PRIMITIVE_TYPES,
+        derived: true,
+    },
+];
+
+pub fn builtin_class(name: &str) -> Option<&'static BuiltinClass> {
+    BUILTIN_CLASSES.iter().find(|class| class.name == name)
+}
+
+/// The name of the head of an instance: the type constructor, `[]` for
+/// lists and `(,)`, `(,,)`... for tuples.
+pub fn tuple_head(arity: usize) -> String {
+    format!("({})", ",".repeat(arity - 1))
+}
+
+/// The name of the head of the type an instance is declared for and its
+/// type variables, if it is a type constructor applied to distinct type
+/// variables.
+pub fn instance_head(ty: &Type) -> Option<(String, Vec<String>)> {
+    fn variables(types: &[Type]) -> Option<Vec<String>> {
+        let mut vars: Vec<String> = Vec::new();
+        for ty in types {
+            match ty {
+                Type::Generic(var) if !vars.contains(&var.name) => vars.push(var.name.clone()),
+                _ => return None,
+            }
+        }
+        Some(vars)
+    }
+
+    match ty {
+        Type::Id(id) => Some((id.name.clone(), Vec::new())),
+        Type::App(id, args, _) => Some((id.name.clone(), variables(args)?)),
+        Type::List(item, _) => Some((LIST.to_string(), variables(std::slice::from_ref(item))?)),
+        Type::Tuple(items, _) => Some((tuple_head(items.len()), variables(items)?)),
+        _ => None,
+    }
+}
+
+/// The head of a type and its arguments, under the names of `instance_head`.
+fn type_head(ty: &Ty) -> Option<(String, Vec<Ty>)> {
+    match ty {
+        Ty::Con(name, args) => Some((name.clone(), args.clone())),
+        Ty::Tuple(items) => Some((tuple_head(items.len()), items.clone())),
+        _ => None,
+    }
+}
+
+/// The type whose head is `head`, applied to `args`.
+fn head_type(head: &str, args: Vec<Ty>) -> Ty {
+    match head {
+        LIST => Ty::list(args.into_iter().next().unwrap_or(Ty::con("a"))),
+        _ if head.starts_with("(,") => Ty::Tuple(args),
+        _ => Ty::Con(head.to_string(), args),
+    }
+}
+
+/// Where the dictionary of a class for some type comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dict {
+    /// The `i`-th dictionary taken by the enclosing definition.
+    Param(usize),
+    /// The dictionary of `superclass` held by a dictionary of `class`.
+    Super {
+        class: String,
+        superclass: String,
+        dict: Box<Dict>,
+    },
+    /// The instance of `class` for `head`, given the dictionaries of its
+    /// context.
+    Instance {
+        class: String,
+        head: String,
+        args: Vec<Dict>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ClassInfo {
+    pub name: String,
+    pub superclasses: Vec<String>,
+    /// The methods, with the number of arguments their signature gives
+    /// them and the number of dictionaries they take besides the one of
+    /// the class.
+    pub methods: Vec<Method>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: String,
+    pub arity: usize,
+    pub dicts: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstanceKind {
+    /// An instance of a builtin class for a primitive type.
+    Primitive,
+    /// An instance of `Eq`, `Ord` or `Show` for lists or tuples.
+    Structure,
+    /// An instance of `Eq`, `Ord` or `Show` for a type that declares none.
+    /// Holds the dictionaries of the fields of each variant.
+    Derived(Vec<Vec<Dict>>),
+    Declared,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instance {
+    pub kind: InstanceKind,
+    /// The constraints of the context, as a class and the index of the
+    /// argument of the head it constrains. The instance takes one
+    /// dictionary for each.
+    pub context: Vec<(String, usize)>,
+    /// The dictionaries of the superclasses, given the ones of the context.
+    pub superclasses: Vec<Dict>,
+}
+
+/// A definition whose body may use overloaded names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+    /// A top-level bind.
+    Bind(String),
+    /// The method `method` of the instance of `class` for `head`.
+    Method {
+        class: String,
+        head: String,
+        method: String,
+    },
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct UnitEvidence {
+    /// How many dictionaries the definition takes.
+    pub params: usize,
+    /// The dictionaries given to each overloaded name, builtin operator or
+    /// negation, by the span of the identifier or the operation.
+    pub sites: HashMap<Span, Vec<Dict>>,
+}
+
+/// What the type checker found out about overloading, for the elaboration.
+#[derive(Debug, Default)]
+pub struct Evidence {
+    pub classes: Vec<ClassInfo>,
+    pub instances: HashMap<(String, String), Instance>,
+    pub units: HashMap<Unit, UnitEvidence>,
+}
+
+/// A class as the type checker sees it.
+#[derive(Debug)]
+pub(super) struct Class {
+    pub(super) var: String,
+    pub(super) superclasses: Vec<String>,
+    /// The methods and their signatures, over `var`.
+    pub(super) methods: Vec<(String, Type)>,
+}
+
+/// The constraints an overloaded name, operator or negation needs.
+#[derive(Debug)]
+pub(super) struct Site {
+    pub(super) preds: Vec<Pred>,
+    pub(super) span: Span,
+}
+
+/// The constraints arising in some definitions, solved once inference is
+/// over from the ones `given` by their signature or generalization.
+#[derive(Debug)]
+pub(super) struct Pending {
+    pub(super) units: Vec<Unit>,
+    pub(super) given: Vec<Pred>,
+    pub(super) sites: Vec<Site>,
+    /// Whether the constraints that cannot be solved are left unreported,
+    /// because they are reported elsewhere.
+    pub(super) quiet: bool,
+}
+
+impl TypeChecker {
+    /// Declares the builtin classes, with their instances for the primitive
+    /// types and lists. The ones for tuples are added on demand.
+    pub(super) fn declare_builtin_classes(&mut self) {
+        for builtin in BUILTIN_CLASSES {
+            let class = Class {
+                var: "a".to_string(),
+                superclasses: builtin.superclasses.iter().map(|s| s.to_string()).collect(),
+                methods: builtin
+                    .methods
+                    .iter()
+                    .map(|(name, signature)| (name.to_string(), super::parse_signature(signature)))
+                    .collect(),
+            };
+            self.add_class(builtin.name, class);
+
+            for primitive in builtin.primitives {
+                let instance = Instance {
+                    kind: InstanceKind::Primitive,
+                    context: Vec::new(),
+                    superclasses: Vec::new(),
+                };
+                self.instances
+                    .insert((builtin.name.to_string(), primitive.to_string()), instance);
+            }
+            if builtin.derived {
+                let instance = Instance {
+                    kind: InstanceKind::Structure,
+                    context: vec![(builtin.name.to_string(), 0)],
+                    superclasses: Vec::new(),
+                };
+                self.instances
+                    .insert((builtin.name.to_string(), LIST.to_string()), instance);
+            }
+        }
+    }
+
+    pub(super) fn declare_class(&mut self, decl: &ClassDecl<Span>) {
+        // Redefinitions are reported by the semantic analysis.
+        if self.classes.contains_key(&decl.name.name) {
+            return;
+        }
+        let class = Class {
+            var: decl.var.name.clone(),
+            superclasses: decl.context.iter().map(|c| c.class.name.clone()).collect(),
+            methods: decl
+                .signatures
+                .iter()
+                .map(|signature| (signature.id.name.clone(), signature.ty.clone()))
+                .collect(),
+        };
+        self.add_class(&decl.name.name, class);
+    }
+
+    /// Gives each method of a class its signature, under the constraint of
+    /// the class first.
+    fn add_class(&mut self, name: &str, class: Class) {
+        let mut methods = Vec::new();
+        for (method, signature) in &class.methods {
+            self.enter();
+            let var = self.fresh();
+            let mut generics = HashMap::from([(class.var.clone(), var.clone())]);
+            let ty = self.convert_type(signature, &mut generics, false);
+            let own = self.convert_context(signature, &mut generics, false);
+            self.exit();
+
+            methods.push(Method {
+                name: method.clone(),
+                arity: arity(&ty),
+                dicts: own.len(),
+            });
+            let mut scheme = self.generalize(&ty);
+            scheme.preds = std::iter::once(Pred::new(name, var)).chain(own).collect();
+            self.globals.insert(method.clone(), scheme);
+        }
+
+        self.evidence.classes.push(ClassInfo {
+            name: name.to_string(),
+            superclasses: class.superclasses.clone(),
+            methods,
+        });
+        self.classes.insert(name.to_string(), class);
+    }
+
+    pub(super) fn declare_instance(&mut self, decl: &InstanceDecl<Span>) {
+        let Some((head, vars)) = instance_head(&decl.ty) else {
+            return;
+        };
+        let key = (decl.class.name.clone(), head);
+        // Unknown classes and duplicates are reported by the semantic
+        // analysis.
+        if !self.classes.contains_key(&key.0) || self.instances.contains_key(&key) {
+            return;
+        }
+        let context = decl
+            .context
+            .iter()
+            .filter_map(|c| {
+                let index = vars.iter().position(|var| *var == c.var.name)?;
+                Some((c.class.name.clone(), index))
+            })
+            .collect();
+        let instance = Instance {
+            kind: InstanceKind::Declared,
+            context,
+            superclasses: Vec::new(),
+        };
+        self.instances.insert(key.clone(), instance);
+        self.declared.insert(key, (vars, decl.span));
+    }
+
+    /// Gives the types that declare no instance of `Eq`, `Ord` or `Show`
+    /// the one that compares or prints their fields, when their fields
+    /// have instances themselves.
+    pub(super) fn derive_instances(&mut self, decls: &[&TypeDecl]) {
+        let mut candidates = Vec::new();
+        for decl in decls {
+            for class in BUILTIN_CLASSES.iter().filter(|class| class.derived) {
+                let key = (class.name.to_string(), decl.name.name.clone());
+                if self.instances.contains_key(&key) {
+                    continue;
+                }
+                let instance = Instance {
+                    kind: InstanceKind::Derived(Vec::new()),
+                    context: (0..decl.typevars.len()).map(|i| (class.name.to_string(), i)).collect(),
+                    superclasses: Vec::new(),
+                };
+                self.instances.insert(key.clone(), instance);
+                candidates.push((key, *decl));
+            }
+        }
+
+        // Dropping a candidate can make others fail, e.g. a type holding a
+        // type that holds a function.
+        loop {
+            let count = candidates.len();
+            candidates.retain(|(key, decl)| {
+                let superclasses = self.classes[&key.0].superclasses.clone();
+                let keep = self.derived_fields(&key.0, decl).is_some()
+                    && superclasses
+                        .into_iter()
+                        .all(|superclass| self.instances.contains_key(&(superclass, key.1.clone())));
+                if !keep {
+                    self.instances.remove(key);
+                }
+                keep
+            });
+            if candidates.len() == count {
+                break;
+            }
+        }
+
+        for (key, decl) in candidates {
+            let fields = self.derived_fields(&key.0, decl).unwrap_or_default();
+            if let Some(instance) = self.instances.get_mut(&key) {
+                instance.kind = InstanceKind::Derived(fields);
+            }
+        }
+    }
+
+    /// The dictionaries of `class` for the fields of each variant of a
+    /// type, given the ones for its type variables.
+    fn derived_fields(&mut self, class: &str, decl: &TypeDecl) -> Option<Vec<Vec<Dict>>> {
+        let mut generics: HashMap<String, Ty> = HashMap::new();
+        let given: Vec<Pred> = decl
+            .typevars
+            .iter()
+            .map(|var| Pred::new(class, Ty::con(&var.name)))
+            .collect();
+        decl.variants
+            .iter()
+            .map(|variant| {
+                variant
+                    .types
+                    .iter()
+                    .map(|field| {
+                        let ty = self.convert_type(field, &mut generics, true);
+                        self.entail(&Pred::new(class, ty), &given)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The instance of `class` for `head`. The ones for tuples are made when
+    /// first needed, as there is one per size.
+    fn instance(&mut self, class: &str, head: &str) -> Option<&Instance> {
+        let key = (class.to_string(), head.to_string());
+        let derived = builtin_class(class).is_some_and(|builtin| builtin.derived);
+        if derived && head.starts_with("(,") && !self.instances.contains_key(&key) {
+            let instance = Instance {
+                kind: InstanceKind::Structure,
+                context: (0..head.len() - 1).map(|i| (class.to_string(), i)).collect(),
+                superclasses: Vec::new(),
+            };
+            self.instances.insert(key.clone(), instance);
+        }
+        self.instances.get(&key)
+    }
+
+    /// The dictionary proving `pred`, from the ones `given` or from an
+    /// instance.
+    pub(super) fn entail(&mut self, pred: &Pred, given: &[Pred]) -> Option<Dict> {
+        let ty = self.zonk(&pred.ty);
+        for (i, other) in given.iter().enumerate() {
+            if self.zonk(&other.ty) == ty {
+                let dict = self.superclass_dict(&other.class, &pred.class, Dict::Param(i), &mut Vec::new());
+                if dict.is_some() {
+                    return dict;
+                }
+            }
+        }
+
+        let (head, args) = type_head(&ty)?;
+        let context = self.instance(&pred.class, &head)?.context.clone();
+        let args = context
+            .iter()
+            .map(|(class, i)| self.entail(&Pred::new(class, args[*i].clone()), given))
+            .collect::<Option<_>>()?;
+        Some(Dict::Instance {
+            class: pred.class.clone(),
+            head,
+            args,
+        })
+    }
+
+    /// The dictionary of `target` reached from `dict`, a dictionary of
+    /// `class`, through superclasses.
+    fn superclass_dict(&self, class: &str, target: &str, dict: Dict, seen: &mut Vec<String>) -> Option<Dict> {
+        if class == target {
+            return Some(dict);
+        }
+        // Cyclic superclasses are not rejected beforehand.
+        if seen.iter().any(|other| other == class) {
+            return None;
+        }
+        seen.push(class.to_string());
+        for superclass in &self.classes.get(class)?.superclasses {
+            let dict = Dict::Super {
+                class: class.to_string(),
+                superclass: superclass.clone(),
+                dict: Box::new(dict.clone()),
+            };
+            if let Some(found) = self.superclass_dict(superclass, target, dict, seen) {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    /// Reduces `pred` through the instances to constraints on type
+    /// variables. Constraints without an instance are dropped, they are
+    /// reported when solved.
+    pub(super) fn reduce(&mut self, pred: &Pred) -> Vec<(String, TyVar)> {
+        let ty = self.zonk(&pred.ty);
+        if let Ty::Var(v) = ty {
+            return vec![(pred.class.clone(), v)];
+        }
+        let Some((head, args)) = type_head(&ty) else {
+            return Vec::new();
+        };
+        let Some(instance) = self.instance(&pred.class, &head) else {
+            return Vec::new();
+        };
+        instance
+            .context
+            .clone()
+            .iter()
+            .flat_map(|(class, i)| self.reduce(&Pred::new(class, args[*i].clone())))
+            .collect()
+    }
+
+    /// Defaults the type variables that constraints leave ambiguous to
+    /// `Integer`, then finds the dictionary of every constraint and of the
+    /// superclasses of every instance.
+    pub(super) fn solve(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        for Pending { given, sites, .. } in &pending {
+            let mut fixed = Vec::new();
+            for pred in given {
+                self.free_vars(&pred.ty, &mut fixed);
+            }
+            for pred in sites.iter().flat_map(|site| &site.preds) {
+                let mut vars = Vec::new();
+                self.free_vars(&pred.ty, &mut vars);
+                for v in vars.into_iter().filter(|v| !fixed.contains(v)) {
+                    self.bindings[v] = Some(Ty::con("Integer"));
+                }
+            }
+        }
+
+        for Pending {
+            units,
+            given,
+            sites,
+            quiet,
+        } in pending
+        {
+            let mut found = HashMap::new();
+            for site in sites {
+                let mut dicts = Vec::new();
+                for pred in &site.preds {
+                    match self.entail(pred, &given) {
+                        Some(dict) => dicts.push(dict),
+                        None if quiet => break,
+                        None => {
+                            self.no_instance(pred, site.span);
+                            break;
+                        }
+                    }
+                }
+                if dicts.len() == site.preds.len() {
+                    found.insert(site.span, dicts);
+                }
+            }
+            for unit in units {
+                let evidence = UnitEvidence {
+                    params: given.len(),
+                    sites: found.clone(),
+                };
+                self.evidence.units.insert(unit, evidence);
+            }
+        }
+
+        // Solving may add instances for tuples, which have superclasses too.
+        let mut done = HashSet::new();
+        loop {
+            let todo: Vec<_> = self
+                .instances
+                .keys()
+                .filter(|key| !done.contains(*key))
+                .cloned()
+                .collect();
+            if todo.is_empty() {
+                break;
+            }
+            for key in todo {
+                let superclasses = self.instance_superclasses(&key);
+                if let Some(instance) = self.instances.get_mut(&key) {
+                    instance.superclasses = superclasses;
+                }
+                done.insert(key);
+            }
+        }
+        self.evidence.instances = self.instances.clone();
+    }
+
+    /// The dictionaries of the superclasses of an instance, given the ones
+    /// of its context.
+    fn instance_superclasses(&mut self, key: &(String, String)) -> Vec<Dict> {
+        let context = self.instances[key].context.clone();
+        let (vars, span) = match self.declared.get(key) {
+            Some((vars, span)) => (vars.clone(), Some(*span)),
+            None => {
+                let count = context.iter().map(|(_, i)| i + 1).max().unwrap_or(0);
+                ((0..count).map(|i| format!("a{}", i)).collect(), None)
+            }
+        };
+        let args: Vec<Ty> = vars.iter().map(|var| Ty::con(var)).collect();
+        let given: Vec<Pred> = context
+            .iter()
+            .map(|(class, i)| Pred::new(class, args[*i].clone()))
+            .collect();
+        let ty = head_type(&key.1, args);
+
+        let superclasses = self.classes.get(&key.0).map(|class| class.superclasses.clone()).unwrap_or_default();
+        let mut dicts = Vec::new();
+        for superclass in superclasses {
+            let pred = Pred::new(&superclass, ty.clone());
+            match self.entail(&pred, &given) {
+                Some(dict) => dicts.push(dict),
+                None => {
+                    if let Some(span) = span {
+                        self.no_instance(&pred, span);
+                    }
+                }
+            }
+        }
+        dicts
+    }
+
+    fn no_instance(&mut self, pred: &Pred, span: Span) {
+        let ty = self.zonk(&pred.ty).to_string();
+        let kind = match pred.class.as_str() {
+            "Num" => TypeCheckerErrorKind::NotNumeric { found: ty },
+            _ => TypeCheckerErrorKind::NoInstance {
+                class: pred.class.clone(),
+                ty,
+            },
+        };
+        self.errors.push(TypeCheckerError { kind, span });
+    }
+}
+
+/// The number of arguments a function of type `ty` takes.
+fn arity(ty: &Ty) -> usize {
+    match ty {
+        Ty::Func(_, ret) => 1 + arity(ret),
+        _ => 0,
+    }
+}
@@ -0,0 +1,260 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Exhaustiveness of the clauses of functions, of the arms of `match` and
+//! of the arguments of lambdas. A clause whose guards can all fail matches
+//! nothing as far as coverage goes, unless its last guard is `otherwise`
+//! or `True`.
+
+use super::error::{TypeCheckerError, TypeCheckerErrorKind};
+use super::types::display_name;
+use super::TypeChecker;
+use crate::ast::{AnnExpr, Bind, Bool, LiteralKind, ParsedExpr, Pattern, Span};
+
+/// A pattern as coverage sees it: what it binds does not matter.
+#[derive(Debug, Clone, PartialEq)]
+enum Pat {
+    Any,
+    Con(Ctor, Vec<Pat>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Named(String),
+    Tuple(usize),
+    Nil,
+    Cons,
+    Bool(bool),
+    /// A literal of a type with too many values to list them.
+    Literal(LiteralKind),
+}
+
+impl TypeChecker {
+    /// Reports the arguments none of the clauses of a function match.
+    pub(super) fn check_clauses(&mut self, clauses: &[&Bind<Span>]) {
+        let Some(first) = clauses.first() else {
+            return;
+        };
+        if first.args.is_empty() {
+            return;
+        }
+        let rows: Vec<Vec<Pat>> = clauses
+            .iter()
+            .filter(|bind| covers(&bind.expr))
+            .map(|bind| bind.args.iter().map(|p| self.lower(p)).collect())
+            .collect();
+        if let Some(witness) = self.uncovered(&rows, first.args.len()) {
+            let args: Vec<String> = witness.iter().map(|p| render(p, true)).collect();
+            let missing = format!("{} {}", first.name.name, args.join(" "));
+            self.missing(missing, rows.len() < clauses.len(), first.name.span);
+        }
+    }
+
+    /// Reports the values none of the arms of a `match` match.
+    pub(super) fn check_cases(&mut self, cases: &[(Pattern, Box<ParsedExpr>)], span: Span) {
+        let rows: Vec<Vec<Pat>> = cases
+            .iter()
+            .filter(|(_, body)| covers(body))
+            .map(|(pattern, _)| vec![self.lower(pattern)])
+            .collect();
+        if let Some(witness) = self.uncovered(&rows, 1) {
+            self.missing(render(&witness[0], false), rows.len() < cases.len(), span);
+        }
+    }
+
+    /// Reports the arguments the patterns of a lambda do not match.
+    pub(super) fn check_lambda(&mut self, args: &[Pattern], span: Span) {
+        let rows = vec![args.iter().map(|p| self.lower(p)).collect()];
+        if let Some(witness) = self.uncovered(&rows, args.len()) {
+            let args: Vec<String> = witness.iter().map(|p| render(p, true)).collect();
+            self.missing(format!("\\{}", args.join(" ")), false, span);
+        }
+    }
+
+    /// `guarded` is whether some clauses were left out for their guards.
+    fn missing(&mut self, missing: String, guarded: bool, span: Span) {
+        self.errors.push(TypeCheckerError {
+            kind: TypeCheckerErrorKind::NonExhaustiveMatch { missing, guarded },
+            span,
+        });
+    }
+
+    /// Constructors nobody declared have been reported by the semantic
+    /// analysis, they match anything here.
+    fn lower(&self, pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Wildcard(_) | Pattern::Variable(_) => Pat::Any,
+            Pattern::As(_, pattern, _) => self.lower(pattern),
+            Pattern::Literal(lit) => match lit.lit {
+                LiteralKind::Bool(Bool::True) => Pat::Con(Ctor::Bool(true), Vec::new()),
+                LiteralKind::Bool(Bool::False) => Pat::Con(Ctor::Bool(false), Vec::new()),
+                _ => Pat::Con(Ctor::Literal(lit.lit.clone()), Vec::new()),
+            },
+            Pattern::Id(id) if self.variants.contains_key(&id.name) => {
+                Pat::Con(Ctor::Named(id.name.clone()), Vec::new())
+            }
+            Pattern::App(id, patterns, _) if self.variants.contains_key(&id.name) => Pat::Con(
+                Ctor::Named(id.name.clone()),
+                patterns.iter().map(|p| self.lower(p)).collect(),
+            ),
+            Pattern::Record(id, fields, _) if self.variants.contains_key(&id.name) => {
+                let names = self.records.get(&id.name).cloned().unwrap_or_default();
+                let args = names
+                    .iter()
+                    .map(|name| match fields.iter().find(|(field, _)| field.name == *name) {
+                        Some((_, pattern)) => self.lower(pattern),
+                        None => Pat::Any,
+                    })
+                    .collect();
+                Pat::Con(Ctor::Named(id.name.clone()), args)
+            }
+            Pattern::Id(_) | Pattern::App(..) | Pattern::Record(..) => Pat::Any,
+            Pattern::ListCons(head, tail, _) => {
+                Pat::Con(Ctor::Cons, vec![self.lower(head), self.lower(tail)])
+            }
+            Pattern::List(items, _) => items.iter().rev().fold(Pat::Con(Ctor::Nil, Vec::new()), |tail, item| {
+                Pat::Con(Ctor::Cons, vec![self.lower(item), tail])
+            }),
+            Pattern::Tuple(items, _) => Pat::Con(
+                Ctor::Tuple(items.len()),
+                items.iter().map(|p| self.lower(p)).collect(),
+            ),
+        }
+    }
+
+    /// Every constructor of the type `ctor` builds, with its arity, when
+    /// they can be listed.
+    fn signature(&self, ctor: &Ctor) -> Option<Vec<(Ctor, usize)>> {
+        match ctor {
+            Ctor::Named(name) => {
+                let siblings = self.variants.get(name)?;
+                Some(siblings.iter().map(|(name, arity)| (Ctor::Named(name.clone()), *arity)).collect())
+            }
+            Ctor::Tuple(arity) => Some(vec![(Ctor::Tuple(*arity), *arity)]),
+            Ctor::Nil | Ctor::Cons => Some(vec![(Ctor::Nil, 0), (Ctor::Cons, 2)]),
+            Ctor::Bool(_) => Some(vec![(Ctor::Bool(true), 0), (Ctor::Bool(false), 0)]),
+            Ctor::Literal(_) => None,
+        }
+    }
+
+    /// A row of `width` values that none of `rows` matches, if there is
+    /// one. The usefulness algorithm of Maranget's "Warnings for pattern
+    /// matching".
+    fn uncovered(&self, rows: &[Vec<Pat>], width: usize) -> Option<Vec<Pat>> {
+        if width == 0 {
+            return rows.is_empty().then(Vec::new);
+        }
+        let heads: Vec<&Ctor> = rows
+            .iter()
+            .filter_map(|row| match &row[0] {
+                Pat::Con(ctor, _) => Some(ctor),
+                Pat::Any => None,
+            })
+            .collect();
+        let signature = heads.first().and_then(|ctor| self.signature(ctor));
+
+        let absent = match &signature {
+            Some(signature) => signature.iter().find(|(ctor, _)| !heads.contains(&ctor)).cloned(),
+            None => None,
+        };
+        if let (Some(signature), None) = (&signature, &absent) {
+            // Every constructor appears: a value is missed if one is missed
+            // under some constructor.
+            for (ctor, arity) in signature {
+                let specialized = specialize(rows, ctor, *arity);
+                if let Some(mut args) = self.uncovered(&specialized, arity + width - 1) {
+                    let rest = args.split_off(*arity);
+                    let mut witness = vec![Pat::Con(ctor.clone(), args)];
+                    witness.extend(rest);
+                    return Some(witness);
+                }
+            }
+            return None;
+        }
+
+        // Some constructor is missing, only the rows that match anything in
+        // this column can match it.
+        let defaults: Vec<Vec<Pat>> = rows
+            .iter()
+            .filter(|row| row[0] == Pat::Any)
+            .map(|row| row[1..].to_vec())
+            .collect();
+        let mut witness = self.uncovered(&defaults, width - 1)?;
+        let head = match absent {
+            Some((ctor, arity)) if !heads.is_empty() => Pat::Con(ctor, vec![Pat::Any; arity]),
+            _ => Pat::Any,
+        };
+        witness.insert(0, head);
+        Some(witness)
+    }
+}
+
+/// The rows that match values built by `ctor`, with the arguments of the
+/// constructor in place of the first column.
+fn specialize(rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut specialized = match &row[0] {
+                Pat::Con(head, args) if head == ctor => args.clone(),
+                Pat::Con(..) => return None,
+                Pat::Any => vec![Pat::Any; arity],
+            };
+            specialized.extend_from_slice(&row[1..]);
+            Some(specialized)
+        })
+        .collect()
+}
+
+/// Whether a clause or an arm with this body matches whatever its patterns
+/// match.
+fn covers(body: &ParsedExpr) -> bool {
+    match body {
+        AnnExpr::Guards { guards, .. } => match guards.last() {
+            Some((AnnExpr::Identifier { id }, _)) => id.name == "otherwise",
+            Some((AnnExpr::Literal(lit), _)) => lit.lit == LiteralKind::Bool(Bool::True),
+            _ => false,
+        },
+        AnnExpr::Let { ret, .. } => covers(ret),
+        _ => true,
+    }
+}
+
+/// A pattern in Risk syntax, in parentheses if `atomic` and it has parts.
+fn render(pat: &Pat, atomic: bool) -> String {
+    let (text, compound) = match pat {
+        Pat::Any => ("_".to_string(), false),
+        Pat::Con(Ctor::Named(name), args) if args.is_empty() => (display_name(name).to_string(), false),
+        Pat::Con(Ctor::Named(name), args) => {
+            let args: Vec<String> = args.iter().map(|p| render(p, true)).collect();
+            (format!("{} {}", display_name(name), args.join(" ")), true)
+        }
+        Pat::Con(Ctor::Tuple(_), items) => {
+            let items: Vec<String> = items.iter().map(|p| render(p, false)).collect();
+            (format!("({})", items.join(", ")), false)
+        }
+        Pat::Con(Ctor::Nil, _) => ("[]".to_string(), false),
+        Pat::Con(Ctor::Cons, args) => (format!("{} : {}", render(&args[0], true), render(&args[1], false)), true),
+        Pat::Con(Ctor::Bool(b), _) => ((if *b { "True" } else { "False" }).to_string(), false),
+        Pat::Con(Ctor::Literal(_), _) => ("_".to_string(), false),
+    };
+    if atomic && compound {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
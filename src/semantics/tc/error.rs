@@ -1,29 +1,32 @@
+use crate::source::SourceMap;
 use ariadne::*;
-use crate::ast::{Type, Span};
-use super::ast::Annot;
+use crate::ast::Span;
+use crate::semantics::tc::types::display_name;
 
 #[derive(Debug)]
 pub enum TypeCheckerErrorKind {
     MismatchedTypes {
-        expected: Annot,
-        found: Annot,
-    },
-    NonExhaustiveMatch {
-        span: Span,
+        expected: String,
+        found: String,
+        /// A type variable of a signature where an `Integer` was found.
+        integer_for: Option<String>,
     },
     InfiniteType {
-        span: Span,
+        ty: String,
     },
-    AmbiguousType {
-        span: Span,
+    NotNumeric {
+        found: String,
     },
-    IncompatibleTypes {
-        expected: Annot,
-        found: Annot,
-        span: Span,
+    NotAFunction {
+        found: String,
     },
-    UnificationError {
-        span: Span,
+    NoInstance {
+        class: String,
+        ty: String,
+    },
+    NonExhaustiveMatch {
+        missing: String,
+        guarded: bool,
     },
 }
 
@@ -34,51 +37,99 @@ pub struct TypeCheckerError {
 }
 
 impl TypeCheckerError {
-    pub fn report(&self, filename: &str) {
-        let source = &std::fs::read_to_string(filename).unwrap();
-        let mut report = Report::build(
-            ReportKind::Error,
-            filename,
-            self.span.get_line_number(source),
-        );
+    pub fn report(&self, sources: &SourceMap) {
+        let mut report = Report::build(ReportKind::Error, self.span.file, sources.label(self.span).1.start);
 
         match &self.kind {
-            TypeCheckerErrorKind::MismatchedTypes { expected, found } => {
-                report = report.with_message(format!(
-                    "Mismatched types. Expected `{:?}`, found `{:?}`",
-                    expected.1, found.1
-                )).with_label(
-                    Label::new((filename, expected.0.start..expected.0.end))
-                        .with_message("Expected type here")
-                );
+            TypeCheckerErrorKind::MismatchedTypes { expected, found, integer_for } => {
+                report = report
+                    .with_code("mismatched-types")
+                    .with_message(format!(
+                        "Mismatched types. Expected `{}`, found `{}`",
+                        expected, found
+                    ))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message(format!("This has type `{}`", found))
+                            .with_color(Color::Cyan),
+                    );
+                if let Some(var) = integer_for {
+                    report = report.with_note(format!(
+                        "Integer literals are always `Integer`. Where `{}` is an instance of `Num`, `fromInteger 0` is a `0` of type `{}`.",
+                        var, var
+                    ));
+                }
             }
-            TypeCheckerErrorKind::NonExhaustiveMatch { span } => {
-                report = report.with_message("Non-exhaustive pattern match");
+            TypeCheckerErrorKind::InfiniteType { ty } => {
+                report = report
+                    .with_code("infinite-type")
+                    .with_message(format!("Infinite type `{}`", ty))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("This would have an infinite type")
+                            .with_color(Color::Cyan),
+                    );
             }
-            TypeCheckerErrorKind::InfiniteType { span } => {
-                report = report.with_message("Infinite type");
+            TypeCheckerErrorKind::NotNumeric { found } => {
+                report = report
+                    .with_code("not-numeric")
+                    .with_message(format!(
+                        "Arithmetic on `{}`, expected `Integer` or `Float`",
+                        found
+                    ))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Operands must be both `Integer` or both `Float`")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Use `toFloat`, `truncate`, `round`, `floor` or `ceiling` to convert between numeric types.");
             }
-            TypeCheckerErrorKind::AmbiguousType { span } => {
-                report = report.with_message("Ambiguous type");
+            TypeCheckerErrorKind::NotAFunction { found } => {
+                report = report
+                    .with_code("not-a-function")
+                    .with_message(format!("A value of type `{}` is applied to an argument", found))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("One argument too many")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Only functions and constructors can be applied, to at most as many arguments as they take.");
             }
-            TypeCheckerErrorKind::IncompatibleTypes {
-                expected,
-                found,
-                span,
-            } => {
-                report = report.with_message(format!(
-                    "Incompatible types. Expected `{:?}`, found `{:?}`",
-                    expected.1, found.1
-                ));
+            TypeCheckerErrorKind::NoInstance { class, ty } => {
+                report = report
+                    .with_code("no-instance")
+                    .with_message(format!("No instance of `{}` for `{}`", display_name(class), ty))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message(format!(
+                                "This needs `{}` to be an instance of `{}`",
+                                ty,
+                                display_name(class)
+                            ))
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Functions are never instances of `Eq`, `Ord` or `Show`.");
             }
-            TypeCheckerErrorKind::UnificationError { span } => {
-                report = report.with_message("Unification error");
+            TypeCheckerErrorKind::NonExhaustiveMatch { missing, guarded } => {
+                report = report
+                    .with_code("non-exhaustive")
+                    .with_message(format!("Non-exhaustive patterns, `{}` is not matched", missing))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Some values are not matched here")
+                            .with_color(Color::Cyan),
+                    );
+                if *guarded {
+                    report = report.with_note(
+                        "Clauses with guards are taken to match nothing, unless their last guard is `otherwise`.",
+                    );
+                }
             }
         }
 
         report
             .finish()
-            .print((filename, Source::from(source)))
+            .print(sources)
             .unwrap();
     }
-}
\ No newline at end of file
+}
@@ -16,118 +16,1165 @@
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
 
-mod ast;
-mod error;
+pub mod class;
+mod coverage;
+pub mod error;
+mod types;
+
+#[cfg(test)]
+mod test;
 
 use std::collections::HashMap;
 
 use crate::ast::*;
-use ast::Annot;
+use crate::source::FileId;
+use crate::vm::native::NATIVES;
+use class::{instance_head, Class, Evidence, Instance, Pending, Site, Unit};
+use error::{TypeCheckerError, TypeCheckerErrorKind};
+use types::{Pred, Scheme, Ty, TyVar};
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
-pub struct Symbol {
-    pub span: Span,
-    pub scope_id: u16,
-    pub level: u16
+enum UnifyError {
+    Mismatch,
+    Occurs,
 }
 
-type Variant = String;
-
+/// Hindley-Milner inference with destructive unification and level-based
+/// generalization.
+///
+/// Overloading goes through classes: methods, operators and negation
+/// constrain the types they are used at, and the constraints are solved
+/// once inference is done, giving the `Evidence` the elaboration needs.
+/// Top-level functions are generalized over the constraints on their type
+/// variables; other definitions are not, and the constrained type
+/// variables nothing else fixes default to `Integer`. There is no implicit
+/// conversion; `toFloat`, `truncate`, `round`, `floor` and `ceiling` are
+/// provided as natives.
 #[derive(Debug)]
 pub struct TypeChecker {
-    pub output: Vec<Statement<Annot>>,
-    pub symbols: HashMap<Symbol, Type>, // Symbol -> Type
-    pub signatures: HashMap<Symbol, Type>, // Symbol -> Signature
-    pub variants: HashMap<Variant, Type>, // Variant -> App Type
-    pub filename: &'static str,
-    pub scope_id: u16,
-    pub level: u16,
+    pub errors: Vec<TypeCheckerError>,
+    pub evidence: Evidence,
+    globals: HashMap<String, Scheme>,
+    constructors: HashMap<String, Scheme>,
+    /// The fields of the record constructors, in order.
+    records: HashMap<String, Vec<String>>,
+    /// The constructors of the type of each constructor, with their arity.
+    variants: HashMap<String, Vec<(String, usize)>>,
+    classes: HashMap<String, Class>,
+    instances: HashMap<(String, String), Instance>,
+    /// The type variables and the span of the instances written in the
+    /// program.
+    declared: HashMap<(String, String), (Vec<String>, Span)>,
+    locals: Vec<(String, Scheme)>,
+    bindings: Vec<Option<Ty>>,
+    levels: Vec<usize>,
+    level: usize,
+    /// The constraints of the definition being inferred.
+    wanted: Vec<Site>,
+    pending: Vec<Pending>,
+    /// The functions being inferred together, and where they refer to each
+    /// other.
+    group: Vec<String>,
+    recursive: Vec<Span>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
-    pub fn new(filename: &'static str) -> Self {
+    pub fn new() -> Self {
         TypeChecker {
-            output: Vec::new(),
-            symbols: HashMap::new(),
-            signatures: HashMap::new(),
+            errors: Vec::new(),
+            evidence: Evidence::default(),
+            globals: HashMap::new(),
+            constructors: HashMap::new(),
+            records: HashMap::new(),
             variants: HashMap::new(),
-            filename,
-            scope_id: 0,
+            classes: HashMap::new(),
+            instances: HashMap::new(),
+            declared: HashMap::new(),
+            locals: Vec::new(),
+            bindings: Vec::new(),
+            levels: Vec::new(),
             level: 0,
+            wanted: Vec::new(),
+            pending: Vec::new(),
+            group: Vec::new(),
+            recursive: Vec::new(),
         }
     }
 
-    pub fn get_type(&self, expr: AnnExpr<Span>) -> Option<Type> {
-        match expr {
-            AnnExpr::Identifier { id } => {
-                if let Some(typ) = self.symbols.get(&Symbol {
-                    span: id.span.clone(),
-                    scope_id: self.scope_id,
-                    level: self.level
-                }) {
-                    Some(typ.clone())
-                } else {
-                    None
+    pub fn check_program(&mut self, program: &Program<Span>) {
+        for native in NATIVES {
+            let scheme = self.signature_scheme(&parse_signature(native.signature));
+            self.globals.insert(native.name.to_string(), scheme);
+        }
+        self.declare_builtin_classes();
+
+        let mut signatures: HashMap<&str, &Type> = HashMap::new();
+        let mut groups: Vec<(&str, Vec<&Bind<Span>>)> = Vec::new();
+        let mut types = Vec::new();
+        let mut classes = Vec::new();
+        let mut instances = Vec::new();
+
+        for statement in &program.statements {
+            match statement {
+                Statement::TypeDecl(decl) => {
+                    self.declare_type(decl);
+                    types.push(decl);
                 }
+                Statement::Class(decl) => classes.push(decl),
+                Statement::Instance(decl) => instances.push(decl),
+                Statement::TypeAssign(assign) => {
+                    let scheme = self.signature_scheme(&assign.ty);
+                    self.globals.insert(assign.id.name.clone(), scheme);
+                    signatures.insert(&assign.id.name, &assign.ty);
+                }
+                Statement::Bind(bind) => add_clause(&mut groups, bind),
+                Statement::Fixity(_) | Statement::Module(_) | Statement::Import(_) => {}
             }
-            AnnExpr::PCIdentifier { id } => {
-                if let Some(typ) = self.variants.get(&id.name) {
-                    Some(typ.clone())
-                } else {
-                    None
+        }
+
+        for decl in &classes {
+            self.declare_class(decl);
+        }
+        for decl in &instances {
+            self.declare_instance(decl);
+        }
+        self.derive_instances(&types);
+
+        let (annotated, inferred): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(name, _)| signatures.contains_key(name));
+
+        for component in dependency_order(&inferred) {
+            let members: Vec<_> = component.into_iter().map(|i| &inferred[i]).collect();
+            self.infer_group(&members);
+        }
+
+        for (name, clauses) in &annotated {
+            self.check_annotated(name, clauses, signatures[name]);
+        }
+
+        for decl in &classes {
+            self.check_defaults(decl);
+        }
+        for decl in &instances {
+            self.check_instance(decl, &classes);
+        }
+
+        self.solve();
+    }
+
+    fn declare_type(&mut self, decl: &TypeDecl) {
+        self.enter();
+        let mut generics = HashMap::new();
+        let params: Vec<Ty> = decl
+            .typevars
+            .iter()
+            .map(|var| {
+                let ty = self.fresh();
+                generics.insert(var.name.clone(), ty.clone());
+                ty
+            })
+            .collect();
+        let result = Ty::Con(decl.name.name.clone(), params);
+
+        let variants: Vec<Vec<Ty>> = decl
+            .variants
+            .iter()
+            .map(|variant| {
+                variant
+                    .types
+                    .iter()
+                    .map(|field| self.convert_type(field, &mut generics, false))
+                    .collect()
+            })
+            .collect();
+
+        // The selector of a field shared by several variants has one type.
+        let mut selectors: Vec<(&str, Ty)> = Vec::new();
+        for (variant, types) in decl.variants.iter().zip(&variants) {
+            for (field, ty) in variant.fields.iter().zip(types) {
+                let selector = Ty::func(result.clone(), ty.clone());
+                match selectors.iter().find(|(name, _)| *name == field.name) {
+                    Some((_, other)) => {
+                        let other = other.clone();
+                        self.unify(&other, &selector, &field.span);
+                    }
+                    None => selectors.push((&field.name, selector)),
+                }
+            }
+        }
+        self.exit();
+
+        for (variant, types) in decl.variants.iter().zip(variants) {
+            let ty = types
+                .into_iter()
+                .rev()
+                .fold(result.clone(), |acc, field| Ty::func(field, acc));
+            let scheme = self.generalize(&ty);
+            self.constructors.insert(variant.id.name.clone(), scheme);
+            if !variant.fields.is_empty() {
+                let fields = variant.fields.iter().map(|field| field.name.clone()).collect();
+                self.records.insert(variant.id.name.clone(), fields);
+            }
+        }
+        let siblings: Vec<(String, usize)> = decl
+            .variants
+            .iter()
+            .map(|variant| (variant.id.name.clone(), variant.types.len()))
+            .collect();
+        for (name, _) in &siblings {
+            self.variants.insert(name.clone(), siblings.clone());
+        }
+        for (name, ty) in selectors {
+            let scheme = self.generalize(&ty);
+            self.globals.insert(name.to_string(), scheme);
+        }
+    }
+
+    /// The types of the fields of the record constructor `id`, by name, and
+    /// the type of the record.
+    fn record_fields(&mut self, id: &Identifier) -> (Vec<(String, Ty)>, Ty) {
+        let mut ty = self.lookup(id);
+        let names = self.records.get(&id.name).cloned().unwrap_or_default();
+        let mut fields = Vec::new();
+        for name in names {
+            match ty {
+                Ty::Func(arg, ret) => {
+                    fields.push((name, *arg));
+                    ty = *ret;
                 }
+                _ => break,
+            }
+        }
+        (fields, ty)
+    }
+
+    /// Unifies the types of the fields given to a record with the ones they
+    /// are declared with. Unknown fields have been reported by the semantic
+    /// analysis.
+    fn unify_fields(&mut self, declared: &[(String, Ty)], given: &[(Identifier, Ty, Span)]) {
+        for (name, ty, span) in given {
+            if let Some((_, expected)) = declared.iter().find(|(field, _)| *field == name.name) {
+                self.unify(expected, ty, span);
+            }
+        }
+    }
+
+    fn signature_scheme(&mut self, ty: &Type) -> Scheme {
+        self.enter();
+        let mut generics = HashMap::new();
+        let converted = self.convert_type(ty, &mut generics, false);
+        let preds = self.convert_context(ty, &mut generics, false);
+        self.exit();
+        let mut scheme = self.generalize(&converted);
+        scheme.preds = preds;
+        scheme
+    }
+
+    /// Infers a group of mutually recursive, unannotated functions.
+    ///
+    /// The constraints on the type variables of the group become the
+    /// constraints of its members, unless one of them takes no argument:
+    /// the constrained type variables of a constant stay monomorphic.
+    fn infer_group(&mut self, members: &[&(&str, Vec<&Bind<Span>>)]) {
+        let outer = std::mem::take(&mut self.wanted);
+        self.group = members.iter().map(|(name, _)| name.to_string()).collect();
+
+        self.enter();
+        let vars: Vec<Ty> = members.iter().map(|_| self.fresh()).collect();
+        for ((name, _), var) in members.iter().copied().zip(&vars) {
+            self.globals.insert(name.to_string(), Scheme::mono(var.clone()));
+        }
+        for ((_, clauses), var) in members.iter().copied().zip(&vars) {
+            for bind in clauses {
+                let ty = self.infer_clause(bind);
+                self.unify(var, &ty, &bind.span);
             }
-            AnnExpr::App(App { ident, args, span }) => {
-                if let Some(typ) = self.variants.get(&ident.name) {
-                    Some(typ.clone())
+            self.check_clauses(clauses);
+        }
+        self.exit();
+
+        let mut sites = std::mem::replace(&mut self.wanted, outer);
+        let recursive = std::mem::take(&mut self.recursive);
+        self.group.clear();
+
+        // Only the type variables all members share can be abstracted over.
+        let mut shared: Option<Vec<TyVar>> = None;
+        for var in &vars {
+            let mut free = Vec::new();
+            self.free_vars(var, &mut free);
+            shared = Some(match shared {
+                Some(shared) => shared.into_iter().filter(|v| free.contains(v)).collect(),
+                None => free,
+            });
+        }
+        let shared = shared.unwrap_or_default();
+        let functions = members
+            .iter()
+            .all(|(_, clauses)| clauses.iter().all(|bind| !bind.args.is_empty()));
+
+        let mut params: Vec<Pred> = Vec::new();
+        for pred in sites.iter().flat_map(|site| site.preds.clone()).collect::<Vec<_>>() {
+            for (class, v) in self.reduce(&pred) {
+                let param = Pred::new(&class, Ty::Var(v));
+                if functions && self.levels[v] > self.level && shared.contains(&v) {
+                    if !params.contains(&param) {
+                        params.push(param);
+                    }
                 } else {
-                    None
-                }
-            }
-            _ => todo!()
-        }
-    }
-
-    pub fn analyze_statement(&mut self, statement: Statement<Span>) {
-        match statement {
-            Statement::Bind(Bind {
-                name,
-                args,
-                expr,
-                span
-            }) => {
-                // let sym = Symbol {
-                //     span: name.span.clone(),
-                //     scope_id: self.scope_id,
-                //     level: self.level
-                // };
-
-                // let expr_type = expr.get_type();
-
-                // if let Some(symbol_type) = self.signatures.get(&sym) {
-                //     if expr.get_type() != *symbol_type {
-                //         error::TypeCheckerError {
-                //             kind: error::TypeCheckerErrorKind::MismatchedTypes {
-                //                 expected: (symbol_type.get_span().clone(), symbol_type.clone()),
-                //                 found: (expr_type.get_span().clone(), expr_type)
-                //             },
-                //             span: span.clone()
-                //         }.report(self.filename);
-                //     }
-                // } else {
-                //     if let Some(typ) = expr.get_type() {
-                //         self.signatures.insert(sym, typ);
-                //     } else {
-                //         error::TypeCheckerError {
-                //             kind: error::TypeCheckerErrorKind::AmbiguousType {
-                //                 span: span.clone()
-                //             },
-                //             span: span.clone()
-                //         }.report(self.filename);
-                //     }
-                // }
-            }
-            _ => todo!()
-        }
-    }
-}
\ No newline at end of file
+                    self.levels[v] = self.levels[v].min(self.level);
+                }
+            }
+        }
+        // Members refer to each other with the dictionaries they are given.
+        for span in recursive {
+            sites.push(Site {
+                preds: params.clone(),
+                span,
+            });
+        }
+
+        for ((name, _), var) in members.iter().copied().zip(&vars) {
+            let mut scheme = self.generalize(var);
+            scheme.preds = params.clone();
+            self.globals.insert(name.to_string(), scheme);
+        }
+        self.pending.push(Pending {
+            units: members.iter().map(|(name, _)| Unit::Bind(name.to_string())).collect(),
+            given: params,
+            sites,
+            quiet: false,
+        });
+    }
+
+    /// Checks the clauses of a function against its signature. Type
+    /// variables of the signature are rigid inside the body.
+    fn check_annotated(&mut self, name: &str, clauses: &[&Bind<Span>], signature: &Type) {
+        let mut generics = HashMap::new();
+        let expected = self.convert_type(signature, &mut generics, true);
+        let given = self.convert_context(signature, &mut generics, true);
+        self.check_unit(vec![Unit::Bind(name.to_string())], clauses, &expected, given, false);
+    }
+
+    /// Checks the default definitions of the methods of a class, for any
+    /// instance of it.
+    fn check_defaults(&mut self, decl: &ClassDecl<Span>) {
+        let Some(class) = self.classes.get(&decl.name.name) else {
+            return;
+        };
+        let methods = class.methods.clone();
+        for (method, signature) in methods {
+            let clauses: Vec<_> = decl.defaults.iter().filter(|bind| bind.name.name == method).collect();
+            if clauses.is_empty() {
+                continue;
+            }
+            let var = Ty::con(&decl.var.name);
+            let mut generics = HashMap::from([(decl.var.name.clone(), var.clone())]);
+            let expected = self.convert_type(&signature, &mut generics, true);
+            let mut given = vec![Pred::new(&decl.name.name, var)];
+            given.extend(self.convert_context(&signature, &mut generics, true));
+            self.check_unit(Vec::new(), &clauses, &expected, given, false);
+        }
+    }
+
+    /// Checks the methods of an instance against the signatures of its
+    /// class, and the defaults of the methods it does not define at its
+    /// type.
+    fn check_instance(&mut self, decl: &InstanceDecl<Span>, classes: &[&ClassDecl<Span>]) {
+        let Some((head, vars)) = instance_head(&decl.ty) else {
+            return;
+        };
+        let Some(class) = self.classes.get(&decl.class.name) else {
+            return;
+        };
+        let (var, methods) = (class.var.clone(), class.methods.clone());
+        let defaults = classes
+            .iter()
+            .find(|class| class.name == decl.class)
+            .map(|class| &class.defaults[..])
+            .unwrap_or_default();
+        let ty = self.convert_type(&decl.ty, &mut HashMap::new(), true);
+        let context: Vec<Pred> = decl
+            .context
+            .iter()
+            .filter(|c| vars.contains(&c.var.name))
+            .map(|c| Pred::new(&c.class.name, Ty::con(&c.var.name)))
+            .collect();
+
+        for (method, signature) in methods {
+            let unit = Unit::Method {
+                class: decl.class.name.clone(),
+                head: head.clone(),
+                method: method.clone(),
+            };
+            let mut generics = HashMap::from([(var.clone(), ty.clone())]);
+            let expected = self.convert_type(&signature, &mut generics, true);
+            let mut given = context.clone();
+            given.extend(self.convert_context(&signature, &mut generics, true));
+
+            let clauses: Vec<_> = decl.binds.iter().filter(|bind| bind.name.name == method).collect();
+            if !clauses.is_empty() {
+                self.check_unit(vec![unit], &clauses, &expected, given, false);
+                continue;
+            }
+            // A default that fits the class fits each instance, its errors
+            // have been reported once.
+            let clauses: Vec<_> = defaults.iter().filter(|bind| bind.name.name == method).collect();
+            if !clauses.is_empty() {
+                let errors = self.errors.len();
+                self.check_unit(vec![unit], &clauses, &expected, given, true);
+                self.errors.truncate(errors);
+            }
+        }
+    }
+
+    /// Checks clauses against the type `expected`, under the constraints
+    /// `given` to them.
+    fn check_unit(&mut self, units: Vec<Unit>, clauses: &[&Bind<Span>], expected: &Ty, given: Vec<Pred>, quiet: bool) {
+        let outer = std::mem::take(&mut self.wanted);
+        self.enter();
+        for bind in clauses {
+            let ty = self.infer_clause(bind);
+            self.unify(expected, &ty, &bind.span);
+        }
+        self.check_clauses(clauses);
+        self.exit();
+        let sites = std::mem::replace(&mut self.wanted, outer);
+        self.pending.push(Pending {
+            units,
+            given,
+            sites,
+            quiet,
+        });
+    }
+
+    /// Records that the type `ty` must be an instance of `class` at `span`.
+    fn want(&mut self, class: &str, ty: Ty, span: Span) {
+        self.wanted.push(Site {
+            preds: vec![Pred::new(class, ty)],
+            span,
+        });
+    }
+
+    /// Infers a group of mutually recursive local binds, and brings them
+    /// in scope. Local definitions are not overloaded.
+    fn infer_local_group(&mut self, members: Vec<&(&str, Vec<&Bind<Span>>)>) {
+        let wanted = self.wanted.len();
+        self.enter();
+        let vars: Vec<Ty> = members.iter().map(|_| self.fresh()).collect();
+        let depth = self.locals.len();
+        for ((name, _), var) in members.iter().copied().zip(&vars) {
+            self.locals.push((name.to_string(), Scheme::mono(var.clone())));
+        }
+        for ((_, clauses), var) in members.iter().copied().zip(&vars) {
+            for bind in clauses {
+                let ty = self.infer_clause(bind);
+                self.unify(var, &ty, &bind.span);
+            }
+            self.check_clauses(clauses);
+        }
+        self.locals.truncate(depth);
+        self.exit();
+
+        let mut constrained = Vec::new();
+        for pred in self.wanted[wanted..].iter().flat_map(|site| &site.preds) {
+            self.free_vars(&pred.ty, &mut constrained);
+        }
+        for v in constrained {
+            self.levels[v] = self.levels[v].min(self.level);
+        }
+        for ((name, _), var) in members.into_iter().zip(&vars) {
+            let scheme = self.generalize(var);
+            self.locals.push((name.to_string(), scheme));
+        }
+    }
+
+    fn infer_clause(&mut self, bind: &Bind<Span>) -> Ty {
+        let depth = self.locals.len();
+        let args: Vec<Ty> = bind.args.iter().map(|p| self.infer_pattern(p)).collect();
+        let body = self.infer_expr(&bind.expr);
+        self.locals.truncate(depth);
+        args.into_iter().rev().fold(body, |acc, arg| Ty::func(arg, acc))
+    }
+
+    fn infer_expr(&mut self, expr: &ParsedExpr) -> Ty {
+        match expr {
+            AnnExpr::Literal(lit) => literal_type(&lit.lit),
+            AnnExpr::Identifier { id } | AnnExpr::PCIdentifier { id } => self.lookup(id),
+            AnnExpr::App(App { head, args, span }) => {
+                let mut ty = self.infer_expr(head);
+                for arg in args {
+                    let arg_ty = self.infer_expr(arg);
+                    ty = self.apply(ty, arg_ty, arg.get_span(), span);
+                }
+                ty
+            }
+            AnnExpr::Condition {
+                cond, then, els, ..
+            } => {
+                let cond_ty = self.infer_expr(cond);
+                self.unify(&Ty::con("Bool"), &cond_ty, cond.get_span());
+                let then_ty = self.infer_expr(then);
+                let els_ty = self.infer_expr(els);
+                self.unify(&then_ty, &els_ty, els.get_span());
+                then_ty
+            }
+            AnnExpr::Let { binds, ret, .. } => {
+                let depth = self.locals.len();
+                // The binds of a block can refer to each other, whatever
+                // their order, as the top-level ones do.
+                let groups = group_binds(binds);
+                for component in dependency_order(&groups) {
+                    self.infer_local_group(component.into_iter().map(|i| &groups[i]).collect());
+                }
+                let ty = self.infer_expr(ret);
+                self.locals.truncate(depth);
+                ty
+            }
+            AnnExpr::Match {
+                referral, cases, ..
+            } => {
+                self.check_cases(cases, *referral.get_span());
+                let scrutinee = self.infer_expr(referral);
+                let result = self.fresh();
+                for (pattern, body) in cases {
+                    let depth = self.locals.len();
+                    let pattern_ty = self.infer_pattern(pattern);
+                    self.unify(&scrutinee, &pattern_ty, pattern.get_span());
+                    let body_ty = self.infer_expr(body);
+                    self.unify(&result, &body_ty, body.get_span());
+                    self.locals.truncate(depth);
+                }
+                result
+            }
+            AnnExpr::Record { id, fields, .. } => {
+                let (declared, ty) = self.record_fields(id);
+                let given: Vec<_> = fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), self.infer_expr(value), *value.get_span()))
+                    .collect();
+                self.unify_fields(&declared, &given);
+                ty
+            }
+            AnnExpr::Update { expr, fields, .. } => {
+                let ty = self.infer_expr(expr);
+                for (name, value) in fields {
+                    let value_ty = self.infer_expr(value);
+                    // Fields cannot be shadowed by local variables here.
+                    let selector = match self.globals.get(&name.name).cloned() {
+                        Some(scheme) => self.instantiate(&scheme).0,
+                        None => continue,
+                    };
+                    if let Ty::Func(record, field) = selector {
+                        self.unify(&record, &ty, expr.get_span());
+                        self.unify(&field, &value_ty, value.get_span());
+                    }
+                }
+                ty
+            }
+            AnnExpr::Guards { guards, .. } => {
+                let result = self.fresh();
+                for (cond, body) in guards {
+                    let cond_ty = self.infer_expr(cond);
+                    self.unify(&Ty::con("Bool"), &cond_ty, cond.get_span());
+                    let body_ty = self.infer_expr(body);
+                    self.unify(&result, &body_ty, body.get_span());
+                }
+                result
+            }
+            AnnExpr::BinOp { op, lhs, rhs, ann } => {
+                let l = self.infer_expr(lhs);
+                let r = self.infer_expr(rhs);
+                match op {
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod | BinOp::Exp => {
+                        self.unify(&l, &r, rhs.get_span());
+                        self.want("Num", l.clone(), *ann);
+                        l
+                    }
+                    BinOp::LessThan | BinOp::GreaterThan | BinOp::LessThanOrEq | BinOp::GreaterThanOrEq => {
+                        self.unify(&l, &r, rhs.get_span());
+                        self.want("Ord", l, *ann);
+                        Ty::con("Bool")
+                    }
+                    BinOp::Eq | BinOp::Ineq => {
+                        self.unify(&l, &r, rhs.get_span());
+                        self.want("Eq", l, *ann);
+                        Ty::con("Bool")
+                    }
+                    BinOp::And | BinOp::Or => {
+                        self.unify(&Ty::con("Bool"), &l, lhs.get_span());
+                        self.unify(&Ty::con("Bool"), &r, rhs.get_span());
+                        Ty::con("Bool")
+                    }
+                    BinOp::ListCons => {
+                        self.unify(&Ty::list(l), &r, rhs.get_span());
+                        r
+                    }
+                }
+            }
+            AnnExpr::UnOp { op, expr, ann } => {
+                let ty = self.infer_expr(expr);
+                match op {
+                    UnOp::Neg => {
+                        self.want("Num", ty.clone(), *ann);
+                        ty
+                    }
+                    UnOp::Not => {
+                        self.unify(&Ty::con("Bool"), &ty, expr.get_span());
+                        ty
+                    }
+                }
+            }
+            AnnExpr::Lambda { args, ret, ann } => {
+                self.check_lambda(args, *ann);
+                let depth = self.locals.len();
+                let args: Vec<Ty> = args.iter().map(|p| self.infer_pattern(p)).collect();
+                let body = self.infer_expr(ret);
+                self.locals.truncate(depth);
+                args.into_iter().rev().fold(body, |acc, arg| Ty::func(arg, acc))
+            }
+            AnnExpr::Ann { expr, ann } => {
+                let ty = self.infer_expr(expr);
+                let annotation = self.convert_type(&ann.1, &mut HashMap::new(), false);
+                self.unify(&annotation, &ty, expr.get_span());
+                annotation
+            }
+            AnnExpr::List { list, .. } => {
+                let elem = self.fresh();
+                for item in list {
+                    let ty = self.infer_expr(item);
+                    self.unify(&elem, &ty, item.get_span());
+                }
+                Ty::list(elem)
+            }
+            AnnExpr::Tuple { list, .. } => {
+                Ty::Tuple(list.iter().map(|item| self.infer_expr(item)).collect())
+            }
+            // Anything goes, the parser has reported the error.
+            AnnExpr::Error { .. } => self.fresh(),
+        }
+    }
+
+    /// Applies a value of type `func` to an argument of type `arg`.
+    fn apply(&mut self, func: Ty, arg: Ty, arg_span: &Span, span: &Span) -> Ty {
+        match self.resolve(&func) {
+            Ty::Func(param, ret) => {
+                self.unify(&param, &arg, arg_span);
+                *ret
+            }
+            func @ Ty::Var(_) => {
+                let ret = self.fresh();
+                self.unify(&func, &Ty::func(arg, ret.clone()), span);
+                ret
+            }
+            // Either the head is not a function or it is given more
+            // arguments than it takes.
+            func => {
+                self.errors.push(TypeCheckerError {
+                    kind: TypeCheckerErrorKind::NotAFunction {
+                        found: self.zonk(&func).to_string(),
+                    },
+                    span: *arg_span,
+                });
+                self.fresh()
+            }
+        }
+    }
+
+    /// Infers the type of a pattern, binding its variables monomorphically.
+    fn infer_pattern(&mut self, pattern: &Pattern) -> Ty {
+        match pattern {
+            Pattern::Wildcard(_) => self.fresh(),
+            Pattern::Variable(id) => {
+                let ty = self.fresh();
+                self.locals.push((id.name.clone(), Scheme::mono(ty.clone())));
+                ty
+            }
+            Pattern::Literal(lit) => literal_type(&lit.lit),
+            Pattern::Id(id) => self.lookup(id),
+            Pattern::App(id, patterns, span) => {
+                let mut ty = self.lookup(id);
+                for pattern in patterns {
+                    let field = self.infer_pattern(pattern);
+                    ty = self.apply(ty, field, pattern.get_span(), span);
+                }
+                ty
+            }
+            Pattern::ListCons(head, tail, _) => {
+                let head_ty = self.infer_pattern(head);
+                let tail_ty = self.infer_pattern(tail);
+                self.unify(&Ty::list(head_ty), &tail_ty, tail.get_span());
+                tail_ty
+            }
+            Pattern::Tuple(patterns, _) => {
+                Ty::Tuple(patterns.iter().map(|pattern| self.infer_pattern(pattern)).collect())
+            }
+            Pattern::List(patterns, _) => {
+                let item = self.fresh();
+                for pattern in patterns {
+                    let ty = self.infer_pattern(pattern);
+                    self.unify(&item, &ty, pattern.get_span());
+                }
+                Ty::list(item)
+            }
+            Pattern::As(id, pattern, _) => {
+                let ty = self.infer_pattern(pattern);
+                self.locals.push((id.name.clone(), Scheme::mono(ty.clone())));
+                ty
+            }
+            Pattern::Record(id, fields, _) => {
+                let (declared, ty) = self.record_fields(id);
+                let given: Vec<_> = fields
+                    .iter()
+                    .map(|(name, pattern)| (name.clone(), self.infer_pattern(pattern), *pattern.get_span()))
+                    .collect();
+                self.unify_fields(&declared, &given);
+                ty
+            }
+        }
+    }
+
+    fn lookup(&mut self, id: &Identifier) -> Ty {
+        let scheme = if unqualified(&id.name).starts_with(char::is_uppercase) {
+            self.constructors.get(&id.name).cloned()
+        } else {
+            let local = self
+                .locals
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == id.name)
+                .map(|(_, scheme)| scheme.clone());
+            local.or_else(|| {
+                if self.group.contains(&id.name) {
+                    self.recursive.push(id.span);
+                }
+                self.globals.get(&id.name).cloned()
+            })
+        };
+        // Unknown names have already been reported by the semantic analysis.
+        match scheme {
+            Some(scheme) => {
+                let (ty, preds) = self.instantiate(&scheme);
+                if !preds.is_empty() {
+                    self.wanted.push(Site { preds, span: id.span });
+                }
+                ty
+            }
+            None => self.fresh(),
+        }
+    }
+
+    /// Converts a written type. Type variables become fresh unification
+    /// variables, or rigid types when `rigid` is set.
+    fn convert_type(&mut self, ty: &Type, generics: &mut HashMap<String, Ty>, rigid: bool) -> Ty {
+        match ty {
+            Type::Generic(id) => {
+                if let Some(ty) = generics.get(&id.name) {
+                    return ty.clone();
+                }
+                let ty = if rigid { Ty::con(&id.name) } else { self.fresh() };
+                generics.insert(id.name.clone(), ty.clone());
+                ty
+            }
+            Type::Id(id) => Ty::con(&id.name),
+            Type::App(id, args, _) => Ty::Con(
+                id.name.clone(),
+                args.iter()
+                    .map(|arg| self.convert_type(arg, generics, rigid))
+                    .collect(),
+            ),
+            Type::List(item, _) => Ty::list(self.convert_type(item, generics, rigid)),
+            Type::Tuple(items, _) => Ty::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.convert_type(item, generics, rigid))
+                    .collect(),
+            ),
+            Type::Func(first, rest, _) => {
+                let mut types = vec![self.convert_type(first, generics, rigid)];
+                types.extend(rest.iter().map(|ty| self.convert_type(ty, generics, rigid)));
+                let ret = types.pop().unwrap();
+                types.into_iter().rev().fold(ret, |acc, arg| Ty::func(arg, acc))
+            }
+            // The constraints are read by `convert_context`.
+            Type::Qualified(_, ty, _) => self.convert_type(ty, generics, rigid),
+        }
+    }
+
+    /// Converts the constraints of a written type, sharing the type
+    /// variables of `convert_type`.
+    fn convert_context(&mut self, ty: &Type, generics: &mut HashMap<String, Ty>, rigid: bool) -> Vec<Pred> {
+        match ty {
+            Type::Qualified(context, _, _) => context
+                .iter()
+                .map(|constraint| {
+                    let var = Type::Generic(constraint.var.clone());
+                    Pred::new(&constraint.class.name, self.convert_type(&var, generics, rigid))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn enter(&mut self) {
+        self.level += 1;
+    }
+
+    fn exit(&mut self) {
+        self.level -= 1;
+    }
+
+    fn fresh(&mut self) -> Ty {
+        self.bindings.push(None);
+        self.levels.push(self.level);
+        Ty::Var(self.bindings.len() - 1)
+    }
+
+    /// Follows the bindings of `ty` until it is not a bound variable.
+    fn resolve(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(v) => match &self.bindings[*v] {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    /// Substitutes every bound variable in `ty`.
+    fn zonk(&self, ty: &Ty) -> Ty {
+        match self.resolve(ty) {
+            Ty::Var(v) => Ty::Var(v),
+            Ty::Con(name, args) => Ty::Con(name, args.iter().map(|t| self.zonk(t)).collect()),
+            Ty::Func(arg, ret) => Ty::func(self.zonk(&arg), self.zonk(&ret)),
+            Ty::Tuple(items) => Ty::Tuple(items.iter().map(|t| self.zonk(t)).collect()),
+        }
+    }
+
+    fn free_vars(&self, ty: &Ty, out: &mut Vec<TyVar>) {
+        match self.resolve(ty) {
+            Ty::Var(v) => {
+                if !out.contains(&v) {
+                    out.push(v)
+                }
+            }
+            Ty::Con(_, args) | Ty::Tuple(args) => {
+                args.iter().for_each(|t| self.free_vars(t, out))
+            }
+            Ty::Func(arg, ret) => {
+                self.free_vars(&arg, out);
+                self.free_vars(&ret, out);
+            }
+        }
+    }
+
+    fn unify(&mut self, expected: &Ty, found: &Ty, span: &Span) {
+        if let Err(e) = self.unify_types(expected, found) {
+            let kind = match e {
+                UnifyError::Mismatch => {
+                    let (expected, found) = (self.zonk(expected), self.zonk(found));
+                    TypeCheckerErrorKind::MismatchedTypes {
+                        integer_for: integer_for(&expected, &found),
+                        expected: expected.to_string(),
+                        found: found.to_string(),
+                    }
+                }
+                UnifyError::Occurs => TypeCheckerErrorKind::InfiniteType {
+                    ty: self.zonk(found).to_string(),
+                },
+            };
+            self.errors.push(TypeCheckerError {
+                kind,
+                span: *span,
+            });
+        }
+    }
+
+    fn unify_types(&mut self, a: &Ty, b: &Ty) -> Result<(), UnifyError> {
+        match (self.resolve(a), self.resolve(b)) {
+            (Ty::Var(x), Ty::Var(y)) if x == y => Ok(()),
+            (Ty::Var(x), t) | (t, Ty::Var(x)) => self.bind(x, t),
+            (Ty::Con(n1, a1), Ty::Con(n2, a2)) if n1 == n2 && a1.len() == a2.len() => a1
+                .iter()
+                .zip(&a2)
+                .try_for_each(|(x, y)| self.unify_types(x, y)),
+            (Ty::Func(a1, r1), Ty::Func(a2, r2)) => {
+                self.unify_types(&a1, &a2)?;
+                self.unify_types(&r1, &r2)
+            }
+            (Ty::Tuple(t1), Ty::Tuple(t2)) if t1.len() == t2.len() => t1
+                .iter()
+                .zip(&t2)
+                .try_for_each(|(x, y)| self.unify_types(x, y)),
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    fn bind(&mut self, var: TyVar, ty: Ty) -> Result<(), UnifyError> {
+        let mut vars = Vec::new();
+        self.free_vars(&ty, &mut vars);
+        if vars.contains(&var) {
+            return Err(UnifyError::Occurs);
+        }
+        // Variables reachable from `var` must not be generalized sooner.
+        for v in vars {
+            self.levels[v] = self.levels[v].min(self.levels[var]);
+        }
+        self.bindings[var] = Some(ty);
+        Ok(())
+    }
+
+    fn generalize(&self, ty: &Ty) -> Scheme {
+        let mut vars = Vec::new();
+        self.free_vars(ty, &mut vars);
+        vars.retain(|v| self.levels[*v] > self.level);
+        Scheme {
+            vars,
+            preds: Vec::new(),
+            ty: self.zonk(ty),
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> (Ty, Vec<Pred>) {
+        let mapping: HashMap<TyVar, Ty> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        let preds = scheme
+            .preds
+            .iter()
+            .map(|pred| Pred::new(&pred.class, substitute(&pred.ty, &mapping)))
+            .collect();
+        (substitute(&scheme.ty, &mapping), preds)
+    }
+}
+
+/// Parses a type written in Risk syntax by the interpreter itself.
+fn parse_signature(signature: &str) -> Type {
+    let source = format!("x :: {}", signature);
+    match crate::parser::Parser::new(FileId::default(), &source).parse() {
+        (Program { statements }, errors) if errors.is_empty() => match statements.into_iter().next() {
+            Some(Statement::TypeAssign(assign)) => assign.ty,
+            _ => unreachable!("malformed builtin signature"),
+        },
+        _ => unreachable!("malformed builtin signature"),
+    }
+}
+
+/// A rigid type variable of `expected` found to be `Integer`, which is
+/// what happens when an integer literal is used at an overloaded type.
+fn integer_for(expected: &Ty, found: &Ty) -> Option<String> {
+    match (expected, found) {
+        (Ty::Con(var, args), Ty::Con(integer, _))
+            if args.is_empty() && var.starts_with(char::is_lowercase) && integer == "Integer" =>
+        {
+            Some(var.clone())
+        }
+        (Ty::Con(_, a), Ty::Con(_, b)) | (Ty::Tuple(a), Ty::Tuple(b)) => {
+            a.iter().zip(b).find_map(|(a, b)| integer_for(a, b))
+        }
+        (Ty::Func(a1, r1), Ty::Func(a2, r2)) => integer_for(a1, a2).or_else(|| integer_for(r1, r2)),
+        _ => None,
+    }
+}
+
+fn literal_type(lit: &LiteralKind) -> Ty {
+    Ty::con(match lit {
+        LiteralKind::Integer(_) => "Integer",
+        LiteralKind::Float(_) => "Float",
+        LiteralKind::String(_) => "String",
+        LiteralKind::Char(_) => "Char",
+        LiteralKind::Bool(_) => "Bool",
+    })
+}
+
+fn substitute(ty: &Ty, mapping: &HashMap<TyVar, Ty>) -> Ty {
+    match ty {
+        Ty::Var(v) => mapping.get(v).cloned().unwrap_or(Ty::Var(*v)),
+        Ty::Con(name, args) => Ty::Con(
+            name.clone(),
+            args.iter().map(|t| substitute(t, mapping)).collect(),
+        ),
+        Ty::Func(arg, ret) => Ty::func(substitute(arg, mapping), substitute(ret, mapping)),
+        Ty::Tuple(items) => Ty::Tuple(items.iter().map(|t| substitute(t, mapping)).collect()),
+    }
+}
+
+/// Splits the top-level functions into strongly connected components of the
+/// call graph (Tarjan), dependencies first.
+/// Adds `bind` to the clauses of its name, kept in the order the names are
+/// first defined.
+fn add_clause<'b>(groups: &mut Vec<(&'b str, Vec<&'b Bind<Span>>)>, bind: &'b Bind<Span>) {
+    match groups.iter_mut().find(|(name, _)| *name == bind.name.name) {
+        Some((_, clauses)) => clauses.push(bind),
+        None => groups.push((&bind.name.name, vec![bind])),
+    }
+}
+
+/// The clauses of `binds`, by name.
+pub(crate) fn group_binds(binds: &[Bind<Span>]) -> Vec<(&str, Vec<&Bind<Span>>)> {
+    let mut groups = Vec::new();
+    for bind in binds {
+        add_clause(&mut groups, bind);
+    }
+    groups
+}
+
+/// The groups of clauses that refer to each other, each after the groups it
+/// refers to.
+pub(crate) fn dependency_order(groups: &[(&str, Vec<&Bind<Span>>)]) -> Vec<Vec<usize>> {
+    struct Tarjan {
+        edges: Vec<Vec<usize>>,
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        counter: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.counter);
+            self.lowlink[v] = self.counter;
+            self.counter += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+
+            for w in self.edges[v].clone() {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.lowlink[v] = self.lowlink[v].min(self.lowlink[w]);
+                    }
+                    Some(index) if self.on_stack[w] => {
+                        self.lowlink[v] = self.lowlink[v].min(index);
+                    }
+                    _ => {}
+                }
+            }
+
+            if Some(self.lowlink[v]) == self.index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+
+    let positions: HashMap<&str, usize> = groups
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| (*name, i))
+        .collect();
+    let edges = groups
+        .iter()
+        .map(|(_, clauses)| {
+            let mut names = Vec::new();
+            for bind in clauses {
+                clause_references(bind, &mut names);
+            }
+            names.iter().filter_map(|name| positions.get(name).copied()).collect()
+        })
+        .collect();
+
+    let mut tarjan = Tarjan {
+        edges,
+        index: vec![None; groups.len()],
+        lowlink: vec![0; groups.len()],
+        on_stack: vec![false; groups.len()],
+        stack: Vec::new(),
+        counter: 0,
+        components: Vec::new(),
+    };
+    for v in 0..groups.len() {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+    tarjan.components
+}
+
+/// Collects the names the clause `bind` refers to, other than its
+/// arguments.
+pub(crate) fn clause_references<'e>(bind: &'e Bind<Span>, out: &mut Vec<&'e str>) {
+    let mut bound = Vec::new();
+    bind.args.iter().for_each(|arg| arg.variables(&mut bound));
+    references(&bind.expr, &mut bound, out);
+}
+
+/// Collects the names an expression refers to, but those in `bound` or
+/// bound inside it.
+fn references<'e>(expr: &'e ParsedExpr, bound: &mut Vec<&'e str>, out: &mut Vec<&'e str>) {
+    let depth = bound.len();
+    match expr {
+        AnnExpr::Identifier { id } => {
+            if !bound.contains(&id.name.as_str()) {
+                out.push(&id.name);
+            }
+        }
+        AnnExpr::PCIdentifier { .. } | AnnExpr::Literal(_) | AnnExpr::Error { .. } => {}
+        AnnExpr::App(app) => {
+            references(&app.head, bound, out);
+            app.args.iter().for_each(|arg| references(arg, bound, out));
+        }
+        AnnExpr::Condition {
+            cond, then, els, ..
+        } => {
+            references(cond, bound, out);
+            references(then, bound, out);
+            references(els, bound, out);
+        }
+        AnnExpr::Let { binds, ret, .. } => {
+            bound.extend(binds.iter().map(|bind| bind.name.name.as_str()));
+            for bind in binds {
+                let depth = bound.len();
+                bind.args.iter().for_each(|arg| arg.variables(bound));
+                references(&bind.expr, bound, out);
+                bound.truncate(depth);
+            }
+            references(ret, bound, out);
+        }
+        AnnExpr::Match {
+            referral, cases, ..
+        } => {
+            references(referral, bound, out);
+            for (pattern, body) in cases {
+                pattern.variables(bound);
+                references(body, bound, out);
+                bound.truncate(depth);
+            }
+        }
+        AnnExpr::BinOp { lhs, rhs, .. } => {
+            references(lhs, bound, out);
+            references(rhs, bound, out);
+        }
+        AnnExpr::UnOp { expr, .. } => references(expr, bound, out),
+        AnnExpr::Lambda { args, ret, .. } => {
+            args.iter().for_each(|arg| arg.variables(bound));
+            references(ret, bound, out);
+        }
+        AnnExpr::Ann { expr, .. } => references(expr, bound, out),
+        AnnExpr::List { list, .. } | AnnExpr::Tuple { list, .. } => {
+            list.iter().for_each(|item| references(item, bound, out))
+        }
+        AnnExpr::Record { fields, .. } => fields.iter().for_each(|(_, value)| references(value, bound, out)),
+        AnnExpr::Update { expr, fields, .. } => {
+            references(expr, bound, out);
+            fields.iter().for_each(|(_, value)| references(value, bound, out));
+        }
+        AnnExpr::Guards { guards, .. } => guards.iter().for_each(|(cond, body)| {
+            references(cond, bound, out);
+            references(body, bound, out);
+        }),
+    }
+    bound.truncate(depth);
+}
@@ -0,0 +1,259 @@
+use crate::semantics::tc::error::TypeCheckerErrorKind;
+use crate::semantics::tc::TypeChecker;
+use crate::source::FileId;
+
+#[cfg(test)]
+fn check(content: &str) -> Vec<TypeCheckerErrorKind> {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut tc = TypeChecker::new();
+    tc.check_program(&program);
+    tc.errors.into_iter().map(|e| e.kind).collect()
+}
+
+#[test]
+fn test_arithmetic() {
+    assert!(check("main = 1 + 2 * 3").is_empty());
+    assert!(check("main = 1.5 / 2.0").is_empty());
+    assert!(check("half x = x / 2.0\nmain = half 3.0").is_empty());
+}
+
+#[test]
+fn test_no_implicit_conversion() {
+    assert!(matches!(
+        check("main = 1 + 2.0")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check("half x = x / 2.0\nmain = half 3")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_conversions() {
+    assert!(check("main = (toFloat 1) + 2.0").is_empty());
+    assert!(check("main = (round 2.5) + 1").is_empty());
+    assert!(check("main = (truncate 1.5, floor 1.5, ceiling 1.5)").is_empty());
+    assert!(matches!(
+        check("main = toFloat 1.0")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_not_numeric() {
+    assert!(matches!(
+        check("main = \"a\" + \"b\"")[..],
+        [TypeCheckerErrorKind::NotNumeric { .. }]
+    ));
+    assert!(matches!(
+        check("f :: a -> a\nf x = x * x")[..],
+        [TypeCheckerErrorKind::NotNumeric { .. }]
+    ));
+}
+
+#[test]
+fn test_polymorphism() {
+    assert!(check("id x = x\nmain = (id 1, id 'a')").is_empty());
+    assert!(check("type Box a = (Box a)\nmain = (Box 1, Box 'a')").is_empty());
+    assert!(matches!(
+        check("f :: a -> a\nf x = 1")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_operators() {
+    assert!(check("infixl 1 |>\nx |> f = f x\nmain = 2 |> toFloat").is_empty());
+    assert!(check("apply f x y = f x y\nmain = apply (+) 1.5 2.0").is_empty());
+    assert!(matches!(
+        check("x |> f = f x\nmain = 2.0 |> toFloat")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_prefix_operators() {
+    assert!(check("f x = -x\nmain = f 1.5 + -2.0").is_empty());
+    assert!(check("main = not (1 < 2) || False").is_empty());
+    assert!(matches!(
+        check("main = -'a'")[..],
+        [TypeCheckerErrorKind::NotNumeric { .. }]
+    ));
+    assert!(matches!(
+        check("main = not 1")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_application() {
+    assert!(check("main = (\\x -> x + 1) 2").is_empty());
+    assert!(check("add x y = x + y\ninc = add 1\nmain = inc 2 + (add 1) 2").is_empty());
+    assert!(check("compose f g x = f (g x)\nmain = (compose toFloat round) 2.5").is_empty());
+    assert!(matches!(
+        check("f x = x + 1\nmain = f 1 2")[..],
+        [TypeCheckerErrorKind::NotAFunction { .. }]
+    ));
+    assert!(matches!(
+        check("main = (\\x -> x) 'a' 1")[..],
+        [TypeCheckerErrorKind::NotAFunction { .. }]
+    ));
+}
+
+#[test]
+fn test_nested_patterns() {
+    assert!(check("swap (a, b) = (b, a)\nmain = swap (1, 'a')").is_empty());
+    assert!(check("f all@(x:_) = x : all\nf [] = []\nmain = f [1, 2]").is_empty());
+    assert!(check("isA 'a' = True\nisA _ = False\nmain = isA 'b'").is_empty());
+    assert!(matches!(
+        check("f [x, 'a'] = x\nf _ = 'b'\nmain = f [1]")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check("f (a, b) = a\nmain = f (1, 2, 3)")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_list_types() {
+    assert!(check("sum :: [Integer] -> Integer\nsum [] = 0\nsum (x:xs) = x + sum xs\nmain = sum [1, 2]").is_empty());
+    assert!(check("wrap :: a -> [[a]]\nwrap x = [[x]]\nmain = wrap 'a'").is_empty());
+    assert!(matches!(
+        check("f :: [Integer] -> Integer\nf x = x")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check("f :: [Integer] -> Integer\nf _ = 0\nmain = f 1")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check("type List a = (Nil) (Cons a (List a))\nf :: List Integer -> Integer\nf _ = 0\nmain = f [1]")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_guards() {
+    assert!(check("sign x | x < 0 = -1 | otherwise = 1\nmain = sign 2").is_empty());
+    assert!(check("f xs = match xs with\n    (x:_) | x -> 'a'\n    _ -> 'b'\nmain = f [True]").is_empty());
+    assert!(matches!(
+        check("f x | x = 1 | otherwise = 'a'")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check("f x | x + 1 = x | otherwise = x")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    // The clauses of a local function are checked together, whatever the
+    // order of the binds.
+    assert!(check("main = f 5\n  where f x = go x\n        go 0 = 1\n        go n = n * go (n - 1)").is_empty());
+    assert!(matches!(
+        check("main = f 5\n  where f x = go x\n        go 0 = 1\n        go n = 'a'")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_records() {
+    let person = "type Person = (Person { name :: String, age :: Integer })\n";
+    assert!(check(&format!(
+        "{person}older p = p {{ age = age p + 1 }}\nmain = name (older (Person {{ age = 1, name = \"x\" }}))"
+    ))
+    .is_empty());
+    assert!(check(&format!("{person}f (Person {{ age = a }}) = a + 1\nmain = f (Person {{ name = \"\", age = 2 }})")).is_empty());
+    // Selectors of a polymorphic record are polymorphic.
+    assert!(check("type Box a = (Box { item :: a })\nmain = (item (Box { item = 1 }), item (Box { item = 'a' }))").is_empty());
+    assert!(matches!(
+        check(&format!("{person}main = Person {{ name = 1, age = 2 }}"))[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check(&format!("{person}main = (Person {{ name = \"\", age = 2 }}) {{ age = True }}"))[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+    assert!(matches!(
+        check(&format!("{person}main = 1 {{ age = 2 }}"))[..],
+        [TypeCheckerErrorKind::MismatchedTypes { .. }]
+    ));
+}
+
+#[test]
+fn test_classes() {
+    let shape = "class Shape a where\n  area :: a -> Float\ntype Sq = (Sq Float)\ninstance Shape Sq where\n  area (Sq s) = s * s\n";
+    assert!(check(&format!("{}total x y = area x + area y\nmain = total (Sq 1.0) (Sq 2.0)", shape)).is_empty());
+    assert!(matches!(
+        &check(&format!("{}main = area 1.0", shape))[..],
+        [TypeCheckerErrorKind::NoInstance { class, .. }] if class == "Shape"
+    ));
+
+    // Constraints are inferred, and must be declared by signatures.
+    assert!(check("same x y = x == y\nmain = (same 1 2, same 'a' 'b')").is_empty());
+    assert!(check("f :: Ord a => a -> a -> Bool\nf x y = x < y\nmain = f \"a\" \"b\"").is_empty());
+    assert!(matches!(
+        &check("f :: Eq a => a -> a -> Bool\nf x y = x < y")[..],
+        [TypeCheckerErrorKind::NoInstance { class, .. }] if class == "Ord"
+    ));
+    assert!(matches!(
+        &check("main = show (\\x -> x)")[..],
+        [TypeCheckerErrorKind::NoInstance { class, .. }] if class == "Show"
+    ));
+
+    // Integer literals are not overloaded, `fromInteger` converts them.
+    assert!(check("sumSq :: Num a => [a] -> a\nsumSq xs = foldr (\\x acc -> x * x + acc) (fromInteger 0) xs\nfoldr f z xs = z\nmain = (sumSq [1.5], fromInteger 2 + 0.5)").is_empty());
+    assert!(matches!(
+        &check("sumSq :: Num a => [a] -> a\nsumSq xs = foldr (\\x acc -> x * x + acc) 0 xs\nfoldr f z xs = z")[..],
+        [TypeCheckerErrorKind::MismatchedTypes { integer_for: Some(var), .. }] if var == "a"
+    ));
+}
+
+#[test]
+fn test_derived_instances() {
+    // User types get `Eq`, `Ord` and `Show` when their fields have them.
+    assert!(check("type Box a = (Box a)\nmain = (Box 1 == Box 2, show [Box 'a'])").is_empty());
+    assert!(matches!(
+        &check("type F = (F (Integer -> Integer))\nmain = F (\\x -> x) == F (\\x -> x)")[..],
+        [TypeCheckerErrorKind::NoInstance { class, .. }] if class == "Eq"
+    ));
+}
+
+#[cfg(test)]
+fn missing(content: &str) -> Vec<String> {
+    check(content)
+        .into_iter()
+        .map(|kind| match kind {
+            TypeCheckerErrorKind::NonExhaustiveMatch { missing, .. } => missing,
+            kind => panic!("unexpected error {:?}", kind),
+        })
+        .collect()
+}
+
+#[test]
+fn test_exhaustiveness() {
+    assert_eq!(missing("f xs = match xs with\n    [] -> 0\nmain = f [1]"), ["_ : _"]);
+    assert_eq!(missing("g (x, 1) = x\nmain = g (2, 1)"), ["g (_, _)"]);
+    assert_eq!(missing("f [] = 0\nf [x] = x\nmain = f [1]"), ["f (_ : _ : _)"]);
+    assert_eq!(missing("f (x:xs) = x\nmain = f [1]"), ["f []"]);
+    assert_eq!(
+        missing("type T = (A) (B Integer) (C T)\nf A = 0\nf (C (B _)) = 1\nf (B n) = n\nmain = f A"),
+        ["f (C A)"]
+    );
+    assert_eq!(missing("f True False = 0\nf _ True = 1\nmain = f True True"), ["f False False"]);
+    assert_eq!(missing("type Maybe a = (Nothing) (Just a)\nf a@(Just _) = a\nmain = f (Just 1)"), ["f Nothing"]);
+    assert_eq!(missing("type Maybe a = (Nothing) (Just a)\nmain = (\\(Just x) -> x) (Just 1)"), ["\\Nothing"]);
+    assert_eq!(missing("f 'a' = 0\nmain = f 'b'"), ["f _"]);
+    // Guards can all fail, unless the last one is `otherwise`.
+    assert_eq!(missing("f x | x > 0 = 1\nmain = f 1"), ["f _"]);
+    assert_eq!(missing("main = match [1] with\n    (y:_) | y > 0 -> y\n    [] -> 0"), ["_ : _"]);
+    assert_eq!(missing("main = f 5\n  where f 0 = 1"), ["f _"]);
+
+    assert!(check("f [] = 0\nf [x] = x\nf (x:y:_) = x + y\nmain = f [1]").is_empty());
+    assert!(check("f (x, 1) = x\nf (_, _) = 0\nmain = f (2, 1)").is_empty());
+    assert!(check("type T = (A) (B Integer)\nf A = 0\nf (B n) = n\nmain = f A").is_empty());
+    assert!(check("type P = (P { x :: Integer, y :: Integer })\nf P { x = 0 } = 0\nf P { y = n } = n\nmain = f (P 1 2)").is_empty());
+    assert!(check("f True = 0\nf False = 1\nmain = f True").is_empty());
+    assert!(check("f all@(_:_) = all\nf [] = []\nmain = f [1]").is_empty());
+}
@@ -0,0 +1,129 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::semantics::loader::PRELUDE;
+use std::fmt::Display;
+
+pub type TyVar = usize;
+
+/// The constructor of list types. It cannot be written as a type name, so a
+/// user type called `List` stays distinct from `[a]`.
+pub const LIST: &str = "[]";
+
+/// Type used during inference. Unlike `ast::Type` it carries no spans and
+/// can contain unification variables.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Var(TyVar),
+    Con(String, Vec<Ty>),
+    Func(Box<Ty>, Box<Ty>),
+    Tuple(Vec<Ty>),
+}
+
+impl Ty {
+    pub fn con(name: &str) -> Ty {
+        Ty::Con(name.to_string(), Vec::new())
+    }
+
+    pub fn list(elem: Ty) -> Ty {
+        Ty::Con(LIST.to_string(), vec![elem])
+    }
+
+    pub fn func(arg: Ty, ret: Ty) -> Ty {
+        Ty::Func(Box::new(arg), Box::new(ret))
+    }
+
+    fn is_atomic(&self) -> bool {
+        match self {
+            Ty::Con(name, args) => args.is_empty() || name == LIST,
+            Ty::Func(..) => false,
+            _ => true,
+        }
+    }
+}
+
+/// How a type or a class is named in messages: those of the Prelude as
+/// they are written, without the module.
+pub fn display_name(name: &str) -> &str {
+    name.strip_prefix(PRELUDE)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .unwrap_or(name)
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ty::Var(v) => write!(f, "t{}", v),
+            Ty::Con(name, args) if name == LIST && args.len() == 1 => write!(f, "[{}]", args[0]),
+            Ty::Con(name, args) => {
+                write!(f, "{}", display_name(name))?;
+                for arg in args {
+                    if arg.is_atomic() {
+                        write!(f, " {}", arg)?;
+                    } else {
+                        write!(f, " ({})", arg)?;
+                    }
+                }
+                Ok(())
+            }
+            Ty::Func(arg, ret) => match **arg {
+                Ty::Func(..) => write!(f, "({}) -> {}", arg, ret),
+                _ => write!(f, "{} -> {}", arg, ret),
+            },
+            Ty::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            }
+        }
+    }
+}
+
+/// The constraint that `ty` is an instance of `class`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pred {
+    pub class: String,
+    pub ty: Ty,
+}
+
+impl Pred {
+    pub fn new(class: &str, ty: Ty) -> Self {
+        Pred {
+            class: class.to_string(),
+            ty,
+        }
+    }
+}
+
+/// A type quantified over `vars`, under the constraints `preds`. A use of
+/// the name is given one dictionary per constraint, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub vars: Vec<TyVar>,
+    pub preds: Vec<Pred>,
+    pub ty: Ty,
+}
+
+impl Scheme {
+    pub fn mono(ty: Ty) -> Self {
+        Scheme {
+            vars: Vec::new(),
+            preds: Vec::new(),
+            ty,
+        }
+    }
+}
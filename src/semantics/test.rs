@@ -0,0 +1,384 @@
+use crate::semantics::error::{SemanticError, SemanticErrorKind};
+use crate::semantics::warning::SemanticWarningKind;
+use crate::ast::*;
+use crate::semantics::loader::{Loader, Module};
+use crate::semantics::module::resolve;
+use crate::semantics::{analyze, AnalysisOutput};
+use crate::source::{FileId, SourceMap};
+
+#[cfg(test)]
+fn check(content: &str) -> AnalysisOutput {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut ao = AnalysisOutput::new();
+    analyze(&mut ao, program);
+    ao
+}
+
+#[test]
+fn test_user_types() {
+    let ao = check("type Box a = (Box a)\nf :: Box Integer -> Integer\nf b = 1\nmain = f (Box 1)");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+}
+
+#[test]
+fn test_type_already_defined() {
+    let ao = check("type A = (A)\ntype A = (B)");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::TypeAlreadyDefined { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_warning_spans() {
+    // Symbols are told apart by name, and warnings point at the declaration.
+    let ao = check("f x = 1\nmain = f 2");
+    let mut unused: Vec<(usize, usize)> = ao
+        .warnings
+        .iter()
+        .filter(|w| matches!(w.kind, SemanticWarningKind::UnusedSymbol))
+        .map(|w| (w.span.start, w.span.end))
+        .collect();
+    unused.sort();
+    assert_eq!(unused, [(2, 3)]);
+}
+
+#[test]
+fn test_higher_order() {
+    // Functions are values, how many arguments they get is checked by types.
+    let ao = check("type M = (Just Integer)\nadd x y = x + y\ntwice f x = f (f x)\nmain = (twice (add 1) 0, add, Just)");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+}
+
+#[test]
+fn test_scopes() {
+    // Top-level binds can be used above their definition, and each arm of a
+    // match is its own scope.
+    let ao = check("main = f 2\nf x = match x with\n    0 -> 1\n    _ -> let g y = x + y in g x");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    let ao = check("main = g 2\nf x = match x with\n    0 -> 1\n    y -> y\nh = y");
+    assert!(matches!(
+        ao.errors[..],
+        [
+            SemanticError {
+                kind: SemanticErrorKind::UndefinedSymbol { .. },
+                ..
+            },
+            SemanticError {
+                kind: SemanticErrorKind::UndefinedSymbol { .. },
+                ..
+            }
+        ]
+    ));
+}
+
+#[test]
+fn test_not_a_callee() {
+    let ao = check("f x = x\nmain = [f] 1");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::NotACallee { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_nested_patterns() {
+    // Variables bound anywhere in a pattern are in scope in its arm, and
+    // constructors are checked however deep they are.
+    let ao = check("type M a = (Just a)\nf all@(x:_) = (all, x)\nmain = match (Just [1], 'a') with\n    (Just [y], c) -> (f [y], c)\n    _ -> f []");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    let ao = check("main = match (1, 2) with\n    (x, Nope y) -> x");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::UndefinedConstructor { .. },
+            ..
+        }]
+    ));
+    let ao = check("f (x, x) = x\nmain = f (1, 2)");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::MultipleDeclarations { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_list_types() {
+    let ao = check("f :: [Nope] -> Integer\nf _ = 0\nmain = f []");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::UndefinedType { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_guards() {
+    // Guards see the variables of their patterns.
+    let ao = check("f x | x > 0 = x | otherwise = 0\nmain = match [1] with\n    (y:_) | y > 0 -> f y\n    _ -> 0");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    let ao = check("f x | y > 0 = x\nmain = f 1");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::UndefinedSymbol { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_where() {
+    // A `where` block sees the arguments of its clause, and its binds are
+    // only visible in that clause.
+    let ao = check("f x | x > n = x | otherwise = n\n  where n = x * 2\nmain = f 1");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    // They can be used above their definition, and a function can have
+    // several clauses.
+    let ao = check("g x = a\n  where a = b + go 1\n        b = x * 2\n        go 0 = 1\n        go n = n\nmain = g 1");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    let ao = check("f x = n where n = x\nmain = n");
+    assert!(matches!(
+        ao.errors[..],
+        [SemanticError {
+            kind: SemanticErrorKind::UndefinedSymbol { .. },
+            ..
+        }]
+    ));
+}
+
+#[test]
+fn test_records() {
+    let ao = check(
+        "type P = (P { name :: String, age :: Integer })\nmain = match (P { age = 1, name = \"x\" }) { age = 2 } with\n  P { name = n } -> (n, age (P { name = n, age = 3 }))",
+    );
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    assert_eq!(ao.fields["age"], "P");
+
+    let kinds = |content| -> Vec<SemanticErrorKind> {
+        check(content).errors.into_iter().map(|e| e.kind).collect()
+    };
+    assert!(matches!(
+        &kinds("type P = (P { x :: Integer, x :: Bool })")[..],
+        [SemanticErrorKind::DuplicateField { name, .. }] if name == "x"
+    ));
+    assert!(matches!(
+        &kinds("type P = (P { x :: Integer })\ntype Q = (Q { x :: Integer })")[..],
+        [SemanticErrorKind::DuplicateField { name, .. }] if name == "x"
+    ));
+    assert!(matches!(
+        &kinds("type P = (P { x :: Integer })\nx = 1")[..],
+        [SemanticErrorKind::MultipleDeclarations { name, .. }] if name == "x"
+    ));
+    assert!(matches!(
+        &kinds("type P = (P { x :: Integer, y :: Integer })\nmain = P { x = 1, x = 2 }")[..],
+        [SemanticErrorKind::DuplicateField { .. }, SemanticErrorKind::MissingFields { fields, .. }]
+            if fields == &["y"]
+    ));
+    assert!(matches!(
+        &kinds("type P = (P { x :: Integer })\nmain = P { x = 1, z = 2 }")[..],
+        [SemanticErrorKind::UndefinedField { name, constructor: Some(c), .. }] if name == "z" && c == "P"
+    ));
+    assert!(matches!(
+        &kinds("type P = (P { x :: Integer })\nmain = (P { x = 1 }) { z = 2 }")[..],
+        [SemanticErrorKind::UndefinedField { name, constructor: None, .. }] if name == "z"
+    ));
+}
+
+#[test]
+fn test_classes() {
+    let ao = check(
+        "class Shape a where\n  area :: a -> Float\n  name :: a -> String\n  name x = \"shape\"\ntype Sq = (Sq Float)\ninstance Shape Sq where\n  area (Sq s) = s * s\nmain = (area (Sq 2.0), name (Sq 1.0))",
+    );
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    assert_eq!(ao.methods["area"], "Shape");
+    assert_eq!(ao.instances, [("Shape".to_string(), "Sq".to_string())]);
+
+    // Methods without a definition nor a default are reported at the class.
+    let ao = check("class C a where\n  f :: a -> a\n  g :: a -> a\ninstance C Integer where\n  f x = x\nmain = f 1");
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    let missing: Vec<_> = ao
+        .warnings
+        .iter()
+        .filter_map(|w| match &w.kind {
+            SemanticWarningKind::MissingMethods { class_name, methods } => Some((class_name, methods)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(missing, [(&"C".to_string(), &vec!["g".to_string()])]);
+
+    let kinds = |content| -> Vec<SemanticErrorKind> {
+        check(content).errors.into_iter().map(|e| e.kind).collect()
+    };
+    assert!(matches!(
+        &kinds("class C a where\n  f :: a -> a\nclass C a where\n  g :: a -> a")[..],
+        [SemanticErrorKind::ClassAlreadyDefined { class_name }] if class_name == "C"
+    ));
+    assert!(matches!(
+        &kinds("f :: Foo a => a -> a\nf x = x")[..],
+        [SemanticErrorKind::UndefinedClass { class_name, .. }] if class_name == "Foo"
+    ));
+    assert!(matches!(
+        &kinds("f :: Eq b => a -> a\nf x = x")[..],
+        [SemanticErrorKind::UndefinedTypeVariable { name, .. }] if name == "b"
+    ));
+    assert!(matches!(
+        &kinds("instance Show Integer where\n  show x = \"x\"")[..],
+        [SemanticErrorKind::DuplicateInstance { class_name, type_name }]
+            if class_name == "Show" && type_name == "Integer"
+    ));
+    assert!(matches!(
+        &kinds("type Box a = (Box a)\ninstance Eq (Box Integer) where\n  x == y = True")[..],
+        [SemanticErrorKind::InvalidInstance { .. }]
+    ));
+    assert!(matches!(
+        &kinds("type Box a = (Box a)\ninstance Eq (Box a) where\n  x < y = True")[..],
+        [SemanticErrorKind::UndefinedMethod { method, .. }] if method == "<"
+    ));
+    assert!(matches!(
+        &kinds("class C a where\n  f :: a -> a\nf x = x")[..],
+        [SemanticErrorKind::MultipleDeclarations { name, .. }] if name == "f"
+    ));
+}
+
+/// Resolves and analyzes modules given by name and text, each after the
+/// modules it imports, returning the linked program.
+#[cfg(test)]
+fn check_modules(modules: &[(&str, &str)]) -> (AnalysisOutput, Program<Span>) {
+    let mut modules: Vec<Module> = modules
+        .iter()
+        .map(|(name, content)| {
+            let mut parser = crate::parser::Parser::new(FileId::default(), content);
+            let (program, errors) = parser.parse();
+            assert!(errors.is_empty(), "{:?}", errors);
+            Module {
+                name: name.to_string(),
+                program,
+                fixities: parser.fixities,
+            }
+        })
+        .collect();
+    let mut ao = AnalysisOutput::new();
+    resolve(&mut ao, &mut modules);
+    let program = Program::new(modules.into_iter().flat_map(|module| module.program.statements).collect());
+    analyze(&mut ao, program.clone());
+    (ao, program)
+}
+
+#[test]
+fn test_modules() {
+    let geometry = (
+        "Geometry",
+        "module Geometry (Shape, area, Named)\ntype Shape = (Square Float)\nclass Named a where\n  label :: a -> String\narea (Square s) = s * s\nhelper x = x",
+    );
+    let (ao, program) = check_modules(&[
+        geometry,
+        (
+            "Main",
+            "import Geometry as G\ntype T = (T)\ninstance Named T where\n  label t = \"t\"\nf area = area\nmain = (G.area (Square 1.0), area (G.Square 2.0), f 1, label T)",
+        ),
+    ]);
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    // Only `helper` and the argument of `label` go unused: what a module
+    // exports may be used elsewhere, and `main` is run.
+    assert_eq!(ao.warnings.len(), 2, "{:?}", ao.warnings);
+    let names: Vec<&str> = program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Bind(bind) => Some(bind.name.name.as_str()),
+            Statement::TypeDecl(decl) => Some(decl.name.name.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, ["Geometry.Shape", "Geometry.area", "Geometry.helper", "T", "f", "main"]);
+    match &program.statements[..] {
+        [.., Statement::Instance(instance), _, _] => {
+            assert_eq!(instance.class.name, "Geometry.Named");
+            assert_eq!(instance.binds[0].name.name, "Geometry.label");
+        }
+        statements => panic!("expected an instance, got {:?}", statements),
+    }
+
+    let kinds = |modules: &[(&str, &str)]| -> Vec<SemanticErrorKind> {
+        check_modules(modules).0.errors.into_iter().map(|e| e.kind).collect()
+    };
+    assert!(matches!(
+        &kinds(&[geometry, ("Main", "import Geometry (area, helper)\nmain = area")])[..],
+        [SemanticErrorKind::NotExported { name, .. }] if name == "helper"
+    ));
+    assert!(matches!(
+        &kinds(&[geometry, ("Main", "import qualified Geometry\nmain = (Geometry.area, area)")])[..],
+        [SemanticErrorKind::UndefinedSymbol { symbol_name, .. }] if symbol_name == "area"
+    ));
+    assert!(matches!(
+        &kinds(&[geometry, ("Other", "area = 1"), ("Main", "import Geometry\nimport Other\nmain = area")])[..],
+        [SemanticErrorKind::AmbiguousName { candidates, .. }] if candidates == &["Geometry.area", "Other.area"]
+    ));
+    // The declarations of a module hide the imported ones.
+    assert!(kinds(&[geometry, ("Main", "import Geometry\narea = 1\nmain = area")]).is_empty());
+    assert!(matches!(
+        &kinds(&[("Main", "module Main (main, nope)\nmain = 1")])[..],
+        [SemanticErrorKind::UndefinedExport { name, .. }] if name == "nope"
+    ));
+    assert!(matches!(
+        &kinds(&[("Main", "Geometry.f x = x")])[..],
+        [SemanticErrorKind::QualifiedDeclaration { .. }, ..]
+    ));
+}
+
+#[test]
+fn test_loader() {
+    let root = std::env::temp_dir().join(format!("risk-loader-{}", std::process::id()));
+    let write = |path: &str, content: &str| {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    };
+    write("Data/Pipe.rk", "module Data.Pipe ((|>))\ninfixl 0 |>\n(|>) x f = f x");
+    write("A.rk", "import B\na = 1");
+    write("B.rk", "module C\nimport A\nb = 1");
+
+    let mut sources = SourceMap::new();
+    let mut loader = Loader::new(root.clone(), &mut sources);
+    loader.load_main("main.rk", "import Data.Pipe\nimport A\nimport Nope\nmain = 1 + 1 |> negate".to_string());
+    let Loader { modules, errors, .. } = loader;
+    let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+    // The Prelude is imported first, by every module but itself.
+    assert_eq!(names, ["Prelude", "Data.Pipe", "B", "A", "Main"]);
+    let kinds: Vec<&SemanticErrorKind> = errors.iter().map(|e| &e.kind).collect();
+    assert!(matches!(
+        kinds[..],
+        [
+            SemanticErrorKind::CyclicImport { module_name: a, .. },
+            SemanticErrorKind::WrongModuleName { expected: b, .. },
+            SemanticErrorKind::UndefinedModule { module_name: nope, .. },
+        ] if a == "A" && b == "B" && nope == "Nope"
+    ));
+    // Errors name the file they are found in.
+    assert!(sources.file(errors[0].span.file).name.ends_with("B.rk"));
+    // The imported operator keeps its fixity.
+    match &modules[4].program.statements[..] {
+        [.., Statement::Bind(bind)] => assert!(matches!(
+            &bind.expr,
+            AnnExpr::App(App { head, args, .. })
+                if matches!(&**head, AnnExpr::Identifier { id } if id.name == "|>")
+                    && matches!(args[0], AnnExpr::BinOp { .. })
+        )),
+        statements => panic!("expected a bind, got {:?}", statements),
+    }
+    std::fs::remove_dir_all(root).unwrap();
+}
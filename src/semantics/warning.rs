@@ -17,6 +17,7 @@
 */
 
 use crate::semantics::Span;
+use crate::source::SourceMap;
 use ariadne::*;
 
 #[derive(Debug)]
@@ -30,24 +31,24 @@ pub enum SemanticWarningKind {
     UnusedSymbol,
     UnusedType,
     UnusedVariant,
+    /// Methods an instance defines neither itself nor through a default.
+    MissingMethods {
+        class_name: String,
+        methods: Vec<String>,
+    },
 }
 
 impl SemanticWarning {
-    pub fn report(&self, filename: &str) {
-        let source = &std::fs::read_to_string(filename).unwrap();
-        let mut report = Report::build(
-            ReportKind::Warning,
-            filename,
-            self.span.get_line_number(source),
-        );
+    pub fn report(&self, sources: &SourceMap) {
+        let mut report = Report::build(ReportKind::Warning, self.span.file, sources.label(self.span).1.start);
 
         match &self.kind {
             SemanticWarningKind::UnusedSymbol => {
                 report = report
                     .with_code("unused-symbol")
-                    .with_message(format!("The '{}' symbol is never used.", self.span.input))
+                    .with_message(format!("The '{}' symbol is never used.", sources.text(self.span)))
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message("Never used")
                             .with_color(Color::Cyan),
                     )
@@ -55,9 +56,9 @@ impl SemanticWarning {
             SemanticWarningKind::UnusedType => {
                 report = report
                     .with_code("unused-type")
-                    .with_message(format!("The '{}' type is never used.", self.span.input))
+                    .with_message(format!("The '{}' type is never used.", sources.text(self.span)))
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message("Never used")
                             .with_color(Color::Cyan),
                     )
@@ -65,18 +66,33 @@ impl SemanticWarning {
             SemanticWarningKind::UnusedVariant => {
                 report = report
                     .with_code("unused-variant")
-                    .with_message(format!("The '{}' variant is never used.", self.span.input))
+                    .with_message(format!("The '{}' variant is never used.", sources.text(self.span)))
                     .with_label(
-                        Label::new((filename, self.span.start..self.span.end))
+                        Label::new(sources.label(self.span))
                             .with_message("Never used")
                             .with_color(Color::Cyan),
                     )
             }
+            SemanticWarningKind::MissingMethods { class_name, methods } => {
+                report = report
+                    .with_code("missing-methods")
+                    .with_message(format!(
+                        "The instance of '{}' does not define {}",
+                        class_name,
+                        methods.iter().map(|m| format!("'{}'", m)).collect::<Vec<_>>().join(", ")
+                    ))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Methods missing")
+                            .with_color(Color::Cyan),
+                    )
+                    .with_note("Calling a missing method stops the program with a runtime error.")
+            }
         }
 
         report
             .finish()
-            .print((filename, Source::from(source)))
+            .print(sources)
             .unwrap();
     }
 }
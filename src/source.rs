@@ -0,0 +1,162 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! The source files of a program. Spans only store a file id and byte
+//! offsets, and are resolved to text, lines, columns and report labels
+//! through the `SourceMap`.
+
+use crate::ast::Span;
+use ariadne::{Cache, Source};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(u32);
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub name: String,
+    text: Arc<str>,
+    /// Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    /// Character offset of the start of each line, as `ariadne` counts in
+    /// characters.
+    line_chars: Vec<usize>,
+    /// The same text, indexed the way `ariadne` wants it for reports.
+    report: Source<Arc<str>>,
+}
+
+impl SourceFile {
+    fn new(name: String, text: String) -> Self {
+        let text: Arc<str> = text.into();
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let line_chars = std::iter::once(0)
+            .chain(line_starts.windows(2).scan(0, |chars, line| {
+                *chars += text[line[0]..line[1]].chars().count();
+                Some(*chars)
+            }))
+            .collect();
+        SourceFile {
+            name,
+            report: Source::from(text.clone()),
+            text,
+            line_starts,
+            line_chars,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The line and column of the byte at `offset`, both starting at 1.
+    /// Columns are counted in characters.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let line = self.line(offset);
+        let column = self.text[self.line_starts[line]..offset].chars().count();
+        (line + 1, column + 1)
+    }
+
+    /// The character offset of the byte at `offset`.
+    pub fn char_offset(&self, offset: usize) -> usize {
+        let line = self.line(offset);
+        self.line_chars[line] + self.text[self.line_starts[line]..offset].chars().count()
+    }
+
+    /// The index of the line containing the byte at `offset`.
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap { files: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: &str, text: String) -> FileId {
+        self.files.push(SourceFile::new(name.to_string(), text));
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    /// The text covered by `span`.
+    pub fn text(&self, span: Span) -> &str {
+        &self.file(span.file).text()[span.start..span.end]
+    }
+
+    /// The line and column where `span` starts.
+    pub fn location(&self, span: Span) -> (usize, usize) {
+        self.file(span.file).location(span.start)
+    }
+
+    pub fn line(&self, span: Span) -> usize {
+        self.location(span).0
+    }
+
+    /// What `ariadne` needs to label `span`: its file, and its range counted
+    /// in characters.
+    pub fn label(&self, span: Span) -> (FileId, Range<usize>) {
+        let file = self.file(span.file);
+        (span.file, file.char_offset(span.start)..file.char_offset(span.end))
+    }
+}
+
+impl Cache<FileId> for &SourceMap {
+    type Storage = Arc<str>;
+
+    fn fetch(&mut self, id: &FileId) -> Result<&Source<Arc<str>>, Box<dyn fmt::Debug + '_>> {
+        Ok(&self.file(*id).report)
+    }
+
+    fn display<'a>(&self, id: &'a FileId) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.file(*id).name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SourceMap;
+    use crate::ast::Span;
+
+    #[test]
+    fn test_location() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.rk", "x = 1\n\nf é y =\n  y".to_string());
+        let location = |start, end| sources.location(Span::new(file, start, end));
+        assert_eq!(location(0, 1), (1, 1));
+        assert_eq!(location(4, 5), (1, 5));
+        assert_eq!(location(6, 6), (2, 1));
+        assert_eq!(location(12, 13), (3, 5));
+        assert_eq!(location(18, 19), (4, 3));
+        assert_eq!(sources.text(Span::new(file, 7, 13)), "f é y");
+        assert_eq!(sources.label(Span::new(file, 12, 13)), (file, 11..12));
+        assert_eq!(sources.label(Span::new(file, 18, 19)), (file, 17..18));
+    }
+}
@@ -0,0 +1,286 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::ast::Span;
+use crate::source::SourceMap;
+use ariadne::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RuntimeErrorKind {
+    UndefinedSymbol { name: String },
+    NotAFunction,
+    MatchFailure,
+    CyclicDefinition { name: String },
+    TypeMismatch { expected: String },
+    DivisionByZero,
+    NegativeExponent,
+    ExponentTooLarge,
+    InvalidConversion { value: String, target: Target },
+    StackOverflow { depth: usize },
+    OutOfFuel { fuel: u64 },
+    HeapExhausted { limit: usize },
+    MissingMethod { method: String },
+}
+
+/// The type a value is converted to. It is kept small, as the errors are
+/// carried through the recursion of the evaluator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Target {
+    Integer,
+    Char,
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Integer => write!(f, "an Integer"),
+            Target::Char => write!(f, "a Char"),
+        }
+    }
+}
+
+/// Number of call frames labelled in a report. The innermost ones are shown,
+/// the rest are summarized in a note.
+const MAX_REPORTED_FRAMES: usize = 12;
+
+/// A call that was active when the error occurred.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TraceFrame {
+    pub name: String,
+    /// Where the function was called from. The entry point has none.
+    pub call_site: Option<Span>,
+    /// Number of identical consecutive calls folded into this frame.
+    pub repeated: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+    /// Call stack, innermost call first.
+    pub trace: Vec<TraceFrame>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> Self {
+        RuntimeError {
+            kind,
+            span,
+            trace: Vec::new(),
+        }
+    }
+
+    /// Records that the error went through a call to `name` made at
+    /// `call_site`. Direct recursion is folded into a single frame.
+    pub fn in_frame(mut self, name: &str, call_site: Option<&Span>) -> Self {
+        match self.trace.last_mut() {
+            Some(last) if last.name == name && last.call_site.as_ref() == call_site => {
+                last.repeated += 1
+            }
+            _ => self.trace.push(TraceFrame {
+                name: name.to_string(),
+                call_site: call_site.cloned(),
+                repeated: 1,
+            }),
+        }
+        self
+    }
+
+    pub fn report(&self, sources: &SourceMap) {
+        let mut report = Report::build(ReportKind::Error, self.span.file, sources.label(self.span).1.start);
+
+        match &self.kind {
+            RuntimeErrorKind::UndefinedSymbol { name } => {
+                report = report
+                    .with_code("undefined-symbol")
+                    .with_message(format!("Undefined symbol '{}' at runtime", name))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Symbol not found")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::NotAFunction => {
+                report = report
+                    .with_code("not-a-function")
+                    .with_message("Applied a value that is not a function")
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Not a function")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::MatchFailure => {
+                report = report
+                    .with_code("match-failure")
+                    .with_message("No pattern matched the value")
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Non-exhaustive patterns")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::CyclicDefinition { name } => {
+                report = report
+                    .with_code("cyclic-definition")
+                    .with_message(format!("'{}' depends on its own value", name))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Evaluated while already being evaluated")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::TypeMismatch { expected } => {
+                report = report
+                    .with_code("type-mismatch")
+                    .with_message(format!("Expected {} at runtime", expected))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message(format!("Expected {}", expected))
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::DivisionByZero => {
+                report = report
+                    .with_code("division-by-zero")
+                    .with_message("Division by zero")
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("The divisor of this operation is zero")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::NegativeExponent => {
+                report = report
+                    .with_code("negative-exponent")
+                    .with_message("Integer raised to a negative power")
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("The exponent of this operation is negative")
+                            .with_color(Color::Red),
+                    )
+                    .with_note("Use `toFloat` to compute a fractional power.");
+            }
+            RuntimeErrorKind::ExponentTooLarge => {
+                report = report
+                    .with_code("exponent-too-large")
+                    .with_message("Exponent is too large")
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("The result of this operation cannot be represented")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::StackOverflow { depth } => {
+                report = report
+                    .with_code("stack-overflow")
+                    .with_message(format!("Stack overflow after {} nested calls", depth))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("This call exceeded the maximum depth")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::OutOfFuel { fuel } => {
+                report = report
+                    .with_code("out-of-fuel")
+                    .with_message(format!("Evaluation step limit of {} reached", fuel))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("The program was stopped here")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::HeapExhausted { limit } => {
+                report = report
+                    .with_code("heap-exhausted")
+                    .with_message(format!("Heap limit of {} bytes exceeded", limit))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("This allocation does not fit")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::MissingMethod { method } => {
+                report = report
+                    .with_code("missing-method")
+                    .with_message(format!("This instance does not define `{}`", method))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("In this instance")
+                            .with_color(Color::Red),
+                    );
+            }
+            RuntimeErrorKind::InvalidConversion { value, target } => {
+                report = report
+                    .with_code("invalid-conversion")
+                    .with_message(format!("Cannot convert {} to {}", value, target))
+                    .with_label(
+                        Label::new(sources.label(self.span))
+                            .with_message("Converted here")
+                            .with_color(Color::Red),
+                    );
+            }
+        }
+
+        // Each call site is labelled once, so mutual recursion stays readable.
+        let mut labelled = Vec::new();
+        let frames = self.trace.iter().filter(|frame| match &frame.call_site {
+            Some(span) if !labelled.contains(span) => {
+                labelled.push(*span);
+                true
+            }
+            _ => false,
+        });
+        for (order, frame) in frames.take(MAX_REPORTED_FRAMES).enumerate() {
+            let span = frame.call_site.as_ref().unwrap();
+            let message = if frame.repeated > 1 {
+                format!("{}: in `{}` ({} recursive calls)", order + 1, frame.name, frame.repeated)
+            } else {
+                format!("{}: in `{}`", order + 1, frame.name)
+            };
+            report = report.with_label(
+                Label::new(sources.label(*span))
+                    .with_message(message)
+                    .with_color(Color::Yellow)
+                    .with_order(order as i32 + 1),
+            );
+        }
+
+        if !self.trace.is_empty() {
+            let mut stack: Vec<String> = self
+                .trace
+                .iter()
+                .take(MAX_REPORTED_FRAMES)
+                .map(|frame| match frame.repeated {
+                    1 => frame.name.clone(),
+                    n => format!("{} (x{})", frame.name, n),
+                })
+                .collect();
+            if self.trace.len() > MAX_REPORTED_FRAMES {
+                stack.push(format!("{} more", self.trace.len() - MAX_REPORTED_FRAMES));
+            }
+            report = report.with_note(format!("Call stack, innermost first: {}", stack.join(" <- ")));
+        }
+
+        report
+            .finish()
+            .print(sources)
+            .unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::ast::{ParsedExpr, Pattern};
+use crate::vm::native::Native;
+use crate::vm::value::{ObjRef, Value};
+use num_bigint::BigInt;
+use std::fmt::Display;
+use std::mem::size_of;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_HEAP_SIZE: usize = 1024 * 1024;
+
+/// After a collection, the next one is triggered once the live size has
+/// grown by this factor.
+const GROWTH_FACTOR: usize = 2;
+
+pub type Env<'a> = Vec<(&'a str, Value)>;
+
+#[derive(Debug)]
+pub struct Clause<'a> {
+    pub patterns: &'a [Pattern],
+    pub body: &'a ParsedExpr,
+}
+
+#[derive(Debug)]
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub arity: usize,
+    pub clauses: Vec<Clause<'a>>,
+}
+
+#[derive(Debug)]
+pub enum Object<'a> {
+    String(String),
+    /// An integer that does not fit in a `Value::Int`.
+    BigInt(BigInt),
+    Cons(Value, Value),
+    Tuple(Vec<Value>),
+    /// A constructor cell. While `fields.len() < arity` it is a partially
+    /// applied constructor and can still be called.
+    Constructor {
+        name: &'a str,
+        arity: usize,
+        fields: Vec<Value>,
+    },
+    /// A function together with its captured environment and the arguments
+    /// it has been partially applied to.
+    Closure {
+        function: Rc<Function<'a>>,
+        env: Env<'a>,
+        args: Vec<Value>,
+    },
+    /// A native function and the arguments it has been partially applied to.
+    Native {
+        native: &'static Native,
+        args: Vec<Value>,
+    },
+    /// The function reading the field `field` of a record.
+    Selector {
+        field: &'a str,
+    },
+}
+
+impl<'a> Object<'a> {
+    pub fn for_each_child(&self, mut f: impl FnMut(Value)) {
+        match self {
+            Object::String(_) | Object::BigInt(_) | Object::Selector { .. } => {}
+            Object::Cons(head, tail) => {
+                f(*head);
+                f(*tail);
+            }
+            Object::Tuple(items) => items.iter().copied().for_each(f),
+            Object::Constructor { fields, .. } => fields.iter().copied().for_each(f),
+            Object::Closure { env, args, .. } => {
+                env.iter().map(|(_, v)| *v).for_each(&mut f);
+                args.iter().copied().for_each(f);
+            }
+            Object::Native { args, .. } => args.iter().copied().for_each(f),
+        }
+    }
+
+    /// Approximate number of bytes owned by this object.
+    pub fn size(&self) -> usize {
+        size_of::<Object>()
+            + match self {
+                Object::String(s) => s.capacity(),
+                Object::BigInt(i) => i.bits() as usize / 8,
+                Object::Cons(..) | Object::Selector { .. } => 0,
+                Object::Tuple(items) => items.capacity() * size_of::<Value>(),
+                Object::Constructor { fields, .. } => fields.capacity() * size_of::<Value>(),
+                Object::Closure { env, args, .. } => {
+                    env.capacity() * size_of::<(&str, Value)>()
+                        + args.capacity() * size_of::<Value>()
+                }
+                Object::Native { args, .. } => args.capacity() * size_of::<Value>(),
+            }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapConfig {
+    /// Number of allocated bytes after which the first collection happens.
+    pub heap_size: usize,
+}
+
+impl Default for HeapConfig {
+    fn default() -> Self {
+        HeapConfig {
+            heap_size: DEFAULT_HEAP_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GcStats {
+    pub collections: usize,
+    pub objects_freed: usize,
+    pub bytes_freed: usize,
+    pub total_pause: Duration,
+    pub max_pause: Duration,
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "collections:   {}", self.collections)?;
+        writeln!(f, "objects freed: {}", self.objects_freed)?;
+        writeln!(f, "bytes freed:   {}", self.bytes_freed)?;
+        writeln!(f, "total pause:   {:?}", self.total_pause)?;
+        write!(f, "max pause:     {:?}", self.max_pause)
+    }
+}
+
+/// Mark-sweep heap. Objects live in slots indexed by `ObjRef`; freed slots
+/// are recycled through a free list.
+#[derive(Debug)]
+pub struct Heap<'a> {
+    objects: Vec<Option<Object<'a>>>,
+    marks: Vec<bool>,
+    /// The size each object was counted with when it was allocated, which
+    /// is what freeing it gives back, whatever happened to it since.
+    sizes: Vec<usize>,
+    free: Vec<usize>,
+    bytes_allocated: usize,
+    next_gc: usize,
+    config: HeapConfig,
+    pub stats: GcStats,
+}
+
+impl<'a> Heap<'a> {
+    pub fn new(config: HeapConfig) -> Self {
+        Heap {
+            objects: Vec::new(),
+            marks: Vec::new(),
+            sizes: Vec::new(),
+            free: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: config.heap_size,
+            config,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.bytes_allocated >= self.next_gc
+    }
+
+    pub fn alloc(&mut self, object: Object<'a>) -> ObjRef {
+        let size = object.size();
+        self.bytes_allocated += size;
+        if let Some(index) = self.free.pop() {
+            self.objects[index] = Some(object);
+            self.sizes[index] = size;
+            ObjRef(index)
+        } else {
+            self.objects.push(Some(object));
+            self.marks.push(false);
+            self.sizes.push(size);
+            ObjRef(self.objects.len() - 1)
+        }
+    }
+
+    pub fn get(&self, r: ObjRef) -> &Object<'a> {
+        self.objects[r.0]
+            .as_ref()
+            .expect("dangling reference to a collected object")
+    }
+
+    pub fn get_mut(&mut self, r: ObjRef) -> &mut Object<'a> {
+        self.objects[r.0]
+            .as_mut()
+            .expect("dangling reference to a collected object")
+    }
+
+    /// Marks everything reachable from `roots` and frees the rest.
+    pub fn collect(&mut self, roots: impl Iterator<Item = Value>) {
+        let start = Instant::now();
+
+        let mut gray: Vec<ObjRef> = roots.filter_map(|v| v.as_object()).collect();
+        while let Some(r) = gray.pop() {
+            if self.marks[r.0] {
+                continue;
+            }
+            self.marks[r.0] = true;
+            if let Some(object) = &self.objects[r.0] {
+                object.for_each_child(|child| {
+                    if let Some(c) = child.as_object() {
+                        if !self.marks[c.0] {
+                            gray.push(c);
+                        }
+                    }
+                });
+            }
+        }
+
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            if self.marks[index] {
+                self.marks[index] = false;
+            } else if slot.take().is_some() {
+                let size = self.sizes[index];
+                self.bytes_allocated -= size;
+                self.stats.bytes_freed += size;
+                self.stats.objects_freed += 1;
+                self.free.push(index);
+            }
+        }
+
+        self.next_gc = (self.bytes_allocated * GROWTH_FACTOR).max(self.config.heap_size);
+
+        let pause = start.elapsed();
+        self.stats.collections += 1;
+        self.stats.total_pause += pause;
+        self.stats.max_pause = self.stats.max_pause.max(pause);
+    }
+}
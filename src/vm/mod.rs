@@ -0,0 +1,1290 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+mod error;
+pub mod heap;
+pub mod native;
+pub mod value;
+
+#[cfg(test)]
+mod test;
+
+use crate::ast::*;
+use crate::semantics::tc::{clause_references, dependency_order, group_binds};
+use crate::vm::error::{RuntimeError, RuntimeErrorKind};
+use crate::vm::heap::{Clause, Env, Function, GcStats, Heap, HeapConfig, Object};
+use crate::vm::native::{NATIVES, PRIMITIVES};
+use crate::vm::value::{format_float, Value};
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub(crate) type VmResult<T> = Result<T, RuntimeError>;
+
+#[derive(Debug)]
+enum Global<'a> {
+    Thunk(&'a ParsedExpr),
+    Evaluating,
+    Value(Value),
+}
+
+/// The bind a program is run from.
+pub const ENTRY: &str = "main";
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/// The largest call depth limit, whose native stack can still be reserved
+/// on most machines.
+pub const MAX_CALL_DEPTH: usize = 50_000;
+
+/// Native stack reserved per nested call, generous enough for debug builds.
+const STACK_PER_CALL: usize = 64 * 1024;
+
+/// Native stack kept free below the guard of `guard_stack`, for what is
+/// evaluated between two calls and for reporting the error.
+const STACK_MARGIN: usize = 1024 * 1024;
+
+/// Limits on the resources a program may use. Reaching one stops the
+/// program with a runtime error.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Number of expressions that may be evaluated, unlimited when `None`.
+    pub fuel: Option<u64>,
+    /// Maximum number of nested calls, at most `MAX_CALL_DEPTH`.
+    pub max_call_depth: usize,
+    /// Maximum number of live bytes on the heap, unlimited when `None`.
+    pub max_heap: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            fuel: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_heap: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Native stack size the evaluator needs to reach `max_call_depth`. The
+    /// evaluator recurses, so it must run on a thread with that much stack.
+    pub fn stack_size(&self) -> usize {
+        (self.max_call_depth.min(MAX_CALL_DEPTH) + 1) * STACK_PER_CALL
+    }
+}
+
+/// What running a program gave.
+#[derive(Debug)]
+pub struct Run {
+    /// The value of `main`, displayed, or the error that stopped it. `None`
+    /// when there is no `main`.
+    pub result: Option<Result<String, RuntimeError>>,
+    pub stats: GcStats,
+}
+
+/// Runs `main` of `program`, once type checked and elaborated, within
+/// `limits`. The evaluator recurses on the native stack, so it runs on a
+/// thread of its own, large enough to reach the call depth limit. Fails
+/// only when that thread cannot be created.
+pub fn run_with_limits(program: &Program<Span>, config: HeapConfig, limits: Limits) -> std::io::Result<Run> {
+    let stack_size = limits.stack_size();
+    std::thread::scope(|scope| {
+        let evaluator = std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, || {
+                let mut vm = Vm::new(program, config, limits);
+                vm.guard_stack(stack_size);
+                let result = vm.run(ENTRY).map(|result| result.map(|value| vm.display(value)));
+                Run {
+                    result,
+                    stats: vm.heap.stats.clone(),
+                }
+            })?;
+        Ok(evaluator.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+    })
+}
+
+#[derive(Debug)]
+struct Frame<'a> {
+    /// Environment of the caller, restored when the call returns.
+    env: Env<'a>,
+}
+
+/// Evaluates a parsed program by walking its syntax tree; the chunks of
+/// `compiler` are not run. Every intermediate value is kept on `stack`, in
+/// `env` or in a saved frame so that the collector can find all roots.
+#[derive(Debug)]
+pub struct Vm<'a> {
+    pub heap: Heap<'a>,
+    stack: Vec<Value>,
+    env: Env<'a>,
+    frames: Vec<Frame<'a>>,
+    globals: HashMap<&'a str, Global<'a>>,
+    constructors: HashMap<&'a str, usize>,
+    /// The fields of the record constructors, in order.
+    records: HashMap<&'a str, &'a [Identifier]>,
+    limits: Limits,
+    fuel: Option<u64>,
+    /// The lowest address the native stack may reach, see `guard_stack`.
+    stack_guard: Option<usize>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a Program<Span>, config: HeapConfig, mut limits: Limits) -> Self {
+        limits.max_call_depth = limits.max_call_depth.min(MAX_CALL_DEPTH);
+        let mut vm = Vm {
+            heap: Heap::new(config),
+            stack: Vec::new(),
+            env: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            constructors: HashMap::new(),
+            records: HashMap::new(),
+            limits,
+            fuel: limits.fuel,
+            stack_guard: None,
+        };
+        vm.load(program);
+        vm
+    }
+
+    fn load(&mut self, program: &'a Program<Span>) {
+        for native in NATIVES.iter().chain(PRIMITIVES) {
+            let value = if native.arity == 0 {
+                (native.function)(self, &[], &Span::default())
+                    .expect("native constants do not fail")
+            } else {
+                Value::Object(self.heap.alloc(Object::Native {
+                    native,
+                    args: Vec::new(),
+                }))
+            };
+            self.globals.insert(native.name, Global::Value(value));
+        }
+
+        let mut functions: Vec<Function<'a>> = Vec::new();
+
+        for statement in &program.statements {
+            match statement {
+                Statement::Bind(bind) if bind.args.is_empty() => {
+                    if !matches!(self.globals.get(bind.name.name.as_str()), Some(Global::Thunk(_))) {
+                        self.globals.insert(&bind.name.name, Global::Thunk(&bind.expr));
+                    }
+                }
+                Statement::Bind(bind) => {
+                    let clause = Clause {
+                        patterns: &bind.args,
+                        body: &bind.expr,
+                    };
+                    match functions.iter_mut().find(|f| f.name == bind.name.name) {
+                        Some(function) => function.clauses.push(clause),
+                        None => functions.push(Function {
+                            name: &bind.name.name,
+                            arity: bind.args.len(),
+                            clauses: vec![clause],
+                        }),
+                    }
+                }
+                Statement::TypeDecl(decl) => {
+                    for variant in &decl.variants {
+                        self.constructors
+                            .insert(&variant.id.name, variant.types.len());
+                        if variant.fields.is_empty() {
+                            continue;
+                        }
+                        self.records.insert(&variant.id.name, &variant.fields);
+                        for field in &variant.fields {
+                            if !self.globals.contains_key(field.name.as_str()) {
+                                let r = self.heap.alloc(Object::Selector { field: &field.name });
+                                self.globals.insert(&field.name, Global::Value(Value::Object(r)));
+                            }
+                        }
+                    }
+                }
+                // Classes and instances have been elaborated into
+                // dictionaries, and names resolved across modules.
+                Statement::TypeAssign(_)
+                | Statement::Fixity(_)
+                | Statement::Class(_)
+                | Statement::Instance(_)
+                | Statement::Module(_)
+                | Statement::Import(_) => {}
+            }
+        }
+
+        for function in functions {
+            let name = function.name;
+            let r = self.heap.alloc(Object::Closure {
+                function: Rc::new(function),
+                env: Vec::new(),
+                args: Vec::new(),
+            });
+            self.globals.insert(name, Global::Value(Value::Object(r)));
+        }
+    }
+
+    /// Evaluates the global named `name`, if it exists.
+    /// Makes the calls that would overflow the native stack stop the
+    /// program, when the evaluator runs from here on a thread with `size`
+    /// bytes of stack. The stack is assumed to grow downwards.
+    pub fn guard_stack(&mut self, size: usize) {
+        let here = 0u8;
+        let base = std::ptr::addr_of!(here).addr();
+        self.stack_guard = Some(base.saturating_sub(size.saturating_sub(STACK_MARGIN)));
+    }
+
+    pub fn run(&mut self, name: &str) -> Option<VmResult<Value>> {
+        let (&name, _) = self.globals.get_key_value(name)?;
+        self.force_global(name, None).transpose()
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("vm stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn alloc(&mut self, object: Object<'a>, span: &Span) -> VmResult<Value> {
+        let size = object.size();
+        let exceeds = |vm: &Self| {
+            vm.limits
+                .max_heap
+                .is_some_and(|max| vm.heap.bytes_allocated() + size > max)
+        };
+        if self.heap.should_collect() || exceeds(self) {
+            self.collect(&object);
+        }
+        if exceeds(self) {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::HeapExhausted {
+                    limit: self.limits.max_heap.unwrap(),
+                },
+                *span,
+            ));
+        }
+        Ok(Value::Object(self.heap.alloc(object)))
+    }
+
+    /// Runs a collection. The children of `pending`, the object about to be
+    /// allocated, are treated as roots too.
+    fn collect(&mut self, pending: &Object<'a>) {
+        let mut extra = Vec::new();
+        pending.for_each_child(|v| extra.push(v));
+
+        let env = self.env.iter().map(|(_, v)| *v);
+        let frames = self
+            .frames
+            .iter()
+            .flat_map(|frame| frame.env.iter().map(|(_, v)| *v));
+        let globals = self.globals.values().filter_map(|global| match global {
+            Global::Value(v) => Some(*v),
+            _ => None,
+        });
+
+        self.heap.collect(
+            self.stack
+                .iter()
+                .copied()
+                .chain(env)
+                .chain(frames)
+                .chain(globals)
+                .chain(extra),
+        );
+    }
+
+    fn force_global(&mut self, name: &'a str, span: Option<&'a Span>) -> VmResult<Option<Value>> {
+        let expr = match self.globals.get(name) {
+            None => return Ok(None),
+            Some(Global::Value(v)) => return Ok(Some(*v)),
+            Some(Global::Evaluating) => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::CyclicDefinition {
+                        name: name.to_string(),
+                    },
+                    span.cloned().unwrap_or_default(),
+                ))
+            }
+            Some(Global::Thunk(expr)) => *expr,
+        };
+
+        self.check_depth(span.unwrap_or(expr.get_span()))?;
+        self.globals.insert(name, Global::Evaluating);
+        let caller = std::mem::take(&mut self.env);
+        self.frames.push(Frame { env: caller });
+        let result = self.eval(expr);
+        self.env = self.frames.pop().unwrap().env;
+        result.map_err(|e| e.in_frame(name, span))?;
+
+        let value = self.pop();
+        self.globals.insert(name, Global::Value(value));
+        Ok(Some(value))
+    }
+
+    fn lookup(&mut self, id: &'a Identifier) -> VmResult<Value> {
+        if let Some((_, v)) = self.env.iter().rev().find(|(name, _)| *name == id.name) {
+            return Ok(*v);
+        }
+        match self.force_global(&id.name, Some(&id.span))? {
+            Some(v) => Ok(v),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::UndefinedSymbol {
+                    name: id.name.clone(),
+                },
+                id.span,
+            )),
+        }
+    }
+
+    fn constructor(&mut self, id: &'a Identifier) -> VmResult<Value> {
+        match self.constructors.get(id.name.as_str()) {
+            Some(&arity) => self.alloc(
+                Object::Constructor {
+                    name: &id.name,
+                    arity,
+                    fields: Vec::new(),
+                },
+                &id.span,
+            ),
+            None => Err(RuntimeError::new(
+                RuntimeErrorKind::UndefinedSymbol {
+                    name: id.name.clone(),
+                },
+                id.span,
+            )),
+        }
+    }
+
+    fn literal(&mut self, lit: &Literal) -> VmResult<Value> {
+        Ok(match &lit.lit {
+            LiteralKind::Integer(i) => self.integer(i.clone(), &lit.span)?,
+            LiteralKind::Float(f) => Value::Float(*f),
+            LiteralKind::Bool(b) => Value::Bool((*b).into()),
+            LiteralKind::Char(c) => Value::Char(*c),
+            LiteralKind::String(s) => self.alloc(Object::String(s.clone()), &lit.span)?,
+        })
+    }
+
+    /// Evaluates `expr` and pushes its value on the stack.
+    fn eval(&mut self, expr: &'a ParsedExpr) -> VmResult<()> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::OutOfFuel {
+                        fuel: self.limits.fuel.unwrap(),
+                    },
+                    *expr.get_span(),
+                ));
+            }
+            *fuel -= 1;
+        }
+
+        match expr {
+            AnnExpr::Literal(lit) => {
+                let v = self.literal(lit)?;
+                self.push(v);
+            }
+            AnnExpr::Identifier { id } => {
+                let v = self.lookup(id)?;
+                self.push(v);
+            }
+            AnnExpr::PCIdentifier { id } => {
+                let v = self.constructor(id)?;
+                self.push(v);
+            }
+            AnnExpr::App(App { head, args, span }) => {
+                self.eval(head)?;
+                for arg in args {
+                    self.eval(arg)?;
+                }
+                self.apply(args.len(), span)?;
+            }
+            AnnExpr::Condition {
+                cond, then, els, ..
+            } => {
+                self.eval(cond)?;
+                match self.pop() {
+                    Value::Bool(true) => self.eval(then)?,
+                    Value::Bool(false) => self.eval(els)?,
+                    _ => return Err(type_mismatch("Bool", cond.get_span())),
+                }
+            }
+            AnnExpr::Let { binds, ret, .. } => {
+                let depth = self.env.len();
+                let result = self.bind_locals(binds).and_then(|_| self.eval(ret));
+                self.env.truncate(depth);
+                result?;
+            }
+            AnnExpr::Match {
+                referral,
+                cases,
+                ann,
+            } => {
+                self.eval(referral)?;
+                let value = self.peek(0);
+                let depth = self.env.len();
+                for (pattern, body) in cases {
+                    if self.match_pattern(pattern, value) {
+                        let result = self.eval_body(body);
+                        self.env.truncate(depth);
+                        if result? {
+                            let v = self.pop();
+                            self.pop();
+                            self.push(v);
+                            return Ok(());
+                        }
+                    }
+                    self.env.truncate(depth);
+                }
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::MatchFailure,
+                    *ann,
+                ));
+            }
+            AnnExpr::BinOp { op, lhs, rhs, ann } => self.binop(*op, lhs, rhs, ann)?,
+            AnnExpr::UnOp { op, expr, ann } => {
+                self.eval(expr)?;
+                let v = self.pop();
+                let v = self.unop(*op, v, ann)?;
+                self.push(v);
+            }
+            AnnExpr::Lambda { args, ret, ann } => {
+                let function = Rc::new(Function {
+                    name: "<lambda>",
+                    arity: args.len(),
+                    clauses: vec![Clause {
+                        patterns: args,
+                        body: ret,
+                    }],
+                });
+                let closure = self.alloc(
+                    Object::Closure {
+                        function,
+                        env: self.env.clone(),
+                        args: Vec::new(),
+                    },
+                    ann,
+                )?;
+                self.push(closure);
+            }
+            AnnExpr::Ann { expr, .. } => self.eval(expr)?,
+            AnnExpr::List { list, ann } => {
+                for item in list {
+                    self.eval(item)?;
+                }
+                let mut acc = Value::Nil;
+                for distance in 0..list.len() {
+                    let head = self.peek(distance);
+                    acc = self.alloc(Object::Cons(head, acc), ann)?;
+                }
+                self.stack.truncate(self.stack.len() - list.len());
+                self.push(acc);
+            }
+            AnnExpr::Tuple { list, ann } => {
+                for item in list {
+                    self.eval(item)?;
+                }
+                let items = self.stack.split_off(self.stack.len() - list.len());
+                let v = self.alloc(Object::Tuple(items), ann)?;
+                self.push(v);
+            }
+            AnnExpr::Record { id, fields, ann } => self.record(id, fields, ann)?,
+            AnnExpr::Update { expr, fields, ann } => self.update(expr, fields, ann)?,
+            AnnExpr::Guards { ann, .. } => {
+                // Only clauses and arms fall through, a bind without
+                // arguments has nothing to fall through to.
+                if !self.eval_body(expr)? {
+                    return Err(RuntimeError::new(RuntimeErrorKind::MatchFailure, *ann));
+                }
+            }
+            AnnExpr::Error { .. } => unreachable!("programs with syntax errors are not run"),
+        }
+        Ok(())
+    }
+
+    /// Builds the record `id`, evaluating its fields in declaration order.
+    fn record(
+        &mut self,
+        id: &'a Identifier,
+        fields: &'a [(Identifier, ParsedExpr)],
+        ann: &Span,
+    ) -> VmResult<()> {
+        let declared = self.records.get(id.name.as_str()).copied().unwrap_or_default();
+        for field in declared {
+            let (_, value) = fields
+                .iter()
+                .find(|(name, _)| name.name == field.name)
+                .expect("missing fields are reported by the semantic analysis");
+            self.eval(value)?;
+        }
+        let values = self.stack.split_off(self.stack.len() - declared.len());
+        let v = self.alloc(
+            Object::Constructor {
+                name: &id.name,
+                arity: declared.len(),
+                fields: values,
+            },
+            ann,
+        )?;
+        self.push(v);
+        Ok(())
+    }
+
+    /// Pushes a copy of the record `expr` with `fields` replaced.
+    fn update(
+        &mut self,
+        expr: &'a ParsedExpr,
+        fields: &'a [(Identifier, ParsedExpr)],
+        ann: &Span,
+    ) -> VmResult<()> {
+        self.eval(expr)?;
+        let (name, arity, mut values) = match self.peek(0) {
+            Value::Object(r) => match self.heap.get(r) {
+                Object::Constructor { name, arity, fields } => (*name, *arity, fields.clone()),
+                _ => return Err(type_mismatch("record", expr.get_span())),
+            },
+            _ => return Err(type_mismatch("record", expr.get_span())),
+        };
+        let mut indices = Vec::new();
+        for (field, value) in fields {
+            // The record may be built by another constructor of its type,
+            // which does not have this field.
+            match self.field_index(name, &field.name) {
+                Some(index) if index < values.len() => indices.push(index),
+                _ => return Err(RuntimeError::new(RuntimeErrorKind::MatchFailure, field.span)),
+            }
+            self.eval(value)?;
+        }
+        let updated = self.stack.split_off(self.stack.len() - fields.len());
+        for (index, value) in indices.into_iter().zip(updated) {
+            values[index] = value;
+        }
+        let v = self.alloc(
+            Object::Constructor {
+                name,
+                arity,
+                fields: values,
+            },
+            ann,
+        )?;
+        self.pop();
+        self.push(v);
+        Ok(())
+    }
+
+    /// The position of `field` among the fields of the record `constructor`.
+    fn field_index(&self, constructor: &str, field: &str) -> Option<usize> {
+        self.records
+            .get(constructor)?
+            .iter()
+            .position(|other| other.name == field)
+    }
+
+    /// Evaluates the body of a clause or an arm. Returns `false`, having
+    /// pushed nothing, when the body has guards and none of them holds. The
+    /// guards may be under the `let` of a `where` block.
+    fn eval_body(&mut self, body: &'a ParsedExpr) -> VmResult<bool> {
+        let guards = match body {
+            AnnExpr::Guards { guards, .. } => guards,
+            AnnExpr::Let { binds, ret, .. } => {
+                let depth = self.env.len();
+                let result = self.bind_locals(binds).and_then(|_| self.eval_body(ret));
+                self.env.truncate(depth);
+                return result;
+            }
+            _ => {
+                self.eval(body)?;
+                return Ok(true);
+            }
+        };
+        for (cond, expr) in guards {
+            self.eval(cond)?;
+            match self.pop() {
+                Value::Bool(true) => {
+                    self.eval(expr)?;
+                    return Ok(true);
+                }
+                Value::Bool(false) => {}
+                _ => return Err(type_mismatch("Bool", cond.get_span())),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Binds a `let` or `where` block. The clauses of a name make one
+    /// function, and the binds are evaluated after those they refer to,
+    /// functions that refer to each other sharing one environment.
+    fn bind_locals(&mut self, binds: &'a [Bind<Span>]) -> VmResult<()> {
+        let groups = group_binds(binds);
+        for mut component in dependency_order(&groups) {
+            component.sort_unstable();
+            let members: Vec<&(&'a str, Vec<&'a Bind<Span>>)> =
+                component.iter().map(|&i| &groups[i]).collect();
+            let functions = members.iter().all(|(_, clauses)| !clauses[0].args.is_empty());
+
+            if !functions {
+                let (name, clauses) = members[0];
+                let mut refs = Vec::new();
+                clause_references(clauses[0], &mut refs);
+                // A constant cannot be evaluated before itself.
+                if members.len() > 1 || refs.contains(name) {
+                    let (name, clauses) = members
+                        .iter()
+                        .find(|(_, clauses)| clauses[0].args.is_empty())
+                        .unwrap();
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::CyclicDefinition {
+                            name: name.to_string(),
+                        },
+                        clauses[0].span,
+                    ));
+                }
+                self.eval(&clauses[0].expr)?;
+                let v = self.pop();
+                self.env.push((name, v));
+                continue;
+            }
+
+            // Each sees all of them: their environment has a slot for every
+            // member from the start, filled once all are allocated, so that
+            // no closure changes size after its allocation was counted.
+            let depth = self.env.len();
+            let mut shared = self.env.clone();
+            shared.extend(members.iter().map(|(name, _)| (*name, Value::Nil)));
+            for (name, clauses) in members {
+                let function = Rc::new(Function {
+                    name,
+                    arity: clauses[0].args.len(),
+                    clauses: clauses
+                        .iter()
+                        .map(|bind| Clause {
+                            patterns: &bind.args,
+                            body: &bind.expr,
+                        })
+                        .collect(),
+                });
+                let closure = self.alloc(
+                    Object::Closure {
+                        function,
+                        env: shared.clone(),
+                        args: Vec::new(),
+                    },
+                    &clauses[0].span,
+                )?;
+                self.env.push((name, closure));
+            }
+            for &(_, closure) in &self.env[depth..] {
+                if let Value::Object(r) = closure {
+                    if let Object::Closure { env, .. } = self.heap.get_mut(r) {
+                        for (slot, member) in env[depth..].iter_mut().zip(&self.env[depth..]) {
+                            slot.1 = member.1;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the callee found below the `argc` topmost values of the stack
+    /// and replaces all of them with the result.
+    fn apply(&mut self, argc: usize, span: &'a Span) -> VmResult<()> {
+        let base = self.stack.len() - argc - 1;
+        let callee = match self.stack[base] {
+            Value::Object(r) => r,
+            _ => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::NotAFunction,
+                    *span,
+                ))
+            }
+        };
+
+        match self.heap.get(callee) {
+            Object::Constructor {
+                name,
+                arity,
+                fields,
+            } if fields.len() < *arity => {
+                let (name, arity) = (*name, *arity);
+                let used = argc.min(arity - fields.len());
+                let mut fields = fields.clone();
+                fields.extend_from_slice(&self.stack[base + 1..base + 1 + used]);
+                let v = self.alloc(
+                    Object::Constructor {
+                        name,
+                        arity,
+                        fields,
+                    },
+                    span,
+                )?;
+                self.finish_application(base, used, argc, v, span)
+            }
+            Object::Closure {
+                function,
+                env,
+                args,
+            } => {
+                let function = function.clone();
+                let missing = function.arity - args.len();
+                let mut args = args.clone();
+                let env = env.clone();
+
+                if argc < missing {
+                    args.extend_from_slice(&self.stack[base + 1..]);
+                    let v = self.alloc(
+                        Object::Closure {
+                            function,
+                            env,
+                            args,
+                        },
+                        span,
+                    )?;
+                    self.stack.truncate(base);
+                    self.push(v);
+                    return Ok(());
+                }
+
+                args.extend_from_slice(&self.stack[base + 1..base + 1 + missing]);
+                self.call(function, env, &args, span)?;
+                let v = self.pop();
+                self.finish_application(base, missing, argc, v, span)
+            }
+            Object::Native { native, args } => {
+                let native = *native;
+                let missing = native.arity - args.len();
+                let mut args = args.clone();
+
+                if argc < missing {
+                    args.extend_from_slice(&self.stack[base + 1..]);
+                    let v = self.alloc(Object::Native { native, args }, span)?;
+                    self.stack.truncate(base);
+                    self.push(v);
+                    return Ok(());
+                }
+
+                args.extend_from_slice(&self.stack[base + 1..base + 1 + missing]);
+                let v = (native.function)(self, &args, span)?;
+                self.finish_application(base, missing, argc, v, span)
+            }
+            Object::Selector { field } => {
+                let field = *field;
+                let record = self.stack[base + 1];
+                let v = match record {
+                    Value::Object(r) => match self.heap.get(r) {
+                        Object::Constructor { name, fields, .. } => self
+                            .field_index(name, field)
+                            .and_then(|index| fields.get(index).copied()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match v {
+                    Some(v) => self.finish_application(base, 1, argc, v, span),
+                    None => Err(RuntimeError::new(RuntimeErrorKind::MatchFailure, *span)),
+                }
+            }
+            _ => Err(RuntimeError::new(
+                RuntimeErrorKind::NotAFunction,
+                *span,
+            )),
+        }
+    }
+
+    /// Replaces the callee and its arguments with `result`, applying it to
+    /// the arguments that were not consumed yet.
+    fn finish_application(
+        &mut self,
+        base: usize,
+        used: usize,
+        argc: usize,
+        result: Value,
+        span: &'a Span,
+    ) -> VmResult<()> {
+        self.push(result);
+        if used < argc {
+            for index in base + 1 + used..base + 1 + argc {
+                let arg = self.stack[index];
+                self.push(arg);
+            }
+            self.apply(argc - used, span)?;
+        }
+        let v = self.pop();
+        self.stack.truncate(base);
+        self.push(v);
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        function: Rc<Function<'a>>,
+        env: Env<'a>,
+        args: &[Value],
+        span: &'a Span,
+    ) -> VmResult<()> {
+        self.check_depth(span)?;
+        let caller = std::mem::replace(&mut self.env, env);
+        self.frames.push(Frame { env: caller });
+
+        let depth = self.env.len();
+        let mut result = Err(RuntimeError::new(
+            RuntimeErrorKind::MatchFailure,
+            *span,
+        ));
+        for clause in &function.clauses {
+            if clause.patterns.len() == args.len()
+                && clause
+                    .patterns
+                    .iter()
+                    .zip(args)
+                    .all(|(pattern, arg)| self.match_pattern(pattern, *arg))
+            {
+                match self.eval_body(clause.body) {
+                    Ok(false) => {}
+                    done => {
+                        result = done.map(|_| ());
+                        break;
+                    }
+                }
+            }
+            self.env.truncate(depth);
+        }
+
+        self.env = self.frames.pop().unwrap().env;
+        result.map_err(|e| e.in_frame(function.name, Some(span)))
+    }
+
+    fn check_depth(&self, span: &Span) -> VmResult<()> {
+        let here = 0u8;
+        let exhausted = self
+            .stack_guard
+            .is_some_and(|guard| std::ptr::addr_of!(here).addr() < guard);
+
+        if exhausted || self.frames.len() >= self.limits.max_call_depth {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::StackOverflow {
+                    depth: self.frames.len(),
+                },
+                *span,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Matches `value` against `pattern`, pushing the bound variables on the
+    /// environment. On failure the environment may contain partial bindings.
+    fn match_pattern(&mut self, pattern: &'a Pattern, value: Value) -> bool {
+        match pattern {
+            Pattern::Wildcard(_) => true,
+            Pattern::Variable(id) => {
+                self.env.push((&id.name, value));
+                true
+            }
+            Pattern::Literal(lit) => match (&lit.lit, value) {
+                (LiteralKind::Integer(a), _) => self.as_bigint(value).is_some_and(|b| *a == b),
+                (LiteralKind::Float(a), Value::Float(b)) => *a == b,
+                (LiteralKind::Char(a), Value::Char(b)) => *a == b,
+                (LiteralKind::Bool(a), Value::Bool(b)) => Into::<bool>::into(*a) == b,
+                (LiteralKind::String(a), Value::Object(r)) => {
+                    matches!(self.heap.get(r), Object::String(b) if a == b)
+                }
+                _ => false,
+            },
+            Pattern::Id(id) => match value {
+                Value::Object(r) => matches!(
+                    self.heap.get(r),
+                    Object::Constructor { name, fields, .. } if *name == id.name && fields.is_empty()
+                ),
+                _ => false,
+            },
+            Pattern::App(id, patterns, _) => {
+                let fields = match value {
+                    Value::Object(r) => match self.heap.get(r) {
+                        Object::Constructor { name, fields, .. }
+                            if *name == id.name && fields.len() == patterns.len() =>
+                        {
+                            fields.clone()
+                        }
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                patterns
+                    .iter()
+                    .zip(fields)
+                    .all(|(pattern, field)| self.match_pattern(pattern, field))
+            }
+            Pattern::ListCons(head, tail, _) => {
+                let (h, t) = match value {
+                    Value::Object(r) => match self.heap.get(r) {
+                        Object::Cons(h, t) => (*h, *t),
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                self.match_pattern(head, h) && self.match_pattern(tail, t)
+            }
+            Pattern::Tuple(patterns, _) => {
+                let items = match value {
+                    Value::Object(r) => match self.heap.get(r) {
+                        Object::Tuple(items) if items.len() == patterns.len() => items.clone(),
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                patterns
+                    .iter()
+                    .zip(items)
+                    .all(|(pattern, item)| self.match_pattern(pattern, item))
+            }
+            Pattern::List(patterns, _) => {
+                let mut value = value;
+                for pattern in patterns {
+                    let (h, t) = match value {
+                        Value::Object(r) => match self.heap.get(r) {
+                            Object::Cons(h, t) => (*h, *t),
+                            _ => return false,
+                        },
+                        _ => return false,
+                    };
+                    if !self.match_pattern(pattern, h) {
+                        return false;
+                    }
+                    value = t;
+                }
+                matches!(value, Value::Nil)
+            }
+            Pattern::As(id, pattern, _) => {
+                self.env.push((&id.name, value));
+                self.match_pattern(pattern, value)
+            }
+            Pattern::Record(id, patterns, _) => {
+                let values = match value {
+                    Value::Object(r) => match self.heap.get(r) {
+                        Object::Constructor { name, fields, .. } if *name == id.name => fields.clone(),
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                patterns.iter().all(|(field, pattern)| {
+                    match self
+                        .field_index(&id.name, &field.name)
+                        .and_then(|index| values.get(index).copied())
+                    {
+                        Some(v) => self.match_pattern(pattern, v),
+                        None => false,
+                    }
+                })
+            }
+        }
+    }
+
+    fn binop(
+        &mut self,
+        op: BinOp,
+        lhs: &'a ParsedExpr,
+        rhs: &'a ParsedExpr,
+        span: &'a Span,
+    ) -> VmResult<()> {
+        match op {
+            BinOp::And | BinOp::Or => {
+                self.eval(lhs)?;
+                match (op, self.pop()) {
+                    (BinOp::And, Value::Bool(false)) => self.push(Value::Bool(false)),
+                    (BinOp::Or, Value::Bool(true)) => self.push(Value::Bool(true)),
+                    (_, Value::Bool(_)) => {
+                        self.eval(rhs)?;
+                        if !matches!(self.peek(0), Value::Bool(_)) {
+                            return Err(type_mismatch("Bool", rhs.get_span()));
+                        }
+                    }
+                    _ => return Err(type_mismatch("Bool", lhs.get_span())),
+                }
+            }
+            BinOp::ListCons => {
+                self.eval(lhs)?;
+                self.eval(rhs)?;
+                let (head, tail) = (self.peek(1), self.peek(0));
+                let v = self.alloc(Object::Cons(head, tail), span)?;
+                self.pop();
+                self.pop();
+                self.push(v);
+            }
+            BinOp::Eq | BinOp::Ineq => {
+                self.eval(lhs)?;
+                self.eval(rhs)?;
+                let (r, l) = (self.pop(), self.pop());
+                let equal = self.values_equal(l, r);
+                self.push(Value::Bool(equal == (op == BinOp::Eq)));
+            }
+            BinOp::LessThan | BinOp::GreaterThan | BinOp::LessThanOrEq | BinOp::GreaterThanOrEq => {
+                self.eval(lhs)?;
+                self.eval(rhs)?;
+                let (r, l) = (self.pop(), self.pop());
+                let ordering = self
+                    .compare(l, r)
+                    .ok_or_else(|| type_mismatch("comparable operands", span))?;
+                self.push(Value::Bool(match op {
+                    BinOp::LessThan => ordering == Ordering::Less,
+                    BinOp::GreaterThan => ordering == Ordering::Greater,
+                    BinOp::LessThanOrEq => ordering != Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                }));
+            }
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod | BinOp::Exp => {
+                self.eval(lhs)?;
+                self.eval(rhs)?;
+                let (r, l) = (self.pop(), self.pop());
+                let v = self.arithmetic(op, l, r, span)?;
+                self.push(v);
+            }
+        }
+        Ok(())
+    }
+
+    fn unop(&mut self, op: UnOp, v: Value, span: &Span) -> VmResult<Value> {
+        match (op, v) {
+            (UnOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+            (UnOp::Not, _) => Err(type_mismatch("Bool", span)),
+            (UnOp::Neg, Value::Float(f)) => Ok(Value::Float(-f)),
+            // `-i64::MIN` overflows and falls through to `BigInt`.
+            (UnOp::Neg, Value::Int(i)) if i != i64::MIN => Ok(Value::Int(-i)),
+            (UnOp::Neg, _) => match self.as_bigint(v) {
+                Some(i) => self.integer(-i, span),
+                None => Err(type_mismatch("a number", span)),
+            },
+        }
+    }
+
+    fn values_equal(&self, l: Value, r: Value) -> bool {
+        match (l, r) {
+            (Value::Object(a), Value::Object(b)) => match (self.heap.get(a), self.heap.get(b)) {
+                (Object::String(a), Object::String(b)) => a == b,
+                (Object::BigInt(a), Object::BigInt(b)) => a == b,
+                (Object::Cons(h1, t1), Object::Cons(h2, t2)) => {
+                    self.values_equal(*h1, *h2) && self.values_equal(*t1, *t2)
+                }
+                (Object::Tuple(a), Object::Tuple(b)) => {
+                    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| self.values_equal(*x, *y))
+                }
+                (
+                    Object::Constructor {
+                        name: n1,
+                        fields: f1,
+                        ..
+                    },
+                    Object::Constructor {
+                        name: n2,
+                        fields: f2,
+                        ..
+                    },
+                ) => {
+                    n1 == n2
+                        && f1.len() == f2.len()
+                        && f1.iter().zip(f2).all(|(x, y)| self.values_equal(*x, *y))
+                }
+                _ => a == b,
+            },
+            _ => l == r,
+        }
+    }
+
+    fn compare(&self, l: Value, r: Value) -> Option<Ordering> {
+        match (l, r) {
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(&b)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(&b),
+            (Value::Char(a), Value::Char(b)) => Some(a.cmp(&b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(&b)),
+            (Value::Object(a), Value::Object(b)) => match (self.heap.get(a), self.heap.get(b)) {
+                (Object::String(a), Object::String(b)) => Some(a.cmp(b)),
+                _ => Some(self.as_bigint(l)?.cmp(&self.as_bigint(r)?)),
+            },
+            _ => Some(self.as_bigint(l)?.cmp(&self.as_bigint(r)?)),
+        }
+    }
+
+    /// Wraps an integer, keeping it inline when it fits in an `i64`.
+    pub(crate) fn integer(&mut self, i: BigInt, span: &Span) -> VmResult<Value> {
+        match i64::try_from(&i) {
+            Ok(i) => Ok(Value::Int(i)),
+            Err(_) => self.alloc(Object::BigInt(i), span),
+        }
+    }
+
+    pub(crate) fn as_bigint(&self, value: Value) -> Option<BigInt> {
+        match value {
+            Value::Int(i) => Some(i.into()),
+            Value::Object(r) => match self.heap.get(r) {
+                Object::BigInt(i) => Some(i.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Integer operations run on `i64` and are promoted to `BigInt` when they
+    /// overflow. Integer division and modulo round towards negative infinity,
+    /// so `a == (a / b) * b + a % b` and the remainder has the sign of `b`.
+    /// Float operations follow IEEE 754, except that `%` is floored too.
+    fn arithmetic(&mut self, op: BinOp, l: Value, r: Value, span: &Span) -> VmResult<Value> {
+        match (l, r) {
+            (Value::Int(a), Value::Int(b)) => {
+                let result = match op {
+                    BinOp::Add => a.checked_add(b),
+                    BinOp::Sub => a.checked_sub(b),
+                    BinOp::Mul => a.checked_mul(b),
+                    // `i64::MIN / -1` overflows and falls through to `BigInt`.
+                    BinOp::Div if b != 0 && !(a == i64::MIN && b == -1) => Some(Integer::div_floor(&a, &b)),
+                    BinOp::Mod if b != 0 && !(a == i64::MIN && b == -1) => Some(Integer::mod_floor(&a, &b)),
+                    BinOp::Exp => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+                    _ => None,
+                };
+                if let Some(i) = result {
+                    return Ok(Value::Int(i));
+                }
+            }
+            (Value::Float(a), Value::Float(b)) => {
+                return Ok(Value::Float(match op {
+                    BinOp::Add => a + b,
+                    BinOp::Sub => a - b,
+                    BinOp::Mul => a * b,
+                    BinOp::Div => a / b,
+                    BinOp::Mod => a - b * (a / b).floor(),
+                    BinOp::Exp => a.powf(b),
+                    _ => unreachable!(),
+                }))
+            }
+            _ => {}
+        }
+
+        let (a, b) = match (self.as_bigint(l), self.as_bigint(r)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Err(type_mismatch("two numbers of the same type", span)),
+        };
+        let error = |kind| Err(RuntimeError::new(kind, *span));
+        let result = match op {
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div | BinOp::Mod if b.is_zero() => {
+                return error(RuntimeErrorKind::DivisionByZero)
+            }
+            BinOp::Div => a.div_floor(&b),
+            BinOp::Mod => a.mod_floor(&b),
+            BinOp::Exp if b.is_negative() => return error(RuntimeErrorKind::NegativeExponent),
+            BinOp::Exp => match b.to_u32() {
+                Some(b) => a.pow(b),
+                None => return error(RuntimeErrorKind::ExponentTooLarge),
+            },
+            _ => unreachable!(),
+        };
+        self.integer(result, span)
+    }
+
+    /// Whether `value` goes between parentheses when it is the field of a
+    /// constructor: a constructor with fields, or a negative number.
+    pub fn needs_parens(&self, value: Value) -> bool {
+        match value {
+            Value::Int(i) => i < 0,
+            Value::Float(f) => f.is_sign_negative(),
+            Value::Object(r) => match self.heap.get(r) {
+                Object::BigInt(i) => i.is_negative(),
+                Object::Constructor { fields, .. } => !fields.is_empty(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Renders a value the way it would be written in Risk source.
+    pub fn display(&self, value: Value) -> String {
+        match value {
+            Value::Int(i) => i.to_string(),
+            Value::Float(f) => format_float(f),
+            Value::Bool(true) => "True".to_string(),
+            Value::Bool(false) => "False".to_string(),
+            Value::Char(c) => format!("{:?}", c),
+            Value::Nil => "[]".to_string(),
+            Value::Object(r) => match self.heap.get(r) {
+                Object::String(s) => format!("{:?}", s),
+                Object::BigInt(i) => i.to_string(),
+                Object::Cons(..) => {
+                    let mut items = Vec::new();
+                    let mut current = value;
+                    while let Value::Object(r) = current {
+                        match self.heap.get(r) {
+                            Object::Cons(head, tail) => {
+                                items.push(self.display(*head));
+                                current = *tail;
+                            }
+                            _ => break,
+                        }
+                    }
+                    format!("[{}]", items.join(", "))
+                }
+                Object::Tuple(items) => {
+                    let items: Vec<String> = items.iter().map(|v| self.display(*v)).collect();
+                    format!("({})", items.join(", "))
+                }
+                Object::Constructor { name, fields, .. }
+                    if self.records.contains_key(name) && !fields.is_empty() =>
+                {
+                    let fields: Vec<String> = self.records[name]
+                        .iter()
+                        .zip(fields)
+                        .map(|(field, value)| format!("{} = {}", unqualified(&field.name), self.display(*value)))
+                        .collect();
+                    format!("{} {{ {} }}", unqualified(name), fields.join(", "))
+                }
+                Object::Constructor { name, fields, .. } => {
+                    let mut out = unqualified(name).to_string();
+                    for field in fields {
+                        let shown = self.display(*field);
+                        if self.needs_parens(*field) {
+                            out.push_str(&format!(" ({})", shown));
+                        } else {
+                            out.push_str(&format!(" {}", shown));
+                        }
+                    }
+                    out
+                }
+                Object::Closure { function, .. } => format!("<function {}>", function.name),
+                Object::Native { native, .. } => format!("<function {}>", native.name),
+                Object::Selector { field } => format!("<function {}>", field),
+            },
+        }
+    }
+}
+
+pub(crate) fn type_mismatch(expected: &str, span: &Span) -> RuntimeError {
+    RuntimeError::new(
+        RuntimeErrorKind::TypeMismatch {
+            expected: expected.to_string(),
+        },
+        *span,
+    )
+}
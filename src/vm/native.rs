@@ -0,0 +1,345 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+use crate::ast::{BinOp, Span, UnOp};
+use crate::vm::error::{RuntimeError, RuntimeErrorKind, Target};
+use crate::vm::heap::Object;
+use crate::vm::value::Value;
+use crate::vm::{type_mismatch, Vm, VmResult};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+/// A function implemented in Rust. `signature` is written in Risk syntax and
+/// is what the type checker sees. A native without arguments is a constant.
+#[derive(Debug)]
+pub struct Native {
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub arity: usize,
+    pub function: fn(&mut Vm, &[Value], &Span) -> VmResult<Value>,
+}
+
+pub const NATIVES: &[Native] = &[
+    Native {
+        name: "otherwise",
+        signature: "Bool",
+        arity: 0,
+        function: |_, _, _| Ok(Value::Bool(true)),
+    },
+    Native {
+        name: "toFloat",
+        signature: "Integer -> Float",
+        arity: 1,
+        function: to_float,
+    },
+    Native {
+        name: "truncate",
+        signature: "Float -> Integer",
+        arity: 1,
+        function: |vm, args, span| float_to_integer(vm, args[0], f64::trunc, span),
+    },
+    Native {
+        name: "round",
+        signature: "Float -> Integer",
+        arity: 1,
+        // Halfway values go to the even neighbour, as in Haskell.
+        function: |vm, args, span| float_to_integer(vm, args[0], f64::round_ties_even, span),
+    },
+    Native {
+        name: "floor",
+        signature: "Float -> Integer",
+        arity: 1,
+        function: |vm, args, span| float_to_integer(vm, args[0], f64::floor, span),
+    },
+    Native {
+        name: "ceiling",
+        signature: "Float -> Integer",
+        arity: 1,
+        function: |vm, args, span| float_to_integer(vm, args[0], f64::ceil, span),
+    },
+    Native {
+        name: "ord",
+        signature: "Char -> Integer",
+        arity: 1,
+        function: ord,
+    },
+    Native {
+        name: "chr",
+        signature: "Integer -> Char",
+        arity: 1,
+        function: chr,
+    },
+    Native {
+        name: "unpack",
+        signature: "String -> [Char]",
+        arity: 1,
+        function: unpack,
+    },
+    Native {
+        name: "pack",
+        signature: "[Char] -> String",
+        arity: 1,
+        function: pack,
+    },
+    // The Prelude gives these their final form: `++` for strings, and
+    // `readInteger` and `readFloat` returning a `Maybe`, which is declared
+    // there.
+    Native {
+        name: "primAppend",
+        signature: "String -> String -> String",
+        arity: 2,
+        function: append,
+    },
+    Native {
+        name: "primReadInteger",
+        signature: "String -> (Bool, Integer)",
+        arity: 1,
+        function: read_integer,
+    },
+    Native {
+        name: "primReadFloat",
+        signature: "String -> (Bool, Float)",
+        arity: 1,
+        function: read_float,
+    },
+];
+
+/// The operations the dictionaries of the builtin classes are made of, see
+/// `compiler::elaborate`. Their names cannot be written in Risk, so they are
+/// invisible to programs and need no signature.
+pub const PRIMITIVES: &[Native] = &[
+    Native {
+        name: "$==",
+        signature: "",
+        arity: 2,
+        function: |vm, args, _| Ok(Value::Bool(vm.values_equal(args[0], args[1]))),
+    },
+    Native {
+        name: "$<",
+        signature: "",
+        arity: 2,
+        function: |vm, args, _| Ok(Value::Bool(vm.compare(args[0], args[1]).is_some_and(|o| o.is_lt()))),
+    },
+    Native {
+        name: "$+",
+        signature: "",
+        arity: 2,
+        function: |vm, args, span| vm.arithmetic(BinOp::Add, args[0], args[1], span),
+    },
+    Native {
+        name: "$-",
+        signature: "",
+        arity: 2,
+        function: |vm, args, span| vm.arithmetic(BinOp::Sub, args[0], args[1], span),
+    },
+    Native {
+        name: "$*",
+        signature: "",
+        arity: 2,
+        function: |vm, args, span| vm.arithmetic(BinOp::Mul, args[0], args[1], span),
+    },
+    Native {
+        name: "$/",
+        signature: "",
+        arity: 2,
+        function: |vm, args, span| vm.arithmetic(BinOp::Div, args[0], args[1], span),
+    },
+    Native {
+        name: "$%",
+        signature: "",
+        arity: 2,
+        function: |vm, args, span| vm.arithmetic(BinOp::Mod, args[0], args[1], span),
+    },
+    Native {
+        name: "$^",
+        signature: "",
+        arity: 2,
+        function: |vm, args, span| vm.arithmetic(BinOp::Exp, args[0], args[1], span),
+    },
+    Native {
+        name: "$negate",
+        signature: "",
+        arity: 1,
+        function: |vm, args, span| vm.unop(UnOp::Neg, args[0], span),
+    },
+    Native {
+        name: "$fromInteger",
+        signature: "",
+        arity: 1,
+        function: |_, args, _| Ok(args[0]),
+    },
+    // The only operation whose result is not made of its arguments, so it
+    // differs between the primitive instances.
+    Native {
+        name: "$Num Float.fromInteger",
+        signature: "",
+        arity: 1,
+        function: to_float,
+    },
+    Native {
+        name: "$show",
+        signature: "",
+        arity: 1,
+        function: |vm, args, span| {
+            let shown = vm.display(args[0]);
+            vm.alloc(Object::String(shown), span)
+        },
+    },
+    Native {
+        name: "$++",
+        signature: "",
+        arity: 2,
+        function: append,
+    },
+    Native {
+        name: "$parens",
+        signature: "",
+        arity: 2,
+        function: parens,
+    },
+    Native {
+        name: "$missing",
+        signature: "",
+        arity: 1,
+        function: missing,
+    },
+];
+
+fn string<'v>(vm: &'v Vm, value: Value, span: &Span) -> VmResult<&'v str> {
+    match value {
+        Value::Object(r) => match vm.heap.get(r) {
+            Object::String(s) => Ok(s),
+            _ => Err(type_mismatch("String", span)),
+        },
+        _ => Err(type_mismatch("String", span)),
+    }
+}
+
+fn append(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    let joined = format!("{}{}", string(vm, args[0], span)?, string(vm, args[1], span)?);
+    vm.alloc(Object::String(joined), span)
+}
+
+/// Shows a field of a constructor, `shown` being what its instance of
+/// `Show` gives: in parentheses when it is a constructor with fields or a
+/// negative number, as `Vm::display` does.
+fn parens(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    if !vm.needs_parens(args[0]) {
+        return Ok(args[1]);
+    }
+    let shown = format!("({})", string(vm, args[1], span)?);
+    vm.alloc(Object::String(shown), span)
+}
+
+fn missing(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    Err(RuntimeError::new(
+        RuntimeErrorKind::MissingMethod {
+            method: string(vm, args[0], span)?.to_string(),
+        },
+        *span,
+    ))
+}
+
+fn ord(_: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    match args[0] {
+        Value::Char(c) => Ok(Value::Int(c as i64)),
+        _ => Err(type_mismatch("Char", span)),
+    }
+}
+
+fn chr(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    let Some(i) = vm.as_bigint(args[0]) else {
+        return Err(type_mismatch("Integer", span));
+    };
+    match i.to_u32().and_then(char::from_u32) {
+        Some(c) => Ok(Value::Char(c)),
+        None => Err(RuntimeError::new(
+            RuntimeErrorKind::InvalidConversion {
+                value: i.to_string(),
+                target: Target::Char,
+            },
+            *span,
+        )),
+    }
+}
+
+fn unpack(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    let chars: Vec<char> = string(vm, args[0], span)?.chars().collect();
+    let mut list = Value::Nil;
+    for c in chars.into_iter().rev() {
+        list = vm.alloc(Object::Cons(Value::Char(c), list), span)?;
+    }
+    Ok(list)
+}
+
+fn pack(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    let mut packed = String::new();
+    let mut list = args[0];
+    while let Value::Object(r) = list {
+        match vm.heap.get(r) {
+            Object::Cons(Value::Char(c), tail) => {
+                packed.push(*c);
+                list = *tail;
+            }
+            _ => return Err(type_mismatch("[Char]", span)),
+        }
+    }
+    if list != Value::Nil {
+        return Err(type_mismatch("[Char]", span));
+    }
+    vm.alloc(Object::String(packed), span)
+}
+
+/// Reads a whole string as an integer, with an optional `-`. Whether it
+/// could comes first, so that no `Maybe` is needed here.
+fn read_integer(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    let read = string(vm, args[0], span)?.parse::<BigInt>().ok();
+    let ok = read.is_some();
+    let i = vm.integer(read.unwrap_or_default(), span)?;
+    vm.alloc(Object::Tuple(vec![Value::Bool(ok), i]), span)
+}
+
+fn read_float(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    let read = string(vm, args[0], span)?.parse::<f64>().ok();
+    let ok = read.is_some();
+    vm.alloc(Object::Tuple(vec![Value::Bool(ok), Value::Float(read.unwrap_or(0.0))]), span)
+}
+
+fn to_float(vm: &mut Vm, args: &[Value], span: &Span) -> VmResult<Value> {
+    match vm.as_bigint(args[0]) {
+        Some(i) => Ok(Value::Float(i.to_f64().unwrap_or(f64::NAN))),
+        None => Err(type_mismatch("Integer", span)),
+    }
+}
+
+fn float_to_integer(vm: &mut Vm, value: Value, f: fn(f64) -> f64, span: &Span) -> VmResult<Value> {
+    let x = match value {
+        Value::Float(x) => x,
+        _ => return Err(type_mismatch("Float", span)),
+    };
+    match BigInt::from_f64(f(x)) {
+        Some(i) => vm.integer(i, span),
+        None => Err(RuntimeError::new(
+            RuntimeErrorKind::InvalidConversion {
+                value: crate::vm::value::format_float(x),
+                target: Target::Integer,
+            },
+            *span,
+        )),
+    }
+}
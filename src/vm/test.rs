@@ -0,0 +1,564 @@
+use crate::ast::Program;
+use crate::compiler::elaborate::elaborate;
+use crate::semantics::loader::{Loader, PRELUDE};
+use crate::semantics::tc::TypeChecker;
+use crate::semantics::{analyze, module, AnalysisOutput};
+use crate::vm::error::{RuntimeError, RuntimeErrorKind, Target};
+use crate::vm::heap::{GcStats, Heap, HeapConfig, Object};
+use crate::source::{FileId, SourceMap};
+use crate::vm::value::Value;
+use crate::vm::{Limits, Vm};
+
+#[cfg(test)]
+fn run_with(content: &str, config: HeapConfig) -> (String, GcStats) {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut vm = Vm::new(&program, config, Limits::default());
+    let value = vm.run("main").unwrap().unwrap();
+    (vm.display(value), vm.heap.stats.clone())
+}
+
+#[cfg(test)]
+fn run(content: &str) -> String {
+    run_with(content, HeapConfig::default()).0
+}
+
+#[cfg(test)]
+fn run_limited(content: &str, limits: Limits) -> RuntimeError {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut vm = Vm::new(&program, HeapConfig::default(), limits);
+    vm.run("main").unwrap().unwrap_err()
+}
+
+#[cfg(test)]
+fn run_error(content: &str) -> RuntimeError {
+    run_limited(content, Limits::default())
+}
+
+/// Type checks `content` and passes dictionaries for its classes before
+/// running it, as the driver does.
+#[cfg(test)]
+fn run_elaborated(content: &str) -> Result<String, RuntimeErrorKind> {
+    let mut parser = crate::parser::Parser::new(FileId::default(), content);
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let mut tc = TypeChecker::new();
+    tc.check_program(&program);
+    assert!(tc.errors.is_empty(), "{:?}", tc.errors);
+    let program = elaborate(program, &tc.evidence);
+    let mut vm = Vm::new(&program, HeapConfig::default(), Limits::default());
+    match vm.run("main").unwrap() {
+        Ok(value) => Ok(vm.display(value)),
+        Err(error) => Err(error.kind),
+    }
+}
+
+/// Runs `content` as the driver does, with the Prelude. Nothing in the
+/// Prelude may be reported.
+#[cfg(test)]
+fn run_prelude(content: &str) -> String {
+    let mut sources = SourceMap::new();
+    let mut loader = Loader::new(std::env::temp_dir(), &mut sources);
+    loader.load_main("main.rk", content.to_string());
+    let Loader {
+        mut modules,
+        syntax_errors,
+        errors,
+        ..
+    } = loader;
+    assert!(syntax_errors.is_empty(), "{:?}", syntax_errors);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(modules[0].name, PRELUDE);
+
+    let mut ao = AnalysisOutput::new();
+    module::resolve(&mut ao, &mut modules);
+    let program = Program::new(modules.into_iter().flat_map(|module| module.program.statements).collect());
+    analyze(&mut ao, program.clone());
+    assert!(ao.errors.is_empty(), "{:?}", ao.errors);
+    // The file of the program is the first one.
+    assert!(
+        ao.warnings.iter().all(|w| w.span.file == FileId::default()),
+        "{:?}",
+        ao.warnings
+    );
+
+    let mut tc = TypeChecker::new();
+    tc.check_program(&program);
+    assert!(tc.errors.is_empty(), "{:?}", tc.errors);
+    let program = elaborate(program, &tc.evidence);
+    let mut vm = Vm::new(&program, HeapConfig::default(), Limits::default());
+    let value = vm.run("main").unwrap().unwrap();
+    vm.display(value)
+}
+
+#[cfg(test)]
+fn run_err(content: &str) -> RuntimeErrorKind {
+    run_error(content).kind
+}
+
+#[test]
+fn test_arithmetic() {
+    assert_eq!(run("main = 1 + 2 * 3"), "7");
+    assert_eq!(run("main = 7.5 - 2.5"), "5.0");
+}
+
+#[test]
+fn test_functions() {
+    assert_eq!(run("double x = x * 2\nmain = double 21"), "42");
+    assert_eq!(
+        run("fact 0 = 1\nfact n = n * fact (n - 1)\nmain = fact 10"),
+        "3628800"
+    );
+}
+
+#[test]
+fn test_layout() {
+    let program = "
+main = let fact n = match n with
+                      0 -> 1
+                      _ -> n * fact (n - 1)
+       in fact 5
+";
+    assert_eq!(run(program), "120");
+}
+
+#[test]
+fn test_operators() {
+    assert_eq!(
+        run("infixl 1 |>\nx |> f = f x\ndouble x = x * 2\nmain = 3 |> double |> double"),
+        "12"
+    );
+    assert_eq!(run("minus x y = x - y\nmain = 7 `minus` 2"), "5");
+    assert_eq!(run("apply f x y = f x y\nmain = apply (+) 1 2"), "3");
+    assert_eq!(
+        run("(<+>) x y = x * 10 + y\napply f x y = f x y\nmain = apply (<+>) 1 2"),
+        "12"
+    );
+}
+
+#[test]
+fn test_prefix_operators() {
+    assert_eq!(run("f x = -x\nmain = (f 3, -2.5, -2 ^ 2, - -1)"), "(-3, -2.5, -4, 1)");
+    assert_eq!(run("main = -(9223372036854775807 + 1)"), "-9223372036854775808");
+    assert_eq!(run("n = 0 - 9223372036854775807 - 1\nmain = -n"), "9223372036854775808");
+    assert_eq!(run("main = (not True, not (1 > 2))"), "(False, True)");
+}
+
+#[test]
+fn test_application() {
+    assert_eq!(run("main = (\\x y -> x - y) 5 3"), "2");
+    assert_eq!(run("compose f g x = f (g x)\ninc x = x + 1\nmain = (compose inc inc) 1"), "3");
+    assert_eq!(
+        run("pick b = match b with\n    True -> \\x -> x * 2\n    False -> \\x -> x\nmain = (pick True) 21"),
+        "42"
+    );
+    // Partial application and currying work the same for every head.
+    assert_eq!(
+        run("add x y z = x + y + z\nf = add 1\nmain = ((f 2) 3, (add 1 2) 3, f 2 3)"),
+        "(6, 6, 6)"
+    );
+    assert_eq!(run("type B = (B Integer)\nwrap = B\nmain = [wrap 2]"), "[B 2]");
+}
+
+#[test]
+fn test_data() {
+    assert_eq!(run("main = [1, 2, 3]"), "[1, 2, 3]");
+    assert_eq!(run("main = (1, 'a', \"b\")"), "(1, 'a', \"b\")");
+    assert_eq!(run("type T = (A Integer) (B)\nmain = (A 3, B)"), "(A 3, B)");
+}
+
+#[test]
+fn test_gc_reclaims_garbage() {
+    let (value, stats) = run_with(
+        "loop n = if n == 0 then \"done\" else if [\"a\", \"b\"] == [\"a\", \"b\"] then loop (n - 1) else \"no\"\nmain = loop 50",
+        HeapConfig { heap_size: 1024 },
+    );
+    assert_eq!(value, "\"done\"");
+    assert!(stats.collections > 0);
+    assert!(stats.objects_freed > 0);
+}
+
+#[test]
+fn test_gc_keeps_reachable_values() {
+    let (value, stats) = run_with(
+        "keep = [1, 2, 3]\nloop n = if n == 0 then keep else if (keep, \"garbage\") == (keep, \"garbage\") then loop (n - 1) else keep\nmain = loop 50",
+        HeapConfig { heap_size: 512 },
+    );
+    assert_eq!(value, "[1, 2, 3]");
+    assert!(stats.objects_freed > 0);
+}
+
+#[test]
+fn test_gc_accounting() {
+    // Freeing an object gives back what its allocation counted, even when
+    // it has grown since.
+    let mut heap = Heap::new(HeapConfig::default());
+    let r = heap.alloc(Object::Tuple(vec![Value::Nil]));
+    if let Object::Tuple(items) = heap.get_mut(r) {
+        items.extend([Value::Nil; 64]);
+    }
+    heap.alloc(Object::String("garbage".to_string()));
+    heap.collect(std::iter::empty());
+    assert_eq!(heap.bytes_allocated(), 0);
+}
+
+#[test]
+fn test_gc_local_functions() {
+    // Every call allocates the closures of a `where` or a `let` block.
+    let program = "f n = go n\n  where go 0 = 0\n        go k = 1 + go (k - 1)\ng n = let h 0 = 0\n          h k = h (k - 1) in h n\nloop i acc = if i == 0 then acc else loop (i - 1) (acc + f 3 + g 2)\nmain = loop 5000 0";
+    let mut parser = crate::parser::Parser::new(FileId::default(), program);
+    let (program, errors) = parser.parse();
+    assert!(errors.is_empty(), "{:?}", errors);
+    let limits = Limits {
+        max_heap: Some(1024 * 1024),
+        ..Limits::default()
+    };
+    let run = crate::run_with_limits(&program, HeapConfig { heap_size: 20000 }, limits).unwrap();
+    assert_eq!(run.result.unwrap().unwrap(), "15000");
+    assert!(run.stats.collections > 0);
+}
+
+#[test]
+fn test_run_source() {
+    let run_source = |text: &str| {
+        crate::run_source("main.rk", text.to_string(), std::env::temp_dir(), HeapConfig::default(), Limits::default())
+    };
+    let (run, diagnostics) = run_source("double x = x * 2\nunused = 1\nmain = map double [1, 2]").unwrap();
+    assert_eq!(run.result.unwrap().unwrap(), "[2, 4]");
+    assert!(!diagnostics.failed());
+    assert_eq!(diagnostics.warnings.len(), 1, "{:?}", diagnostics.warnings);
+
+    let diagnostics = run_source("main = y").unwrap_err();
+    assert_eq!(diagnostics.errors.len(), 1, "{:?}", diagnostics.errors);
+    let diagnostics = run_source("main = 1 + 'a'").unwrap_err();
+    assert!(!diagnostics.type_errors.is_empty());
+    let diagnostics = run_source("main = (1 +").unwrap_err();
+    assert!(!diagnostics.syntax_errors.is_empty());
+}
+
+#[test]
+fn test_big_integers() {
+    assert_eq!(
+        run("main = 9223372036854775807 + 1"),
+        "9223372036854775808"
+    );
+    assert_eq!(run("main = 2 ^ 100"), "1267650600228229401496703205376");
+    assert_eq!(
+        run("main = 123456789012345678901234567890 % 1000"),
+        "890"
+    );
+    assert_eq!(run("main = (2 ^ 64) / (2 ^ 62)"), "4");
+    assert_eq!(run("main = 2 ^ 64 > 1"), "True");
+    assert_eq!(
+        run("fact 0 = 1\nfact n = n * fact (n - 1)\nmain = fact 25"),
+        "15511210043330985984000000"
+    );
+}
+
+#[test]
+fn test_integer_division() {
+    assert_eq!(run("main = 7 / 2"), "3");
+    assert_eq!(run("main = (0 - 7) / 2"), "-4");
+    assert_eq!(run("main = (0 - 7) % 2"), "1");
+    assert_eq!(run("main = 7 % (0 - 2)"), "-1");
+    assert_eq!(run("main = (0 - 9223372036854775807 - 1) / (0 - 1)"), "9223372036854775808");
+    assert_eq!(run_err("main = 1 / 0"), RuntimeErrorKind::DivisionByZero);
+    assert_eq!(run_err("main = 1 % 0"), RuntimeErrorKind::DivisionByZero);
+    assert_eq!(run_err("main = 2 ^ (0 - 1)"), RuntimeErrorKind::NegativeExponent);
+    assert_eq!(run_err("main = 2 ^ 99999999999"), RuntimeErrorKind::ExponentTooLarge);
+}
+
+#[test]
+fn test_floats() {
+    assert_eq!(run("main = 1.0 / 0.0"), "Infinity");
+    assert_eq!(run("main = 0.0 / 0.0"), "NaN");
+    assert_eq!(run("main = 0.0 - 5.5 % 2.0"), "-1.5");
+    assert_eq!(run("main = toFloat 3"), "3.0");
+    assert_eq!(run("main = (round 2.5, round 3.5, floor (0.0 - 1.5), ceiling 1.2)"), "(2, 4, -2, 2)");
+    assert_eq!(
+        run_err("main = truncate (1.0 / 0.0)"),
+        RuntimeErrorKind::InvalidConversion {
+            value: "Infinity".to_string(),
+            target: Target::Integer,
+        }
+    );
+}
+
+#[test]
+fn test_stack_trace() {
+    let content = "f x = x / 0\ng x = f x\nmain = g 3";
+    let error = run_error(content);
+    assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+    let trace: Vec<(&str, Option<&str>)> = error
+        .trace
+        .iter()
+        .map(|frame| {
+            let call_site = frame.call_site.map(|s| &content[s.start..s.end]);
+            (frame.name.as_str(), call_site)
+        })
+        .collect();
+    assert_eq!(trace, [("f", Some("f x")), ("g", Some("g 3")), ("main", None)]);
+}
+
+#[test]
+fn test_stack_overflow() {
+    let error = std::thread::Builder::new()
+        .stack_size(Limits::default().stack_size())
+        .spawn(|| run_error("loop n = 1 + loop (n + 1)\nmain = loop 0"))
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(matches!(error.kind, RuntimeErrorKind::StackOverflow { .. }));
+    assert_eq!(error.trace[0].name, "loop");
+    assert_eq!(error.trace[0].repeated, crate::vm::DEFAULT_MAX_CALL_DEPTH - 2);
+}
+
+#[test]
+fn test_stack_guard() {
+    // On a stack too small for the depth limit, the program still stops
+    // with an error instead of overflowing it.
+    let program = "build n = if n == 0 then [] else n : build (n - 1)\nmain = build 40000";
+    let mut parser = crate::parser::Parser::new(FileId::default(), program);
+    let (program, _) = parser.parse();
+    let limits = Limits {
+        max_call_depth: crate::vm::MAX_CALL_DEPTH,
+        ..Limits::default()
+    };
+    let error = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(4 * 1024 * 1024)
+            .spawn_scoped(scope, || {
+                let mut vm = Vm::new(&program, HeapConfig::default(), limits);
+                vm.guard_stack(4 * 1024 * 1024);
+                vm.run("main").unwrap().unwrap_err()
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    });
+    assert!(matches!(error.kind, RuntimeErrorKind::StackOverflow { depth } if depth < 40000));
+
+    // `run_with_limits` gives the evaluator the stack it needs.
+    let run = crate::run_with_limits(&program, HeapConfig::default(), Limits::default()).unwrap();
+    assert!(matches!(
+        run.result,
+        Some(Err(RuntimeError {
+            kind: RuntimeErrorKind::StackOverflow { depth: crate::vm::DEFAULT_MAX_CALL_DEPTH },
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn test_limits() {
+    let program = "loop n = if n == 0 then 0 else 1 + loop (n - 1)\nmain = loop 40";
+    let limits = Limits {
+        max_call_depth: 20,
+        ..Limits::default()
+    };
+    assert_eq!(
+        run_limited(program, limits).kind,
+        RuntimeErrorKind::StackOverflow { depth: 20 }
+    );
+
+    let limits = Limits {
+        fuel: Some(100),
+        ..Limits::default()
+    };
+    assert_eq!(
+        run_limited(program, limits).kind,
+        RuntimeErrorKind::OutOfFuel { fuel: 100 }
+    );
+
+    let program = "f n = let xs = [n, n, n, n]; in if n == 0 then xs else if (f (n - 1)) == xs then xs else xs\nmain = f 40";
+    let limits = Limits {
+        max_heap: Some(1024),
+        ..Limits::default()
+    };
+    assert_eq!(
+        run_limited(program, limits).kind,
+        RuntimeErrorKind::HeapExhausted { limit: 1024 }
+    );
+}
+
+#[test]
+fn test_nested_patterns() {
+    assert_eq!(run("swap (a, b) = (b, a)\nmain = swap (1, 'a')"), "('a', 1)");
+    assert_eq!(
+        run("type M a = (Just a) (Nothing)\nf (Just (x:_)) = x\nf _ = 0\nmain = (f (Just [7, 8]), f (Just []), f Nothing)"),
+        "(7, 0, 0)"
+    );
+    assert_eq!(
+        run("size [] = 0\nsize [_] = 1\nsize [_, _] = 2\nsize _ = 3\nmain = (size [], size [1], size [1, 2], size [1, 2, 3])"),
+        "(0, 1, 2, 3)"
+    );
+    assert_eq!(run("dup all@(x:_) = x : all\nmain = dup [1, 2]"), "[1, 1, 2]");
+    assert_eq!(run("isA 'a' = True\nisA _ = False\nmain = (isA 'a', isA 'b')"), "(True, False)");
+    assert_eq!(run_err("f (0, x) = x\nmain = f (1, 2)"), RuntimeErrorKind::MatchFailure);
+}
+
+#[test]
+fn test_guards() {
+    assert_eq!(
+        run("sign x\n  | x < 0 = -1\n  | x > 0 = 1\nsign _ = 0\nmain = (sign (-5), sign 0, sign 3)"),
+        "(-1, 0, 1)"
+    );
+    assert_eq!(
+        run("f xs = match xs with\n    (x:_) | x < 0 -> \"negative\"\n          | x > 9 -> \"big\"\n    _ -> \"other\"\nmain = (f [-1], f [10], f [5], f [])"),
+        "(\"negative\", \"big\", \"other\", \"other\")"
+    );
+    assert_eq!(
+        run("main = let half n | n % 2 == 0 = n / 2\n                   | otherwise = n\n       in (half 4, half 5)"),
+        "(2, 5)"
+    );
+    // A local function falls through to its next clause too.
+    assert_eq!(
+        run("main = (f 5, f (-5))\n  where f x | x > 0 = \"pos\"\n        f _ = \"other\""),
+        "(\"pos\", \"other\")"
+    );
+    // When no guard holds and there is no clause left, matching fails.
+    assert_eq!(run_err("f x | x > 0 = x\nmain = f 0"), RuntimeErrorKind::MatchFailure);
+    assert_eq!(run_err("a | False = 1\nmain = a"), RuntimeErrorKind::MatchFailure);
+}
+
+#[test]
+fn test_where() {
+    assert_eq!(run("area r = pi * r * r\n  where pi = 3.0\nmain = area 2.0"), "12.0");
+    // When the guards under a `where` all fail, the next clause is tried.
+    assert_eq!(
+        run("size n\n  | n < small = \"small\"\n  where small = 10\nsize _ = \"large\"\nmain = (size 5, size 50)"),
+        "(\"small\", \"large\")"
+    );
+    assert_eq!(
+        run("main = g 4\n  where g x = h x + 1\n          where h y = y * 10"),
+        "41"
+    );
+    // The clauses of a local function make one function, and a bind can
+    // refer to the ones below it.
+    assert_eq!(
+        run("main = f 5\n  where f x = go x\n        go 0 = 1\n        go n = n * go (n - 1)"),
+        "120"
+    );
+    assert_eq!(run("g x = a\n  where a = b + 1\n        b = x * 2\nmain = g 1"), "3");
+    assert_eq!(
+        run("main = (even 4, odd 4)\n  where even 0 = True\n        even n = odd (n - 1)\n        odd 0 = False\n        odd n = even (n - 1)"),
+        "(True, False)"
+    );
+    assert_eq!(
+        run_err("h x = a\n  where a = b + x\n        b = a\nmain = h 1"),
+        RuntimeErrorKind::CyclicDefinition { name: "a".to_string() }
+    );
+}
+
+#[test]
+fn test_records() {
+    let shape = "type Shape = (Rect { width :: Float, height :: Float }) (Circle { radius :: Float })\n";
+    assert_eq!(
+        run(&format!("{shape}main = Rect {{ height = 2.0, width = 1.0 }}")),
+        "Rect { width = 1.0, height = 2.0 }"
+    );
+    assert_eq!(
+        run(&format!(
+            "{shape}area (Rect {{ width = w, height = h }}) = w * h\narea (Circle {{ radius = r }}) = 3.0 * r * r\nmain = (area (Rect {{ width = 2.0, height = 3.0 }}), area (Circle {{ radius = 1.0 }}))"
+        )),
+        "(6.0, 3.0)"
+    );
+    assert_eq!(
+        run(&format!("{shape}main = let r = Rect {{ width = 1.0, height = 2.0 }}\n       in (r {{ height = 5.0 }}, height r, map width [r])\nmap f xs = match xs with\n  [] -> []\n  (x:rest) -> f x : map f rest")),
+        "(Rect { width = 1.0, height = 5.0 }, 2.0, [1.0])"
+    );
+    // A selector or an update of a field the constructor does not have fails.
+    assert_eq!(
+        run_err(&format!("{shape}main = radius (Rect {{ width = 1.0, height = 2.0 }})")),
+        RuntimeErrorKind::MatchFailure
+    );
+    assert_eq!(
+        run_err(&format!("{shape}main = (Circle {{ radius = 1.0 }}) {{ width = 2.0 }}")),
+        RuntimeErrorKind::MatchFailure
+    );
+}
+
+#[test]
+fn test_classes() {
+    let shape = "class Shape a where\n  area :: a -> Float\n  describe :: a -> String\n  describe x = \"shape\"\ntype Sq = (Sq Float)\ninstance Shape Sq where\n  area (Sq s) = s * s\n";
+    assert_eq!(
+        run_elaborated(&format!("{shape}total x y = area x + area y\nmain = (total (Sq 1.0) (Sq 2.0), describe (Sq 1.0))")),
+        Ok("(5.0, \"shape\")".to_string())
+    );
+
+    // Superclasses come with the dictionary of their subclass.
+    assert_eq!(
+        run_elaborated("class Eq a => Named a where\n  label :: a -> String\ninstance Named Integer where\n  label x = \"n\"\nsame :: Named a => a -> a -> String\nsame x y = if x == y then label x else \"different\"\nmain = (same 1 1, same 1 2)"),
+        Ok("(\"n\", \"different\")".to_string())
+    );
+
+    // `fromInteger` gives an integer at the type of each instance.
+    assert_eq!(
+        run_elaborated("type V = (V Integer Integer)\ninstance Num V where\n  (+) (V a b) (V c d) = V (a + c) (b + d)\n  fromInteger n = V n n\ntwice :: Num a => a -> a\ntwice x = x + fromInteger 1\nmain = (twice 1, twice 1.5, twice (V 1 2), fromInteger 2 + 0.5)"),
+        Ok("(2, 2.5, V 2 3, 2.5)".to_string())
+    );
+
+    // Operators of a user instance of `Num`.
+    assert_eq!(
+        run_elaborated("type V = (V Integer Integer)\ninstance Num V where\n  (+) (V a b) (V c d) = V (a + c) (b + d)\n  (-) (V a b) (V c d) = V (a - c) (b - d)\n  (*) (V a b) (V c d) = V (a * c) (b * d)\n  (/) (V a b) (V c d) = V (a / c) (b / d)\n  (%) (V a b) (V c d) = V (a % c) (b % d)\n  (^) (V a b) (V c d) = V (a ^ c) (b ^ d)\n  negate (V a b) = V (0 - a) (0 - b)\nmain = (V 1 2 + V 3 4, -(V 1 2))"),
+        Ok("(V 4 6, V (-1) (-2))".to_string())
+    );
+
+    // Calling a method an instance does not define stops the program.
+    assert_eq!(
+        run_elaborated("class C a where\n  f :: a -> a\n  g :: a -> a\ninstance C Integer where\n  f x = x\nmain = g 1"),
+        Err(RuntimeErrorKind::MissingMethod { method: "g".to_string() })
+    );
+}
+
+#[test]
+fn test_derived_instances() {
+    let tree = "type Tree a = (Leaf) (Node (Tree a) a (Tree a))\n";
+    assert_eq!(
+        run_elaborated(&format!("{tree}main = (show (Node Leaf 1 Leaf), Node Leaf 'a' Leaf == Node Leaf 'a' Leaf, Leaf < Node Leaf 1 Leaf)")),
+        Ok("(\"Node Leaf 1 Leaf\", True, True)".to_string())
+    );
+    assert_eq!(
+        run_elaborated("main = (show [(1, 'a')], show \"s\", show 2.5)"),
+        Ok("(\"[(1, 'a')]\", \"\\\"s\\\"\", \"2.5\")".to_string())
+    );
+    // A declared instance is used instead of a derived one, also inside
+    // other types.
+    assert_eq!(
+        run_elaborated("type C = (R) (G)\ninstance Show C where\n  show x = \"c\"\nmain = show [Just R]\ntype Maybe a = (Nothing) (Just a)"),
+        Ok("\"[Just c]\"".to_string())
+    );
+}
+
+#[test]
+fn test_prelude() {
+    assert_eq!(
+        run_prelude("main = (map (\\x -> x * 2) (filter even (range 1 6)), foldr (+) 0 [1, 2, 3], length [[], [1]])"),
+        "([4, 8, 12], 6, 2)"
+    );
+    assert_eq!(
+        run_prelude("main = (show 1.5 ++ \"!\", concat [[1], [2, 3]], unwords [\"a\", \"b\"], (show . negate) $ 4)"),
+        "(\"1.5!\", [1, 2, 3], \"a b\", \"-4\")"
+    );
+    assert_eq!(
+        run_prelude("main = (readInteger \"-12\", readFloat \"x\", toFloat 2, pack (reverse (unpack \"ab\")), chr (ord 'a' + 1))"),
+        "(Just (-12), Nothing, 2.0, \"ba\", 'b')"
+    );
+    assert_eq!(
+        run_prelude("main = (lookup 2 (zip [1, 2] (unpack \"ab\")), either show id (Left 3), fromMaybe 0 (head []))"),
+        "(Just 'b', \"3\", 0)"
+    );
+    // Negative fields are shown in parentheses, so that they read back.
+    assert_eq!(
+        run_prelude("main = (show (Just (-1)), show (Left (-1.5)), show [Just (Just 2)])"),
+        "(\"Just (-1)\", \"Left (-1.5)\", \"[Just (Just 2)]\")"
+    );
+    // The declarations of the program hide those of the Prelude.
+    assert_eq!(
+        run_prelude("type Maybe a = (None) (Some a)\nmap f x = f x\nmain = (map negate 1, Some 1, Just 2)"),
+        "(-1, Some 1, Just 2)"
+    );
+}
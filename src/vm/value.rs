@@ -0,0 +1,61 @@
+/*
+   Risk is a purely functional, strongly typed language.
+   Copyright (C) 2024, Lokasku & NightProg
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+/// Index of an object slot in the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(pub(super) usize);
+
+/// A runtime value. Scalars are stored inline, everything else lives in the
+/// garbage-collected heap and is referenced through an `ObjRef`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Nil,
+    Object(ObjRef),
+}
+
+impl Value {
+    pub fn as_object(&self) -> Option<ObjRef> {
+        match self {
+            Value::Object(r) => Some(*r),
+            _ => None,
+        }
+    }
+}
+
+/// Formats a float so that it always reads back as a float: `5.0`, `1.5e-7`,
+/// `NaN`, `Infinity`.
+pub fn format_float(f: f64) -> String {
+    if f.is_nan() {
+        return "NaN".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let s = format!("{:?}", f);
+    if s.contains('.') {
+        return s;
+    }
+    match s.find('e') {
+        Some(e) => format!("{}.0{}", &s[..e], &s[e..]),
+        None => format!("{}.0", s),
+    }
+}